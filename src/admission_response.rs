@@ -5,6 +5,10 @@ use kubewarden_policy_sdk::response::ValidationResponse as PolicyValidationRespo
 use serde::{Deserialize, Serialize};
 use std::{collections::HashMap, result::Result};
 
+pub mod mutation;
+
+use mutation::{PatchGeneration, PolicyMutation, mutation_to_json_patch};
+
 /// This models the admission/v1/AdmissionResponse object of Kubernetes
 /// See https://pkg.go.dev/k8s.io/kubernetes/pkg/apis/admission#AdmissionResponse
 #[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq, Clone)]
@@ -107,43 +111,87 @@ impl AdmissionResponse {
         AdmissionResponse::reject(uid, format!("internal server error: {message}"), 500)
     }
 
+    /// Decode the base64 encoded JSONPatch of the response, if any
+    pub fn decode_patch(&self) -> Result<Option<json_patch::Patch>, ResponseError> {
+        let Some(patch) = &self.patch else {
+            return Ok(None);
+        };
+
+        let decoded = general_purpose::STANDARD
+            .decode(patch)
+            .map_err(ResponseError::InvalidPatchEncoding)?;
+        serde_json::from_slice(&decoded)
+            .map(Some)
+            .map_err(ResponseError::Deserialize)
+    }
+
     pub fn from_policy_validation_response(
         uid: String,
         req_obj: Option<&serde_json::Value>,
         pol_val_resp: &PolicyValidationResponse,
     ) -> Result<AdmissionResponse, ResponseError> {
-        if pol_val_resp.mutated_object.is_some() && req_obj.is_none() {
-            let message = "Incoming object is null, which happens only with DELETE operations, but the policy is attempting a mutation. This is not allowed";
-
-            return Ok(AdmissionResponse {
-                uid,
-                allowed: false,
-                warnings: None,
-                audit_annotations: None,
-                patch_type: None,
-                patch: None,
-                status: Some(AdmissionResponseStatus {
-                    message: Some(message.to_string()),
-                    code: None,
-                    ..Default::default()
-                }),
-            });
-        }
+        Self::from_policy_validation_response_with_mutation(
+            uid,
+            req_obj,
+            pol_val_resp,
+            None,
+            PatchGeneration::default(),
+        )
+    }
+
+    /// Build an `AdmissionResponse` from the validation response of a policy.
+    ///
+    /// The mutation explicitly returned by the policy, if any, takes precedence over
+    /// the `mutated_object` of the validation response. The `patch_generation`
+    /// strategy is used to compute the JSON Patch when the policy returns
+    /// either the mutated object or a JSON Merge Patch.
+    pub fn from_policy_validation_response_with_mutation(
+        uid: String,
+        req_obj: Option<&serde_json::Value>,
+        pol_val_resp: &PolicyValidationResponse,
+        mutation: Option<&PolicyMutation>,
+        patch_generation: PatchGeneration,
+    ) -> Result<AdmissionResponse, ResponseError> {
+        let is_mutating = mutation.is_some() || pol_val_resp.mutated_object.is_some();
+
+        let req_obj = match req_obj {
+            Some(req_obj) => req_obj,
+            None if is_mutating => {
+                let message = "Incoming object is null, which happens only with DELETE operations, but the policy is attempting a mutation. This is not allowed";
+
+                return Ok(AdmissionResponse {
+                    uid,
+                    allowed: false,
+                    warnings: None,
+                    audit_annotations: None,
+                    patch_type: None,
+                    patch: None,
+                    status: Some(AdmissionResponseStatus {
+                        message: Some(message.to_string()),
+                        code: None,
+                        ..Default::default()
+                    }),
+                });
+            }
+            None => &serde_json::Value::Null,
+        };
+
+        let diff = match (mutation, &pol_val_resp.mutated_object) {
+            (Some(mutation), _) => {
+                Some(mutation_to_json_patch(req_obj, mutation, patch_generation)?)
+            }
+            (None, Some(mut_obj)) => Some(patch_generation.diff(req_obj, mut_obj)?),
+            (None, None) => None,
+        };
 
-        let patch = match pol_val_resp.mutated_object.clone() {
-            Some(mut_obj) => {
-                let diff = json_patch::diff(req_obj.unwrap(), &mut_obj);
-                let empty_patch = json_patch::Patch(Vec::<json_patch::PatchOperation>::new());
-                if diff == empty_patch {
-                    None
-                } else {
-                    let diff_str = serde_json::to_string(&diff)
-                        .map(|s| general_purpose::STANDARD.encode(s))
-                        .map_err(ResponseError::Deserialize)?;
-                    Some(diff_str)
-                }
+        let patch = match diff {
+            Some(diff) if !diff.0.is_empty() => {
+                let diff_str = serde_json::to_string(&diff)
+                    .map(|s| general_purpose::STANDARD.encode(s))
+                    .map_err(ResponseError::Deserialize)?;
+                Some(diff_str)
             }
-            None => None,
+            _ => None,
         };
 
         let patch_type: Option<PatchType> = if patch.is_some() {
@@ -563,4 +611,100 @@ mod tests {
             serde_json::from_slice(patch_decoded_str.as_slice()).unwrap();
         assert_eq!(patch, expected_diff);
     }
+
+    #[test]
+    fn create_from_policy_validation_response_with_explicit_mutation() {
+        let uid = String::from("UID");
+        let req_obj = json!({"hello": "world"});
+        let mutation: PolicyMutation = serde_json::from_value(json!({
+            "type": "JSONPatch",
+            "patch": [{"op": "add", "path": "/ciao", "value": "mondo"}]
+        }))
+        .unwrap();
+
+        let pol_val_resp = PolicyValidationResponse {
+            accepted: true,
+            message: None,
+            code: None,
+            // ignored, the explicit mutation takes precedence
+            mutated_object: Some(json!({"hello": "world", "hola": "mundo"})),
+            audit_annotations: None,
+            warnings: None,
+        };
+
+        let response = AdmissionResponse::from_policy_validation_response_with_mutation(
+            uid.clone(),
+            Some(&req_obj),
+            &pol_val_resp,
+            Some(&mutation),
+            PatchGeneration::Diff,
+        )
+        .expect("cannot build response");
+
+        assert!(response.allowed);
+        assert_eq!(response.patch_type, Some(PatchType::JSONPatch));
+
+        let patch_decoded_str = general_purpose::STANDARD
+            .decode(response.patch.unwrap())
+            .unwrap();
+        let patch: json_patch::Patch =
+            serde_json::from_slice(patch_decoded_str.as_slice()).unwrap();
+        assert_eq!(PolicyMutation::JsonPatch(patch), mutation);
+    }
+
+    #[test]
+    fn explicit_mutation_on_delete_operation_is_not_allowed() {
+        let pol_val_resp = PolicyValidationResponse {
+            accepted: true,
+            message: None,
+            code: None,
+            mutated_object: None,
+            warnings: None,
+            audit_annotations: None,
+        };
+        let mutation = PolicyMutation::MergePatch(json!({"hello": "world"}));
+
+        let response = AdmissionResponse::from_policy_validation_response_with_mutation(
+            "UID".to_string(),
+            None,
+            &pol_val_resp,
+            Some(&mutation),
+            PatchGeneration::Diff,
+        )
+        .expect("cannot build response");
+
+        assert!(!response.allowed);
+        assert!(response.patch.is_none());
+    }
+
+    #[test]
+    fn decode_patch() {
+        let patch: json_patch::Patch =
+            serde_json::from_value(json!([{"op": "add", "path": "/ciao", "value": "mondo"}]))
+                .unwrap();
+        let response = AdmissionResponse {
+            uid: "UID".to_string(),
+            allowed: true,
+            patch_type: Some(PatchType::JSONPatch),
+            patch: Some(general_purpose::STANDARD.encode(serde_json::to_vec(&patch).unwrap())),
+            ..Default::default()
+        };
+        assert_eq!(response.decode_patch().unwrap(), Some(patch));
+
+        let response = AdmissionResponse {
+            patch: Some("not base64!".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            response.decode_patch(),
+            Err(ResponseError::InvalidPatchEncoding(_))
+        ));
+
+        assert!(
+            AdmissionResponse::default()
+                .decode_patch()
+                .unwrap()
+                .is_none()
+        );
+    }
 }
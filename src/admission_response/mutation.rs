@@ -0,0 +1,315 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+
+use crate::errors::ResponseError;
+
+/// The mutation explicitly returned by a policy.
+///
+/// Policies usually mutate a request by returning the whole mutated object, which is
+/// then diffed by the host against the original one. As an alternative, a policy can
+/// return the mutation by adding a `mutation` field to its validation response:
+///
+/// ```json
+/// {
+///   "accepted": true,
+///   "mutation": {
+///     "type": "JSONPatch",
+///     "patch": [{"op": "add", "path": "/metadata/labels/owner", "value": "team-a"}]
+///   }
+/// }
+/// ```
+///
+/// When `mutation` is set, the `mutated_object` field of the response is ignored.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", content = "patch")]
+pub enum PolicyMutation {
+    /// A JSON Patch, as defined by RFC 6902. The patch is sent as-is to the API server
+    #[serde(rename = "JSONPatch")]
+    JsonPatch(json_patch::Patch),
+    /// A JSON Merge Patch, as defined by RFC 7386. The host converts it into a JSON Patch
+    #[serde(rename = "MergePatch")]
+    MergePatch(Value),
+}
+
+/// The fields of the policy validation response that are not part of the
+/// Kubewarden SDK `ValidationResponse`
+#[derive(Deserialize, Debug, Default)]
+pub(crate) struct PolicyValidationResponseExtensions {
+    #[serde(default)]
+    pub mutation: Option<PolicyMutation>,
+}
+
+impl PolicyValidationResponseExtensions {
+    /// Extract the extensions from the raw response of a policy. Malformed extensions are
+    /// reported as errors, while their absence is not
+    pub(crate) fn from_slice(response: &[u8]) -> Result<Self, ResponseError> {
+        serde_json::from_slice(response).map_err(ResponseError::InvalidMutation)
+    }
+}
+
+/// How the host generates the JSON Patch describing the difference between the
+/// original object and the mutated one
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum PatchGeneration {
+    /// Generic JSON diff. Arrays are compared by index, hence reordering the elements of a
+    /// list can produce a large patch
+    #[default]
+    Diff,
+    /// Arrays of objects that can be identified by their `name` field (like containers,
+    /// volumes, environment variables,...) are compared by key, following the Kubernetes
+    /// list semantics. Reordered elements are moved, changed elements are patched in place.
+    /// This produces smaller and stable patches
+    KeyedByName,
+}
+
+impl PatchGeneration {
+    /// Compute the JSON Patch that turns `original` into `mutated`
+    pub fn diff(
+        &self,
+        original: &Value,
+        mutated: &Value,
+    ) -> Result<json_patch::Patch, ResponseError> {
+        match self {
+            PatchGeneration::Diff => Ok(json_patch::diff(original, mutated)),
+            PatchGeneration::KeyedByName => {
+                let mut operations = Vec::new();
+                keyed_diff(original, mutated, "", &mut operations);
+                serde_json::from_value(Value::Array(operations)).map_err(ResponseError::Deserialize)
+            }
+        }
+    }
+}
+
+/// Compute the JSON Patch that has to be applied to `original` to obtain the result
+/// of the given mutation
+pub(crate) fn mutation_to_json_patch(
+    original: &Value,
+    mutation: &PolicyMutation,
+    patch_generation: PatchGeneration,
+) -> Result<json_patch::Patch, ResponseError> {
+    match mutation {
+        PolicyMutation::JsonPatch(patch) => {
+            // Make sure the patch can actually be applied, otherwise the
+            // API server would reject the whole admission response
+            let mut patched = original.clone();
+            json_patch::patch(&mut patched, patch).map_err(ResponseError::InvalidJsonPatch)?;
+            Ok(patch.clone())
+        }
+        PolicyMutation::MergePatch(merge_patch) => {
+            let mut patched = original.clone();
+            json_patch::merge(&mut patched, merge_patch);
+            patch_generation.diff(original, &patched)
+        }
+    }
+}
+
+/// Escape a key so that it can be used as a JSON Pointer reference token (RFC 6901)
+fn escape_pointer_token(token: &str) -> String {
+    token.replace('~', "~0").replace('/', "~1")
+}
+
+/// Returns the name of each element of the array, when all the elements are objects
+/// with a unique string `name` field
+fn element_names(elements: &[Value]) -> Option<Vec<&str>> {
+    let names: Vec<&str> = elements
+        .iter()
+        .map(|element| element.get("name").and_then(Value::as_str))
+        .collect::<Option<Vec<&str>>>()?;
+
+    let mut unique = names.clone();
+    unique.sort_unstable();
+    unique.dedup();
+
+    (unique.len() == names.len()).then_some(names)
+}
+
+fn keyed_diff(original: &Value, mutated: &Value, path: &str, operations: &mut Vec<Value>) {
+    if original == mutated {
+        return;
+    }
+
+    match (original, mutated) {
+        (Value::Object(original), Value::Object(mutated)) => {
+            keyed_diff_objects(original, mutated, path, operations)
+        }
+        (Value::Array(original), Value::Array(mutated)) => {
+            match (element_names(original), element_names(mutated)) {
+                (Some(original_names), Some(mutated_names)) if !original.is_empty() => {
+                    keyed_diff_lists(
+                        original,
+                        &original_names,
+                        mutated,
+                        &mutated_names,
+                        path,
+                        operations,
+                    )
+                }
+                // Lists without a merge key are atomic, like in Kubernetes
+                _ => operations.push(json!({"op": "replace", "path": path, "value": mutated})),
+            }
+        }
+        _ => operations.push(json!({"op": "replace", "path": path, "value": mutated})),
+    }
+}
+
+fn keyed_diff_objects(
+    original: &Map<String, Value>,
+    mutated: &Map<String, Value>,
+    path: &str,
+    operations: &mut Vec<Value>,
+) {
+    for (key, original_value) in original {
+        let key_path = format!("{path}/{}", escape_pointer_token(key));
+        match mutated.get(key) {
+            Some(mutated_value) => keyed_diff(original_value, mutated_value, &key_path, operations),
+            None => operations.push(json!({"op": "remove", "path": key_path})),
+        }
+    }
+
+    for (key, mutated_value) in mutated {
+        if !original.contains_key(key) {
+            let key_path = format!("{path}/{}", escape_pointer_token(key));
+            operations.push(json!({"op": "add", "path": key_path, "value": mutated_value}));
+        }
+    }
+}
+
+fn keyed_diff_lists(
+    original: &[Value],
+    original_names: &[&str],
+    mutated: &[Value],
+    mutated_names: &[&str],
+    path: &str,
+    operations: &mut Vec<Value>,
+) {
+    // Start by removing the elements that are no longer present, starting from the
+    // end of the list to keep the indexes of the other elements stable
+    let mut current: Vec<(&str, &Value)> = Vec::with_capacity(original.len());
+    for (index, (name, element)) in original_names.iter().zip(original).enumerate().rev() {
+        if mutated_names.contains(name) {
+            current.insert(0, (*name, element));
+        } else {
+            operations.push(json!({"op": "remove", "path": format!("{path}/{index}")}));
+        }
+    }
+
+    // Then walk the mutated list, moving or adding the elements to their final position
+    for (index, (name, mutated_element)) in mutated_names.iter().zip(mutated).enumerate() {
+        let element_path = format!("{path}/{index}");
+
+        match current
+            .iter()
+            .position(|(current_name, _)| current_name == name)
+        {
+            Some(position) => {
+                if position != index {
+                    let moved = current.remove(position);
+                    current.insert(index, moved);
+                    operations.push(json!({
+                        "op": "move",
+                        "from": format!("{path}/{position}"),
+                        "path": element_path,
+                    }));
+                }
+                keyed_diff(current[index].1, mutated_element, &element_path, operations);
+            }
+            None => {
+                current.insert(index, (*name, mutated_element));
+                operations
+                    .push(json!({"op": "add", "path": element_path, "value": mutated_element}));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rstest::rstest;
+
+    fn pod(containers: Value) -> Value {
+        json!({
+            "apiVersion": "v1",
+            "kind": "Pod",
+            "metadata": {"name": "nginx"},
+            "spec": {"containers": containers}
+        })
+    }
+
+    #[rstest]
+    #[case::reorder(
+        pod(json!([{"name": "a", "image": "a:1"}, {"name": "b", "image": "b:1"}])),
+        pod(json!([{"name": "b", "image": "b:1"}, {"name": "a", "image": "a:1"}])),
+        json!([{"op": "move", "from": "/spec/containers/1", "path": "/spec/containers/0"}]),
+    )]
+    #[case::change_inside_of_element(
+        pod(json!([{"name": "a", "image": "a:1"}, {"name": "b", "image": "b:1"}])),
+        pod(json!([{"name": "a", "image": "a:1"}, {"name": "b", "image": "b:2"}])),
+        json!([{"op": "replace", "path": "/spec/containers/1/image", "value": "b:2"}]),
+    )]
+    #[case::add_and_remove(
+        pod(json!([{"name": "a", "image": "a:1"}, {"name": "b", "image": "b:1"}])),
+        pod(json!([{"name": "c", "image": "c:1"}, {"name": "b", "image": "b:1"}])),
+        json!([
+            {"op": "remove", "path": "/spec/containers/0"},
+            {"op": "add", "path": "/spec/containers/0", "value": {"name": "c", "image": "c:1"}},
+        ]),
+    )]
+    #[case::list_without_keys(
+        json!({"args": ["a", "b"]}),
+        json!({"args": ["b", "a"]}),
+        json!([{"op": "replace", "path": "/args", "value": ["b", "a"]}]),
+    )]
+    #[case::escape_keys(
+        json!({"metadata": {"labels": {}}}),
+        json!({"metadata": {"labels": {"example.com/owner": "team~a"}}}),
+        json!([{"op": "add", "path": "/metadata/labels/example.com~1owner", "value": "team~a"}]),
+    )]
+    fn keyed_by_name_diff(
+        #[case] original: Value,
+        #[case] mutated: Value,
+        #[case] expected: Value,
+    ) {
+        let patch = PatchGeneration::KeyedByName
+            .diff(&original, &mutated)
+            .expect("cannot compute diff");
+        assert_eq!(serde_json::to_value(&patch).unwrap(), expected);
+
+        let mut patched = original.clone();
+        json_patch::patch(&mut patched, &patch).expect("cannot apply patch");
+        assert_eq!(patched, mutated);
+    }
+
+    #[test]
+    fn convert_merge_patch() {
+        let original = json!({"metadata": {"labels": {"app": "nginx", "tier": "web"}}});
+        let mutation = PolicyMutation::MergePatch(json!({
+            "metadata": {"labels": {"tier": null, "owner": "team-a"}}
+        }));
+
+        let patch = mutation_to_json_patch(&original, &mutation, PatchGeneration::Diff)
+            .expect("cannot convert merge patch");
+
+        let mut patched = original.clone();
+        json_patch::patch(&mut patched, &patch).expect("cannot apply patch");
+        assert_eq!(
+            patched,
+            json!({"metadata": {"labels": {"app": "nginx", "owner": "team-a"}}})
+        );
+    }
+
+    #[test]
+    fn reject_json_patch_that_cannot_be_applied() {
+        let original = json!({"metadata": {}});
+        let mutation: PolicyMutation = serde_json::from_value(json!({
+            "type": "JSONPatch",
+            "patch": [{"op": "remove", "path": "/metadata/labels/app"}]
+        }))
+        .expect("cannot deserialize mutation");
+
+        let result = mutation_to_json_patch(&original, &mutation, PatchGeneration::Diff);
+        assert!(matches!(result, Err(ResponseError::InvalidJsonPatch(_))));
+    }
+}
@@ -1,13 +1,33 @@
+use crate::admission_request::AdmissionRequest;
 use crate::admission_response::{
     AdmissionResponse, AdmissionResponseStatus, StatusCause, StatusDetails,
 };
-use tracing::info;
+use tracing::{info, warn};
 
+pub mod decision_log;
 pub mod errors;
+pub mod mutation_guard;
 pub mod policy_id;
 pub mod policy_mode;
+pub mod policy_mode_override;
+pub mod rejection_message_template;
 
-use crate::admission_response_handler::{policy_id::PolicyID, policy_mode::PolicyMode};
+use crate::admission_response_handler::{
+    decision_log::{DecisionRecord, DecisionSink},
+    mutation_guard::{MutationGuard, MutationGuardAction},
+    policy_id::PolicyID,
+    policy_mode::PolicyMode,
+    policy_mode_override::{PolicyModeOverride, find_matching_override},
+    rejection_message_template::{RejectionMessageTemplate, TemplateContext},
+};
+
+/// The audit annotation holding the mode used to process the admission response,
+/// set when policy mode overrides are configured
+pub const AUDIT_ANNOTATION_POLICY_MODE: &str = "policy-mode";
+
+/// The audit annotation holding the name of the policy mode override rule that
+/// matched the request
+pub const AUDIT_ANNOTATION_POLICY_MODE_OVERRIDE: &str = "policy-mode-override";
 
 /// Applies a series of mutation constrains to the admission response.
 ///
@@ -16,13 +36,27 @@ use crate::admission_response_handler::{policy_id::PolicyID, policy_mode::Policy
 ///   configuration does not allow it to mutate
 /// - A policy might be running in "Monitor" mode, that always
 ///   accepts the request (without mutation), logging the answer
+/// - A policy might have tried to mutate paths of the object that are
+///   protected by the `MutationGuard` of the policy
 /// - A policy might have a custom rejection message that should be used instead of the error
 ///   returned by the policy. The original error is added in the warnings list.
+///   The custom rejection message can also be a `RejectionMessageTemplate`.
+///
+/// The mode of the policy can be overridden on a per-request basis by a list of
+/// `PolicyModeOverride` rules. The first rule matching the request determines the
+/// effective mode.
+///
+/// When a `DecisionSink` is configured, a `DecisionRecord` is emitted for each
+/// processed response.
 pub struct AdmissionResponseHandler<'a> {
     policy_id: &'a PolicyID,
     policy_mode: &'a PolicyMode,
     allowed_to_mutate: bool,
     custom_rejection_message: Option<String>,
+    custom_rejection_message_template: Option<&'a RejectionMessageTemplate>,
+    policy_mode_overrides: &'a [PolicyModeOverride],
+    decision_sink: Option<&'a dyn DecisionSink>,
+    mutation_guard: Option<&'a MutationGuard>,
 }
 
 impl<'a> AdmissionResponseHandler<'a> {
@@ -37,23 +71,179 @@ impl<'a> AdmissionResponseHandler<'a> {
             policy_mode,
             allowed_to_mutate,
             custom_rejection_message,
+            custom_rejection_message_template: None,
+            policy_mode_overrides: &[],
+            decision_sink: None,
+            mutation_guard: None,
+        }
+    }
+
+    /// Set the rules that can override the mode of the policy, depending on the request
+    /// being evaluated. The overrides are taken into account only by
+    /// `process_response_for_request`.
+    pub fn with_policy_mode_overrides(
+        self,
+        policy_mode_overrides: &'a [PolicyModeOverride],
+    ) -> Self {
+        AdmissionResponseHandler {
+            policy_mode_overrides,
+            ..self
+        }
+    }
+
+    /// Set the template used to build the custom rejection message. The template
+    /// takes precedence over the static custom rejection message.
+    pub fn with_custom_rejection_message_template(
+        self,
+        custom_rejection_message_template: &'a RejectionMessageTemplate,
+    ) -> Self {
+        AdmissionResponseHandler {
+            custom_rejection_message_template: Some(custom_rejection_message_template),
+            ..self
+        }
+    }
+
+    /// Set the sink that receives a `DecisionRecord` for each processed response
+    pub fn with_decision_sink(self, decision_sink: &'a dyn DecisionSink) -> Self {
+        AdmissionResponseHandler {
+            decision_sink: Some(decision_sink),
+            ..self
+        }
+    }
+
+    /// Set the guard restricting the paths the policy is allowed to mutate
+    pub fn with_mutation_guard(self, mutation_guard: &'a MutationGuard) -> Self {
+        AdmissionResponseHandler {
+            mutation_guard: Some(mutation_guard),
+            ..self
         }
     }
 
     pub fn process_response(&'a self, admission_response: AdmissionResponse) -> AdmissionResponse {
-        let admission_response = self.apply_monitor_mode(admission_response);
-        let admission_response = self.apply_mutation_constraint(admission_response);
+        self.process_response_with_mode(self.policy_mode, None, admission_response)
+    }
+
+    /// Process the admission response produced by the evaluation of the given request.
+    ///
+    /// The request is matched against the policy mode overrides to determine the effective
+    /// mode of the policy. When overrides are configured, the chosen mode is logged and
+    /// recorded inside of the audit annotations of the response.
+    pub fn process_response_for_request(
+        &'a self,
+        request: &AdmissionRequest,
+        admission_response: AdmissionResponse,
+    ) -> AdmissionResponse {
+        if self.policy_mode_overrides.is_empty() {
+            return self.process_response_with_mode(
+                self.policy_mode,
+                Some(request),
+                admission_response,
+            );
+        }
+
+        let matching_override = find_matching_override(self.policy_mode_overrides, request);
+        let policy_mode = matching_override
+            .map(|rule| &rule.mode)
+            .unwrap_or(self.policy_mode);
+
+        info!(
+            policy_id = self.policy_id.to_string(),
+            request_uid = request.uid.as_str(),
+            policy_mode = policy_mode.to_string(),
+            policy_mode_override = matching_override.map(|rule| rule.name.as_str()),
+            "policy mode chosen",
+        );
+
+        let admission_response =
+            self.process_response_with_mode(policy_mode, Some(request), admission_response);
+
+        let mut audit_annotations = admission_response.audit_annotations.unwrap_or_default();
+        audit_annotations.insert(
+            AUDIT_ANNOTATION_POLICY_MODE.to_string(),
+            policy_mode.to_string(),
+        );
+        if let Some(rule) = matching_override {
+            audit_annotations.insert(
+                AUDIT_ANNOTATION_POLICY_MODE_OVERRIDE.to_string(),
+                rule.name.clone(),
+            );
+        }
+
+        AdmissionResponse {
+            audit_annotations: Some(audit_annotations),
+            ..admission_response
+        }
+    }
+
+    fn process_response_with_mode(
+        &'a self,
+        policy_mode: &PolicyMode,
+        request: Option<&AdmissionRequest>,
+        original_response: AdmissionResponse,
+    ) -> AdmissionResponse {
+        // The response produced by the policy is needed only to record the
+        // decision, don't copy it when no decision sink is configured
+        let recorded_response = self.decision_sink.map(|_| original_response.clone());
+
+        let admission_response = self.apply_monitor_mode(policy_mode, original_response);
+        let admission_response = self.apply_mutation_constraint(policy_mode, admission_response);
+        let admission_response = self.apply_mutation_guard(request, admission_response);
 
         // Note: apply the custom rejection message as a last step, so that it can override
         // any previous status message.
-        self.apply_custom_rejection_message(admission_response)
+        let admission_response = self.apply_custom_rejection_message(request, admission_response);
+
+        if let Some(original_response) = recorded_response {
+            self.record_decision(
+                policy_mode,
+                request,
+                &original_response,
+                &admission_response,
+            );
+        }
+
+        admission_response
+    }
+
+    /// Send the decision to the decision sink, if one is configured.
+    /// Failing to record a decision must not have any impact on the admission
+    /// response, hence errors are just logged.
+    fn record_decision(
+        &'a self,
+        policy_mode: &PolicyMode,
+        request: Option<&AdmissionRequest>,
+        original_response: &AdmissionResponse,
+        final_response: &AdmissionResponse,
+    ) {
+        let Some(decision_sink) = self.decision_sink else {
+            return;
+        };
+
+        let record = DecisionRecord::new(
+            self.policy_id,
+            policy_mode,
+            request,
+            original_response,
+            final_response,
+        );
+        if let Err(error) = decision_sink.record(&record) {
+            warn!(
+                policy_id = self.policy_id.to_string(),
+                ?error,
+                "cannot record policy decision"
+            );
+        }
     }
 
     // In monitor mode we always accept the request, but log what would have been the decision of the
     // policy. We also force mutating patches to be none. Status is also overridden, as it's only taken into
     // account when a request is rejected.
-    fn apply_monitor_mode(&'a self, admission_response: AdmissionResponse) -> AdmissionResponse {
-        if self.policy_mode != &PolicyMode::Monitor {
+    fn apply_monitor_mode(
+        &'a self,
+        policy_mode: &PolicyMode,
+        admission_response: AdmissionResponse,
+    ) -> AdmissionResponse {
+        if policy_mode != &PolicyMode::Monitor {
             return admission_response;
         }
 
@@ -77,9 +267,10 @@ impl<'a> AdmissionResponseHandler<'a> {
     /// the request is rejected.
     fn apply_mutation_constraint(
         &'a self,
+        policy_mode: &PolicyMode,
         admission_response: AdmissionResponse,
     ) -> AdmissionResponse {
-        if self.policy_mode != &PolicyMode::Protect {
+        if policy_mode != &PolicyMode::Protect {
             return admission_response;
         }
 
@@ -110,6 +301,100 @@ impl<'a> AdmissionResponseHandler<'a> {
         }
     }
 
+    /// This check is applied only when the policy produced a patch that survived
+    /// the previous constraints, and a mutation guard is configured.
+    ///
+    /// Depending on the guard configuration, patches touching protected paths either
+    /// cause the rejection of the request, or have their offending operations removed.
+    /// In the latter case, a warning is added to the response. The request is still
+    /// rejected when the remaining operations cannot be applied to the object of the
+    /// request, or when the object is not known.
+    fn apply_mutation_guard(
+        &'a self,
+        request: Option<&AdmissionRequest>,
+        admission_response: AdmissionResponse,
+    ) -> AdmissionResponse {
+        let (Some(mutation_guard), Some(patch)) =
+            (self.mutation_guard, admission_response.patch.as_ref())
+        else {
+            return admission_response;
+        };
+
+        let guarded_patch = match mutation_guard.inspect(patch) {
+            Ok(guarded_patch) => guarded_patch,
+            Err(error) => {
+                return AdmissionResponse::reject_internal_server_error(
+                    admission_response.uid,
+                    error.to_string(),
+                );
+            }
+        };
+
+        if guarded_patch.violations.is_empty() {
+            return admission_response;
+        }
+
+        let violations = guarded_patch.violations.join(", ");
+        let rejection_message = format!(
+            "Request rejected by policy {}. The policy attempted to mutate protected paths: {}",
+            self.policy_id, violations
+        );
+        let reject = |admission_response: AdmissionResponse, message: String| AdmissionResponse {
+            allowed: false,
+            status: Some(AdmissionResponseStatus {
+                message: Some(message),
+                code: None,
+                ..Default::default()
+            }),
+            patch: None,
+            patch_type: None,
+            ..admission_response
+        };
+
+        match mutation_guard.action() {
+            MutationGuardAction::Reject => reject(admission_response, rejection_message),
+            MutationGuardAction::Strip => {
+                let object = request.and_then(|request| request.object.as_ref());
+                if let Err(error) = guarded_patch.check_applies_to(object.map(|object| &object.0)) {
+                    warn!(
+                        policy_id = self.policy_id.to_string(),
+                        violations = violations.as_str(),
+                        error = error.as_str(),
+                        "cannot strip the operations mutating protected paths"
+                    );
+                    return reject(
+                        admission_response,
+                        format!(
+                            "{rejection_message}. The remaining changes cannot be applied: {error}"
+                        ),
+                    );
+                }
+
+                warn!(
+                    policy_id = self.policy_id.to_string(),
+                    violations = violations.as_str(),
+                    "policy attempted to mutate protected paths"
+                );
+
+                let mut warnings = admission_response.warnings.unwrap_or_default();
+                warnings.push(format!(
+                    "policy {} attempted to mutate protected paths, these changes have been discarded: {}",
+                    self.policy_id, violations
+                ));
+
+                AdmissionResponse {
+                    patch_type: guarded_patch
+                        .patch
+                        .as_ref()
+                        .and(admission_response.patch_type),
+                    patch: guarded_patch.patch,
+                    warnings: Some(warnings),
+                    ..admission_response
+                }
+            }
+        }
+    }
+
     /// This check is applied only when the admission response is not allowed.
     ///
     /// If the policy has a custom rejection message, it is applied to the
@@ -118,6 +403,7 @@ impl<'a> AdmissionResponseHandler<'a> {
     /// to preserve the original error message.
     fn apply_custom_rejection_message(
         &'a self,
+        request: Option<&AdmissionRequest>,
         admission_response: AdmissionResponse,
     ) -> AdmissionResponse {
         if admission_response.allowed {
@@ -125,7 +411,9 @@ impl<'a> AdmissionResponseHandler<'a> {
             return admission_response;
         }
 
-        if self.custom_rejection_message.is_none() {
+        if self.custom_rejection_message.is_none()
+            && self.custom_rejection_message_template.is_none()
+        {
             // If the policy does not have a custom rejection message,
             // we don't need to do anything
             return admission_response;
@@ -135,6 +423,17 @@ impl<'a> AdmissionResponseHandler<'a> {
         let original_rejection_message = status.message.unwrap_or_default();
 
         let mut causes = status.details.clone().unwrap_or_default().causes;
+
+        let custom_rejection_message = match self.custom_rejection_message_template {
+            Some(template) => Some(template.render(&TemplateContext {
+                policy_id: self.policy_id,
+                request,
+                original_message: &original_rejection_message,
+                causes: &causes,
+            })),
+            None => self.custom_rejection_message.clone(),
+        };
+
         causes.push(StatusCause {
             message: Some(original_rejection_message),
             ..Default::default()
@@ -142,7 +441,7 @@ impl<'a> AdmissionResponseHandler<'a> {
 
         AdmissionResponse {
             status: Some(AdmissionResponseStatus {
-                message: self.custom_rejection_message.clone(),
+                message: custom_rejection_message,
                 details: Some(StatusDetails {
                     causes,
                     ..status.details.unwrap_or_default()
@@ -165,7 +464,12 @@ fn rejection_message_because_policy_is_not_allowed_to_mutate(policy_id: &PolicyI
 mod tests {
     use super::*;
 
+    use std::collections::{BTreeSet, HashMap};
+
     use crate::admission_response::{self, AdmissionResponse};
+    use crate::admission_response_handler::decision_log::{InMemoryDecisionSink, Verdict};
+    use crate::test_fixtures::admission_request;
+    use base64::{Engine as _, engine::general_purpose};
     use lazy_static::lazy_static;
     use rstest::rstest;
 
@@ -491,4 +795,186 @@ mod tests {
             processed_response, expected_response
         );
     }
+
+    #[test]
+    fn record_decisions() {
+        let decision_sink = InMemoryDecisionSink::new();
+        let handler = AdmissionResponseHandler::new(&POLICY_ID, &PolicyMode::Monitor, false, None)
+            .with_decision_sink(&decision_sink);
+
+        let request = admission_request();
+        handler.process_response_for_request(&request, mutation_response());
+        handler.process_response(rejection_response(RejectionDetails::default()));
+
+        let records = decision_sink.records();
+        assert_eq!(records.len(), 2);
+
+        assert_eq!(records[0].policy_id, POLICY_ID.to_string());
+        assert_eq!(records[0].mode, PolicyMode::Monitor);
+        assert_eq!(records[0].request_uid, "hello");
+        assert_eq!(records[0].kind, Some(request.kind.clone()));
+        assert_eq!(records[0].namespace, Some("default".to_string()));
+        assert_eq!(records[0].user, Some("admin".to_string()));
+        assert_eq!(records[0].original_verdict, Verdict::Allowed);
+        assert_eq!(records[0].final_verdict, Verdict::Allowed);
+        assert!(records[0].mutation_suppressed);
+        assert_eq!(records[0].rejection_message, None);
+
+        assert_eq!(records[1].kind, None);
+        assert_eq!(records[1].original_verdict, Verdict::Denied);
+        assert_eq!(records[1].final_verdict, Verdict::Allowed);
+        assert!(!records[1].mutation_suppressed);
+        assert_eq!(
+            records[1].rejection_message,
+            Some(DEFAULT_REJECTION_MESSAGE.to_string())
+        );
+    }
+
+    #[test]
+    fn process_rejected_response_with_custom_rejection_message_template() {
+        let template = RejectionMessageTemplate::new(
+            "{{ policy_id }} rejected {{ request.namespace }}: {{ original_message }}",
+        )
+        .expect("template should be valid");
+        let handler = AdmissionResponseHandler::new(
+            &POLICY_ID,
+            &PolicyMode::Protect,
+            false,
+            Some("ignored".to_string()),
+        )
+        .with_custom_rejection_message_template(&template);
+
+        let processed_response = handler.process_response_for_request(
+            &admission_request(),
+            rejection_response(RejectionDetails::default()),
+        );
+        assert_eq!(
+            processed_response,
+            rejection_response(RejectionDetails {
+                message: format!("policy-id rejected default: {DEFAULT_REJECTION_MESSAGE}"),
+                cause: Some(DEFAULT_REJECTION_MESSAGE.to_string()),
+            })
+        );
+    }
+
+    fn encode_patch(patch: serde_json::Value) -> String {
+        general_purpose::STANDARD.encode(serde_json::to_string(&patch).unwrap())
+    }
+
+    #[rstest]
+    #[case::reject(
+        MutationGuardAction::Reject,
+        serde_json::json!([
+            {"op": "add", "path": "/metadata/labels/owner", "value": "me"},
+            {"op": "replace", "path": "/metadata/name", "value": "hijacked"},
+        ]),
+        false,
+        None,
+        None,
+    )]
+    #[case::strip(
+        MutationGuardAction::Strip,
+        serde_json::json!([
+            {"op": "add", "path": "/metadata/labels/owner", "value": "me"},
+            {"op": "replace", "path": "/metadata/name", "value": "hijacked"},
+        ]),
+        true,
+        Some(encode_patch(serde_json::json!([
+            {"op": "add", "path": "/metadata/labels/owner", "value": "me"}
+        ]))),
+        Some(vec![
+            "policy policy-id attempted to mutate protected paths, these changes have been discarded: /metadata/name".to_string()
+        ]),
+    )]
+    #[case::strip_breaks_remaining_operations(
+        MutationGuardAction::Strip,
+        serde_json::json!([
+            {"op": "add", "path": "/status", "value": {"phase": "Pending"}},
+            {"op": "copy", "from": "/status/phase", "path": "/metadata/labels/phase"},
+        ]),
+        false,
+        None,
+        None,
+    )]
+    fn process_mutated_response_with_mutation_guard(
+        #[case] action: MutationGuardAction,
+        #[case] patch: serde_json::Value,
+        #[case] expected_allowed: bool,
+        #[case] expected_patch: Option<String>,
+        #[case] expected_warnings: Option<Vec<String>>,
+    ) {
+        let mutation_guard = MutationGuard::new(
+            mutation_guard::DEFAULT_DENIED_PATHS
+                .iter()
+                .map(|p| p.to_string())
+                .collect(),
+            vec![],
+            action,
+        )
+        .unwrap();
+        let handler = AdmissionResponseHandler::new(&POLICY_ID, &PolicyMode::Protect, true, None)
+            .with_mutation_guard(&mutation_guard);
+
+        let processed_response = handler.process_response_for_request(
+            &admission_request(),
+            AdmissionResponse {
+                patch: Some(encode_patch(patch)),
+                ..mutation_response()
+            },
+        );
+
+        assert_eq!(processed_response.allowed, expected_allowed);
+        assert_eq!(processed_response.patch, expected_patch);
+        assert_eq!(
+            processed_response.patch_type.is_some(),
+            expected_patch.is_some()
+        );
+        assert_eq!(processed_response.warnings, expected_warnings);
+    }
+
+    #[rstest]
+    #[case::override_matches(
+        "migrating-team",
+        accepted_response(),
+        HashMap::from([
+            (AUDIT_ANNOTATION_POLICY_MODE.to_string(), "monitor".to_string()),
+            (AUDIT_ANNOTATION_POLICY_MODE_OVERRIDE.to_string(), "migrating-teams".to_string()),
+        ])
+    )]
+    #[case::override_does_not_match(
+        "other-team",
+        rejection_response(RejectionDetails::default()),
+        HashMap::from([
+            (AUDIT_ANNOTATION_POLICY_MODE.to_string(), "protect".to_string()),
+        ])
+    )]
+    fn process_rejected_response_with_policy_mode_overrides(
+        #[case] namespace: &str,
+        #[case] expected_response: AdmissionResponse,
+        #[case] expected_audit_annotations: HashMap<String, String>,
+    ) {
+        let overrides = vec![PolicyModeOverride {
+            name: "migrating-teams".to_string(),
+            mode: PolicyMode::Monitor,
+            namespaces: BTreeSet::from(["migrating-team".to_string()]),
+            ..Default::default()
+        }];
+        let handler = AdmissionResponseHandler::new(&POLICY_ID, &PolicyMode::Protect, false, None)
+            .with_policy_mode_overrides(&overrides);
+
+        let processed_response = handler.process_response_for_request(
+            &AdmissionRequest {
+                namespace: Some(namespace.to_string()),
+                ..admission_request()
+            },
+            rejection_response(RejectionDetails::default()),
+        );
+        assert_eq!(
+            processed_response,
+            AdmissionResponse {
+                audit_annotations: Some(expected_audit_annotations),
+                ..expected_response
+            }
+        );
+    }
 }
@@ -0,0 +1,265 @@
+use std::{
+    io::Write,
+    sync::{Mutex, PoisonError},
+};
+
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::{
+    admission_request::{AdmissionRequest, GroupVersionKind},
+    admission_response::AdmissionResponse,
+    admission_response_handler::{
+        errors::{DecisionLogError, DecisionLogResult},
+        policy_id::PolicyID,
+        policy_mode::PolicyMode,
+    },
+};
+
+/// The verdict of a policy about an admission request
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Verdict {
+    Allowed,
+    Denied,
+}
+
+impl From<&AdmissionResponse> for Verdict {
+    fn from(admission_response: &AdmissionResponse) -> Self {
+        if admission_response.allowed {
+            Verdict::Allowed
+        } else {
+            Verdict::Denied
+        }
+    }
+}
+
+/// A record describing a decision taken by the `AdmissionResponseHandler`.
+///
+/// Comparing the original verdict of the policy with the final one allows to
+/// find out which requests would be rejected once a policy running in
+/// monitor mode is switched to protect mode.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct DecisionRecord {
+    /// When the decision has been taken
+    #[serde(with = "time::serde::rfc3339")]
+    pub timestamp: OffsetDateTime,
+    /// The ID of the policy
+    pub policy_id: String,
+    /// The mode used to process the policy response
+    pub mode: PolicyMode,
+    /// The UID of the admission request
+    pub request_uid: String,
+    /// The kind of the object being admitted, when known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<GroupVersionKind>,
+    /// The namespace of the object being admitted, when known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    /// The user that issued the request, when known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    /// The verdict returned by the policy
+    pub original_verdict: Verdict,
+    /// The verdict sent back to the Kubernetes API server
+    pub final_verdict: Verdict,
+    /// Whether the mutation proposed by the policy has been dropped
+    pub mutation_suppressed: bool,
+    /// The message explaining why the request has been, or would have been, rejected
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rejection_message: Option<String>,
+}
+
+impl DecisionRecord {
+    pub fn new(
+        policy_id: &PolicyID,
+        mode: &PolicyMode,
+        request: Option<&AdmissionRequest>,
+        original_response: &AdmissionResponse,
+        final_response: &AdmissionResponse,
+    ) -> Self {
+        let final_verdict = Verdict::from(final_response);
+        let rejection_message = match (final_verdict, original_response.allowed) {
+            (Verdict::Denied, _) => final_response.status.as_ref(),
+            // the request has been accepted only because of the policy mode
+            (Verdict::Allowed, false) => original_response.status.as_ref(),
+            (Verdict::Allowed, true) => None,
+        }
+        .and_then(|status| status.message.clone());
+
+        DecisionRecord {
+            timestamp: OffsetDateTime::now_utc(),
+            policy_id: policy_id.to_string(),
+            mode: mode.clone(),
+            request_uid: request
+                .map(|req| req.uid.clone())
+                .unwrap_or_else(|| original_response.uid.clone()),
+            kind: request.map(|req| req.kind.clone()),
+            namespace: request.and_then(|req| req.namespace.clone()),
+            user: request.and_then(|req| req.user_info.username.clone()),
+            original_verdict: Verdict::from(original_response),
+            final_verdict,
+            mutation_suppressed: original_response.patch.is_some()
+                && final_response.patch.is_none(),
+            rejection_message,
+        }
+    }
+}
+
+/// A destination for the decisions taken by the `AdmissionResponseHandler`
+pub trait DecisionSink: Send + Sync {
+    /// Store the given decision record
+    fn record(&self, record: &DecisionRecord) -> DecisionLogResult<()>;
+}
+
+/// A sink writing each decision as a JSON document on its own line
+pub struct JsonLinesDecisionSink<W: Write + Send> {
+    writer: Mutex<W>,
+}
+
+impl<W: Write + Send> JsonLinesDecisionSink<W> {
+    pub fn new(writer: W) -> Self {
+        JsonLinesDecisionSink {
+            writer: Mutex::new(writer),
+        }
+    }
+
+    /// Consume the sink, returning the underlying writer
+    pub fn into_inner(self) -> W {
+        self.writer
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<W: Write + Send> DecisionSink for JsonLinesDecisionSink<W> {
+    fn record(&self, record: &DecisionRecord) -> DecisionLogResult<()> {
+        let mut line = serde_json::to_vec(record).map_err(DecisionLogError::Serialize)?;
+        line.push(b'\n');
+
+        let mut writer = self.writer.lock().unwrap_or_else(PoisonError::into_inner);
+        writer.write_all(&line).map_err(DecisionLogError::Write)?;
+        writer.flush().map_err(DecisionLogError::Write)
+    }
+}
+
+/// A sink keeping all the decisions in memory. Useful for testing and for
+/// short lived processes, like CLI tools
+#[derive(Default)]
+pub struct InMemoryDecisionSink {
+    records: Mutex<Vec<DecisionRecord>>,
+}
+
+impl InMemoryDecisionSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a copy of all the decisions recorded so far
+    pub fn records(&self) -> Vec<DecisionRecord> {
+        self.records
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Remove all the decisions recorded so far, returning them
+    pub fn drain(&self) -> Vec<DecisionRecord> {
+        std::mem::take(&mut *self.records.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+}
+
+impl DecisionSink for InMemoryDecisionSink {
+    fn record(&self, record: &DecisionRecord) -> DecisionLogResult<()> {
+        self.records
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(record.clone());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::admission_response::AdmissionResponseStatus;
+
+    fn rejected_response() -> AdmissionResponse {
+        AdmissionResponse {
+            uid: "uid".to_string(),
+            allowed: false,
+            status: Some(AdmissionResponseStatus {
+                message: Some("rejected".to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn record_monitor_mode_decision() {
+        let original = rejected_response();
+        let final_response = AdmissionResponse {
+            uid: "uid".to_string(),
+            allowed: true,
+            ..Default::default()
+        };
+
+        let record = DecisionRecord::new(
+            &PolicyID::Policy("policy".to_string()),
+            &PolicyMode::Monitor,
+            None,
+            &original,
+            &final_response,
+        );
+
+        assert_eq!(record.policy_id, "policy");
+        assert_eq!(record.request_uid, "uid");
+        assert_eq!(record.original_verdict, Verdict::Denied);
+        assert_eq!(record.final_verdict, Verdict::Allowed);
+        assert!(!record.mutation_suppressed);
+        assert_eq!(record.rejection_message, Some("rejected".to_string()));
+    }
+
+    #[test]
+    fn write_json_lines() {
+        let record = DecisionRecord::new(
+            &PolicyID::Policy("policy".to_string()),
+            &PolicyMode::Protect,
+            None,
+            &rejected_response(),
+            &rejected_response(),
+        );
+
+        let sink = JsonLinesDecisionSink::new(Vec::new());
+        sink.record(&record).expect("cannot record decision");
+        sink.record(&record).expect("cannot record decision");
+
+        let output = String::from_utf8(sink.into_inner()).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 2);
+        for line in lines {
+            let decoded: DecisionRecord = serde_json::from_str(line).unwrap();
+            assert_eq!(decoded, record);
+        }
+    }
+
+    #[test]
+    fn drain_in_memory_sink() {
+        let record = DecisionRecord::new(
+            &PolicyID::Policy("policy".to_string()),
+            &PolicyMode::Protect,
+            None,
+            &rejected_response(),
+            &rejected_response(),
+        );
+
+        let sink = InMemoryDecisionSink::new();
+        sink.record(&record).expect("cannot record decision");
+
+        assert_eq!(sink.drain(), vec![record]);
+        assert!(sink.records().is_empty());
+    }
+}
@@ -22,3 +22,42 @@ pub enum EvaluationError {
     #[error("Attempted to rehydrated policy group '{0}'")]
     CannotRehydratePolicyGroup(String),
 }
+
+pub type DecisionLogResult<T> = std::result::Result<T, DecisionLogError>;
+
+#[derive(Debug, Error)]
+pub enum DecisionLogError {
+    #[error("cannot serialize decision record: {0}")]
+    Serialize(#[source] serde_json::Error),
+
+    #[error("cannot write decision record: {0}")]
+    Write(#[source] std::io::Error),
+}
+
+pub type TemplateResult<T> = std::result::Result<T, TemplateError>;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum TemplateError {
+    #[error("unclosed template tag starting at position {0}")]
+    UnclosedTag(usize),
+
+    #[error("empty template tag")]
+    EmptyTag,
+
+    #[error("undefined template variable: {0}")]
+    UndefinedVariable(String),
+
+    #[error("undefined localised message: {0}")]
+    UndefinedMessage(String),
+}
+
+pub type MutationGuardResult<T> = std::result::Result<T, MutationGuardError>;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum MutationGuardError {
+    #[error("not a valid JSON Pointer: {0}")]
+    InvalidPath(String),
+
+    #[error("cannot decode JSONPatch: {0}")]
+    InvalidPatch(String),
+}
@@ -0,0 +1,402 @@
+use base64::{Engine as _, engine::general_purpose};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::admission_response_handler::errors::{MutationGuardError, MutationGuardResult};
+
+/// The paths that cannot be mutated by default
+pub const DEFAULT_DENIED_PATHS: [&str; 4] = [
+    "/metadata/name",
+    "/metadata/namespace",
+    "/metadata/uid",
+    "/status",
+];
+
+/// What to do when a policy attempts to mutate a protected path
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum MutationGuardAction {
+    /// Reject the request
+    #[default]
+    Reject,
+    /// Remove the offending operations from the patch, and add a warning to the response.
+    /// The request is rejected when the remaining operations cannot be applied to the object
+    Strip,
+}
+
+/// Restricts the paths of the objects that a mutating policy can change.
+///
+/// Paths are expressed as JSON Pointers (RFC 6901), like `/metadata/labels`.
+/// A path covers also all its children. An operation touches a path when it
+/// changes the path itself, one of its children or one of its parents (e.g.
+/// replacing `/metadata` touches `/metadata/name`).
+///
+/// A patch operation is considered a violation when:
+/// - it touches one of the denied paths
+/// - the list of allowed paths is not empty, and the operation is not changing
+///   one of the allowed paths or one of their children
+///
+/// The guard can be part of the settings of each policy, for example:
+///
+/// ```yaml
+/// mutationGuard:
+///   deniedPaths: ["/metadata/name", "/spec/nodeName"]
+///   action: strip
+/// ```
+///
+/// Omitted fields take their default values: the `DEFAULT_DENIED_PATHS`, no
+/// allowed paths and the `Reject` action.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", try_from = "MutationGuardSettings")]
+pub struct MutationGuard {
+    denied_paths: Vec<String>,
+    allowed_paths: Vec<String>,
+    action: MutationGuardAction,
+}
+
+/// The unvalidated representation of a `MutationGuard`
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MutationGuardSettings {
+    #[serde(default = "default_denied_paths")]
+    denied_paths: Vec<String>,
+    #[serde(default)]
+    allowed_paths: Vec<String>,
+    #[serde(default)]
+    action: MutationGuardAction,
+}
+
+impl TryFrom<MutationGuardSettings> for MutationGuard {
+    type Error = MutationGuardError;
+
+    fn try_from(settings: MutationGuardSettings) -> MutationGuardResult<Self> {
+        MutationGuard::new(
+            settings.denied_paths,
+            settings.allowed_paths,
+            settings.action,
+        )
+    }
+}
+
+fn default_denied_paths() -> Vec<String> {
+    DEFAULT_DENIED_PATHS.iter().map(|p| p.to_string()).collect()
+}
+
+impl Default for MutationGuard {
+    /// Forbid changes to the name, the namespace, the UID and the status of the object
+    fn default() -> Self {
+        MutationGuard {
+            denied_paths: default_denied_paths(),
+            allowed_paths: Vec::new(),
+            action: MutationGuardAction::default(),
+        }
+    }
+}
+
+/// The outcome of the inspection of a patch
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct GuardedPatch {
+    /// The patch, without the offending operations. `None` when all the
+    /// operations have been removed
+    pub patch: Option<String>,
+    /// The paths touched by the offending operations
+    pub violations: Vec<String>,
+}
+
+impl GuardedPatch {
+    /// Ensure the patch, without the offending operations, can still be applied
+    /// to the object. Removing an operation can leave the following ones pointing
+    /// to paths that no longer exist.
+    pub fn check_applies_to(&self, object: Option<&Value>) -> Result<(), String> {
+        let Some(patch) = self.patch.as_ref() else {
+            return Ok(());
+        };
+        let object = object.ok_or("the object being mutated is not known")?;
+
+        let decoded = general_purpose::STANDARD
+            .decode(patch)
+            .map_err(|e| e.to_string())?;
+        let operations: json_patch::Patch =
+            serde_json::from_slice(&decoded).map_err(|e| e.to_string())?;
+
+        json_patch::patch(&mut object.clone(), &operations).map_err(|e| e.to_string())
+    }
+}
+
+impl MutationGuard {
+    pub fn new(
+        denied_paths: Vec<String>,
+        allowed_paths: Vec<String>,
+        action: MutationGuardAction,
+    ) -> MutationGuardResult<Self> {
+        if let Some(invalid) = denied_paths
+            .iter()
+            .chain(allowed_paths.iter())
+            .find(|path| !path.starts_with('/'))
+        {
+            return Err(MutationGuardError::InvalidPath(invalid.to_owned()));
+        }
+
+        Ok(MutationGuard {
+            denied_paths,
+            allowed_paths,
+            action,
+        })
+    }
+
+    pub fn action(&self) -> MutationGuardAction {
+        self.action
+    }
+
+    /// Inspect the given base64 encoded JSON Patch
+    pub(crate) fn inspect(&self, patch: &str) -> MutationGuardResult<GuardedPatch> {
+        let decoded = general_purpose::STANDARD
+            .decode(patch)
+            .map_err(|e| MutationGuardError::InvalidPatch(e.to_string()))?;
+        let operations: Vec<Value> = serde_json::from_slice(&decoded)
+            .map_err(|e| MutationGuardError::InvalidPatch(e.to_string()))?;
+
+        let mut violations = Vec::new();
+        let mut allowed_operations = Vec::new();
+
+        for operation in operations {
+            match self.violation(&operation) {
+                Some(path) => violations.push(path),
+                None => allowed_operations.push(operation),
+            }
+        }
+
+        if violations.is_empty() {
+            return Ok(GuardedPatch {
+                patch: Some(patch.to_owned()),
+                violations,
+            });
+        }
+
+        let patch = if allowed_operations.is_empty() {
+            None
+        } else {
+            let encoded = serde_json::to_string(&allowed_operations)
+                .map(|s| general_purpose::STANDARD.encode(s))
+                .map_err(|e| MutationGuardError::InvalidPatch(e.to_string()))?;
+            Some(encoded)
+        };
+
+        Ok(GuardedPatch { patch, violations })
+    }
+
+    /// Returns the offending path changed by the operation, if any
+    fn violation(&self, operation: &Value) -> Option<String> {
+        let op = operation
+            .get("op")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        if op == "test" {
+            // `test` operations do not change the object
+            return None;
+        }
+
+        let mut changed_paths = vec![
+            operation
+                .get("path")
+                .and_then(Value::as_str)
+                .unwrap_or_default(),
+        ];
+        if op == "move" {
+            // a `move` removes the source path
+            changed_paths.push(
+                operation
+                    .get("from")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default(),
+            );
+        }
+
+        changed_paths
+            .into_iter()
+            .find(|path| !self.is_path_allowed(path))
+            .map(|path| path.to_owned())
+    }
+
+    fn is_path_allowed(&self, path: &str) -> bool {
+        let denied = self
+            .denied_paths
+            .iter()
+            .any(|denied| is_same_or_child(path, denied) || is_same_or_child(denied, path));
+        if denied {
+            return false;
+        }
+
+        self.allowed_paths.is_empty()
+            || self
+                .allowed_paths
+                .iter()
+                .any(|allowed| is_same_or_child(path, allowed))
+    }
+}
+
+/// Returns true when `path` is equal to `parent`, or is one of its children
+fn is_same_or_child(path: &str, parent: &str) -> bool {
+    path == parent
+        || path
+            .strip_prefix(parent)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rstest::rstest;
+    use serde_json::json;
+
+    fn encode(patch: Value) -> String {
+        general_purpose::STANDARD.encode(serde_json::to_string(&patch).unwrap())
+    }
+
+    #[rstest]
+    #[case::allowed_by_default(
+        MutationGuard::default(),
+        json!([{"op": "add", "path": "/metadata/labels/owner", "value": "me"}]),
+        vec![],
+    )]
+    #[case::denied_path(
+        MutationGuard::default(),
+        json!([
+            {"op": "add", "path": "/metadata/labels/owner", "value": "me"},
+            {"op": "replace", "path": "/metadata/name", "value": "hijacked"},
+        ]),
+        vec!["/metadata/name"],
+    )]
+    #[case::child_of_denied_path(
+        MutationGuard::default(),
+        json!([{"op": "replace", "path": "/status/phase", "value": "Running"}]),
+        vec!["/status/phase"],
+    )]
+    #[case::parent_of_denied_path(
+        MutationGuard::default(),
+        json!([{"op": "replace", "path": "/metadata", "value": {}}]),
+        vec!["/metadata"],
+    )]
+    #[case::similar_prefix_is_not_a_child(
+        MutationGuard::default(),
+        json!([{"op": "add", "path": "/statusCode", "value": 1}]),
+        vec![],
+    )]
+    #[case::move_from_denied_path(
+        MutationGuard::default(),
+        json!([{"op": "move", "from": "/metadata/uid", "path": "/metadata/labels/uid"}]),
+        vec!["/metadata/uid"],
+    )]
+    #[case::test_operations_are_ignored(
+        MutationGuard::default(),
+        json!([{"op": "test", "path": "/metadata/name", "value": "nginx"}]),
+        vec![],
+    )]
+    #[case::outside_of_allow_list(
+        MutationGuard::new(
+            vec![],
+            vec!["/metadata/labels".to_string()],
+            MutationGuardAction::Reject,
+        ).unwrap(),
+        json!([
+            {"op": "add", "path": "/metadata/labels/owner", "value": "me"},
+            {"op": "add", "path": "/metadata/annotations/owner", "value": "me"},
+        ]),
+        vec!["/metadata/annotations/owner"],
+    )]
+    fn inspect_patch(
+        #[case] guard: MutationGuard,
+        #[case] patch: Value,
+        #[case] expected_violations: Vec<&str>,
+    ) {
+        let guarded_patch = guard.inspect(&encode(patch)).expect("cannot inspect patch");
+        assert_eq!(guarded_patch.violations, expected_violations);
+    }
+
+    #[test]
+    fn strip_offending_operations() {
+        let guard = MutationGuard::default();
+        let guarded_patch = guard
+            .inspect(&encode(json!([
+                {"op": "add", "path": "/metadata/labels/owner", "value": "me"},
+                {"op": "remove", "path": "/status"},
+            ])))
+            .expect("cannot inspect patch");
+
+        assert_eq!(
+            guarded_patch.patch,
+            Some(encode(json!([
+                {"op": "add", "path": "/metadata/labels/owner", "value": "me"},
+            ])))
+        );
+    }
+
+    #[rstest]
+    #[case::remaining_operations_apply(
+        json!([
+            {"op": "replace", "path": "/metadata/name", "value": "hijacked"},
+            {"op": "add", "path": "/metadata/labels/owner", "value": "me"},
+        ]),
+        true,
+    )]
+    #[case::remaining_operations_depend_on_stripped_ones(
+        json!([
+            {"op": "add", "path": "/status", "value": {"conditions": []}},
+            {"op": "move", "from": "/metadata/labels", "path": "/spec/labels"},
+            {"op": "copy", "from": "/status/conditions", "path": "/spec/conditions"},
+        ]),
+        false,
+    )]
+    fn stripped_patch_applies_to_object(#[case] patch: Value, #[case] expected_applies: bool) {
+        let object = json!({
+            "metadata": {"name": "nginx", "labels": {"app": "nginx"}},
+            "spec": {},
+        });
+        let guarded_patch = MutationGuard::default()
+            .inspect(&encode(patch))
+            .expect("cannot inspect patch");
+
+        assert_eq!(
+            guarded_patch.check_applies_to(Some(&object)).is_ok(),
+            expected_applies
+        );
+        assert!(guarded_patch.check_applies_to(None).is_err());
+    }
+
+    #[test]
+    fn deserialize_guard() {
+        let guard: MutationGuard = serde_json::from_value(json!({
+            "allowedPaths": ["/metadata/labels"],
+            "action": "strip",
+        }))
+        .expect("cannot deserialize mutation guard");
+        assert_eq!(
+            guard,
+            MutationGuard::new(
+                default_denied_paths(),
+                vec!["/metadata/labels".to_string()],
+                MutationGuardAction::Strip,
+            )
+            .unwrap()
+        );
+
+        let invalid = serde_json::from_value::<MutationGuard>(json!({
+            "deniedPaths": ["metadata.name"],
+        }));
+        assert!(invalid.is_err());
+    }
+
+    #[test]
+    fn invalid_path() {
+        let result = MutationGuard::new(
+            vec!["metadata.name".to_string()],
+            vec![],
+            MutationGuardAction::Reject,
+        );
+        assert_eq!(
+            result,
+            Err(MutationGuardError::InvalidPath("metadata.name".to_string()))
+        );
+    }
+}
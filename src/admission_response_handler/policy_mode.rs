@@ -1,7 +1,9 @@
+use std::fmt;
+
 use kubewarden_policy_sdk::crd::policies::common::PolicyMode as PolicyModeSdk;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum PolicyMode {
     Monitor,
@@ -9,12 +11,18 @@ pub enum PolicyMode {
     Protect,
 }
 
+impl fmt::Display for PolicyMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PolicyMode::Monitor => write!(f, "monitor"),
+            PolicyMode::Protect => write!(f, "protect"),
+        }
+    }
+}
+
 impl From<PolicyMode> for String {
     fn from(policy_mode: PolicyMode) -> String {
-        match policy_mode {
-            PolicyMode::Monitor => String::from("monitor"),
-            PolicyMode::Protect => String::from("protect"),
-        }
+        policy_mode.to_string()
     }
 }
 
@@ -0,0 +1,238 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    admission_request::AdmissionRequest, admission_response_handler::policy_mode::PolicyMode,
+};
+
+/// A rule that overrides the mode of a policy for the requests matching it.
+///
+/// All the criteria that are specified must match for the rule to be applied.
+/// A criterion matches when the request matches at least one of its values,
+/// with the exception of `labels`, which requires the object to have all the
+/// labels listed. A criterion that is left empty matches all the requests.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct PolicyModeOverride {
+    /// The name of the rule, used when logging and auditing the chosen mode
+    pub name: String,
+    /// The mode to be used when the rule matches
+    pub mode: PolicyMode,
+    /// The namespaces of the requests matching the rule
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub namespaces: BTreeSet<String>,
+    /// The names of the users, or service accounts, matching the rule.
+    /// Service accounts are identified by `system:serviceaccount:<namespace>:<name>`
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub users: BTreeSet<String>,
+    /// The groups of the users matching the rule
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub groups: BTreeSet<String>,
+    /// The labels the object of the request must have to match the rule.
+    /// For DELETE operations the labels of the old object are used
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+}
+
+impl PolicyModeOverride {
+    /// Returns true when the given request matches all the criteria of the rule
+    pub fn matches(&self, request: &AdmissionRequest) -> bool {
+        self.matches_namespace(request)
+            && self.matches_user(request)
+            && self.matches_groups(request)
+            && self.matches_labels(request)
+    }
+
+    fn matches_namespace(&self, request: &AdmissionRequest) -> bool {
+        self.namespaces.is_empty()
+            || request
+                .namespace
+                .as_ref()
+                .is_some_and(|namespace| self.namespaces.contains(namespace))
+    }
+
+    fn matches_user(&self, request: &AdmissionRequest) -> bool {
+        self.users.is_empty()
+            || request
+                .user_info
+                .username
+                .as_ref()
+                .is_some_and(|username| self.users.contains(username))
+    }
+
+    fn matches_groups(&self, request: &AdmissionRequest) -> bool {
+        self.groups.is_empty()
+            || request
+                .user_info
+                .groups
+                .iter()
+                .flatten()
+                .any(|group| self.groups.contains(group))
+    }
+
+    fn matches_labels(&self, request: &AdmissionRequest) -> bool {
+        if self.labels.is_empty() {
+            return true;
+        }
+
+        let object = request.object.as_ref().or(request.old_object.as_ref());
+        let labels = match object.and_then(|obj| obj.0.pointer("/metadata/labels")) {
+            Some(serde_json::Value::Object(labels)) => labels,
+            _ => return false,
+        };
+
+        self.labels
+            .iter()
+            .all(|(key, value)| labels.get(key).and_then(|v| v.as_str()) == Some(value.as_str()))
+    }
+}
+
+/// Returns the first override rule matching the given request
+pub fn find_matching_override<'a>(
+    overrides: &'a [PolicyModeOverride],
+    request: &AdmissionRequest,
+) -> Option<&'a PolicyModeOverride> {
+    overrides.iter().find(|rule| rule.matches(request))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_fixtures::admission_request;
+    use k8s_openapi::{
+        api::authentication::v1::UserInfo, apimachinery::pkg::runtime::RawExtension,
+    };
+    use rstest::rstest;
+    use serde_json::json;
+
+    /// A request made by a service account of the `team-a` namespace, while
+    /// migrating the object
+    fn build_admission_request() -> AdmissionRequest {
+        AdmissionRequest {
+            namespace: Some("team-a".to_string()),
+            user_info: UserInfo {
+                username: Some("system:serviceaccount:team-a:deployer".to_string()),
+                groups: Some(vec![
+                    "system:serviceaccounts".to_string(),
+                    "system:authenticated".to_string(),
+                ]),
+                ..Default::default()
+            },
+            object: Some(RawExtension(json!({
+                "apiVersion": "v1",
+                "kind": "Pod",
+                "metadata": {
+                    "name": "nginx",
+                    "labels": {
+                        "app": "nginx",
+                        "migration": "in-progress"
+                    }
+                }
+            }))),
+            ..admission_request()
+        }
+    }
+
+    #[rstest]
+    #[case::no_criteria(PolicyModeOverride::default(), true)]
+    #[case::namespace_matches(
+        PolicyModeOverride {
+            namespaces: BTreeSet::from(["team-b".to_string(), "team-a".to_string()]),
+            ..Default::default()
+        },
+        true
+    )]
+    #[case::namespace_does_not_match(
+        PolicyModeOverride {
+            namespaces: BTreeSet::from(["team-b".to_string()]),
+            ..Default::default()
+        },
+        false
+    )]
+    #[case::user_matches(
+        PolicyModeOverride {
+            users: BTreeSet::from(["system:serviceaccount:team-a:deployer".to_string()]),
+            ..Default::default()
+        },
+        true
+    )]
+    #[case::user_does_not_match(
+        PolicyModeOverride {
+            users: BTreeSet::from(["admin".to_string()]),
+            ..Default::default()
+        },
+        false
+    )]
+    #[case::group_matches(
+        PolicyModeOverride {
+            groups: BTreeSet::from(["system:serviceaccounts".to_string()]),
+            ..Default::default()
+        },
+        true
+    )]
+    #[case::group_does_not_match(
+        PolicyModeOverride {
+            groups: BTreeSet::from(["system:masters".to_string()]),
+            ..Default::default()
+        },
+        false
+    )]
+    #[case::labels_match(
+        PolicyModeOverride {
+            labels: BTreeMap::from([("migration".to_string(), "in-progress".to_string())]),
+            ..Default::default()
+        },
+        true
+    )]
+    #[case::labels_do_not_match(
+        PolicyModeOverride {
+            labels: BTreeMap::from([
+                ("migration".to_string(), "in-progress".to_string()),
+                ("app".to_string(), "httpd".to_string()),
+            ]),
+            ..Default::default()
+        },
+        false
+    )]
+    #[case::all_criteria_must_match(
+        PolicyModeOverride {
+            namespaces: BTreeSet::from(["team-a".to_string()]),
+            users: BTreeSet::from(["admin".to_string()]),
+            ..Default::default()
+        },
+        false
+    )]
+    fn match_policy_mode_override(#[case] rule: PolicyModeOverride, #[case] expected: bool) {
+        let request = build_admission_request();
+        assert_eq!(rule.matches(&request), expected);
+    }
+
+    #[test]
+    fn first_matching_override_wins() {
+        let request = build_admission_request();
+        let overrides = vec![
+            PolicyModeOverride {
+                name: "team-b".to_string(),
+                mode: PolicyMode::Monitor,
+                namespaces: BTreeSet::from(["team-b".to_string()]),
+                ..Default::default()
+            },
+            PolicyModeOverride {
+                name: "team-a".to_string(),
+                mode: PolicyMode::Monitor,
+                namespaces: BTreeSet::from(["team-a".to_string()]),
+                ..Default::default()
+            },
+            PolicyModeOverride {
+                name: "catch-all".to_string(),
+                mode: PolicyMode::Protect,
+                ..Default::default()
+            },
+        ];
+
+        let rule = find_matching_override(&overrides, &request).expect("a rule should match");
+        assert_eq!(rule.name, "team-a");
+    }
+}
@@ -0,0 +1,393 @@
+use std::{collections::HashMap, fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    admission_request::AdmissionRequest,
+    admission_response::StatusCause,
+    admission_response_handler::{
+        errors::{TemplateError, TemplateResult},
+        policy_id::PolicyID,
+    },
+};
+
+const TAG_OPEN: &str = "{{";
+const TAG_CLOSE: &str = "}}";
+
+/// The prefix of the variables referring to a localised message
+const MESSAGES_PREFIX: &str = "messages.";
+
+/// The variables that can be referenced by a rejection message template
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Variable {
+    PolicyId,
+    RequestNamespace,
+    RequestName,
+    OriginalMessage,
+    Causes,
+}
+
+impl FromStr for Variable {
+    type Err = TemplateError;
+
+    fn from_str(s: &str) -> TemplateResult<Self> {
+        match s {
+            "policy_id" => Ok(Variable::PolicyId),
+            "request.namespace" => Ok(Variable::RequestNamespace),
+            "request.name" => Ok(Variable::RequestName),
+            "original_message" => Ok(Variable::OriginalMessage),
+            "causes" => Ok(Variable::Causes),
+            _ => Err(TemplateError::UndefinedVariable(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Text(String),
+    Variable(Variable),
+}
+
+/// Localised messages, grouped by locale
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageCatalog {
+    /// The locale used when a message is not available in the requested locale
+    pub default_locale: String,
+    /// The messages of each locale, like `en` or `it`, indexed by their key
+    pub messages: HashMap<String, HashMap<String, String>>,
+}
+
+impl MessageCatalog {
+    /// Lookup the message with the given key in the requested locale, falling back
+    /// to the default locale when the locale or the message are not available
+    pub fn message(&self, locale: Option<&str>, key: &str) -> Option<&str> {
+        let lookup = |locale: &str| {
+            self.messages
+                .get(locale)
+                .and_then(|messages| messages.get(key))
+                .map(String::as_str)
+        };
+
+        locale
+            .and_then(lookup)
+            .or_else(|| lookup(&self.default_locale))
+    }
+}
+
+/// The values used to render a `RejectionMessageTemplate`
+pub struct TemplateContext<'a> {
+    pub policy_id: &'a PolicyID,
+    /// The request being evaluated, `None` when it is not known
+    pub request: Option<&'a AdmissionRequest>,
+    /// The rejection message produced by the policy
+    pub original_message: &'a str,
+    /// The causes of the rejection produced by the policy
+    pub causes: &'a [StatusCause],
+}
+
+/// A custom rejection message that can reference details about the policy
+/// and the rejected request.
+///
+/// Variables are written as `{{ name }}`. The following variables are available:
+/// - `policy_id`: the ID of the policy
+/// - `request.namespace`: the namespace of the request
+/// - `request.name`: the name of the object being admitted
+/// - `original_message`: the rejection message produced by the policy
+/// - `causes`: the messages of the rejection causes produced by the policy, separated by `; `
+///
+/// Localised messages are referenced with `{{ messages.<key> }}`, and are looked up inside
+/// of the message catalog given when building the template. Localised messages are
+/// templates too: they can reference the variables above, but not other localised
+/// messages.
+///
+/// The template is a plain text substitution: there are no loops, conditionals or functions.
+/// Templates are validated when built, referencing an undefined variable or an unknown
+/// localisation key is an error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RejectionMessageTemplate {
+    segments: Vec<Segment>,
+}
+
+impl RejectionMessageTemplate {
+    /// Parse the given template
+    pub fn new(template: &str) -> TemplateResult<Self> {
+        Self::with_messages(template, &HashMap::new())
+    }
+
+    /// Parse the given template, resolving the localisation keys against the given
+    /// messages
+    pub fn with_messages(
+        template: &str,
+        messages: &HashMap<String, String>,
+    ) -> TemplateResult<Self> {
+        let segments = parse(template, &|key| messages.get(key).map(String::as_str))?;
+        Ok(RejectionMessageTemplate { segments })
+    }
+
+    /// Parse the given template, resolving the localisation keys against the messages
+    /// of the requested locale. The messages of the default locale of the catalog are
+    /// used when the locale is not given, or does not define a message
+    pub fn with_localised_messages(
+        template: &str,
+        catalog: &MessageCatalog,
+        locale: Option<&str>,
+    ) -> TemplateResult<Self> {
+        let segments = parse(template, &|key| catalog.message(locale, key))?;
+        Ok(RejectionMessageTemplate { segments })
+    }
+
+    /// Render the template using the given context. Variables without a value, like
+    /// the namespace of a cluster wide resource, are rendered as empty strings
+    pub fn render(&self, context: &TemplateContext) -> String {
+        let mut rendered = String::new();
+
+        for segment in &self.segments {
+            match segment {
+                Segment::Text(text) => rendered.push_str(text),
+                Segment::Variable(Variable::PolicyId) => {
+                    rendered.push_str(&context.policy_id.to_string())
+                }
+                Segment::Variable(Variable::RequestNamespace) => rendered.push_str(
+                    context
+                        .request
+                        .and_then(|req| req.namespace.as_deref())
+                        .unwrap_or_default(),
+                ),
+                Segment::Variable(Variable::RequestName) => rendered.push_str(
+                    context
+                        .request
+                        .and_then(|req| req.name.as_deref())
+                        .unwrap_or_default(),
+                ),
+                Segment::Variable(Variable::OriginalMessage) => {
+                    rendered.push_str(context.original_message)
+                }
+                Segment::Variable(Variable::Causes) => rendered.push_str(
+                    &context
+                        .causes
+                        .iter()
+                        .filter_map(|cause| cause.message.as_deref())
+                        .collect::<Vec<&str>>()
+                        .join("; "),
+                ),
+            }
+        }
+
+        rendered
+    }
+}
+
+/// Split the template into segments. The localised messages returned by `lookup`
+/// are parsed too, without resolving the localised messages they reference
+fn parse<'m>(
+    template: &str,
+    lookup: &dyn Fn(&str) -> Option<&'m str>,
+) -> TemplateResult<Vec<Segment>> {
+    let mut segments = Vec::new();
+    let mut text = String::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find(TAG_OPEN) {
+        text.push_str(&rest[..start]);
+
+        let tag_start = start + TAG_OPEN.len();
+        let tag_len = rest[tag_start..]
+            .find(TAG_CLOSE)
+            .ok_or_else(|| TemplateError::UnclosedTag(template.len() - rest.len() + start))?;
+        let name = rest[tag_start..tag_start + tag_len].trim();
+        rest = &rest[tag_start + tag_len + TAG_CLOSE.len()..];
+
+        if name.is_empty() {
+            return Err(TemplateError::EmptyTag);
+        }
+
+        let variables = if let Some(key) = name.strip_prefix(MESSAGES_PREFIX) {
+            let message =
+                lookup(key).ok_or_else(|| TemplateError::UndefinedMessage(key.to_string()))?;
+            parse(message, &|_| None)?
+        } else {
+            vec![Segment::Variable(name.parse::<Variable>()?)]
+        };
+
+        for segment in variables {
+            match segment {
+                Segment::Text(message_text) => text.push_str(&message_text),
+                Segment::Variable(variable) => {
+                    if !text.is_empty() {
+                        segments.push(Segment::Text(std::mem::take(&mut text)));
+                    }
+                    segments.push(Segment::Variable(variable));
+                }
+            }
+        }
+    }
+
+    text.push_str(rest);
+    if !text.is_empty() {
+        segments.push(Segment::Text(text));
+    }
+
+    Ok(segments)
+}
+
+impl FromStr for RejectionMessageTemplate {
+    type Err = TemplateError;
+
+    fn from_str(s: &str) -> TemplateResult<Self> {
+        RejectionMessageTemplate::new(s)
+    }
+}
+
+impl fmt::Display for Variable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Variable::PolicyId => "policy_id",
+            Variable::RequestNamespace => "request.namespace",
+            Variable::RequestName => "request.name",
+            Variable::OriginalMessage => "original_message",
+            Variable::Causes => "causes",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl fmt::Display for RejectionMessageTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for segment in &self.segments {
+            match segment {
+                Segment::Text(text) => write!(f, "{text}")?,
+                Segment::Variable(variable) => write!(f, "{TAG_OPEN} {variable} {TAG_CLOSE}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_fixtures::admission_request;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case::static_message("static message", "static message")]
+    #[case::all_variables(
+        "{{ policy_id }} rejected {{request.namespace}}/{{ request.name }}: {{ original_message }} ({{ causes }})",
+        "policy-id rejected default/nginx: original (cause 1; cause 2)"
+    )]
+    #[case::localised_message("{{ messages.rejected }} {{ request.name }}", "Rifiutato nginx")]
+    #[case::localised_message_with_variables(
+        "{{ messages.rejected_by }}: {{ original_message }}",
+        "Rifiutato da policy-id: original"
+    )]
+    #[case::single_braces_are_text("{ policy_id }", "{ policy_id }")]
+    fn render_template(#[case] template: &str, #[case] expected: &str) {
+        let messages = HashMap::from([
+            ("rejected".to_string(), "Rifiutato".to_string()),
+            (
+                "rejected_by".to_string(),
+                "Rifiutato da {{ policy_id }}".to_string(),
+            ),
+        ]);
+        let template = RejectionMessageTemplate::with_messages(template, &messages)
+            .expect("template should be valid");
+
+        let policy_id = PolicyID::Policy("policy-id".to_string());
+        let request = admission_request();
+        let causes = vec![
+            StatusCause {
+                message: Some("cause 1".to_string()),
+                ..Default::default()
+            },
+            StatusCause {
+                message: Some("cause 2".to_string()),
+                ..Default::default()
+            },
+        ];
+        let context = TemplateContext {
+            policy_id: &policy_id,
+            request: Some(&request),
+            original_message: "original",
+            causes: &causes,
+        };
+
+        assert_eq!(template.render(&context), expected);
+    }
+
+    #[rstest]
+    #[case::undefined_variable(
+        "{{ request.uid }}",
+        TemplateError::UndefinedVariable("request.uid".to_string())
+    )]
+    #[case::undefined_message(
+        "{{ messages.unknown }}",
+        TemplateError::UndefinedMessage("unknown".to_string())
+    )]
+    #[case::nested_message(
+        "{{ messages.nested }}",
+        TemplateError::UndefinedMessage("rejected".to_string())
+    )]
+    #[case::unclosed_tag("rejected by {{ policy_id", TemplateError::UnclosedTag(12))]
+    #[case::empty_tag("rejected by {{ }}", TemplateError::EmptyTag)]
+    fn invalid_template(#[case] template: &str, #[case] expected: TemplateError) {
+        let messages =
+            HashMap::from([("nested".to_string(), "{{ messages.rejected }}".to_string())]);
+        let error = RejectionMessageTemplate::with_messages(template, &messages)
+            .expect_err("template should be invalid");
+        assert_eq!(error, expected);
+    }
+
+    #[rstest]
+    #[case::requested_locale(Some("it"), "Rifiutato da policy-id")]
+    #[case::message_missing_in_requested_locale(Some("de"), "Rejected by policy-id")]
+    #[case::unknown_locale(Some("fr"), "Rejected by policy-id")]
+    #[case::default_locale(None, "Rejected by policy-id")]
+    fn render_localised_template(#[case] locale: Option<&str>, #[case] expected: &str) {
+        let catalog = MessageCatalog {
+            default_locale: "en".to_string(),
+            messages: HashMap::from([
+                (
+                    "en".to_string(),
+                    HashMap::from([("rejected".to_string(), "Rejected by".to_string())]),
+                ),
+                (
+                    "it".to_string(),
+                    HashMap::from([("rejected".to_string(), "Rifiutato da".to_string())]),
+                ),
+                ("de".to_string(), HashMap::new()),
+            ]),
+        };
+        let template = RejectionMessageTemplate::with_localised_messages(
+            "{{ messages.rejected }} {{ policy_id }}",
+            &catalog,
+            locale,
+        )
+        .expect("template should be valid");
+
+        let policy_id = PolicyID::Policy("policy-id".to_string());
+        let context = TemplateContext {
+            policy_id: &policy_id,
+            request: None,
+            original_message: "",
+            causes: &[],
+        };
+
+        assert_eq!(template.render(&context), expected);
+    }
+
+    #[test]
+    fn render_without_request() {
+        let template = RejectionMessageTemplate::new("[{{ request.namespace }}]").unwrap();
+        let policy_id = PolicyID::Policy("policy-id".to_string());
+        let context = TemplateContext {
+            policy_id: &policy_id,
+            request: None,
+            original_message: "",
+            causes: &[],
+        };
+
+        assert_eq!(template.render(&context), "[]");
+    }
+}
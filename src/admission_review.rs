@@ -0,0 +1,269 @@
+use serde::{Deserialize, Serialize};
+use std::{convert::TryFrom, fmt, str::FromStr};
+
+use crate::admission_request::AdmissionRequest;
+use crate::admission_response::AdmissionResponse;
+use crate::errors::AdmissionReviewError;
+use crate::policy_evaluator::ValidateRequest;
+
+pub const ADMISSION_REVIEW_KIND: &str = "AdmissionReview";
+
+type Result<T> = std::result::Result<T, AdmissionReviewError>;
+
+/// The versions of the `admission.k8s.io` API group that are supported
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub enum AdmissionReviewVersion {
+    #[default]
+    #[serde(rename = "admission.k8s.io/v1")]
+    V1,
+    #[serde(rename = "admission.k8s.io/v1beta1")]
+    V1Beta1,
+}
+
+impl AdmissionReviewVersion {
+    pub fn api_version(&self) -> &'static str {
+        match self {
+            AdmissionReviewVersion::V1 => "admission.k8s.io/v1",
+            AdmissionReviewVersion::V1Beta1 => "admission.k8s.io/v1beta1",
+        }
+    }
+}
+
+impl fmt::Display for AdmissionReviewVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.api_version())
+    }
+}
+
+impl FromStr for AdmissionReviewVersion {
+    type Err = AdmissionReviewError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "admission.k8s.io/v1" => Ok(AdmissionReviewVersion::V1),
+            "admission.k8s.io/v1beta1" => Ok(AdmissionReviewVersion::V1Beta1),
+            _ => Err(AdmissionReviewError::UnsupportedApiVersion(s.to_string())),
+        }
+    }
+}
+
+/// This models the AdmissionReview object sent by the Kubernetes API server
+/// to a webhook.
+///
+/// The `apiVersion` and `kind` fields are validated when the object is
+/// built via `from_slice` or `from_value`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AdmissionReviewRequest {
+    pub api_version: String,
+    pub kind: String,
+    pub request: Option<AdmissionRequest>,
+}
+
+impl AdmissionReviewRequest {
+    /// Create a new AdmissionReview wrapping the given request
+    pub fn new(version: AdmissionReviewVersion, request: AdmissionRequest) -> Self {
+        AdmissionReviewRequest {
+            api_version: version.api_version().to_string(),
+            kind: ADMISSION_REVIEW_KIND.to_string(),
+            request: Some(request),
+        }
+    }
+
+    /// Parse and validate a JSON encoded AdmissionReview
+    pub fn from_slice(data: &[u8]) -> Result<Self> {
+        let review: AdmissionReviewRequest =
+            serde_json::from_slice(data).map_err(AdmissionReviewError::Deserialize)?;
+        review.validate()?;
+        Ok(review)
+    }
+
+    /// Parse and validate an AdmissionReview
+    pub fn from_value(value: serde_json::Value) -> Result<Self> {
+        let review: AdmissionReviewRequest =
+            serde_json::from_value(value).map_err(AdmissionReviewError::Deserialize)?;
+        review.validate()?;
+        Ok(review)
+    }
+
+    /// Ensure `apiVersion` and `kind` are supported and the review carries a request
+    pub fn validate(&self) -> Result<()> {
+        self.version()?;
+        if self.kind != ADMISSION_REVIEW_KIND {
+            return Err(AdmissionReviewError::InvalidKind(self.kind.clone()));
+        }
+        if self.request.is_none() {
+            return Err(AdmissionReviewError::MissingRequest);
+        }
+        Ok(())
+    }
+
+    pub fn version(&self) -> Result<AdmissionReviewVersion> {
+        self.api_version.parse()
+    }
+
+    /// The uid of the wrapped request, this is the same value returned by
+    /// `ValidateRequest::uid`
+    pub fn uid(&self) -> Option<&str> {
+        self.request.as_ref().map(|request| request.uid.as_str())
+    }
+
+    /// Wrap the given response into an AdmissionReview using the same
+    /// `apiVersion` of the request.
+    ///
+    /// The uid of the response is set to the one of the request when empty.
+    /// A response that has a different uid is rejected, because the
+    /// Kubernetes API server would refuse it.
+    pub fn respond(&self, mut response: AdmissionResponse) -> Result<AdmissionReviewResponse> {
+        let version = self.version()?;
+        let uid = self.uid().ok_or(AdmissionReviewError::MissingRequest)?;
+
+        if response.uid.is_empty() {
+            response.uid = uid.to_string();
+        } else if response.uid != uid {
+            return Err(AdmissionReviewError::UidMismatch {
+                request: uid.to_string(),
+                response: response.uid,
+            });
+        }
+
+        Ok(AdmissionReviewResponse::new(version, response))
+    }
+}
+
+impl TryFrom<AdmissionReviewRequest> for ValidateRequest {
+    type Error = AdmissionReviewError;
+
+    fn try_from(review: AdmissionReviewRequest) -> Result<Self> {
+        review.validate()?;
+        review
+            .request
+            .map(|request| ValidateRequest::AdmissionRequest(Box::new(request)))
+            .ok_or(AdmissionReviewError::MissingRequest)
+    }
+}
+
+/// This models the AdmissionReview object returned by a webhook to the
+/// Kubernetes API server
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct AdmissionReviewResponse {
+    pub api_version: String,
+    pub kind: String,
+    pub response: AdmissionResponse,
+}
+
+impl AdmissionReviewResponse {
+    pub fn new(version: AdmissionReviewVersion, response: AdmissionResponse) -> Self {
+        AdmissionReviewResponse {
+            api_version: version.api_version().to_string(),
+            kind: ADMISSION_REVIEW_KIND.to_string(),
+            response,
+        }
+    }
+
+    /// Parse and validate a JSON encoded AdmissionReview returned by a webhook
+    pub fn from_slice(data: &[u8]) -> Result<Self> {
+        let review: AdmissionReviewResponse =
+            serde_json::from_slice(data).map_err(AdmissionReviewError::Deserialize)?;
+        review.api_version.parse::<AdmissionReviewVersion>()?;
+        if review.kind != ADMISSION_REVIEW_KIND {
+            return Err(AdmissionReviewError::InvalidKind(review.kind));
+        }
+        Ok(review)
+    }
+}
+
+impl From<AdmissionReviewResponse> for AdmissionResponse {
+    fn from(review: AdmissionReviewResponse) -> Self {
+        review.response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use serde_json::json;
+
+    fn admission_review(api_version: &str, kind: &str) -> serde_json::Value {
+        json!({
+            "apiVersion": api_version,
+            "kind": kind,
+            "request": {
+                "uid": "hello",
+                "kind": {"group": "", "version": "v1", "kind": "Pod"},
+                "resource": {"group": "", "version": "v1", "resource": "pods"},
+                "namespace": "default",
+                "operation": "CREATE",
+                "userInfo": {"username": "admin"},
+            }
+        })
+    }
+
+    #[rstest]
+    #[case::v1("admission.k8s.io/v1", AdmissionReviewVersion::V1)]
+    #[case::v1beta1("admission.k8s.io/v1beta1", AdmissionReviewVersion::V1Beta1)]
+    fn parse_admission_review(
+        #[case] api_version: &str,
+        #[case] expected_version: AdmissionReviewVersion,
+    ) {
+        let review = AdmissionReviewRequest::from_slice(
+            &serde_json::to_vec(&admission_review(api_version, "AdmissionReview")).unwrap(),
+        )
+        .expect("cannot parse admission review");
+        assert_eq!(review.version().unwrap(), expected_version);
+
+        let validate_request = ValidateRequest::try_from(review.clone()).unwrap();
+        assert_eq!(validate_request.uid(), review.uid().unwrap());
+
+        let review_response = review
+            .respond(AdmissionResponse {
+                allowed: true,
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(review_response.api_version, api_version);
+        assert_eq!(review_response.kind, ADMISSION_REVIEW_KIND);
+        assert_eq!(review_response.response.uid, "hello");
+    }
+
+    #[rstest]
+    #[case::unsupported_api_version(
+        admission_review("admission.k8s.io/v2", "AdmissionReview"),
+        "unsupported AdmissionReview apiVersion"
+    )]
+    #[case::wrong_kind(
+        admission_review("admission.k8s.io/v1", "Pod"),
+        "invalid AdmissionReview kind"
+    )]
+    #[case::missing_request(
+        json!({"apiVersion": "admission.k8s.io/v1", "kind": "AdmissionReview"}),
+        "AdmissionReview does not contain a request"
+    )]
+    fn reject_invalid_admission_review(
+        #[case] review: serde_json::Value,
+        #[case] expected_error: &str,
+    ) {
+        let error = AdmissionReviewRequest::from_value(review).unwrap_err();
+        assert!(
+            error.to_string().starts_with(expected_error),
+            "unexpected error: {error}"
+        );
+    }
+
+    #[test]
+    fn respond_with_mismatching_uid() {
+        let review = AdmissionReviewRequest::from_value(admission_review(
+            "admission.k8s.io/v1",
+            "AdmissionReview",
+        ))
+        .unwrap();
+        let response = AdmissionResponse::reject("other".to_string(), "nope".to_string(), 400);
+
+        assert!(matches!(
+            review.respond(response),
+            Err(AdmissionReviewError::UidMismatch { .. })
+        ));
+    }
+}
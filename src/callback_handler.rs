@@ -1,26 +1,50 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use anyhow::anyhow;
-use kubewarden_policy_sdk::host_capabilities::net::LookupResponse;
 use tokio::sync::{mpsc, oneshot};
 use tracing::{debug, warn};
 
+use crate::callback_recording::CallbackReplayer;
 use crate::callback_requests::{CallbackRequest, CallbackRequestType, CallbackResponse};
 
 mod builder;
+mod cache;
 mod crypto;
+mod dns;
+mod fixtures;
 mod kubernetes;
 mod oci;
 mod sigstore_verification;
 
 pub use builder::CallbackHandlerBuilder;
+pub use cache::{CacheConfig, CachedCapability, CallbackCaches};
+pub use crypto::{
+    CertificateRevocationList, CertificateVerificationRequestV2, CertificateVerificationResponseV2,
+    DigestAlgorithm, DigestRequest, DigestResponse, ExtendedKeyUsage, SignatureAlgorithm,
+    SignatureVerificationKey, SignatureVerificationRequest, SignatureVerificationResponse,
+};
+pub use dns::{DnsConfig, ReverseLookupResponse, SrvLookupResponse, SrvRecord, TxtLookupResponse};
+pub use fixtures::{
+    CallbackFixtures, CanIFixture, KubernetesFixtures, OciFixture, SigstoreFixture,
+};
+pub(crate) use kubernetes::FieldPath;
+pub use kubernetes::{
+    DiscoveryConfig, ReflectorConfig, ReflectorInfo, ReflectorMode, ReflectorsInspector,
+};
+pub use oci::{OciReferrer, OciReferrersRequest, OciReferrersResponse};
+pub use sigstore_verification::{AttestationVerificationRequest, AttestationVerificationResponse};
 
 use sigstore_verification::{
-    get_sigstore_certificate_verification_cached, get_sigstore_github_actions_verification_cached,
+    get_sigstore_attestation_verification_cached, get_sigstore_certificate_verification_cached,
+    get_sigstore_github_actions_verification_cached,
     get_sigstore_keyless_prefix_verification_cached, get_sigstore_keyless_verification_cached,
     get_sigstore_pub_key_verification_cached,
 };
 
+/// How often the Kubernetes client is maintained: the reflectors that have been
+/// idle for too long are stopped and the discovery data is refreshed when needed
+const KUBERNETES_MAINTENANCE_INTERVAL: Duration = Duration::from_secs(10);
+
 /// Struct that computes request coming from a Wasm guest.
 /// This should be used only to handle the requests that need some async
 /// code in order to be fulfilled.
@@ -28,6 +52,10 @@ pub struct CallbackHandler {
     oci_client: Arc<oci::Client>,
     sigstore_client: sigstore_verification::Client,
     kubernetes_client: Option<kubernetes::Client>,
+    dns_resolver: dns::Resolver,
+    caches: CallbackCaches,
+    fixtures: Option<Arc<CallbackFixtures>>,
+    replayer: Option<CallbackReplayer>,
     rx: mpsc::Receiver<CallbackRequest>,
     tx: mpsc::Sender<CallbackRequest>,
     shutdown_channel: oneshot::Receiver<()>,
@@ -65,6 +93,25 @@ impl CallbackHandler {
         self.tx.clone()
     }
 
+    /// Returns an object that provides details about the reflectors used to
+    /// answer the Kubernetes queries of the policies. Returns `None` when no
+    /// `kube::Client` has been provided.
+    ///
+    /// Can be invoked as many times as wanted.
+    pub fn reflectors_inspector(&self) -> Option<ReflectorsInspector> {
+        self.kubernetes_client
+            .as_ref()
+            .map(|client| client.reflectors_inspector())
+    }
+
+    /// Returns the caches of the results of the host capabilities, which can
+    /// be used to invalidate them.
+    ///
+    /// Can be invoked as many times as wanted.
+    pub fn caches(&self) -> CallbackCaches {
+        self.caches.clone()
+    }
+
     /// Enter an endless loop that:
     ///    1. Waits for requests to be evaluated
     ///    2. Evaluate the request
@@ -73,6 +120,7 @@ impl CallbackHandler {
     /// The loop is interrupted only when a message is sent over the
     /// `shutdown_channel`.
     pub async fn loop_eval(&mut self) {
+        let mut maintenance_interval = tokio::time::interval(KUBERNETES_MAINTENANCE_INTERVAL);
         loop {
             tokio::select! {
                 // place the shutdown check before the message evaluation,
@@ -85,6 +133,12 @@ impl CallbackHandler {
                         self.handle_request(req).await;
                    }
                 }
+                _ = maintenance_interval.tick() => {
+                    if let Some(kubernetes_client) = &self.kubernetes_client {
+                        kubernetes_client.evict_idle_reflectors().await;
+                        kubernetes_client.refresh_discovery();
+                    }
+                }
             }
         }
     }
@@ -93,22 +147,76 @@ impl CallbackHandler {
         let oci_client = self.oci_client.clone();
         let mut sigstore_client = self.sigstore_client.clone();
         let mut kubernetes_client = self.kubernetes_client.clone();
+        let dns_resolver = self.dns_resolver.clone();
+        let caches = self.caches.clone();
+
+        if let Some(replayer) = &self.replayer {
+            let response = replayer
+                .respond(&req.request)
+                .map(|payload| CallbackResponse { payload });
+            if let Err(e) = req.response_channel.send(response) {
+                warn!("callback handler: cannot send response back: {:?}", e);
+            }
+            return;
+        }
+
+        // Crypto operations don't require network access, hence they're
+        // always computed, even when fixtures are provided
+        let fixtures = self.fixtures.as_ref().filter(|_| {
+            !matches!(
+                req.request,
+                CallbackRequestType::CryptoIsCertificateTrusted { .. }
+                    | CallbackRequestType::CryptoIsCertificateTrustedV2 { .. }
+                    | CallbackRequestType::CryptoVerifySignature { .. }
+                    | CallbackRequestType::CryptoDigest { .. }
+            )
+        });
+        if let Some(fixtures) = fixtures {
+            debug!(request = ?req.request, "answering request from fixtures");
+            let response = fixtures
+                .respond(&req.request)
+                .map(|payload| CallbackResponse { payload });
+            if let Err(e) = req.response_channel.send(response) {
+                warn!("callback handler: cannot send response back: {:?}", e);
+            }
+            return;
+        }
 
         tokio::spawn(async move {
             match req.request {
                 CallbackRequestType::OciManifestDigest { image } => {
                     handle_callback!(req, image, "Image digest computed", {
-                        oci::get_oci_digest_cached(&oci_client, &image)
+                        oci::get_oci_digest_cached(&oci_client, &caches.oci_manifest_digest, &image)
                     });
                 }
                 CallbackRequestType::OciManifest { image } => {
                     handle_callback!(req, image, "Image manifest computed", {
-                        oci::get_oci_manifest_cached(&oci_client, &image)
+                        oci::get_oci_manifest_cached(&oci_client, &caches.oci_manifest, &image)
                     });
                 }
                 CallbackRequestType::OciManifestAndConfig { image } => {
                     handle_callback!(req, image, "Image manifest computed", {
-                        oci::get_oci_manifest_and_config_cached(&oci_client, &image)
+                        oci::get_oci_manifest_and_config_cached(
+                            &oci_client,
+                            &caches.oci_manifest_and_config,
+                            &image,
+                        )
+                    });
+                }
+                CallbackRequestType::OciReferrers { request } => {
+                    let image = request.image.clone();
+                    handle_callback!(req, image, "Image referrers found", {
+                        oci::get_oci_referrers_cached(&oci_client, &caches.oci_referrers, &request)
+                    });
+                }
+                CallbackRequestType::SigstoreAttestationVerify { request } => {
+                    let image = request.image.clone();
+                    handle_callback!(req, image, "Sigstore attestation verification done", {
+                        get_sigstore_attestation_verification_cached(
+                            &oci_client,
+                            &caches.sigstore_attestation_verification,
+                            request,
+                        )
                     });
                 }
                 CallbackRequestType::SigstorePubKeyVerify {
@@ -119,6 +227,7 @@ impl CallbackHandler {
                     handle_callback!(req, image, "Sigstore pub key verification done", {
                         get_sigstore_pub_key_verification_cached(
                             &mut sigstore_client,
+                            &caches.sigstore_verification,
                             image.clone(),
                             pub_keys,
                             annotations,
@@ -133,6 +242,7 @@ impl CallbackHandler {
                     handle_callback!(req, image, "Sigstore keyless verification done", {
                         get_sigstore_keyless_verification_cached(
                             &mut sigstore_client,
+                            &caches.sigstore_verification,
                             image.clone(),
                             keyless,
                             annotations,
@@ -147,6 +257,7 @@ impl CallbackHandler {
                     handle_callback!(req, image, "Sigstore keyless prefix verification done", {
                         get_sigstore_keyless_prefix_verification_cached(
                             &mut sigstore_client,
+                            &caches.sigstore_verification,
                             image.clone(),
                             keyless_prefix,
                             annotations,
@@ -162,6 +273,7 @@ impl CallbackHandler {
                     handle_callback!(req, image, "Sigstore GitHub Action verification done", {
                         get_sigstore_github_actions_verification_cached(
                             &mut sigstore_client,
+                            &caches.sigstore_verification,
                             image.clone(),
                             owner,
                             repo,
@@ -179,6 +291,7 @@ impl CallbackHandler {
                     handle_callback!(req, image, "Sigstore GitHub Action verification done", {
                         get_sigstore_certificate_verification_cached(
                             &mut sigstore_client,
+                            &caches.sigstore_verification,
                             &image,
                             &certificate,
                             certificate_chain.as_deref(),
@@ -188,20 +301,24 @@ impl CallbackHandler {
                     })
                 }
                 CallbackRequestType::DNSLookupHost { host } => {
-                    let response = dns_lookup::lookup_host(&host)
-                        .map(|ip_addresses| {
-                            let res = LookupResponse {
-                                ips: ip_addresses.map(|ip| ip.to_string()).collect(),
-                            };
-                            CallbackResponse {
-                                payload: serde_json::to_vec(&res).unwrap(),
-                            }
-                        })
-                        .map_err(anyhow::Error::new);
-
-                    if let Err(e) = req.response_channel.send(response) {
-                        warn!("callback handler: cannot send response back: {:?}", e);
-                    }
+                    handle_callback!(req, host, "DNS lookup done", {
+                        dns_resolver.lookup_host(&host)
+                    })
+                }
+                CallbackRequestType::DNSReverseLookup { address } => {
+                    handle_callback!(req, address, "DNS reverse lookup done", {
+                        dns_resolver.reverse_lookup(&address)
+                    })
+                }
+                CallbackRequestType::DNSLookupSrv { name } => {
+                    handle_callback!(req, name, "DNS SRV lookup done", {
+                        dns_resolver.lookup_srv(&name)
+                    })
+                }
+                CallbackRequestType::DNSLookupTxt { name } => {
+                    handle_callback!(req, name, "DNS TXT lookup done", {
+                        dns_resolver.lookup_txt(&name)
+                    })
                 }
                 CallbackRequestType::KubernetesListResourceNamespace {
                     api_version,
@@ -209,6 +326,7 @@ impl CallbackHandler {
                     namespace,
                     label_selector,
                     field_selector,
+                    fields,
                 } => {
                     handle_callback!(
                         req,
@@ -222,6 +340,7 @@ impl CallbackHandler {
                                 &namespace,
                                 label_selector,
                                 field_selector,
+                                fields,
                             )
                         }
                     )
@@ -231,6 +350,7 @@ impl CallbackHandler {
                     kind,
                     label_selector,
                     field_selector,
+                    fields,
                 } => {
                     handle_callback!(
                         req,
@@ -243,6 +363,7 @@ impl CallbackHandler {
                                 &kind,
                                 label_selector,
                                 field_selector,
+                                fields,
                             )
                         }
                     )
@@ -253,6 +374,7 @@ impl CallbackHandler {
                     name,
                     namespace,
                     disable_cache,
+                    fields,
                 } => {
                     if disable_cache {
                         handle_callback!(
@@ -266,6 +388,7 @@ impl CallbackHandler {
                                     &kind,
                                     &name,
                                     namespace.as_deref(),
+                                    fields.as_deref(),
                                 )
                             }
                         )
@@ -277,10 +400,12 @@ impl CallbackHandler {
                             {
                                 kubernetes::get_resource_cached(
                                     kubernetes_client.as_mut(),
+                                    &caches.kubernetes_get_resource,
                                     &api_version,
                                     &kind,
                                     &name,
                                     namespace.as_deref(),
+                                    fields.as_deref(),
                                 )
                             }
                         )
@@ -339,7 +464,13 @@ impl CallbackHandler {
                             req,
                             "can_i".to_owned(),
                             "Check if user or service account has permission to perform operation",
-                            { kubernetes::can_i_cached(kubernetes_client.as_mut(), request) }
+                            {
+                                kubernetes::can_i_cached(
+                                    kubernetes_client.as_mut(),
+                                    &caches.kubernetes_can_i,
+                                    request,
+                                )
+                            }
                         )
                     }
                 }
@@ -349,6 +480,24 @@ impl CallbackHandler {
                         async { crypto::verify_certificate(request) }
                     })
                 }
+                CallbackRequestType::CryptoIsCertificateTrustedV2 { request } => {
+                    let cert_description = request.cert.to_string();
+                    handle_callback!(req, cert_description, "Certificate verification done", {
+                        async { crypto::verify_certificate_v2(request) }
+                    })
+                }
+                CallbackRequestType::CryptoVerifySignature { request } => {
+                    let algorithm = request.algorithm;
+                    handle_callback!(req, algorithm, "Signature verification done", {
+                        async { crypto::verify_signature(request) }
+                    })
+                }
+                CallbackRequestType::CryptoDigest { request } => {
+                    let algorithm = request.algorithm;
+                    handle_callback!(req, algorithm, "Digest computed", {
+                        async { crypto::digest(request) }
+                    })
+                }
             }
         });
     }
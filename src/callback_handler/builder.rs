@@ -1,12 +1,21 @@
 use anyhow::Result;
 use policy_fetcher::sigstore::trust::sigstore::SigstoreTrustRoot;
 use policy_fetcher::sources::Sources;
-use std::sync::Arc;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
 use tokio::sync::{mpsc, oneshot};
 
-use super::CallbackHandler;
-use super::{oci, sigstore_verification};
+use super::kubernetes::FieldProjections;
+use super::{
+    CacheConfig, CachedCapability, CallbackCaches, CallbackFixtures, CallbackHandler,
+    DiscoveryConfig, DnsConfig, ReflectorConfig,
+};
+use super::{dns, oci, sigstore_verification};
+use crate::callback_recording::CallbackReplayer;
 use crate::callback_requests::CallbackRequest;
+use crate::policy_metadata::ContextAwareResource;
 
 const DEFAULT_CHANNEL_BUFF_SIZE: usize = 100;
 
@@ -17,6 +26,13 @@ pub struct CallbackHandlerBuilder {
     shutdown_channel: oneshot::Receiver<()>,
     trust_root: Option<Arc<SigstoreTrustRoot>>,
     kube_client: Option<kube::Client>,
+    reflector_config: ReflectorConfig,
+    discovery_config: DiscoveryConfig,
+    context_aware_resources: BTreeSet<ContextAwareResource>,
+    cache_configs: BTreeMap<CachedCapability, CacheConfig>,
+    dns_config: DnsConfig,
+    fixtures: Option<CallbackFixtures>,
+    replayer: Option<CallbackReplayer>,
 }
 
 impl CallbackHandlerBuilder {
@@ -27,6 +43,13 @@ impl CallbackHandlerBuilder {
             channel_buffer_size: DEFAULT_CHANNEL_BUFF_SIZE,
             trust_root: None,
             kube_client: None,
+            reflector_config: ReflectorConfig::default(),
+            discovery_config: DiscoveryConfig::default(),
+            context_aware_resources: BTreeSet::new(),
+            cache_configs: BTreeMap::new(),
+            dns_config: DnsConfig::default(),
+            fixtures: None,
+            replayer: None,
         }
     }
 
@@ -56,6 +79,69 @@ impl CallbackHandlerBuilder {
         self
     }
 
+    /// Set the limits enforced on the reflectors used to answer the list
+    /// queries made by context aware policies. By default no limit is
+    /// enforced. Optional
+    pub fn reflector_config(mut self, config: ReflectorConfig) -> Self {
+        self.reflector_config = config;
+        self
+    }
+
+    /// Set how the resources served by the Kubernetes API server are
+    /// discovered. By default they are refreshed every 10 minutes and the
+    /// CustomResourceDefinitions are watched. Optional
+    pub fn discovery_config(mut self, config: DiscoveryConfig) -> Self {
+        self.discovery_config = config;
+        self
+    }
+
+    /// Declare the Kubernetes resources accessed by the policies. The objects
+    /// cached by the host retain only the union of the `fields` declared for
+    /// each kind of resource, unless at least one of the policies needs the
+    /// whole objects. The cached objects are extended with the fields needed by
+    /// the policies that have not been declared here. Each policy gets back only
+    /// the fields it declared inside of its own `EvaluationContext`. Optional
+    pub fn context_aware_resources(
+        mut self,
+        resources: impl IntoIterator<Item = ContextAwareResource>,
+    ) -> Self {
+        self.context_aware_resources.extend(resources);
+        self
+    }
+
+    /// Set how the results of the given host capability are cached. By default
+    /// OCI and Sigstore results are cached for 60 seconds, Kubernetes ones for
+    /// 5 seconds, and errors are never cached. Optional
+    pub fn cache_config(mut self, capability: CachedCapability, config: CacheConfig) -> Self {
+        self.cache_configs.insert(capability, config);
+        self
+    }
+
+    /// Set how DNS lookups are performed. By default the nameservers of the
+    /// host are used, lookups time out after 5 seconds and records are cached
+    /// according to their TTL. Optional
+    pub fn dns_config(mut self, config: DnsConfig) -> Self {
+        self.dns_config = config;
+        self
+    }
+
+    /// Answer all the requests using the given fixtures, without performing any
+    /// network operation. This is meant to be used when testing policies.
+    /// Optional
+    pub fn fixtures(mut self, fixtures: CallbackFixtures) -> Self {
+        self.fixtures = Some(fixtures);
+        self
+    }
+
+    /// Answer all the requests with the responses of a recorded session, without
+    /// performing any network operation. Requests that were not recorded are
+    /// answered with an error and can be inspected via the given `CallbackReplayer`.
+    /// This takes precedence over `fixtures`. Optional
+    pub fn replay_session(mut self, replayer: CallbackReplayer) -> Self {
+        self.replayer = Some(replayer);
+        self
+    }
+
     /// Create a CallbackHandler object
     pub async fn build(self) -> Result<CallbackHandler> {
         let (tx, rx) = mpsc::channel::<CallbackRequest>(self.channel_buffer_size);
@@ -65,12 +151,23 @@ impl CallbackHandlerBuilder {
                 .await?
                 .to_owned();
 
-        let kubernetes_client = self.kube_client.map(super::kubernetes::Client::new);
+        let dns_resolver = dns::Resolver::new(&self.dns_config);
+
+        let reflector_config = self.reflector_config;
+        let discovery_config = self.discovery_config;
+        let projections = FieldProjections::new(&self.context_aware_resources)?;
+        let kubernetes_client = self.kube_client.map(|client| {
+            super::kubernetes::Client::new(client, reflector_config, projections, discovery_config)
+        });
 
         Ok(CallbackHandler {
             oci_client,
             sigstore_client,
             kubernetes_client,
+            dns_resolver,
+            caches: CallbackCaches::new(&self.cache_configs),
+            fixtures: self.fixtures.map(Arc::new),
+            replayer: self.replayer,
             tx,
             rx,
             shutdown_channel: self.shutdown_channel,
@@ -0,0 +1,501 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Result, anyhow};
+use k8s_openapi::api::authorization::v1::SubjectAccessReviewStatus;
+use kube::core::DynamicObject;
+use kubewarden_policy_sdk::host_capabilities::{
+    oci::ManifestDigestResponse, verification::VerificationResponse,
+};
+use policy_fetcher::oci_client::manifest::OciManifest;
+use tokio::sync::OnceCell;
+
+use super::oci::{ManifestAndConfigResponse, OciReferrersResponse};
+use super::sigstore_verification::AttestationVerificationResponse;
+
+/// The host capabilities whose results are cached
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CachedCapability {
+    OciManifestDigest,
+    OciManifest,
+    OciManifestAndConfig,
+    OciReferrers,
+    /// All the Sigstore verifications: public key, keyless, keyless prefix,
+    /// GitHub Actions, certificate and attestation
+    SigstoreVerification,
+    KubernetesGetResource,
+    KubernetesCanI,
+}
+
+impl CachedCapability {
+    pub const ALL: [CachedCapability; 7] = [
+        CachedCapability::OciManifestDigest,
+        CachedCapability::OciManifest,
+        CachedCapability::OciManifestAndConfig,
+        CachedCapability::OciReferrers,
+        CachedCapability::SigstoreVerification,
+        CachedCapability::KubernetesGetResource,
+        CachedCapability::KubernetesCanI,
+    ];
+
+    /// The configuration used when none is provided: OCI and Sigstore results
+    /// are cached for 60 seconds, Kubernetes ones for 5 seconds
+    pub fn default_config(self) -> CacheConfig {
+        match self {
+            CachedCapability::OciManifestDigest
+            | CachedCapability::OciManifest
+            | CachedCapability::OciManifestAndConfig
+            | CachedCapability::OciReferrers
+            | CachedCapability::SigstoreVerification => {
+                CacheConfig::with_ttl(Duration::from_secs(60))
+            }
+            CachedCapability::KubernetesGetResource | CachedCapability::KubernetesCanI => {
+                CacheConfig::with_ttl(Duration::from_secs(5))
+            }
+        }
+    }
+}
+
+/// How the results of a host capability are cached
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CacheConfig {
+    /// How long successful results are kept. `None` disables the cache
+    pub ttl: Option<Duration>,
+    /// Maximum number of results kept, the ones closest to their expiration
+    /// are evicted first. `None` means no limit
+    pub max_size: Option<usize>,
+    /// How long errors are kept. `None` means errors are never cached
+    pub negative_ttl: Option<Duration>,
+}
+
+impl CacheConfig {
+    /// Nothing is cached
+    pub fn disabled() -> Self {
+        CacheConfig::default()
+    }
+
+    /// Successful results are cached for the given time, without any size limit
+    pub fn with_ttl(ttl: Duration) -> Self {
+        CacheConfig {
+            ttl: Some(ttl),
+            ..Default::default()
+        }
+    }
+}
+
+struct Entry<V> {
+    value: std::result::Result<V, String>,
+    expires_at: Instant,
+}
+
+/// The result of a computation shared by all the requests waiting for it
+type InFlight<V> = Arc<OnceCell<std::result::Result<V, String>>>;
+
+/// A time bound cache of the results of a host capability.
+///
+/// Concurrent requests for the same missing key wait for a single computation,
+/// whose result is shared by all of them.
+pub(crate) struct Cache<V> {
+    config: CacheConfig,
+    entries: Arc<Mutex<HashMap<String, Entry<V>>>>,
+    in_flight: Arc<Mutex<HashMap<String, InFlight<V>>>>,
+}
+
+impl<V> Clone for Cache<V> {
+    fn clone(&self) -> Self {
+        Cache {
+            config: self.config.clone(),
+            entries: self.entries.clone(),
+            in_flight: self.in_flight.clone(),
+        }
+    }
+}
+
+impl<V: Clone> Cache<V> {
+    pub fn new(config: CacheConfig) -> Self {
+        Cache {
+            config,
+            entries: Arc::new(Mutex::new(HashMap::new())),
+            in_flight: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Return the cached result associated with `key`, or compute and cache it
+    pub async fn get_or_insert_with<F>(&self, key: String, compute: F) -> Result<cached::Return<V>>
+    where
+        F: Future<Output = Result<V>>,
+    {
+        if let Some(cached) = self.get(&key) {
+            return cached
+                .map(|value| cached::Return {
+                    was_cached: true,
+                    value,
+                })
+                .map_err(|e| anyhow!(e));
+        }
+
+        let in_flight = self.in_flight_computation(&key);
+        let mut computed = None;
+        let computed_result = &mut computed;
+        let shared = in_flight
+            .get_or_init(|| async {
+                let result = compute.await;
+                self.insert(key.clone(), &result);
+                let shared = match &result {
+                    Ok(value) => Ok(value.clone()),
+                    Err(e) => Err(format!("{e:#}")),
+                };
+                *computed_result = Some(result);
+                shared
+            })
+            .await
+            .clone();
+        self.release_in_flight(&key, &in_flight);
+
+        match computed {
+            Some(result) => result.map(cached::Return::new),
+            // the result has been computed by a concurrent request
+            None => shared
+                .map(|value| cached::Return {
+                    was_cached: true,
+                    value,
+                })
+                .map_err(|e| anyhow!(e)),
+        }
+    }
+
+    /// The computation of the result associated with `key`, shared by the
+    /// concurrent requests
+    fn in_flight_computation(&self, key: &str) -> InFlight<V> {
+        self.in_flight
+            .lock()
+            .expect("cache lock poisoned")
+            .entry(key.to_string())
+            .or_default()
+            .clone()
+    }
+
+    /// Forget the computation once done, the following requests either find
+    /// its result inside of the cache or compute it again
+    fn release_in_flight(&self, key: &str, in_flight: &InFlight<V>) {
+        let mut computations = self.in_flight.lock().expect("cache lock poisoned");
+        if computations
+            .get(key)
+            .is_some_and(|current| Arc::ptr_eq(current, in_flight))
+        {
+            computations.remove(key);
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<std::result::Result<V, String>> {
+        let mut entries = self.entries.lock().expect("cache lock poisoned");
+        match entries.get(key) {
+            Some(entry) if entry.expires_at > Instant::now() => Some(entry.value.clone()),
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn insert(&self, key: String, result: &Result<V>) {
+        let (value, ttl) = match result {
+            Ok(value) => (Ok(value.clone()), self.config.ttl),
+            Err(e) => (Err(format!("{e:#}")), self.config.negative_ttl),
+        };
+        let Some(ttl) = ttl.filter(|ttl| !ttl.is_zero()) else {
+            return;
+        };
+        if self.config.max_size == Some(0) {
+            return;
+        }
+
+        let now = Instant::now();
+        let mut entries = self.entries.lock().expect("cache lock poisoned");
+        if let Some(max_size) = self.config.max_size
+            && !entries.contains_key(&key)
+            && entries.len() >= max_size
+        {
+            entries.retain(|_, entry| entry.expires_at > now);
+            if entries.len() >= max_size
+                && let Some(oldest) = entries
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.expires_at)
+                    .map(|(key, _)| key.clone())
+            {
+                entries.remove(&oldest);
+            }
+        }
+
+        entries.insert(
+            key,
+            Entry {
+                value,
+                expires_at: now + ttl,
+            },
+        );
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().expect("cache lock poisoned").clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().expect("cache lock poisoned").len()
+    }
+}
+
+/// The caches of the results of the host capabilities.
+///
+/// The caches are owned by the `CallbackHandler` that created them, all the
+/// clones of this object share the same caches.
+#[derive(Clone)]
+pub struct CallbackCaches {
+    pub(crate) oci_manifest_digest: Cache<ManifestDigestResponse>,
+    pub(crate) oci_manifest: Cache<OciManifest>,
+    pub(crate) oci_manifest_and_config: Cache<ManifestAndConfigResponse>,
+    pub(crate) oci_referrers: Cache<OciReferrersResponse>,
+    pub(crate) sigstore_verification: Cache<VerificationResponse>,
+    pub(crate) sigstore_attestation_verification: Cache<AttestationVerificationResponse>,
+    pub(crate) kubernetes_get_resource: Cache<DynamicObject>,
+    pub(crate) kubernetes_can_i: Cache<SubjectAccessReviewStatus>,
+}
+
+impl CallbackCaches {
+    /// Create the caches, the capabilities without an explicit configuration
+    /// use their default one
+    pub(crate) fn new(configs: &BTreeMap<CachedCapability, CacheConfig>) -> Self {
+        let config = |capability: CachedCapability| {
+            configs
+                .get(&capability)
+                .cloned()
+                .unwrap_or_else(|| capability.default_config())
+        };
+
+        CallbackCaches {
+            oci_manifest_digest: Cache::new(config(CachedCapability::OciManifestDigest)),
+            oci_manifest: Cache::new(config(CachedCapability::OciManifest)),
+            oci_manifest_and_config: Cache::new(config(CachedCapability::OciManifestAndConfig)),
+            oci_referrers: Cache::new(config(CachedCapability::OciReferrers)),
+            sigstore_verification: Cache::new(config(CachedCapability::SigstoreVerification)),
+            sigstore_attestation_verification: Cache::new(config(
+                CachedCapability::SigstoreVerification,
+            )),
+            kubernetes_get_resource: Cache::new(config(CachedCapability::KubernetesGetResource)),
+            kubernetes_can_i: Cache::new(config(CachedCapability::KubernetesCanI)),
+        }
+    }
+
+    /// Drop all the results cached for the given capability
+    pub fn invalidate(&self, capability: CachedCapability) {
+        match capability {
+            CachedCapability::OciManifestDigest => self.oci_manifest_digest.clear(),
+            CachedCapability::OciManifest => self.oci_manifest.clear(),
+            CachedCapability::OciManifestAndConfig => self.oci_manifest_and_config.clear(),
+            CachedCapability::OciReferrers => self.oci_referrers.clear(),
+            CachedCapability::SigstoreVerification => {
+                self.sigstore_verification.clear();
+                self.sigstore_attestation_verification.clear();
+            }
+            CachedCapability::KubernetesGetResource => self.kubernetes_get_resource.clear(),
+            CachedCapability::KubernetesCanI => self.kubernetes_can_i.clear(),
+        }
+    }
+
+    /// Drop all the cached results
+    pub fn invalidate_all(&self) {
+        for capability in CachedCapability::ALL {
+            self.invalidate(capability);
+        }
+    }
+
+    /// Number of results cached for the given capability, including the
+    /// expired ones that have not been purged yet
+    pub fn cached_entries(&self, capability: CachedCapability) -> usize {
+        match capability {
+            CachedCapability::OciManifestDigest => self.oci_manifest_digest.len(),
+            CachedCapability::OciManifest => self.oci_manifest.len(),
+            CachedCapability::OciManifestAndConfig => self.oci_manifest_and_config.len(),
+            CachedCapability::OciReferrers => self.oci_referrers.len(),
+            CachedCapability::SigstoreVerification => {
+                self.sigstore_verification.len() + self.sigstore_attestation_verification.len()
+            }
+            CachedCapability::KubernetesGetResource => self.kubernetes_get_resource.len(),
+            CachedCapability::KubernetesCanI => self.kubernetes_can_i.len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Returns the cached value and whether it has been computed
+    async fn lookup(
+        cache: &Cache<usize>,
+        key: &str,
+        result: Result<usize>,
+    ) -> (Result<usize>, bool) {
+        let computed = AtomicUsize::new(0);
+        let response = cache
+            .get_or_insert_with(key.to_string(), async {
+                computed.fetch_add(1, Ordering::SeqCst);
+                result
+            })
+            .await
+            .map(|response| response.value);
+        (response, computed.load(Ordering::SeqCst) == 1)
+    }
+
+    #[tokio::test]
+    async fn cache_successful_results() {
+        let cache = Cache::new(CacheConfig::with_ttl(Duration::from_secs(60)));
+
+        assert!(lookup(&cache, "a", Ok(1)).await.1);
+        let (value, computed) = lookup(&cache, "a", Ok(2)).await;
+        assert_eq!(value.unwrap(), 1);
+        assert!(!computed);
+
+        // errors are not cached by default
+        assert!(lookup(&cache, "b", Err(anyhow!("boom"))).await.0.is_err());
+        assert_eq!(lookup(&cache, "b", Ok(3)).await.0.unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn disabled_cache() {
+        let cache = Cache::new(CacheConfig::disabled());
+
+        lookup(&cache, "a", Ok(1)).await;
+        let (value, computed) = lookup(&cache, "a", Ok(2)).await;
+        assert_eq!(value.unwrap(), 2);
+        assert!(computed);
+        assert_eq!(cache.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn negative_caching() {
+        let cache = Cache::new(CacheConfig {
+            negative_ttl: Some(Duration::from_secs(60)),
+            ..CacheConfig::with_ttl(Duration::from_secs(60))
+        });
+
+        lookup(&cache, "a", Err(anyhow!("boom"))).await;
+        let (value, computed) = lookup(&cache, "a", Ok(1)).await;
+        assert_eq!(value.unwrap_err().to_string(), "boom");
+        assert!(!computed);
+    }
+
+    #[tokio::test]
+    async fn expired_results_are_computed_again() {
+        let cache = Cache::new(CacheConfig::with_ttl(Duration::from_millis(10)));
+
+        lookup(&cache, "a", Ok(1)).await;
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let (value, computed) = lookup(&cache, "a", Ok(2)).await;
+        assert_eq!(value.unwrap(), 2);
+        assert!(computed);
+    }
+
+    #[tokio::test]
+    async fn max_size() {
+        let cache = Cache::new(CacheConfig {
+            max_size: Some(2),
+            ..CacheConfig::with_ttl(Duration::from_secs(60))
+        });
+
+        lookup(&cache, "a", Ok(1)).await;
+        lookup(&cache, "b", Ok(2)).await;
+        lookup(&cache, "c", Ok(3)).await;
+        assert_eq!(cache.len(), 2);
+
+        // the entry closest to its expiration has been evicted
+        assert!(lookup(&cache, "a", Ok(1)).await.1);
+        assert!(!lookup(&cache, "c", Ok(3)).await.1);
+    }
+
+    #[rstest]
+    #[case::success(CacheConfig::with_ttl(Duration::from_secs(60)), Ok(1))]
+    #[case::error(CacheConfig::with_ttl(Duration::from_secs(60)), Err("boom"))]
+    #[case::disabled_cache(CacheConfig::disabled(), Ok(1))]
+    #[tokio::test]
+    async fn concurrent_requests_are_computed_once(
+        #[case] config: CacheConfig,
+        #[case] result: std::result::Result<usize, &'static str>,
+    ) {
+        let cache = Cache::new(config);
+        let computed = Arc::new(AtomicUsize::new(0));
+
+        let requests = (0..10).map(|_| {
+            let cache = cache.clone();
+            let computed = computed.clone();
+            async move {
+                cache
+                    .get_or_insert_with("a".to_string(), async move {
+                        computed.fetch_add(1, Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                        result.map_err(|e| anyhow!(e))
+                    })
+                    .await
+                    .map(|response| response.value)
+                    .map_err(|e| e.to_string())
+            }
+        });
+        let responses = futures::future::join_all(requests).await;
+
+        assert_eq!(computed.load(Ordering::SeqCst), 1);
+        assert!(
+            responses
+                .iter()
+                .all(|response| *response == result.map_err(str::to_string))
+        );
+    }
+
+    #[tokio::test]
+    async fn invalidate() {
+        let caches = CallbackCaches::new(&BTreeMap::from([(
+            CachedCapability::KubernetesCanI,
+            CacheConfig::disabled(),
+        )]));
+        let status = SubjectAccessReviewStatus {
+            allowed: true,
+            ..Default::default()
+        };
+
+        caches
+            .kubernetes_can_i
+            .get_or_insert_with("can_i".to_string(), async { Ok(status.clone()) })
+            .await
+            .unwrap();
+        assert_eq!(caches.cached_entries(CachedCapability::KubernetesCanI), 0);
+
+        caches
+            .oci_manifest_digest
+            .get_or_insert_with("busybox".to_string(), async {
+                Ok(ManifestDigestResponse {
+                    digest: "sha256:1234".to_string(),
+                })
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            caches.cached_entries(CachedCapability::OciManifestDigest),
+            1
+        );
+
+        // all the clones share the same caches
+        caches
+            .clone()
+            .invalidate(CachedCapability::OciManifestDigest);
+        assert_eq!(
+            caches.cached_entries(CachedCapability::OciManifestDigest),
+            0
+        );
+    }
+}
@@ -1,16 +1,166 @@
+use std::net::IpAddr;
+
 use anyhow::{Result, anyhow};
 use kubewarden_policy_sdk::host_capabilities::{
     crypto::{Certificate, CertificateEncoding},
     crypto_v1::{CertificateVerificationRequest, CertificateVerificationResponse},
 };
-use pki_types::{CertificateDer, TrustAnchor, UnixTime, pem::PemObject};
+use pki_types::{
+    CertificateDer, CertificateRevocationListDer, SubjectPublicKeyInfoDer, TrustAnchor, UnixTime,
+    pem::PemObject,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha384, Sha512};
 use webpki::{EndEntityCert, Error};
+use x509_parser::prelude::{FromDer, GeneralName, SubjectPublicKeyInfo, X509Certificate};
 
 const CERTIFICATE_USED_AFTER_EXPIRATION: &str =
     "Certificate is being used after its expiration date";
 const CERTIFICATE_USED_BEFORE_VALIDITY: &str = "Certificate is being used before its validity date";
 const CERTIFICATE_NOT_TRUSTED_BY_CHAIN: &str =
     "Certificate is not trusted by the provided cert chain";
+const CERTIFICATE_REVOKED: &str = "Certificate has been revoked";
+const CRL_EXPIRED: &str = "The CRL used to check the revocation has expired";
+const CERTIFICATE_WITHOUT_EXTENDED_KEY_USAGES: &str =
+    "Certificate does not declare any extended key usage";
+const SIGNATURE_NOT_VALID: &str = "Signature is not valid for the given message and key";
+
+// Object identifiers of the public key algorithms
+const OID_RSA_ENCRYPTION: &str = "1.2.840.113549.1.1.1";
+const OID_RSASSA_PSS: &str = "1.2.840.113549.1.1.10";
+const OID_EC_PUBLIC_KEY: &str = "1.2.840.10045.2.1";
+const OID_CURVE_P256: &str = "1.2.840.10045.3.1.7";
+const OID_CURVE_P384: &str = "1.3.132.0.34";
+const OID_ED25519: &str = "1.3.101.112";
+
+/// The extended key usages a certificate can be required to have
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExtendedKeyUsage {
+    ServerAuth,
+    ClientAuth,
+    CodeSigning,
+    EmailProtection,
+    TimeStamping,
+    OcspSigning,
+}
+
+/// A certificate revocation list
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CertificateRevocationList {
+    /// Which encoding is used by the CRL
+    pub encoding: CertificateEncoding,
+    /// Actual CRL
+    pub data: Vec<u8>,
+}
+
+/// Request of the `crypto` v2 `is_certificate_trusted` operation. On top of
+/// the checks performed by the v1 operation, it can require the certificate
+/// to not be revoked and to satisfy usage and identity constraints.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CertificateVerificationRequestV2 {
+    /// PEM-encoded certificate
+    pub cert: Certificate,
+    /// list of PEM-encoded certs, ordered by trust usage (intermediates first, root last)
+    /// If None, the certificate is verified against the Mozilla root certificates
+    /// shipped with webpki
+    pub cert_chain: Option<Vec<Certificate>>,
+    /// RFC 3339 time format string, to check the expiration of the certificates
+    /// and of the CRLs against. If None, the current time is used
+    pub not_after: Option<String>,
+    /// The extended key usages the certificate must declare. A certificate
+    /// without the extended key usage extension satisfies none of them
+    #[serde(default)]
+    pub extended_key_usages: Vec<ExtendedKeyUsage>,
+    /// The CRLs used to check whether the certificates of the chain have been
+    /// revoked. Certificates whose issuer has no CRL are not checked, while an
+    /// expired CRL makes the certificate not trusted
+    #[serde(default)]
+    pub crls: Vec<CertificateRevocationList>,
+    /// The common name the subject of the certificate must have
+    #[serde(default)]
+    pub subject_common_name: Option<String>,
+    /// The subject alternative names the certificate must include: DNS names,
+    /// email addresses, URIs or IP addresses. Wildcard DNS names of the
+    /// certificate are honored
+    #[serde(default)]
+    pub subject_alternative_names: Vec<String>,
+}
+
+/// Response of the `crypto` v2 `is_certificate_trusted` operation
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CertificateVerificationResponseV2 {
+    pub trusted: bool,
+    /// Why the certificate is not trusted, one entry per failed check
+    pub reasons: Vec<String>,
+}
+
+/// The algorithms that can be used to verify a signature
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignatureAlgorithm {
+    RsaPssSha256,
+    RsaPssSha384,
+    RsaPssSha512,
+    /// ASN.1 DER encoded ECDSA signature, using the P-256 curve
+    EcdsaP256Sha256,
+    /// ASN.1 DER encoded ECDSA signature, using the P-384 curve
+    EcdsaP384Sha384,
+    Ed25519,
+}
+
+/// The key used to verify a signature
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignatureVerificationKey {
+    /// PEM encoded public key
+    PublicKey(String),
+    /// Certificate holding the public key. The certificate itself is not
+    /// verified, `is_certificate_trusted` must be used for that
+    Certificate(Certificate),
+}
+
+/// Request of the `crypto` `verify_signature` operation
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignatureVerificationRequest {
+    pub algorithm: SignatureAlgorithm,
+    pub key: SignatureVerificationKey,
+    /// The data that has been signed
+    pub message: Vec<u8>,
+    /// The detached signature of the message
+    pub signature: Vec<u8>,
+}
+
+/// Response of the `crypto` `verify_signature` operation
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignatureVerificationResponse {
+    pub verified: bool,
+    /// Why the signature is not valid, empty when it is
+    pub reason: String,
+}
+
+/// The algorithms that can be used to compute a digest
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DigestAlgorithm {
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+/// Request of the `crypto` `digest` operation
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DigestRequest {
+    pub algorithm: DigestAlgorithm,
+    pub data: Vec<u8>,
+}
+
+/// Response of the `crypto` `digest` operation
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DigestResponse {
+    /// Lowercase hex encoded digest
+    pub digest: String,
+}
 
 // Helper function to convert a KW Certificate to a webpki CertificateDer
 fn get_certificate_der<'a>(cert: &'a Certificate) -> Result<CertificateDer<'a>> {
@@ -35,9 +185,72 @@ pub fn verify_certificate(
     let cert_der = get_certificate_der(&req.cert)?;
     let end_entity_certificate = EndEntityCert::try_from(&cert_der)
         .map_err(|e| anyhow!("Certificate is not a valid end-entity certificate: {}", e))?;
+    let verification_time = verification_time(req.not_after.as_deref())?;
+
+    let reason = verify_cert_chain(
+        req.cert_chain.as_deref(),
+        &end_entity_certificate,
+        verification_time,
+        &[],
+    )?;
+
+    Ok(cached::Return::new(CertificateVerificationResponse {
+        trusted: reason.is_none(),
+        reason: reason.unwrap_or_default(),
+    }))
+}
+
+/// Verify a certificate like [`verify_certificate`] does, and also check that:
+/// - neither the certificate, nor its issuers, have been revoked by the given CRLs
+/// - the certificate declares all the required extended key usages
+/// - the subject and the subject alternative names of the certificate match the
+///   given ones
+///
+/// All the failed checks are reported.
+pub fn verify_certificate_v2(
+    req: CertificateVerificationRequestV2,
+) -> Result<cached::Return<CertificateVerificationResponseV2>> {
+    let cert_der = get_certificate_der(&req.cert)?;
+    let end_entity_certificate = EndEntityCert::try_from(&cert_der)
+        .map_err(|e| anyhow!("Certificate is not a valid end-entity certificate: {}", e))?;
+    let verification_time = verification_time(req.not_after.as_deref())?;
+    let crls = req
+        .crls
+        .iter()
+        .map(parse_crl)
+        .collect::<Result<Vec<webpki::CertRevocationList>>>()?;
 
-    // verify validity
-    let verification_time = match &req.not_after {
+    let mut reasons = vec![];
+    reasons.extend(verify_cert_chain(
+        req.cert_chain.as_deref(),
+        &end_entity_certificate,
+        verification_time,
+        &crls,
+    )?);
+
+    let (_, certificate) = X509Certificate::from_der(&cert_der)
+        .map_err(|e| anyhow!("Certificate cannot be parsed: {}", e))?;
+    reasons.extend(check_extended_key_usages(
+        &certificate,
+        &req.extended_key_usages,
+    )?);
+    reasons.extend(check_subject_common_name(
+        &certificate,
+        req.subject_common_name.as_deref(),
+    ));
+    reasons.extend(check_subject_alternative_names(
+        &certificate,
+        &req.subject_alternative_names,
+    )?);
+
+    Ok(cached::Return::new(CertificateVerificationResponseV2 {
+        trusted: reasons.is_empty(),
+        reasons,
+    }))
+}
+
+fn verification_time(not_after: Option<&str>) -> Result<UnixTime> {
+    match not_after {
         Some(not_after_str) => {
             // picky - the library we used earlier - deals with UTCTime as defined in:
             //   https://www.rfc-editor.org/rfc/rfc5280#section-4.1.2.5.1
@@ -47,91 +260,344 @@ pub fn verify_certificate(
             let not_after_utc = chrono::DateTime::parse_from_rfc3339(not_after_str)
                 .map_err(|_| anyhow!("Timestamp not_after is not in RFC3339 format"))?
                 .to_utc();
-            UnixTime::since_unix_epoch(std::time::Duration::from_secs(
-                not_after_utc.timestamp() as u64
-            ))
+            Ok(UnixTime::since_unix_epoch(std::time::Duration::from_secs(
+                not_after_utc.timestamp() as u64,
+            )))
         }
         None => {
             let now = std::time::Duration::from_secs(chrono::Utc::now().timestamp() as u64);
-            UnixTime::since_unix_epoch(now)
+            Ok(UnixTime::since_unix_epoch(now))
         }
-    };
-
-    verify_cert_chain(req.cert_chain, end_entity_certificate, verification_time)
+    }
 }
 
+/// Verify the certificate against the chain of trust, and the CRLs when
+/// provided. Returns the reason why the certificate is not trusted, if any
 fn verify_cert_chain(
-    cert_chain: Option<Vec<Certificate>>,
-    end_entity_certificate: EndEntityCert,
+    cert_chain: Option<&[Certificate]>,
+    end_entity_certificate: &EndEntityCert,
     verification_time: UnixTime,
-) -> Result<cached::Return<CertificateVerificationResponse>> {
-    let cert_pool = match &cert_chain {
+    crls: &[webpki::CertRevocationList],
+) -> Result<Option<String>> {
+    let cert_pool = match cert_chain {
         None => CertificatePool::from_webpki_roots(),
         Some(chain) => CertificatePool::from_certificates(chain),
     }?;
 
     let signing_algs = webpki::ALL_VERIFICATION_ALGS;
 
+    let crls: Vec<&webpki::CertRevocationList> = crls.iter().collect();
+    let revocation = webpki::RevocationOptionsBuilder::new(&crls)
+        .ok()
+        .map(|builder| {
+            builder
+                .with_depth(webpki::RevocationCheckDepth::Chain)
+                .with_status_policy(webpki::UnknownStatusPolicy::Allow)
+                .with_expiration_policy(webpki::ExpirationPolicy::Enforce)
+                .build()
+        });
+
     let verification_result = end_entity_certificate.verify_for_usage(
         signing_algs,
         &cert_pool.trusted_roots,
         &cert_pool.intermediates,
         verification_time,
         KeyUsageAlwaysValid::accept_any(),
-        None,
+        revocation,
         None,
     );
 
-    match verification_result {
-        Ok(_) => Ok(cached::Return {
-            value: CertificateVerificationResponse {
-                trusted: true,
-                reason: "".to_string(),
-            },
-            was_cached: false,
-        }),
-        Err(Error::InvalidSignatureForPublicKey) => Ok(cached::Return {
-            value: CertificateVerificationResponse {
-                trusted: false,
-                reason: CERTIFICATE_NOT_TRUSTED_BY_CHAIN.to_string(),
-            },
-            was_cached: false,
-        }),
+    let reason = match verification_result {
+        Ok(_) => return Ok(None),
+        Err(Error::InvalidSignatureForPublicKey) => CERTIFICATE_NOT_TRUSTED_BY_CHAIN.to_string(),
         Err(Error::CertExpired {
             time: _,
             not_after: _,
-        }) => Ok(cached::Return {
-            value: CertificateVerificationResponse {
-                trusted: false,
-                reason: CERTIFICATE_USED_AFTER_EXPIRATION.to_string(),
-            },
-            was_cached: false,
-        }),
+        }) => CERTIFICATE_USED_AFTER_EXPIRATION.to_string(),
         Err(Error::CertNotValidYet {
             time: _,
             not_before: _,
-        }) => Ok(cached::Return {
-            value: CertificateVerificationResponse {
-                trusted: false,
-                reason: CERTIFICATE_USED_BEFORE_VALIDITY.to_string(),
-            },
-            was_cached: false,
-        }),
-        Err(Error::UnknownIssuer) => Ok(cached::Return {
-            value: CertificateVerificationResponse {
-                trusted: false,
-                reason: CERTIFICATE_NOT_TRUSTED_BY_CHAIN.to_string(),
-            },
-            was_cached: false,
-        }),
-        Err(e) => Ok(cached::Return {
-            value: CertificateVerificationResponse {
-                trusted: false,
-                reason: format!("Certificate not trusted: {}", e),
-            },
-            was_cached: false,
-        }),
+        }) => CERTIFICATE_USED_BEFORE_VALIDITY.to_string(),
+        Err(Error::UnknownIssuer) => CERTIFICATE_NOT_TRUSTED_BY_CHAIN.to_string(),
+        Err(Error::CertRevoked) => CERTIFICATE_REVOKED.to_string(),
+        Err(Error::CrlExpired { .. }) => CRL_EXPIRED.to_string(),
+        Err(e) => format!("Certificate not trusted: {}", e),
+    };
+    Ok(Some(reason))
+}
+
+fn parse_crl(crl: &CertificateRevocationList) -> Result<webpki::CertRevocationList<'static>> {
+    let crl_der = match crl.encoding {
+        CertificateEncoding::Pem => CertificateRevocationListDer::from_pem_slice(&crl.data)
+            .map_err(|e| anyhow!("CRL PEM data is not valid: {}", e))?,
+        CertificateEncoding::Der => CertificateRevocationListDer::from(crl.data.as_slice()),
+    };
+    webpki::OwnedCertRevocationList::from_der(&crl_der)
+        .map(webpki::CertRevocationList::from)
+        .map_err(|e| anyhow!("CRL is not valid: {}", e))
+}
+
+fn check_extended_key_usages(
+    certificate: &X509Certificate,
+    required: &[ExtendedKeyUsage],
+) -> Result<Vec<String>> {
+    if required.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let Some(extension) = certificate
+        .extended_key_usage()
+        .map_err(|e| anyhow!("Certificate extended key usage is not valid: {}", e))?
+    else {
+        return Ok(vec![CERTIFICATE_WITHOUT_EXTENDED_KEY_USAGES.to_string()]);
+    };
+    let declared = extension.value;
+
+    Ok(required
+        .iter()
+        .filter(|usage| {
+            let has_usage = match usage {
+                ExtendedKeyUsage::ServerAuth => declared.server_auth,
+                ExtendedKeyUsage::ClientAuth => declared.client_auth,
+                ExtendedKeyUsage::CodeSigning => declared.code_signing,
+                ExtendedKeyUsage::EmailProtection => declared.email_protection,
+                ExtendedKeyUsage::TimeStamping => declared.time_stamping,
+                ExtendedKeyUsage::OcspSigning => declared.ocsp_signing,
+            };
+            !has_usage && !declared.any
+        })
+        .map(|usage| format!("Certificate does not declare the {usage:?} extended key usage"))
+        .collect())
+}
+
+fn check_subject_common_name(
+    certificate: &X509Certificate,
+    required: Option<&str>,
+) -> Option<String> {
+    let required = required?;
+    let matches = certificate
+        .subject()
+        .iter_common_name()
+        .any(|common_name| common_name.as_str().is_ok_and(|cn| cn == required));
+
+    (!matches).then(|| format!("Certificate subject common name is not {required}"))
+}
+
+fn check_subject_alternative_names(
+    certificate: &X509Certificate,
+    required: &[String],
+) -> Result<Vec<String>> {
+    if required.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let declared: Vec<String> = certificate
+        .subject_alternative_name()
+        .map_err(|e| anyhow!("Certificate subject alternative names are not valid: {}", e))?
+        .map(|extension| {
+            extension
+                .value
+                .general_names
+                .iter()
+                .filter_map(general_name_to_string)
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Ok(required
+        .iter()
+        .filter(|name| {
+            !declared
+                .iter()
+                .any(|declared| subject_alternative_name_matches(declared, name))
+        })
+        .map(|name| format!("Certificate subject alternative names do not include {name}"))
+        .collect())
+}
+
+fn general_name_to_string(name: &GeneralName) -> Option<String> {
+    match name {
+        GeneralName::DNSName(name) | GeneralName::RFC822Name(name) | GeneralName::URI(name) => {
+            Some(name.to_string())
+        }
+        GeneralName::IPAddress(octets) => match octets.len() {
+            4 => <[u8; 4]>::try_from(*octets).ok().map(IpAddr::from),
+            16 => <[u8; 16]>::try_from(*octets).ok().map(IpAddr::from),
+            _ => None,
+        }
+        .map(|ip| ip.to_string()),
+        _ => None,
+    }
+}
+
+/// DNS names are compared ignoring their case, a wildcard name of the
+/// certificate (like `*.example.com`) matches exactly one label
+fn subject_alternative_name_matches(declared: &str, required: &str) -> bool {
+    if declared.eq_ignore_ascii_case(required) {
+        return true;
+    }
+    match (declared.strip_prefix("*."), required.split_once('.')) {
+        (Some(declared_domain), Some((label, required_domain))) => {
+            !label.is_empty() && declared_domain.eq_ignore_ascii_case(required_domain)
+        }
+        _ => false,
+    }
+}
+
+/// Verify the detached signature of a message with the given public key.
+///
+/// Malformed keys are reported as errors, while signatures that cannot be
+/// verified, for example because they have been produced with another key,
+/// are reported inside of the response.
+pub fn verify_signature(
+    req: SignatureVerificationRequest,
+) -> Result<cached::Return<SignatureVerificationResponse>> {
+    let reason = match &req.key {
+        SignatureVerificationKey::PublicKey(pem) => {
+            let spki_der = SubjectPublicKeyInfoDer::from_pem_slice(pem.as_bytes())
+                .map_err(|e| anyhow!("Public key PEM data is not valid: {}", e))?;
+            let (_, spki) = SubjectPublicKeyInfo::from_der(&spki_der)
+                .map_err(|e| anyhow!("Public key cannot be parsed: {}", e))?;
+            verify_signature_with_key(&spki, req.algorithm, &req.message, &req.signature)
+        }
+        SignatureVerificationKey::Certificate(cert) => {
+            let cert_der = get_certificate_der(cert)?;
+            let (_, certificate) = X509Certificate::from_der(&cert_der)
+                .map_err(|e| anyhow!("Certificate cannot be parsed: {}", e))?;
+            verify_signature_with_key(
+                certificate.public_key(),
+                req.algorithm,
+                &req.message,
+                &req.signature,
+            )
+        }
+    };
+
+    Ok(cached::Return::new(SignatureVerificationResponse {
+        verified: reason.is_none(),
+        reason: reason.unwrap_or_default(),
+    }))
+}
+
+/// Returns the reason why the signature is not valid, if any
+fn verify_signature_with_key(
+    spki: &SubjectPublicKeyInfo,
+    algorithm: SignatureAlgorithm,
+    message: &[u8],
+    signature: &[u8],
+) -> Option<String> {
+    let (key_algorithm, curve) = key_type(spki);
+
+    let (verification_algorithm, key_matches): (&dyn ring::signature::VerificationAlgorithm, _) =
+        match algorithm {
+            SignatureAlgorithm::RsaPssSha256 => (
+                &ring::signature::RSA_PSS_2048_8192_SHA256,
+                is_rsa_key(&key_algorithm),
+            ),
+            SignatureAlgorithm::RsaPssSha384 => (
+                &ring::signature::RSA_PSS_2048_8192_SHA384,
+                is_rsa_key(&key_algorithm),
+            ),
+            SignatureAlgorithm::RsaPssSha512 => (
+                &ring::signature::RSA_PSS_2048_8192_SHA512,
+                is_rsa_key(&key_algorithm),
+            ),
+            SignatureAlgorithm::EcdsaP256Sha256 => (
+                &ring::signature::ECDSA_P256_SHA256_ASN1,
+                key_algorithm == OID_EC_PUBLIC_KEY && curve.as_deref() == Some(OID_CURVE_P256),
+            ),
+            SignatureAlgorithm::EcdsaP384Sha384 => (
+                &ring::signature::ECDSA_P384_SHA384_ASN1,
+                key_algorithm == OID_EC_PUBLIC_KEY && curve.as_deref() == Some(OID_CURVE_P384),
+            ),
+            SignatureAlgorithm::Ed25519 => {
+                (&ring::signature::ED25519, key_algorithm == OID_ED25519)
+            }
+        };
+    if !key_matches {
+        return Some(format!(
+            "Key cannot be used with the {algorithm:?} algorithm"
+        ));
+    }
+
+    ring::signature::UnparsedPublicKey::new(verification_algorithm, &spki.subject_public_key.data)
+        .verify(message, signature)
+        .err()
+        .map(|_| SIGNATURE_NOT_VALID.to_string())
+}
+
+/// A public key used to verify the signatures produced by the matching private
+/// key. The signature algorithm is inferred from the type of the key like cosign
+/// does: ECDSA keys use the hash matching their curve, RSA keys use PKCS#1 v1.5
+/// with SHA-256
+pub(crate) struct PublicKey {
+    verification_algorithm: &'static dyn ring::signature::VerificationAlgorithm,
+    key: Vec<u8>,
+}
+
+impl PublicKey {
+    /// Parse a PEM encoded public key
+    pub fn from_pem(public_key_pem: &str) -> Result<Self> {
+        let spki_der = SubjectPublicKeyInfoDer::from_pem_slice(public_key_pem.as_bytes())
+            .map_err(|e| anyhow!("Public key PEM data is not valid: {}", e))?;
+        let (_, spki) = SubjectPublicKeyInfo::from_der(&spki_der)
+            .map_err(|e| anyhow!("Public key cannot be parsed: {}", e))?;
+
+        let (key_algorithm, curve) = key_type(&spki);
+        let verification_algorithm: &'static dyn ring::signature::VerificationAlgorithm =
+            match (key_algorithm.as_str(), curve.as_deref()) {
+                (OID_EC_PUBLIC_KEY, Some(OID_CURVE_P256)) => {
+                    &ring::signature::ECDSA_P256_SHA256_ASN1
+                }
+                (OID_EC_PUBLIC_KEY, Some(OID_CURVE_P384)) => {
+                    &ring::signature::ECDSA_P384_SHA384_ASN1
+                }
+                (OID_ED25519, _) => &ring::signature::ED25519,
+                (OID_RSA_ENCRYPTION, _) => &ring::signature::RSA_PKCS1_2048_8192_SHA256,
+                _ => return Err(anyhow!("Public key type is not supported")),
+            };
+
+        Ok(PublicKey {
+            verification_algorithm,
+            key: spki.subject_public_key.data.to_vec(),
+        })
     }
+
+    /// Returns true when the signature of the message has been produced by the
+    /// private key matching this key
+    pub fn verify(&self, message: &[u8], signature: &[u8]) -> bool {
+        ring::signature::UnparsedPublicKey::new(self.verification_algorithm, &self.key)
+            .verify(message, signature)
+            .is_ok()
+    }
+}
+
+/// The object identifiers of the algorithm of the key, and of its curve for
+/// elliptic curve keys
+fn key_type(spki: &SubjectPublicKeyInfo) -> (String, Option<String>) {
+    let key_algorithm = spki.algorithm.algorithm.to_id_string();
+    let curve = spki
+        .algorithm
+        .parameters
+        .as_ref()
+        .and_then(|parameters| parameters.as_oid().ok())
+        .map(|oid| oid.to_id_string());
+    (key_algorithm, curve)
+}
+
+fn is_rsa_key(key_algorithm: &str) -> bool {
+    key_algorithm == OID_RSA_ENCRYPTION || key_algorithm == OID_RSASSA_PSS
+}
+
+/// Compute the digest of the given data
+pub fn digest(req: DigestRequest) -> Result<cached::Return<DigestResponse>> {
+    let digest = match req.algorithm {
+        DigestAlgorithm::Sha256 => format!("{:x}", Sha256::digest(&req.data)),
+        DigestAlgorithm::Sha384 => format!("{:x}", Sha384::digest(&req.data)),
+        DigestAlgorithm::Sha512 => format!("{:x}", Sha512::digest(&req.data)),
+    };
+
+    Ok(cached::Return::new(DigestResponse { digest }))
 }
 
 /// A collection of trusted certificates, both root, and intermediate ones.
@@ -198,7 +664,9 @@ mod tests {
     use kubewarden_policy_sdk::host_capabilities::crypto_v1::CertificateVerificationRequest;
     use lazy_static::lazy_static;
     use rcgen::{
-        CertificateParams, CertifiedKey, ExtendedKeyUsagePurpose, Issuer, KeyPair, KeyUsagePurpose,
+        CertificateParams, CertificateRevocationListParams, CertifiedKey, ExtendedKeyUsagePurpose,
+        Issuer, KeyIdMethod, KeyPair, KeyUsagePurpose, RevocationReason, RevokedCertParams,
+        SerialNumber,
     };
     use rstest::rstest;
     use time::{Duration, OffsetDateTime};
@@ -727,4 +1195,460 @@ mod tests {
             }
         }
     }
+
+    fn verification_request_v2(
+        cert: Certificate,
+        cert_chain: Vec<Certificate>,
+    ) -> CertificateVerificationRequestV2 {
+        CertificateVerificationRequestV2 {
+            cert,
+            cert_chain: Some(cert_chain),
+            not_after: None,
+            extended_key_usages: vec![],
+            crls: vec![],
+            subject_common_name: None,
+            subject_alternative_names: vec![],
+        }
+    }
+
+    fn valid_certificate_chain() -> (Certificate, Vec<Certificate>) {
+        generate_certificate_chain(
+            CertificateGenerationSpec {
+                subject_alt_names: &["root.kubewarden.io"],
+                not_before: *TWO_YEARS_AGO,
+                not_after: *TWO_YEARS_IN_FUTURE,
+            },
+            None,
+            CertificateGenerationSpec {
+                subject_alt_names: &["endentity.kubewarden.io", "10.0.0.1"],
+                not_before: *TEN_DAYS_AGO,
+                not_after: *TEN_DAYS_IN_FUTURE,
+            },
+        )
+    }
+
+    #[rstest]
+    #[case::no_requirement(vec![], vec![])]
+    #[case::declared(vec![ExtendedKeyUsage::ServerAuth], vec![])]
+    #[case::not_declared(
+        vec![ExtendedKeyUsage::ServerAuth, ExtendedKeyUsage::CodeSigning],
+        vec!["Certificate does not declare the CodeSigning extended key usage"]
+    )]
+    fn certificate_v2_extended_key_usages(
+        #[case] extended_key_usages: Vec<ExtendedKeyUsage>,
+        #[case] reasons: Vec<&str>,
+    ) {
+        let (end_entity_cert, cert_chain) = valid_certificate_chain();
+        let req = CertificateVerificationRequestV2 {
+            extended_key_usages,
+            ..verification_request_v2(end_entity_cert, cert_chain)
+        };
+
+        assert_eq!(
+            verify_certificate_v2(req).unwrap().value,
+            CertificateVerificationResponseV2 {
+                trusted: reasons.is_empty(),
+                reasons: reasons.into_iter().map(String::from).collect(),
+            }
+        );
+    }
+
+    #[rstest]
+    #[case::dns_name(vec!["endentity.kubewarden.io"], vec![])]
+    #[case::ip_address(vec!["10.0.0.1", "endentity.kubewarden.io"], vec![])]
+    #[case::missing(
+        vec!["endentity.kubewarden.io", "other.kubewarden.io"],
+        vec!["Certificate subject alternative names do not include other.kubewarden.io"]
+    )]
+    fn certificate_v2_subject_alternative_names(
+        #[case] subject_alternative_names: Vec<&str>,
+        #[case] reasons: Vec<&str>,
+    ) {
+        let (end_entity_cert, cert_chain) = valid_certificate_chain();
+        let req = CertificateVerificationRequestV2 {
+            subject_alternative_names: subject_alternative_names
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            ..verification_request_v2(end_entity_cert, cert_chain)
+        };
+
+        assert_eq!(
+            verify_certificate_v2(req).unwrap().value,
+            CertificateVerificationResponseV2 {
+                trusted: reasons.is_empty(),
+                reasons: reasons.into_iter().map(String::from).collect(),
+            }
+        );
+    }
+
+    #[test]
+    fn certificate_v2_reports_all_failures() {
+        let (end_entity_cert, _) = valid_certificate_chain();
+        let (_, other_chain) = valid_certificate_chain();
+        let req = CertificateVerificationRequestV2 {
+            extended_key_usages: vec![ExtendedKeyUsage::ClientAuth],
+            subject_common_name: Some("endentity".to_string()),
+            ..verification_request_v2(end_entity_cert, other_chain)
+        };
+
+        assert_eq!(
+            verify_certificate_v2(req).unwrap().value,
+            CertificateVerificationResponseV2 {
+                trusted: false,
+                reasons: vec![
+                    CERTIFICATE_NOT_TRUSTED_BY_CHAIN.to_string(),
+                    "Certificate does not declare the ClientAuth extended key usage".to_string(),
+                    "Certificate subject common name is not endentity".to_string(),
+                ],
+            }
+        );
+    }
+
+    #[rstest]
+    #[case::revoked(true, *TEN_DAYS_IN_FUTURE, Some(CERTIFICATE_REVOKED))]
+    #[case::not_revoked(false, *TEN_DAYS_IN_FUTURE, None)]
+    #[case::expired_crl(false, *TEN_DAYS_AGO, Some(CRL_EXPIRED))]
+    fn certificate_v2_revocation(
+        #[case] revoked: bool,
+        #[case] crl_next_update: OffsetDateTime,
+        #[case] expected_reason: Option<&str>,
+    ) {
+        let root_ca = generate_certificate(
+            CertificateGenerationSpec {
+                subject_alt_names: &["root.kubewarden.io"],
+                not_before: *TWO_YEARS_AGO,
+                not_after: *TWO_YEARS_IN_FUTURE,
+            },
+            true,
+            None,
+        )
+        .unwrap();
+        let end_entity = generate_certificate(
+            CertificateGenerationSpec {
+                subject_alt_names: &["endentity.kubewarden.io"],
+                not_before: *TEN_DAYS_AGO,
+                not_after: *TEN_DAYS_IN_FUTURE,
+            },
+            false,
+            Some(CertifiedKey {
+                cert: root_ca.cert.clone(),
+                signing_key: KeyPair::from_pem(root_ca.signing_key.serialize_pem().as_str())
+                    .unwrap(),
+            }),
+        )
+        .unwrap();
+
+        let (_, end_entity_x509) = X509Certificate::from_der(end_entity.cert.der()).unwrap();
+        let revoked_certs = if revoked {
+            vec![RevokedCertParams {
+                serial_number: SerialNumber::from_slice(end_entity_x509.raw_serial()),
+                revocation_time: *TEN_DAYS_AGO,
+                reason_code: Some(RevocationReason::KeyCompromise),
+                invalidity_date: None,
+            }]
+        } else {
+            vec![]
+        };
+        let issuer = Issuer::from_ca_cert_der(root_ca.cert.der(), root_ca.signing_key).unwrap();
+        let crl = CertificateRevocationListParams {
+            this_update: *ONE_YEAR_AGO,
+            next_update: crl_next_update,
+            crl_number: SerialNumber::from(1u64),
+            issuing_distribution_point: None,
+            revoked_certs,
+            key_identifier_method: KeyIdMethod::Sha256,
+        }
+        .signed_by(&issuer)
+        .unwrap();
+
+        let req = CertificateVerificationRequestV2 {
+            crls: vec![CertificateRevocationList {
+                encoding: CertificateEncoding::Pem,
+                data: crl.pem().unwrap().into_bytes(),
+            }],
+            ..verification_request_v2(
+                Certificate {
+                    encoding: CertificateEncoding::Der,
+                    data: end_entity.cert.der().to_vec(),
+                },
+                vec![Certificate {
+                    encoding: CertificateEncoding::Pem,
+                    data: root_ca.cert.pem().into_bytes(),
+                }],
+            )
+        };
+
+        let response = verify_certificate_v2(req).unwrap().value;
+        assert_eq!(response.trusted, expected_reason.is_none());
+        assert_eq!(
+            response.reasons,
+            expected_reason
+                .map(|reason| vec![reason.to_string()])
+                .unwrap_or_default()
+        );
+    }
+
+    #[rstest]
+    #[case::exact("api.kubewarden.io", "api.kubewarden.io", true)]
+    #[case::case_insensitive("API.kubewarden.io", "api.kubewarden.io", true)]
+    #[case::wildcard("*.kubewarden.io", "api.kubewarden.io", true)]
+    #[case::wildcard_single_label("*.kubewarden.io", "a.api.kubewarden.io", false)]
+    #[case::wildcard_apex("*.kubewarden.io", "kubewarden.io", false)]
+    #[case::different("api.kubewarden.io", "web.kubewarden.io", false)]
+    fn subject_alternative_names_matching(
+        #[case] declared: &str,
+        #[case] required: &str,
+        #[case] expected: bool,
+    ) {
+        assert_eq!(
+            subject_alternative_name_matches(declared, required),
+            expected
+        );
+    }
+
+    /// Generate a key usable with the given algorithm. RSA keys cannot be
+    /// generated with ring, a fixed one is used instead
+    fn signing_key(algorithm: SignatureAlgorithm) -> KeyPair {
+        match algorithm {
+            SignatureAlgorithm::RsaPssSha256
+            | SignatureAlgorithm::RsaPssSha384
+            | SignatureAlgorithm::RsaPssSha512 => {
+                KeyPair::from_pem(include_str!("../../tests/data/rsa_signing_key.pem")).unwrap()
+            }
+            SignatureAlgorithm::EcdsaP256Sha256 => {
+                KeyPair::generate_for(&rcgen::PKCS_ECDSA_P256_SHA256).unwrap()
+            }
+            SignatureAlgorithm::EcdsaP384Sha384 => {
+                KeyPair::generate_for(&rcgen::PKCS_ECDSA_P384_SHA384).unwrap()
+            }
+            SignatureAlgorithm::Ed25519 => KeyPair::generate_for(&rcgen::PKCS_ED25519).unwrap(),
+        }
+    }
+
+    fn sign(signing_key: &KeyPair, algorithm: SignatureAlgorithm, message: &[u8]) -> Vec<u8> {
+        let rng = ring::rand::SystemRandom::new();
+        let pkcs8 = signing_key.serialize_der();
+
+        let rsa_padding: &dyn ring::signature::RsaEncoding = match algorithm {
+            SignatureAlgorithm::RsaPssSha256 => &ring::signature::RSA_PSS_SHA256,
+            SignatureAlgorithm::RsaPssSha384 => &ring::signature::RSA_PSS_SHA384,
+            SignatureAlgorithm::RsaPssSha512 => &ring::signature::RSA_PSS_SHA512,
+            SignatureAlgorithm::EcdsaP256Sha256 | SignatureAlgorithm::EcdsaP384Sha384 => {
+                let ecdsa_algorithm = if algorithm == SignatureAlgorithm::EcdsaP256Sha256 {
+                    &ring::signature::ECDSA_P256_SHA256_ASN1_SIGNING
+                } else {
+                    &ring::signature::ECDSA_P384_SHA384_ASN1_SIGNING
+                };
+                let key_pair =
+                    ring::signature::EcdsaKeyPair::from_pkcs8(ecdsa_algorithm, &pkcs8, &rng)
+                        .unwrap();
+                return key_pair.sign(&rng, message).unwrap().as_ref().to_vec();
+            }
+            SignatureAlgorithm::Ed25519 => {
+                let key_pair = ring::signature::Ed25519KeyPair::from_pkcs8(&pkcs8).unwrap();
+                return key_pair.sign(message).as_ref().to_vec();
+            }
+        };
+
+        let key_pair = ring::signature::RsaKeyPair::from_pkcs8(&pkcs8).unwrap();
+        let mut signature = vec![0; key_pair.public().modulus_len()];
+        key_pair
+            .sign(rsa_padding, &rng, message, &mut signature)
+            .unwrap();
+        signature
+    }
+
+    #[rstest]
+    #[case::valid(SignatureAlgorithm::EcdsaP256Sha256, b"hello world", None)]
+    #[case::tampered_message(
+        SignatureAlgorithm::EcdsaP256Sha256,
+        b"hello world!",
+        Some(SIGNATURE_NOT_VALID)
+    )]
+    #[case::wrong_algorithm(
+        SignatureAlgorithm::EcdsaP384Sha384,
+        b"hello world",
+        Some("Key cannot be used with the EcdsaP384Sha384 algorithm")
+    )]
+    #[case::wrong_key_type(
+        SignatureAlgorithm::Ed25519,
+        b"hello world",
+        Some("Key cannot be used with the Ed25519 algorithm")
+    )]
+    fn signature_verification_with_public_key(
+        #[case] algorithm: SignatureAlgorithm,
+        #[case] message: &[u8],
+        #[case] reason: Option<&str>,
+    ) {
+        let signing_key = KeyPair::generate().unwrap();
+        let signature = sign(
+            &signing_key,
+            SignatureAlgorithm::EcdsaP256Sha256,
+            b"hello world",
+        );
+
+        let req = SignatureVerificationRequest {
+            algorithm,
+            key: SignatureVerificationKey::PublicKey(signing_key.public_key_pem()),
+            message: message.to_vec(),
+            signature,
+        };
+        assert_eq!(
+            verify_signature(req).unwrap().value,
+            SignatureVerificationResponse {
+                verified: reason.is_none(),
+                reason: reason.unwrap_or_default().to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn ed25519_signature_verification() {
+        let rng = ring::rand::SystemRandom::new();
+        let pkcs8 = ring::signature::Ed25519KeyPair::generate_pkcs8(&rng).unwrap();
+        let key_pair = ring::signature::Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+        let public_key_pem = KeyPair::try_from(pkcs8.as_ref()).unwrap().public_key_pem();
+
+        let req = SignatureVerificationRequest {
+            algorithm: SignatureAlgorithm::Ed25519,
+            key: SignatureVerificationKey::PublicKey(public_key_pem),
+            message: b"hello world".to_vec(),
+            signature: key_pair.sign(b"hello world").as_ref().to_vec(),
+        };
+        assert!(verify_signature(req).unwrap().value.verified);
+    }
+
+    #[rstest]
+    #[case::rsa_pss_sha256(SignatureAlgorithm::RsaPssSha256)]
+    #[case::rsa_pss_sha384(SignatureAlgorithm::RsaPssSha384)]
+    #[case::rsa_pss_sha512(SignatureAlgorithm::RsaPssSha512)]
+    #[case::ecdsa_p384_sha384(SignatureAlgorithm::EcdsaP384Sha384)]
+    fn signature_verification_with_algorithm(#[case] algorithm: SignatureAlgorithm) {
+        let signing_key = signing_key(algorithm);
+        let signature = sign(&signing_key, algorithm, b"hello world");
+
+        for (message, reason) in [
+            (b"hello world".as_slice(), None),
+            (b"hello world!".as_slice(), Some(SIGNATURE_NOT_VALID)),
+        ] {
+            let req = SignatureVerificationRequest {
+                algorithm,
+                key: SignatureVerificationKey::PublicKey(signing_key.public_key_pem()),
+                message: message.to_vec(),
+                signature: signature.clone(),
+            };
+            assert_eq!(
+                verify_signature(req).unwrap().value,
+                SignatureVerificationResponse {
+                    verified: reason.is_none(),
+                    reason: reason.unwrap_or_default().to_string(),
+                }
+            );
+        }
+    }
+
+    #[rstest]
+    #[case::rsa_key_with_ecdsa(
+        SignatureAlgorithm::RsaPssSha256,
+        SignatureAlgorithm::EcdsaP384Sha384,
+        "Key cannot be used with the EcdsaP384Sha384 algorithm"
+    )]
+    #[case::p384_key_with_rsa_pss(
+        SignatureAlgorithm::EcdsaP384Sha384,
+        SignatureAlgorithm::RsaPssSha384,
+        "Key cannot be used with the RsaPssSha384 algorithm"
+    )]
+    #[case::p384_key_with_p256(
+        SignatureAlgorithm::EcdsaP384Sha384,
+        SignatureAlgorithm::EcdsaP256Sha256,
+        "Key cannot be used with the EcdsaP256Sha256 algorithm"
+    )]
+    #[case::rsa_pss_with_another_hash(
+        SignatureAlgorithm::RsaPssSha256,
+        SignatureAlgorithm::RsaPssSha512,
+        SIGNATURE_NOT_VALID
+    )]
+    fn signature_verification_with_mismatched_algorithm(
+        #[case] signing_algorithm: SignatureAlgorithm,
+        #[case] algorithm: SignatureAlgorithm,
+        #[case] reason: &str,
+    ) {
+        let signing_key = signing_key(signing_algorithm);
+
+        let req = SignatureVerificationRequest {
+            algorithm,
+            key: SignatureVerificationKey::PublicKey(signing_key.public_key_pem()),
+            message: b"hello world".to_vec(),
+            signature: sign(&signing_key, signing_algorithm, b"hello world"),
+        };
+        assert_eq!(
+            verify_signature(req).unwrap().value,
+            SignatureVerificationResponse {
+                verified: false,
+                reason: reason.to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn signature_verification_with_certificate() {
+        let certified_key = generate_certificate(
+            CertificateGenerationSpec {
+                subject_alt_names: &["root.kubewarden.io"],
+                not_before: *TWO_YEARS_AGO,
+                not_after: *TWO_YEARS_IN_FUTURE,
+            },
+            true,
+            None,
+        )
+        .unwrap();
+
+        let req = SignatureVerificationRequest {
+            algorithm: SignatureAlgorithm::EcdsaP256Sha256,
+            key: SignatureVerificationKey::Certificate(Certificate {
+                encoding: CertificateEncoding::Pem,
+                data: certified_key.cert.pem().into_bytes(),
+            }),
+            message: b"hello world".to_vec(),
+            signature: sign(
+                &certified_key.signing_key,
+                SignatureAlgorithm::EcdsaP256Sha256,
+                b"hello world",
+            ),
+        };
+        assert!(verify_signature(req).unwrap().value.verified);
+    }
+
+    #[test]
+    fn signature_verification_with_malformed_key() {
+        let req = SignatureVerificationRequest {
+            algorithm: SignatureAlgorithm::Ed25519,
+            key: SignatureVerificationKey::PublicKey("not a key".to_string()),
+            message: b"hello world".to_vec(),
+            signature: vec![],
+        };
+        assert!(verify_signature(req).is_err());
+    }
+
+    #[rstest]
+    #[case::sha256(
+        DigestAlgorithm::Sha256,
+        "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+    )]
+    #[case::sha384(
+        DigestAlgorithm::Sha384,
+        "38b060a751ac96384cd9327eb1b1e36a21fdb71114be07434c0cc7bf63f6e1da274edebfe76f65fbd51ad2f14898b95b"
+    )]
+    #[case::sha512(
+        DigestAlgorithm::Sha512,
+        "cf83e1357eefb8bdf1542850d66d8007d620e4050b5715dc83f4a921d36ce9ce47d0d13c5d85f2b0ff8318d2877eec2f63b931bd47417a81a538327af927da3e"
+    )]
+    fn digests(#[case] algorithm: DigestAlgorithm, #[case] expected: &str) {
+        let req = DigestRequest {
+            algorithm,
+            data: vec![],
+        };
+        assert_eq!(digest(req).unwrap().value.digest, expected);
+    }
 }
@@ -0,0 +1,294 @@
+use std::{
+    future::Future,
+    net::IpAddr,
+    net::SocketAddr,
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
+
+use anyhow::{Result, anyhow};
+use hickory_resolver::{
+    TokioResolver,
+    config::{LookupIpStrategy, NameServerConfig, NameServerConfigGroup, ResolverConfig},
+    name_server::TokioConnectionProvider,
+    proto::xfer::Protocol,
+};
+use kubewarden_policy_sdk::host_capabilities::net::LookupResponse;
+use serde::{Deserialize, Serialize};
+
+/// How DNS queries are performed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DnsConfig {
+    /// Maximum time a lookup can take, retries included
+    pub timeout: Duration,
+    /// The nameservers to query. When empty, the ones configured on the host
+    /// (e.g. inside of `/etc/resolv.conf`) are used
+    pub nameservers: Vec<SocketAddr>,
+    /// Maximum number of DNS records cached. Records are cached for the time
+    /// specified by their TTL. `0` disables the cache
+    pub cache_size: usize,
+    /// Upper bound of the time a record is cached, regardless of its TTL.
+    /// `None` means the TTL of the record is always honored
+    pub max_ttl: Option<Duration>,
+}
+
+impl Default for DnsConfig {
+    fn default() -> Self {
+        DnsConfig {
+            timeout: Duration::from_secs(5),
+            nameservers: Vec::new(),
+            cache_size: 1024,
+            max_ttl: None,
+        }
+    }
+}
+
+/// The response of a reverse (PTR) lookup
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReverseLookupResponse {
+    /// The hostnames associated with the IP address
+    pub hostnames: Vec<String>,
+}
+
+/// A SRV record
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SrvRecord {
+    pub priority: u16,
+    pub weight: u16,
+    pub port: u16,
+    pub target: String,
+}
+
+/// The response of a SRV lookup
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SrvLookupResponse {
+    pub records: Vec<SrvRecord>,
+}
+
+/// The response of a TXT lookup
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TxtLookupResponse {
+    /// The text of each record. The character strings of a record are
+    /// concatenated
+    pub records: Vec<String>,
+}
+
+/// Asynchronous DNS resolver, with a cache that honors the TTL of the records.
+///
+/// The underlying resolver is built on the first lookup, so that a broken DNS
+/// configuration of the host fails only the DNS lookups. Building the resolver
+/// is attempted again on the next lookup.
+#[derive(Clone)]
+pub(crate) struct Resolver {
+    config: DnsConfig,
+    resolver: Arc<OnceLock<TokioResolver>>,
+}
+
+impl Resolver {
+    pub fn new(config: &DnsConfig) -> Self {
+        Resolver {
+            config: config.clone(),
+            resolver: Arc::new(OnceLock::new()),
+        }
+    }
+
+    fn resolver(&self) -> Result<&TokioResolver> {
+        if let Some(resolver) = self.resolver.get() {
+            return Ok(resolver);
+        }
+        let resolver = build_resolver(&self.config)?;
+        Ok(self.resolver.get_or_init(|| resolver))
+    }
+
+    /// Lookup the IP addresses of the given host
+    pub async fn lookup_host(&self, host: &str) -> Result<cached::Return<LookupResponse>> {
+        let lookup = self
+            .with_timeout(host, self.resolver()?.lookup_ip(host))
+            .await?;
+
+        Ok(cached::Return::new(LookupResponse {
+            ips: lookup.iter().map(|ip| ip.to_string()).collect(),
+        }))
+    }
+
+    /// Lookup the hostnames associated with the given IP address
+    pub async fn reverse_lookup(
+        &self,
+        address: &str,
+    ) -> Result<cached::Return<ReverseLookupResponse>> {
+        let ip: IpAddr = address
+            .parse()
+            .map_err(|e| anyhow!("invalid IP address {address}: {e}"))?;
+        let lookup = self
+            .with_timeout(address, self.resolver()?.reverse_lookup(ip))
+            .await?;
+
+        Ok(cached::Return::new(ReverseLookupResponse {
+            hostnames: lookup
+                .iter()
+                .map(|ptr| hostname(&ptr.to_string()))
+                .collect(),
+        }))
+    }
+
+    /// Lookup the SRV records of the given name, like `_ldap._tcp.example.com`
+    pub async fn lookup_srv(&self, name: &str) -> Result<cached::Return<SrvLookupResponse>> {
+        let lookup = self
+            .with_timeout(name, self.resolver()?.srv_lookup(name))
+            .await?;
+
+        Ok(cached::Return::new(SrvLookupResponse {
+            records: lookup
+                .iter()
+                .map(|srv| SrvRecord {
+                    priority: srv.priority(),
+                    weight: srv.weight(),
+                    port: srv.port(),
+                    target: hostname(&srv.target().to_string()),
+                })
+                .collect(),
+        }))
+    }
+
+    /// Lookup the TXT records of the given name
+    pub async fn lookup_txt(&self, name: &str) -> Result<cached::Return<TxtLookupResponse>> {
+        let lookup = self
+            .with_timeout(name, self.resolver()?.txt_lookup(name))
+            .await?;
+
+        Ok(cached::Return::new(TxtLookupResponse {
+            records: lookup
+                .iter()
+                .map(|txt| txt_record(txt.txt_data()))
+                .collect(),
+        }))
+    }
+
+    async fn with_timeout<T, E, F>(&self, name: &str, lookup: F) -> Result<T>
+    where
+        F: Future<Output = std::result::Result<T, E>>,
+        E: std::fmt::Display,
+    {
+        let timeout = self.config.timeout;
+        tokio::time::timeout(timeout, lookup)
+            .await
+            .map_err(|_| anyhow!("DNS lookup of {name} timed out after {timeout:?}"))?
+            .map_err(|e| anyhow!("DNS lookup of {name} failed: {e}"))
+    }
+}
+
+fn build_resolver(config: &DnsConfig) -> Result<TokioResolver> {
+    let mut builder = if config.nameservers.is_empty() {
+        TokioResolver::builder_tokio()
+            .map_err(|e| anyhow!("cannot read the DNS configuration of the host: {e}"))?
+    } else {
+        TokioResolver::builder_with_config(
+            ResolverConfig::from_parts(None, vec![], nameservers_group(&config.nameservers)),
+            TokioConnectionProvider::default(),
+        )
+    };
+
+    let options = builder.options_mut();
+    options.timeout = config.timeout;
+    options.cache_size = config.cache_size;
+    options.positive_max_ttl = config.max_ttl;
+    options.negative_max_ttl = config.max_ttl;
+    // like getaddrinfo, return both the IPv4 and the IPv6 addresses
+    options.ip_strategy = LookupIpStrategy::Ipv4AndIpv6;
+
+    Ok(builder.build())
+}
+
+/// Every nameserver is queried over UDP, falling back to TCP for truncated
+/// responses
+fn nameservers_group(nameservers: &[SocketAddr]) -> NameServerConfigGroup {
+    nameservers
+        .iter()
+        .flat_map(|address| {
+            [
+                NameServerConfig::new(*address, Protocol::Udp),
+                NameServerConfig::new(*address, Protocol::Tcp),
+            ]
+        })
+        .collect::<Vec<_>>()
+        .into()
+}
+
+/// Remove the trailing dot of fully qualified domain names
+fn hostname(name: &str) -> String {
+    name.strip_suffix('.').unwrap_or(name).to_owned()
+}
+
+fn txt_record(character_strings: &[Box<[u8]>]) -> String {
+    character_strings
+        .iter()
+        .map(|data| String::from_utf8_lossy(data))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case::fqdn("kubewarden.io.", "kubewarden.io")]
+    #[case::relative("kubewarden.io", "kubewarden.io")]
+    fn hostnames(#[case] name: &str, #[case] expected: &str) {
+        assert_eq!(hostname(name), expected);
+    }
+
+    #[test]
+    fn txt_records_are_concatenated() {
+        let character_strings: Vec<Box<[u8]>> = vec![
+            b"v=spf1 ".to_vec().into_boxed_slice(),
+            b"-all".to_vec().into_boxed_slice(),
+        ];
+        assert_eq!(txt_record(&character_strings), "v=spf1 -all");
+    }
+
+    #[tokio::test]
+    async fn lookups_are_bounded_by_the_timeout() {
+        // a nameserver that never answers, neither over UDP nor over TCP
+        let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let address = socket.local_addr().unwrap();
+        let _listener = tokio::net::TcpListener::bind(address).await.unwrap();
+        let timeout = Duration::from_millis(200);
+        let resolver = Resolver::new(&DnsConfig {
+            timeout,
+            nameservers: vec![address],
+            ..Default::default()
+        });
+
+        let start = std::time::Instant::now();
+        let error = resolver
+            .lookup_host("kubewarden.io")
+            .await
+            .expect_err("lookup should fail");
+        let elapsed = start.elapsed();
+
+        let message = error.to_string();
+        assert!(
+            message.contains("kubewarden.io") && message.contains("timed out"),
+            "unexpected error: {message}"
+        );
+        assert!(
+            elapsed >= timeout && elapsed < timeout * 5,
+            "lookup took {elapsed:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn reverse_lookup_of_invalid_address() {
+        let resolver = Resolver::new(&DnsConfig {
+            nameservers: vec!["127.0.0.1:53".parse().unwrap()],
+            ..Default::default()
+        });
+
+        let error = resolver
+            .reverse_lookup("not-an-ip")
+            .await
+            .expect_err("lookup should fail");
+        assert!(error.to_string().contains("invalid IP address"));
+    }
+}
@@ -0,0 +1,800 @@
+use std::{collections::BTreeMap, path::Path};
+
+use anyhow::{Result, anyhow};
+use k8s_openapi::api::authorization::v1::SubjectAccessReviewStatus;
+use kube::{
+    ResourceExt,
+    core::{DynamicObject, GroupVersionKind, ObjectList},
+};
+use kubewarden_policy_sdk::host_capabilities::{
+    kubernetes::SubjectAccessReview as KWSubjectAccessReview, net::LookupResponse,
+    oci::ManifestDigestResponse, verification::VerificationResponse,
+};
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+use super::dns::{ReverseLookupResponse, SrvLookupResponse, SrvRecord, TxtLookupResponse};
+use super::kubernetes::{
+    Projection,
+    selectors::{FieldSelector, LabelSelector},
+};
+use super::oci::{OciReferrer, OciReferrersResponse};
+use super::sigstore_verification::AttestationVerificationResponse;
+use crate::callback_requests::CallbackRequestType;
+
+/// Declarative description of the answers given by a CallbackHandler that
+/// works fully offline.
+///
+/// This is meant to be used when testing policies: the same fixtures always
+/// produce the same responses, without any interaction with a Kubernetes
+/// cluster, OCI registries, Sigstore or DNS servers.
+///
+/// Requests that are not covered by the fixtures are answered with an error.
+/// Crypto requests, like certificate and signature verifications, do not
+/// require network access, hence they are always computed by the CallbackHandler.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct CallbackFixtures {
+    #[serde(default)]
+    pub kubernetes: KubernetesFixtures,
+    #[serde(default)]
+    pub oci: Vec<OciFixture>,
+    #[serde(default)]
+    pub sigstore: Vec<SigstoreFixture>,
+    /// Map of hostnames to the IP addresses they resolve to
+    #[serde(default)]
+    pub dns: BTreeMap<String, Vec<String>>,
+    /// Map of IP addresses to the hostnames associated with them
+    #[serde(default)]
+    pub dns_reverse: BTreeMap<String, Vec<String>>,
+    /// Map of names to their SRV records
+    #[serde(default)]
+    pub dns_srv: BTreeMap<String, Vec<SrvRecord>>,
+    /// Map of names to their TXT records
+    #[serde(default)]
+    pub dns_txt: BTreeMap<String, Vec<String>>,
+    /// The fixtures are immutable, this is used to answer the requests asking
+    /// if a list of Kubernetes resources changed since a given instant
+    #[serde(skip, default = "Instant::now")]
+    loaded_at: Instant,
+}
+
+impl Default for CallbackFixtures {
+    fn default() -> Self {
+        CallbackFixtures {
+            kubernetes: KubernetesFixtures::default(),
+            oci: Vec::new(),
+            sigstore: Vec::new(),
+            dns: BTreeMap::new(),
+            dns_reverse: BTreeMap::new(),
+            dns_srv: BTreeMap::new(),
+            dns_txt: BTreeMap::new(),
+            loaded_at: Instant::now(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct KubernetesFixtures {
+    /// The Kubernetes objects defined inside of the cluster. Objects without a
+    /// namespace are considered to be cluster wide resources
+    #[serde(default)]
+    pub resources: Vec<DynamicObject>,
+    /// Plural names of the resources, indexed by `<apiVersion>/<kind>`.
+    /// When a resource is not listed here, its plural name is guessed
+    #[serde(default)]
+    pub plural_names: BTreeMap<String, String>,
+    /// The outcome of the `can_i` requests
+    #[serde(default)]
+    pub can_i: Vec<CanIFixture>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct CanIFixture {
+    pub request: KWSubjectAccessReview,
+    pub status: SubjectAccessReviewStatus,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct OciFixture {
+    /// String pointing to the object (e.g.: `registry.testing.lan/busybox:1.0.0`)
+    pub image: String,
+    pub digest: String,
+    #[serde(default)]
+    pub manifest: Option<serde_json::Value>,
+    #[serde(default)]
+    pub config: Option<serde_json::Value>,
+    /// The artifacts referring to the image
+    #[serde(default)]
+    pub referrers: Vec<OciReferrer>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct SigstoreFixture {
+    /// String pointing to the object (e.g.: `registry.testing.lan/busybox:1.0.0`)
+    pub image: String,
+    /// The verdict of every Sigstore verification done against the image
+    pub trusted: bool,
+    /// The digest of the verified image. When not set, the digest defined by
+    /// the OCI fixture of the image is used
+    #[serde(default)]
+    pub digest: Option<String>,
+    /// The predicates of the attestations of the image, indexed by their
+    /// predicate type
+    #[serde(default)]
+    pub attestations: BTreeMap<String, serde_json::Value>,
+}
+
+impl CallbackFixtures {
+    /// Load the fixtures from a YAML or JSON file
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let data = std::fs::read(path)
+            .map_err(|e| anyhow!("cannot read fixtures file {}: {e}", path.display()))?;
+        serde_yaml::from_slice(&data)
+            .map_err(|e| anyhow!("cannot parse fixtures file {}: {e}", path.display()))
+    }
+
+    /// Compute the payload of the response to the given request
+    pub(crate) fn respond(&self, request: &CallbackRequestType) -> Result<Vec<u8>> {
+        let payload = match request {
+            CallbackRequestType::OciManifestDigest { image } => {
+                serde_json::to_value(ManifestDigestResponse {
+                    digest: self.oci_fixture(image)?.digest.clone(),
+                })?
+            }
+            CallbackRequestType::OciManifest { image } => self
+                .oci_fixture(image)?
+                .manifest
+                .clone()
+                .ok_or_else(|| anyhow!("no manifest fixture defined for image {image}"))?,
+            CallbackRequestType::OciManifestAndConfig { image } => {
+                let fixture = self.oci_fixture(image)?;
+                let manifest = fixture
+                    .manifest
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("no manifest fixture defined for image {image}"))?;
+                let config = fixture
+                    .config
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("no config fixture defined for image {image}"))?;
+                serde_json::json!({
+                    "manifest": manifest,
+                    "digest": fixture.digest,
+                    "config": config,
+                })
+            }
+            CallbackRequestType::SigstorePubKeyVerify { image, .. }
+            | CallbackRequestType::SigstoreKeylessVerify { image, .. }
+            | CallbackRequestType::SigstoreKeylessPrefixVerify { image, .. }
+            | CallbackRequestType::SigstoreGithubActionsVerify { image, .. }
+            | CallbackRequestType::SigstoreCertificateVerify { image, .. } => {
+                serde_json::to_value(self.sigstore_verification(image)?)?
+            }
+            CallbackRequestType::OciReferrers { request } => {
+                let fixture = self.oci_fixture(&request.image)?;
+                serde_json::to_value(OciReferrersResponse {
+                    digest: fixture.digest.clone(),
+                    referrers: fixture
+                        .referrers
+                        .iter()
+                        .filter(|referrer| {
+                            request.artifact_type.is_none()
+                                || referrer.artifact_type == request.artifact_type
+                        })
+                        .cloned()
+                        .collect(),
+                    cosign_attestations: None,
+                })?
+            }
+            CallbackRequestType::SigstoreAttestationVerify { request } => serde_json::to_value(
+                self.attestation_verification(&request.image, &request.predicate_type)?,
+            )?,
+            CallbackRequestType::DNSLookupHost { host } => {
+                let ips = self
+                    .dns
+                    .get(host)
+                    .ok_or_else(|| anyhow!("no DNS fixture defined for host {host}"))?;
+                serde_json::to_value(LookupResponse { ips: ips.clone() })?
+            }
+            CallbackRequestType::DNSReverseLookup { address } => {
+                let hostnames = self
+                    .dns_reverse
+                    .get(address)
+                    .ok_or_else(|| anyhow!("no DNS fixture defined for address {address}"))?;
+                serde_json::to_value(ReverseLookupResponse {
+                    hostnames: hostnames.clone(),
+                })?
+            }
+            CallbackRequestType::DNSLookupSrv { name } => {
+                let records = self
+                    .dns_srv
+                    .get(name)
+                    .ok_or_else(|| anyhow!("no DNS SRV fixture defined for {name}"))?;
+                serde_json::to_value(SrvLookupResponse {
+                    records: records.clone(),
+                })?
+            }
+            CallbackRequestType::DNSLookupTxt { name } => {
+                let records = self
+                    .dns_txt
+                    .get(name)
+                    .ok_or_else(|| anyhow!("no DNS TXT fixture defined for {name}"))?;
+                serde_json::to_value(TxtLookupResponse {
+                    records: records.clone(),
+                })?
+            }
+            CallbackRequestType::KubernetesListResourceNamespace {
+                api_version,
+                kind,
+                namespace,
+                label_selector,
+                field_selector,
+                fields,
+            } => serde_json::to_value(self.list_resources(
+                api_version,
+                kind,
+                Some(namespace),
+                label_selector.as_deref(),
+                field_selector.as_deref(),
+                fields.as_deref(),
+            )?)?,
+            CallbackRequestType::KubernetesListResourceAll {
+                api_version,
+                kind,
+                label_selector,
+                field_selector,
+                fields,
+            } => serde_json::to_value(self.list_resources(
+                api_version,
+                kind,
+                None,
+                label_selector.as_deref(),
+                field_selector.as_deref(),
+                fields.as_deref(),
+            )?)?,
+            CallbackRequestType::KubernetesGetResource {
+                api_version,
+                kind,
+                name,
+                namespace,
+                fields,
+                ..
+            } => {
+                let projection = fields.as_deref().map(Projection::new).transpose()?;
+                let mut object = self
+                    .kubernetes
+                    .resources
+                    .iter()
+                    .find(|obj| {
+                        is_kind(obj, api_version, kind)
+                            && obj.name_any() == *name
+                            && obj.namespace() == *namespace
+                    })
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "Cannot find {api_version}/{kind} named '{name}' inside of namespace '{namespace:?}'"
+                        )
+                    })?
+                    .clone();
+                if let Some(projection) = projection {
+                    projection.apply_to_object(&mut object);
+                }
+                serde_json::to_value(object)?
+            }
+            CallbackRequestType::KubernetesGetResourcePluralName { api_version, kind } => {
+                serde_json::to_value(self.plural_name(api_version, kind)?)?
+            }
+            CallbackRequestType::HasKubernetesListResourceAllResultChangedSinceInstant {
+                since,
+                ..
+            } => serde_json::to_value(self.loaded_at > *since)?,
+            CallbackRequestType::KubernetesCanI { request, .. } => serde_json::to_value(
+                &self
+                    .kubernetes
+                    .can_i
+                    .iter()
+                    .find(|fixture| fixture.request == *request)
+                    .ok_or_else(|| anyhow!("no can_i fixture defined for request {request:?}"))?
+                    .status,
+            )?,
+            CallbackRequestType::CryptoIsCertificateTrusted { .. }
+            | CallbackRequestType::CryptoIsCertificateTrustedV2 { .. }
+            | CallbackRequestType::CryptoVerifySignature { .. }
+            | CallbackRequestType::CryptoDigest { .. } => {
+                return Err(anyhow!("crypto requests are not answered by fixtures"));
+            }
+        };
+
+        serde_json::to_vec(&payload).map_err(|e| anyhow!("error serializing payload: {e:?}"))
+    }
+
+    fn oci_fixture(&self, image: &str) -> Result<&OciFixture> {
+        self.oci
+            .iter()
+            .find(|fixture| fixture.image == image)
+            .ok_or_else(|| anyhow!("no OCI fixture defined for image {image}"))
+    }
+
+    fn sigstore_verification(&self, image: &str) -> Result<VerificationResponse> {
+        let fixture = self
+            .sigstore
+            .iter()
+            .find(|fixture| fixture.image == image)
+            .ok_or_else(|| anyhow!("no Sigstore fixture defined for image {image}"))?;
+        if !fixture.trusted {
+            return Err(anyhow!("Image verification failed: no signatures found"));
+        }
+
+        let digest = match &fixture.digest {
+            Some(digest) => digest.clone(),
+            None => self.oci_fixture(image)?.digest.clone(),
+        };
+
+        Ok(VerificationResponse {
+            is_trusted: true,
+            digest,
+        })
+    }
+
+    fn attestation_verification(
+        &self,
+        image: &str,
+        predicate_type: &str,
+    ) -> Result<AttestationVerificationResponse> {
+        let verification = self.sigstore_verification(image)?;
+        let predicate = self
+            .sigstore
+            .iter()
+            .find(|fixture| fixture.image == image)
+            .and_then(|fixture| fixture.attestations.get(predicate_type))
+            .ok_or_else(|| {
+                anyhow!("no attestation of type {predicate_type} defined for image {image}")
+            })?;
+
+        Ok(AttestationVerificationResponse {
+            is_trusted: true,
+            digest: verification.digest,
+            predicate_type: predicate_type.to_string(),
+            predicate: predicate.clone(),
+        })
+    }
+
+    fn list_resources(
+        &self,
+        api_version: &str,
+        kind: &str,
+        namespace: Option<&str>,
+        label_selector: Option<&str>,
+        field_selector: Option<&str>,
+        fields: Option<&[String]>,
+    ) -> Result<ObjectList<DynamicObject>> {
+        let label_selector = label_selector.map(LabelSelector::parse).transpose()?;
+        let field_selector = field_selector.map(FieldSelector::parse).transpose()?;
+        let projection = fields.map(Projection::new).transpose()?;
+
+        let mut items = Vec::new();
+        for obj in self
+            .kubernetes
+            .resources
+            .iter()
+            .filter(|obj| is_kind(obj, api_version, kind))
+        {
+            if let Some(namespace) = namespace {
+                if obj.namespace().is_none() {
+                    return Err(anyhow!(
+                        "resource {api_version}/{kind} is cluster wide. Cannot search for it inside of a namespace"
+                    ));
+                }
+                if obj.namespace().as_deref() != Some(namespace) {
+                    continue;
+                }
+            }
+            if label_selector
+                .as_ref()
+                .is_some_and(|selector| !selector.matches(obj.labels()))
+            {
+                continue;
+            }
+            let fields_match = match &field_selector {
+                Some(selector) => selector.matches(&serde_json::to_value(obj)?),
+                None => true,
+            };
+            if !fields_match {
+                continue;
+            }
+            let mut obj = obj.clone();
+            if let Some(projection) = &projection {
+                projection.apply_to_object(&mut obj);
+            }
+            items.push(obj);
+        }
+
+        Ok(ObjectList {
+            types: kube::core::TypeMeta {
+                api_version: api_version.to_owned(),
+                kind: format!("{kind}List"),
+            },
+            metadata: Default::default(),
+            items,
+        })
+    }
+
+    fn plural_name(&self, api_version: &str, kind: &str) -> Result<String> {
+        if let Some(plural) = self
+            .kubernetes
+            .plural_names
+            .get(&format!("{api_version}/{kind}"))
+        {
+            return Ok(plural.clone());
+        }
+
+        let (group, version) = match api_version.split_once('/') {
+            Some((group, version)) => (group, version),
+            None => ("", api_version),
+        };
+        let gvk = GroupVersionKind::gvk(group, version, kind);
+        Ok(kube::api::ApiResource::from_gvk(&gvk).plural)
+    }
+}
+
+fn is_kind(obj: &DynamicObject, api_version: &str, kind: &str) -> bool {
+    obj.types
+        .as_ref()
+        .is_some_and(|types| types.api_version == api_version && types.kind == kind)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::callback_handler::{AttestationVerificationRequest, OciReferrersRequest};
+    use rstest::rstest;
+
+    const FIXTURES: &str = r#"
+kubernetes:
+  resources:
+    - apiVersion: v1
+      kind: Namespace
+      metadata:
+        name: default
+    - apiVersion: v1
+      kind: Service
+      metadata:
+        name: api
+        namespace: default
+        labels:
+          app: api
+    - apiVersion: v1
+      kind: Service
+      metadata:
+        name: db
+        namespace: default
+        labels:
+          app: db
+    - apiVersion: v1
+      kind: Service
+      metadata:
+        name: api
+        namespace: other
+        labels:
+          app: api
+oci:
+  - image: ghcr.io/kubewarden/policy:v1
+    digest: sha256:1234
+    referrers:
+      - mediaType: application/vnd.oci.image.manifest.v1+json
+        artifactType: application/spdx+json
+        digest: sha256:5678
+        size: 512
+      - mediaType: application/vnd.oci.image.manifest.v1+json
+        artifactType: application/vnd.dev.sigstore.bundle.v0.3+json
+        digest: sha256:9abc
+        size: 1024
+sigstore:
+  - image: ghcr.io/kubewarden/policy:v1
+    trusted: true
+    attestations:
+      https://slsa.dev/provenance/v1:
+        buildDefinition:
+          buildType: https://actions.github.io/buildtypes/workflow/v1
+  - image: ghcr.io/kubewarden/untrusted:v1
+    trusted: false
+dns:
+  kubewarden.io:
+    - 127.0.0.1
+dnsReverse:
+  127.0.0.1:
+    - localhost
+dnsSrv:
+  _ldap._tcp.kubewarden.io:
+    - priority: 10
+      weight: 5
+      port: 389
+      target: ldap.kubewarden.io
+dnsTxt:
+  kubewarden.io:
+    - v=spf1 -all
+"#;
+
+    fn fixtures() -> CallbackFixtures {
+        serde_yaml::from_str(FIXTURES).expect("cannot parse fixtures")
+    }
+
+    fn list_names(payload: &[u8]) -> Vec<String> {
+        let list: ObjectList<DynamicObject> = serde_json::from_slice(payload).unwrap();
+        list.items
+            .iter()
+            .map(|obj| format!("{}/{}", obj.namespace().unwrap_or_default(), obj.name_any()))
+            .collect()
+    }
+
+    #[rstest]
+    #[case::all(None, None, None, vec!["default/api", "default/db", "other/api"])]
+    #[case::namespace(Some("default"), None, None, vec!["default/api", "default/db"])]
+    #[case::label_selector(None, Some("app=api"), None, vec!["default/api", "other/api"])]
+    #[case::field_selector(
+        Some("default"),
+        None,
+        Some("metadata.name!=api"),
+        vec!["default/db"]
+    )]
+    fn list_resources(
+        #[case] namespace: Option<&str>,
+        #[case] label_selector: Option<&str>,
+        #[case] field_selector: Option<&str>,
+        #[case] expected: Vec<&str>,
+    ) {
+        let request = match namespace {
+            Some(namespace) => CallbackRequestType::KubernetesListResourceNamespace {
+                api_version: "v1".to_string(),
+                kind: "Service".to_string(),
+                namespace: namespace.to_string(),
+                label_selector: label_selector.map(str::to_string),
+                field_selector: field_selector.map(str::to_string),
+                fields: None,
+            },
+            None => CallbackRequestType::KubernetesListResourceAll {
+                api_version: "v1".to_string(),
+                kind: "Service".to_string(),
+                label_selector: label_selector.map(str::to_string),
+                field_selector: field_selector.map(str::to_string),
+                fields: None,
+            },
+        };
+
+        let payload = fixtures().respond(&request).expect("cannot list resources");
+        assert_eq!(list_names(&payload), expected);
+    }
+
+    #[test]
+    fn list_cluster_wide_resources_inside_of_namespace() {
+        let request = CallbackRequestType::KubernetesListResourceNamespace {
+            api_version: "v1".to_string(),
+            kind: "Namespace".to_string(),
+            namespace: "default".to_string(),
+            label_selector: None,
+            field_selector: None,
+            fields: None,
+        };
+        assert!(fixtures().respond(&request).is_err());
+    }
+
+    #[rstest]
+    #[case::namespaced("api", Some("other"), true)]
+    #[case::cluster_wide_requested_with_namespace("default", Some("default"), false)]
+    #[case::not_found("frontend", Some("default"), false)]
+    fn get_resource(#[case] name: &str, #[case] namespace: Option<&str>, #[case] found: bool) {
+        let request = CallbackRequestType::KubernetesGetResource {
+            api_version: "v1".to_string(),
+            kind: if name == "default" {
+                "Namespace"
+            } else {
+                "Service"
+            }
+            .to_string(),
+            name: name.to_string(),
+            namespace: namespace.map(str::to_string),
+            disable_cache: false,
+            fields: None,
+        };
+        assert_eq!(fixtures().respond(&request).is_ok(), found);
+    }
+
+    #[test]
+    fn objects_retain_the_fields_needed_by_the_policy() {
+        let request = CallbackRequestType::KubernetesListResourceNamespace {
+            api_version: "v1".to_string(),
+            kind: "Service".to_string(),
+            namespace: "default".to_string(),
+            label_selector: None,
+            field_selector: None,
+            fields: Some(vec!["spec.type".to_string()]),
+        };
+
+        let payload = fixtures().respond(&request).unwrap();
+        let list: serde_json::Value = serde_json::from_slice(&payload).unwrap();
+        let items = list["items"].as_array().unwrap();
+        assert_eq!(items.len(), 2);
+        for item in items {
+            assert!(item["metadata"]["name"].is_string());
+            assert!(
+                item["metadata"].get("labels").is_none(),
+                "unexpected fields: {item}"
+            );
+        }
+    }
+
+    #[rstest]
+    #[case::explicit("v1", "Endpoints", "endpoints")]
+    #[case::guessed("apps/v1", "Deployment", "deployments")]
+    fn plural_name(#[case] api_version: &str, #[case] kind: &str, #[case] expected: &str) {
+        let mut fixtures = fixtures();
+        fixtures
+            .kubernetes
+            .plural_names
+            .insert("v1/Endpoints".to_string(), "endpoints".to_string());
+
+        let payload = fixtures
+            .respond(&CallbackRequestType::KubernetesGetResourcePluralName {
+                api_version: api_version.to_string(),
+                kind: kind.to_string(),
+            })
+            .unwrap();
+        assert_eq!(
+            serde_json::from_slice::<String>(&payload).unwrap(),
+            expected
+        );
+    }
+
+    #[rstest]
+    #[case::trusted("ghcr.io/kubewarden/policy:v1", true)]
+    #[case::untrusted("ghcr.io/kubewarden/untrusted:v1", false)]
+    #[case::unknown("ghcr.io/kubewarden/unknown:v1", false)]
+    fn sigstore_verification(#[case] image: &str, #[case] trusted: bool) {
+        let request = CallbackRequestType::SigstorePubKeyVerify {
+            image: image.to_string(),
+            pub_keys: vec![],
+            annotations: None,
+        };
+
+        match fixtures().respond(&request) {
+            Ok(payload) => {
+                assert!(trusted);
+                let response: VerificationResponse = serde_json::from_slice(&payload).unwrap();
+                assert!(response.is_trusted);
+                assert_eq!(response.digest, "sha256:1234");
+            }
+            Err(_) => assert!(!trusted),
+        }
+    }
+
+    #[rstest]
+    #[case::all(None, vec!["sha256:5678", "sha256:9abc"])]
+    #[case::artifact_type(Some("application/spdx+json"), vec!["sha256:5678"])]
+    #[case::no_match(Some("application/vnd.cyclonedx+json"), vec![])]
+    fn oci_referrers(#[case] artifact_type: Option<&str>, #[case] expected: Vec<&str>) {
+        let request = CallbackRequestType::OciReferrers {
+            request: OciReferrersRequest {
+                image: "ghcr.io/kubewarden/policy:v1".to_string(),
+                artifact_type: artifact_type.map(str::to_string),
+            },
+        };
+
+        let payload = fixtures().respond(&request).unwrap();
+        let response: OciReferrersResponse = serde_json::from_slice(&payload).unwrap();
+        assert_eq!(response.digest, "sha256:1234");
+        assert_eq!(
+            response
+                .referrers
+                .iter()
+                .map(|referrer| referrer.digest.as_str())
+                .collect::<Vec<_>>(),
+            expected
+        );
+    }
+
+    #[rstest]
+    #[case::trusted("ghcr.io/kubewarden/policy:v1", "https://slsa.dev/provenance/v1", true)]
+    #[case::unknown_predicate("ghcr.io/kubewarden/policy:v1", "https://spdx.dev/Document", false)]
+    #[case::untrusted(
+        "ghcr.io/kubewarden/untrusted:v1",
+        "https://slsa.dev/provenance/v1",
+        false
+    )]
+    fn attestation_verification(
+        #[case] image: &str,
+        #[case] predicate_type: &str,
+        #[case] trusted: bool,
+    ) {
+        let request = CallbackRequestType::SigstoreAttestationVerify {
+            request: AttestationVerificationRequest {
+                image: image.to_string(),
+                predicate_type: predicate_type.to_string(),
+                pub_keys: vec![],
+            },
+        };
+
+        match fixtures().respond(&request) {
+            Ok(payload) => {
+                assert!(trusted);
+                let response: AttestationVerificationResponse =
+                    serde_json::from_slice(&payload).unwrap();
+                assert!(response.is_trusted);
+                assert_eq!(response.digest, "sha256:1234");
+                assert_eq!(
+                    response.predicate["buildDefinition"]["buildType"],
+                    "https://actions.github.io/buildtypes/workflow/v1"
+                );
+            }
+            Err(_) => assert!(!trusted),
+        }
+    }
+
+    #[test]
+    fn dns_lookup() {
+        let fixtures = fixtures();
+        let payload = fixtures
+            .respond(&CallbackRequestType::DNSLookupHost {
+                host: "kubewarden.io".to_string(),
+            })
+            .unwrap();
+        let response: LookupResponse = serde_json::from_slice(&payload).unwrap();
+        assert_eq!(response.ips, vec!["127.0.0.1".to_string()]);
+
+        assert!(
+            fixtures
+                .respond(&CallbackRequestType::DNSLookupHost {
+                    host: "example.com".to_string(),
+                })
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn dns_reverse_srv_and_txt_lookups() {
+        let fixtures = fixtures();
+
+        let payload = fixtures
+            .respond(&CallbackRequestType::DNSReverseLookup {
+                address: "127.0.0.1".to_string(),
+            })
+            .unwrap();
+        let response: ReverseLookupResponse = serde_json::from_slice(&payload).unwrap();
+        assert_eq!(response.hostnames, vec!["localhost".to_string()]);
+
+        let payload = fixtures
+            .respond(&CallbackRequestType::DNSLookupSrv {
+                name: "_ldap._tcp.kubewarden.io".to_string(),
+            })
+            .unwrap();
+        let response: SrvLookupResponse = serde_json::from_slice(&payload).unwrap();
+        assert_eq!(
+            response.records,
+            vec![SrvRecord {
+                priority: 10,
+                weight: 5,
+                port: 389,
+                target: "ldap.kubewarden.io".to_string(),
+            }]
+        );
+
+        let payload = fixtures
+            .respond(&CallbackRequestType::DNSLookupTxt {
+                name: "kubewarden.io".to_string(),
+            })
+            .unwrap();
+        let response: TxtLookupResponse = serde_json::from_slice(&payload).unwrap();
+        assert_eq!(response.records, vec!["v=spf1 -all".to_string()]);
+
+        assert!(
+            fixtures
+                .respond(&CallbackRequestType::DNSLookupTxt {
+                    name: "example.com".to_string(),
+                })
+                .is_err()
+        );
+    }
+}
@@ -1,22 +1,22 @@
-use std::time::Duration;
-
 mod client;
+mod discovery;
+mod projection;
 mod reflector;
+pub(crate) mod selectors;
 
 use anyhow::{Result, anyhow};
-use cached::proc_macro::cached;
 use k8s_openapi::api::authorization::v1::SubjectAccessReviewStatus;
 use kube::core::ObjectList;
 use kubewarden_policy_sdk::host_capabilities::kubernetes::SubjectAccessReview as KWSubjectAccessReview;
 use serde::Serialize;
 
-pub(crate) use client::Client;
+use super::cache::Cache;
 
-#[derive(Eq, Hash, PartialEq)]
-struct ApiVersionKind {
-    api_version: String,
-    kind: String,
-}
+pub(crate) use client::Client;
+pub use client::ReflectorsInspector;
+pub use discovery::DiscoveryConfig;
+pub(crate) use projection::{FieldPath, FieldProjections, Projection};
+pub use reflector::{ReflectorConfig, ReflectorInfo, ReflectorMode};
 
 #[derive(Debug, Clone, Serialize)]
 pub(crate) struct KubeResource {
@@ -31,6 +31,7 @@ pub(crate) async fn list_resources_by_namespace(
     namespace: &str,
     label_selector: Option<String>,
     field_selector: Option<String>,
+    fields: Option<Vec<String>>,
 ) -> Result<cached::Return<ObjectList<kube::core::DynamicObject>>> {
     if client.is_none() {
         return Err(anyhow!("kube::Client was not initialized properly")).map(cached::Return::new);
@@ -38,7 +39,14 @@ pub(crate) async fn list_resources_by_namespace(
 
     client
         .unwrap()
-        .list_resources_by_namespace(api_version, kind, namespace, label_selector, field_selector)
+        .list_resources_by_namespace(
+            api_version,
+            kind,
+            namespace,
+            label_selector,
+            field_selector,
+            fields,
+        )
         .await
         .map(cached::Return::new)
 }
@@ -49,6 +57,7 @@ pub(crate) async fn list_resources_all(
     kind: &str,
     label_selector: Option<String>,
     field_selector: Option<String>,
+    fields: Option<Vec<String>>,
 ) -> Result<cached::Return<ObjectList<kube::core::DynamicObject>>> {
     if client.is_none() {
         return Err(anyhow!("kube::Client was not initialized properly")).map(cached::Return::new);
@@ -56,7 +65,7 @@ pub(crate) async fn list_resources_all(
 
     client
         .unwrap()
-        .list_resources_all(api_version, kind, label_selector, field_selector)
+        .list_resources_all(api_version, kind, label_selector, field_selector, fields)
         .await
         .map(cached::Return::new)
 }
@@ -67,6 +76,7 @@ pub(crate) async fn get_resource(
     kind: &str,
     name: &str,
     namespace: Option<&str>,
+    fields: Option<&[String]>,
 ) -> Result<cached::Return<kube::core::DynamicObject>> {
     if client.is_none() {
         return Err(anyhow!("kube::Client was not initialized properly"));
@@ -74,7 +84,7 @@ pub(crate) async fn get_resource(
 
     client
         .unwrap()
-        .get_resource(api_version, kind, name, namespace)
+        .get_resource(api_version, kind, name, namespace, fields)
         .await
         .map(|value| cached::Return {
             was_cached: false,
@@ -82,22 +92,23 @@ pub(crate) async fn get_resource(
         })
 }
 
-#[cached(
-    time = 5,
-    result = true,
-    sync_writes = "default",
-    key = "String",
-    convert = r#"{ format!("get_resource_cached({},{}),{},{:?}", api_version, kind, name, namespace) }"#,
-    with_cached_flag = true
-)]
 pub(crate) async fn get_resource_cached(
     client: Option<&mut Client>,
+    cache: &Cache<kube::core::DynamicObject>,
     api_version: &str,
     kind: &str,
     name: &str,
     namespace: Option<&str>,
+    fields: Option<&[String]>,
 ) -> Result<cached::Return<kube::core::DynamicObject>> {
-    get_resource(client, api_version, kind, name, namespace).await
+    let key = format!("{api_version}/{kind}/{name}/{namespace:?}/{fields:?}");
+    cache
+        .get_or_insert_with(key, async {
+            get_resource(client, api_version, kind, name, namespace, fields)
+                .await
+                .map(|response| response.value)
+        })
+        .await
 }
 
 pub(crate) async fn get_resource_plural_name(
@@ -113,12 +124,7 @@ pub(crate) async fn get_resource_plural_name(
         .unwrap()
         .get_resource_plural_name(api_version, kind)
         .await
-        .map(|value| cached::Return {
-            // this is always cached, because the client builds an overview of
-            // the cluster resources at bootstrap time
-            was_cached: true,
-            value,
-        })
+        .map(cached::Return::new)
 }
 
 /// Check if the results of the "list all resources" query have changed since the provided instant
@@ -166,21 +172,15 @@ pub(crate) async fn can_i(
         })
 }
 
-#[cached(
-    time = 5,
-    result = true,
-    // We can use the request type as key because cached requires the key to implement Hash + Eq
-    // traits. As we already implement these traits, there is no need to have a custom logic for key
-    // generation. If we do that, we will only convert it into a type (e.g. string)  that
-    // implements the traits as well. 
-    key = "KWSubjectAccessReview",
-    convert = r#"{request.clone()}"#,
-    sync_writes = "default",
-    with_cached_flag = true
-)]
 pub(crate) async fn can_i_cached(
     client: Option<&mut Client>,
+    cache: &Cache<SubjectAccessReviewStatus>,
     request: KWSubjectAccessReview,
 ) -> Result<cached::Return<SubjectAccessReviewStatus>> {
-    can_i(client, request).await
+    let key = serde_json::to_string(&request)?;
+    cache
+        .get_or_insert_with(key, async {
+            can_i(client, request).await.map(|response| response.value)
+        })
+        .await
 }
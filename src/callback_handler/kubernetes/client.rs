@@ -4,128 +4,277 @@ use kube::{
     Api,
     api::PostParams,
     core::{DynamicObject, ObjectList},
+    runtime::reflector::Store,
 };
 use kubewarden_policy_sdk::host_capabilities::kubernetes::SubjectAccessReview as KWSubjectAccessReview;
 use std::{collections::HashMap, sync::Arc};
 use tokio::{sync::RwLock, time::Instant};
+use tracing::info;
+
+use crate::callback_handler::kubernetes::{
+    KubeResource,
+    discovery::{Discovery, DiscoveryConfig},
+    projection::{FieldProjections, Projection},
+    reflector::{
+        ObjectFilter, Reflector, ReflectorConfig, ReflectorInfo, ReflectorMode, ReflectorScope,
+        reflectors_over_objects_limit, reflectors_to_evict,
+    },
+};
 
-use crate::callback_handler::kubernetes::{ApiVersionKind, KubeResource, reflector::Reflector};
+type Reflectors = Arc<RwLock<HashMap<String, Reflector>>>;
+
+/// The id, the last usage time and the number of cached objects of each reflector
+fn reflectors_usage(reflectors: &HashMap<String, Reflector>) -> Vec<(String, Instant, usize)> {
+    reflectors
+        .iter()
+        .map(|(id, reflector)| {
+            (
+                id.clone(),
+                reflector.last_used_at(),
+                reflector.reader.state().len(),
+            )
+        })
+        .collect()
+}
+
+/// Whether the objects reduced by `projection` retain the fields needed by a
+/// policy. `None` stands for the whole objects
+fn retains_needed_fields(projection: Option<&Projection>, needed: Option<&Projection>) -> bool {
+    match (projection, needed) {
+        (None, _) => true,
+        (Some(_), None) => false,
+        (Some(projection), Some(needed)) => projection.covers(needed),
+    }
+}
+
+/// Extend `projection` to retain also the fields needed by a policy. `None`
+/// stands for the whole objects
+fn widen_projection(
+    projection: Option<Projection>,
+    needed: Option<&Projection>,
+) -> Option<Projection> {
+    match (projection, needed) {
+        (Some(projection), Some(needed)) => Some(projection.union(needed)),
+        _ => None,
+    }
+}
 
 #[derive(Clone)]
 pub(crate) struct Client {
     kube_client: kube::Client,
-    kube_resources: Arc<RwLock<HashMap<ApiVersionKind, KubeResource>>>,
-    reflectors: Arc<RwLock<HashMap<String, Reflector>>>,
+    discovery: Arc<Discovery>,
+    reflectors: Reflectors,
+    reflector_config: ReflectorConfig,
+    projections: Arc<FieldProjections>,
+}
+
+/// Provides read-only access to the reflectors used to answer the
+/// Kubernetes queries made by the policies
+#[derive(Clone)]
+pub struct ReflectorsInspector {
+    reflectors: Reflectors,
+}
+
+impl ReflectorsInspector {
+    /// Details about all the reflectors currently running
+    pub async fn reflectors(&self) -> Vec<ReflectorInfo> {
+        let reflectors = self.reflectors.read().await;
+        let mut infos = Vec::with_capacity(reflectors.len());
+        for reflector in reflectors.values() {
+            infos.push(reflector.info().await);
+        }
+        infos
+    }
+
+    /// Total number of objects cached by the reflectors
+    pub async fn cached_objects(&self) -> usize {
+        self.reflectors
+            .read()
+            .await
+            .values()
+            .map(|reflector| reflector.reader.state().len())
+            .sum()
+    }
 }
 
 impl Client {
-    pub fn new(client: kube::Client) -> Self {
+    pub fn new(
+        client: kube::Client,
+        reflector_config: ReflectorConfig,
+        projections: FieldProjections,
+        discovery_config: DiscoveryConfig,
+    ) -> Self {
         Self {
+            discovery: Arc::new(Discovery::new(client.clone(), discovery_config)),
             kube_client: client,
-            kube_resources: Arc::new(RwLock::new(HashMap::new())),
             reflectors: Arc::new(RwLock::new(HashMap::new())),
+            reflector_config,
+            projections: Arc::new(projections),
         }
     }
 
-    /// Build a KubeResource using the apiVersion and Kind "coordinates" provided.
-    /// The result is then cached locally to avoid further interactions with
-    /// the Kubernetes API Server
-    async fn build_kube_resource(&mut self, api_version: &str, kind: &str) -> Result<KubeResource> {
-        let avk = ApiVersionKind {
-            api_version: api_version.to_owned(),
-            kind: kind.to_owned(),
-        };
-
-        // take a reader lock and search for the resource inside of the
-        // known resources
-        let kube_resource = {
-            let known_resources = self.kube_resources.read().await;
-            known_resources.get(&avk).map(|r| r.to_owned())
-        };
-        if let Some(kr) = kube_resource {
-            return Ok(kr);
+    pub fn reflectors_inspector(&self) -> ReflectorsInspector {
+        ReflectorsInspector {
+            reflectors: self.reflectors.clone(),
         }
+    }
 
-        // the resource is not known yet, we have to search it
-        let resources_list = match api_version {
-            "v1" => {
-                self.kube_client
-                    .list_core_api_resources(api_version)
-                    .await?
+    /// Stop the reflectors that have not been used for longer than the
+    /// configured idle timeout, and the ones that stopped watching because they
+    /// listed too many objects. Then, when the watched objects grew past the
+    /// maximum number of cached objects, stop the least recently used reflectors
+    pub async fn evict_idle_reflectors(&self) {
+        let mut reflectors = self.reflectors.write().await;
+        reflectors.retain(|id, reflector| {
+            let over_objects_limit = reflector.over_objects_limit();
+            if over_objects_limit {
+                info!(
+                    reflector = id.as_str(),
+                    "evicting reflector, too many objects listed"
+                );
             }
-            _ => self
-                .kube_client
-                .list_api_group_resources(api_version)
-                .await
-                .map_err(|e| anyhow!("error finding resource {api_version} / {kind}: {e}"))?,
-        };
+            !over_objects_limit
+        });
+        if let Some(idle_timeout) = self.reflector_config.idle_timeout {
+            reflectors.retain(|id, reflector| {
+                let idle = reflector.last_used_at().elapsed() > idle_timeout;
+                if idle {
+                    info!(reflector = id.as_str(), "evicting idle reflector");
+                }
+                !idle
+            });
+        }
 
-        let resource = resources_list
-            .resources
-            .iter()
-            .find(|r| r.kind == kind)
-            .ok_or_else(|| anyhow!("Cannot find resource {api_version}/{kind}"))?
-            .to_owned();
-
-        let (group, version) = match api_version {
-            "v1" => ("", "v1"),
-            _ => api_version
-                .split_once('/')
-                .ok_or_else(|| anyhow!("cannot determine group and version for {api_version}"))?,
-        };
+        for id in
+            reflectors_over_objects_limit(reflectors_usage(&reflectors), &self.reflector_config)
+        {
+            info!(
+                reflector = id.as_str(),
+                "evicting least recently used reflector, too many objects cached"
+            );
+            reflectors.remove(&id);
+        }
+    }
 
-        let kube_resource = KubeResource {
-            resource: kube::api::ApiResource {
-                group: group.to_owned(),
-                version: version.to_owned(),
-                api_version: api_version.to_owned(),
-                kind: kind.to_owned(),
-                plural: resource.name,
-            },
-            namespaced: resource.namespaced,
-        };
+    /// Discover again the Kubernetes resources in the background, when the
+    /// configured refresh interval has elapsed
+    pub fn refresh_discovery(&self) {
+        let discovery = self.discovery.clone();
+        tokio::spawn(async move { discovery.refresh_if_needed().await });
+    }
+
+    /// Build a KubeResource using the apiVersion and Kind "coordinates" provided.
+    /// `api_version` can also be just the name of an API group, in which case
+    /// the preferred version of the group is used
+    async fn build_kube_resource(&mut self, api_version: &str, kind: &str) -> Result<KubeResource> {
+        self.discovery.resolve(api_version, kind).await
+    }
 
-        // Take a writer lock and cache the resource we just found
-        let mut known_resources = self.kube_resources.write().await;
-        known_resources.insert(avk, kube_resource.clone());
+    /// Find the reflector that can answer the query, either because it watches
+    /// exactly the requested objects, or because it watches a superset of them.
+    /// In the latter case, the returned filter must be applied to the cached objects.
+    /// The cached objects must retain the fields selected by `needed_projection`
+    async fn find_reflector_reader(
+        &self,
+        resource: &KubeResource,
+        scope: &ReflectorScope,
+        needed_projection: Option<&Projection>,
+    ) -> Option<(Store<DynamicObject>, ObjectFilter)> {
+        let reflectors = self.reflectors.read().await;
+        if let Some(reflector) = reflectors.get(&Reflector::compute_id(resource, scope))
+            && retains_needed_fields(reflector.projection.as_ref(), needed_projection)
+        {
+            reflector.touch();
+            return Some((reflector.reader.clone(), ObjectFilter::default()));
+        }
 
-        Ok(kube_resource)
+        reflectors
+            .values()
+            .filter(|reflector| {
+                reflector.api_version == resource.resource.api_version
+                    && reflector.kind == resource.resource.kind
+                    && retains_needed_fields(reflector.projection.as_ref(), needed_projection)
+            })
+            .find_map(|reflector| {
+                reflector
+                    .scope
+                    .filter_for(scope, reflector.projection.as_ref())
+                    .map(|filter| {
+                        reflector.touch();
+                        (reflector.reader.clone(), filter)
+                    })
+            })
     }
 
+    /// Get the reflector answering the query, creating it when needed. The cached
+    /// objects retain at least the fields selected by `needed_projection`, which
+    /// are the ones needed by the policy making the query
     async fn get_reflector_reader(
         &mut self,
-        reflector_id: &str,
         resource: KubeResource,
-        namespace: Option<String>,
-        label_selector: Option<String>,
-        field_selector: Option<String>,
-    ) -> Result<kube::runtime::reflector::Store<kube::core::DynamicObject>> {
-        let reader = {
-            let reflectors = self.reflectors.read().await;
-            reflectors
-                .get(reflector_id)
-                .map(|reflector| reflector.reader.clone())
-        };
-        if let Some(reader) = reader {
-            return Ok(reader);
+        scope: ReflectorScope,
+        needed_projection: Option<&Projection>,
+    ) -> Result<(Store<DynamicObject>, ObjectFilter)> {
+        if let Some(found) = self
+            .find_reflector_reader(&resource, &scope, needed_projection)
+            .await
+        {
+            return Ok(found);
+        }
+
+        // the fields declared via the builder might not include the ones needed
+        // by the policy, for example when it has not been declared
+        let mut projection = widen_projection(
+            self.projections
+                .get(&resource.resource.api_version, &resource.resource.kind)
+                .cloned(),
+            needed_projection,
+        );
+        if self.reflector_config.mode == ReflectorMode::PerKind {
+            // the label selectors of the queries served by a shared reflector
+            // are evaluated against the cached objects
+            projection = projection.map(Projection::with_labels);
         }
 
+        let reflector_scope = scope.for_mode(self.reflector_config.mode, projection.as_ref());
+        let reflector_id = Reflector::compute_id(&resource, &reflector_scope);
+        // a reflector watching the same objects, without retaining all the needed
+        // fields, is replaced by one retaining the fields of both
+        if let Some(replaced) = self.reflectors.read().await.get(&reflector_id) {
+            projection = widen_projection(projection, replaced.projection.as_ref());
+        }
+        let filter = reflector_scope
+            .filter_for(&scope, projection.as_ref())
+            .unwrap_or_default();
+
         let reflector = Reflector::create_and_run(
             self.kube_client.clone(),
             resource,
-            namespace,
-            label_selector,
-            field_selector,
+            reflector_scope.namespace,
+            reflector_scope.label_selector,
+            reflector_scope.field_selector,
+            projection,
+            self.reflector_config.max_cached_objects,
         )
         .await?;
         let reader = reflector.reader.clone();
 
         {
             let mut reflectors = self.reflectors.write().await;
-            reflectors.insert(reflector_id.to_string(), reflector);
+            reflectors.remove(&reflector_id);
+            let usage = reflectors_usage(&reflectors);
+            // the new reflector is dropped, hence stopped, when it cannot be cached
+            for id in reflectors_to_evict(usage, &self.reflector_config, reader.state().len())? {
+                info!(
+                    reflector = id.as_str(),
+                    "evicting least recently used reflector"
+                );
+                reflectors.remove(&id);
+            }
+            reflectors.insert(reflector_id, reflector);
         }
 
-        Ok(reader)
+        Ok((reader, filter))
     }
 
     pub async fn list_resources_by_namespace(
@@ -135,6 +284,7 @@ impl Client {
         namespace: &str,
         label_selector: Option<String>,
         field_selector: Option<String>,
+        fields: Option<Vec<String>>,
     ) -> Result<ObjectList<kube::core::DynamicObject>> {
         let resource = self.build_kube_resource(api_version, kind).await?;
         if !resource.namespaced {
@@ -148,6 +298,7 @@ impl Client {
             Some(namespace.to_owned()),
             label_selector,
             field_selector,
+            fields,
         )
         .await
     }
@@ -158,10 +309,11 @@ impl Client {
         kind: &str,
         label_selector: Option<String>,
         field_selector: Option<String>,
+        fields: Option<Vec<String>>,
     ) -> Result<ObjectList<kube::core::DynamicObject>> {
         let resource = self.build_kube_resource(api_version, kind).await?;
 
-        self.list_resources_from_reflector(resource, None, label_selector, field_selector)
+        self.list_resources_from_reflector(resource, None, label_selector, field_selector, fields)
             .await
     }
 
@@ -186,31 +338,28 @@ impl Client {
             .await)
     }
 
+    /// List the objects via a reflector. When `fields` is provided, the returned
+    /// objects retain only them, even when the reflector retains more fields
     async fn list_resources_from_reflector(
         &mut self,
         resource: KubeResource,
         namespace: Option<String>,
         label_selector: Option<String>,
         field_selector: Option<String>,
+        fields: Option<Vec<String>>,
     ) -> Result<ObjectList<kube::core::DynamicObject>> {
+        let projection = fields.as_deref().map(Projection::new).transpose()?;
         let api_version = resource.resource.api_version.clone();
         let kind = resource.resource.kind.clone();
 
-        let reflector_id = Reflector::compute_id(
-            &resource,
-            namespace.as_deref(),
-            label_selector.as_deref(),
-            field_selector.as_deref(),
-        );
+        let scope = ReflectorScope {
+            namespace,
+            label_selector,
+            field_selector,
+        };
 
-        let reader = self
-            .get_reflector_reader(
-                &reflector_id,
-                resource,
-                namespace,
-                label_selector,
-                field_selector,
-            )
+        let (reader, filter) = self
+            .get_reflector_reader(resource, scope, projection.as_ref())
             .await?;
 
         Ok(ObjectList {
@@ -222,7 +371,14 @@ impl Client {
             items: reader
                 .state()
                 .iter()
-                .map(|v| DynamicObject::clone(v))
+                .filter(|v| filter.matches(v))
+                .map(|v| {
+                    let mut object = DynamicObject::clone(v);
+                    if let Some(projection) = &projection {
+                        projection.apply_to_object(&mut object);
+                    }
+                    object
+                })
                 .collect(),
         })
     }
@@ -236,17 +392,33 @@ impl Client {
         field_selector: Option<String>,
         since: Instant,
     ) -> bool {
-        let reflector_id = Reflector::compute_id(
-            resource,
-            namespace.as_deref(),
-            label_selector.as_deref(),
-            field_selector.as_deref(),
-        );
+        let scope = ReflectorScope {
+            namespace,
+            label_selector,
+            field_selector,
+        };
 
+        // A reflector watching a superset of the objects could have seen changes
+        // that do not affect the query, hence this can lead to false positives
         let last_change_seen_at = {
             let reflectors = self.reflectors.read().await;
-            match reflectors.get(&reflector_id) {
-                Some(reflector) => reflector.last_change_seen_at().await,
+            let reflector = reflectors
+                .get(&Reflector::compute_id(resource, &scope))
+                .or_else(|| {
+                    reflectors.values().find(|reflector| {
+                        reflector.api_version == resource.resource.api_version
+                            && reflector.kind == resource.resource.kind
+                            && reflector
+                                .scope
+                                .filter_for(&scope, reflector.projection.as_ref())
+                                .is_some()
+                    })
+                });
+            match reflector {
+                Some(reflector) => {
+                    reflector.touch();
+                    reflector.last_change_seen_at().await
+                }
                 None => return true,
             }
         };
@@ -260,7 +432,9 @@ impl Client {
         kind: &str,
         name: &str,
         namespace: Option<&str>,
+        fields: Option<&[String]>,
     ) -> Result<kube::core::DynamicObject> {
+        let projection = fields.map(Projection::new).transpose()?;
         let resource = self.build_kube_resource(api_version, kind).await?;
 
         let api = match resource.namespaced {
@@ -281,10 +455,15 @@ impl Client {
             ),
         };
 
-        api.get_opt(name)
+        let mut object = api.get_opt(name)
             .await
             .map_err(anyhow::Error::new)?
-            .ok_or_else(|| anyhow!("Cannot find {api_version}/{kind} named '{name}' inside of namespace '{namespace:?}'"))
+            .ok_or_else(|| anyhow!("Cannot find {api_version}/{kind} named '{name}' inside of namespace '{namespace:?}'"))?;
+        if let Some(projection) = projection {
+            projection.apply_to_object(&mut object);
+        }
+
+        Ok(object)
     }
 
     pub async fn get_resource_plural_name(
@@ -316,3 +495,32 @@ impl Client {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn projection(fields: &[&str]) -> Option<Projection> {
+        Some(Projection::new(&fields.iter().map(|f| f.to_string()).collect::<Vec<_>>()).unwrap())
+    }
+
+    #[rstest]
+    #[case::whole_objects_cached(None, projection(&["spec"]), true)]
+    #[case::whole_objects_needed(projection(&["spec"]), None, false)]
+    #[case::fields_retained(projection(&["spec"]), projection(&["spec.nodeName"]), true)]
+    #[case::fields_not_retained(projection(&["spec"]), projection(&["status"]), false)]
+    fn needed_fields_retained(
+        #[case] cached: Option<Projection>,
+        #[case] needed: Option<Projection>,
+        #[case] retained: bool,
+    ) {
+        assert_eq!(
+            retains_needed_fields(cached.as_ref(), needed.as_ref()),
+            retained
+        );
+
+        let widened = widen_projection(cached, needed.as_ref());
+        assert!(retains_needed_fields(widened.as_ref(), needed.as_ref()));
+    }
+}
@@ -0,0 +1,534 @@
+use anyhow::{Result, anyhow};
+use futures::StreamExt;
+use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::CustomResourceDefinition;
+use kube::{
+    Api,
+    runtime::{WatchStreamExt, watcher},
+};
+use std::{collections::HashMap, hash::Hash, sync::Arc, time::Duration};
+use tokio::{sync::RwLock, task::JoinHandle, time::Instant};
+use tracing::{debug, info, warn};
+
+use crate::callback_handler::kubernetes::KubeResource;
+
+/// Configuration of the discovery of the resources served by the Kubernetes
+/// API server
+#[derive(Clone, Debug)]
+pub struct DiscoveryConfig {
+    /// How often the discovered resources are refreshed. `None` disables the
+    /// periodic refresh
+    pub refresh_interval: Option<Duration>,
+    /// Watch the CustomResourceDefinitions to forget the resources of the
+    /// groups whose definitions change. The watch is started only once a
+    /// custom resource has been accessed
+    pub watch_crds: bool,
+    /// How long a kind that is not served by its group version, or an API group
+    /// that is not served at all, is remembered as missing. During this time, the
+    /// lookups of the kind fail without querying the API server
+    pub miss_ttl: Duration,
+}
+
+impl Default for DiscoveryConfig {
+    fn default() -> Self {
+        DiscoveryConfig {
+            refresh_interval: Some(Duration::from_secs(600)),
+            watch_crds: true,
+            miss_ttl: Duration::from_secs(30),
+        }
+    }
+}
+
+/// The resources served by a group version, indexed by their kind
+type GroupVersionResources = HashMap<String, KubeResource>;
+
+type DiscoveredResources = Arc<RwLock<HashMap<String, GroupVersionResources>>>;
+
+/// The resources, or the API groups, that could not be found, together with the
+/// time of the lookup
+type Misses<K> = Arc<std::sync::Mutex<HashMap<K, Instant>>>;
+
+/// Resolves apiVersion and kind "coordinates" into the resources served by
+/// the Kubernetes API server.
+///
+/// The resources of a group version are discovered the first time one of
+/// them is looked up. They are discovered again when a kind cannot be found,
+/// periodically, and when the definition of a custom resource of the group changes.
+/// Kinds and API groups that cannot be found are remembered for a while, to not
+/// query the API server on each lookup.
+pub(crate) struct Discovery {
+    kube_client: kube::Client,
+    config: DiscoveryConfig,
+    /// The resources discovered so far, indexed by their group version
+    resources: DiscoveredResources,
+    /// The preferred version of each API group, indexed by the group name
+    preferred_versions: Arc<RwLock<HashMap<String, String>>>,
+    /// The apiVersion and kind of the resources that could not be found
+    misses: Misses<(String, String)>,
+    /// The API groups that could not be found
+    group_misses: Misses<String>,
+    last_refresh: std::sync::Mutex<Instant>,
+    crd_watch: std::sync::Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Drop for Discovery {
+    fn drop(&mut self) {
+        if let Some(crd_watch) = self
+            .crd_watch
+            .get_mut()
+            .expect("discovery lock poisoned")
+            .take()
+        {
+            crd_watch.abort();
+        }
+    }
+}
+
+impl Discovery {
+    pub fn new(kube_client: kube::Client, config: DiscoveryConfig) -> Self {
+        Discovery {
+            kube_client,
+            config,
+            resources: Arc::new(RwLock::new(HashMap::new())),
+            preferred_versions: Arc::new(RwLock::new(HashMap::new())),
+            misses: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            group_misses: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            last_refresh: std::sync::Mutex::new(Instant::now()),
+            crd_watch: std::sync::Mutex::new(None),
+        }
+    }
+
+    /// Find the resource of the given kind. `api_version` can also be the name
+    /// of an API group, like `apps`, in which case the preferred version of
+    /// the group is used
+    pub async fn resolve(&self, api_version: &str, kind: &str) -> Result<KubeResource> {
+        let api_version = self.resolve_api_version(api_version).await?;
+        let miss = (api_version.clone(), kind.to_owned());
+        if self.is_recent_miss(&self.misses, &miss) {
+            return Err(anyhow!("Cannot find resource {api_version}/{kind}"));
+        }
+
+        let cached = {
+            let resources = self.resources.read().await;
+            resources
+                .get(&api_version)
+                .map(|group_version| group_version.get(kind).cloned())
+        };
+        match cached {
+            Some(Some(resource)) => return Ok(resource),
+            Some(None) => debug!(
+                api_version = api_version.as_str(),
+                kind, "kind not found among the discovered resources, refreshing"
+            ),
+            None => {}
+        }
+
+        let group_version = self.discover_group_version(&api_version).await?;
+        let resource = group_version.get(kind).cloned();
+        self.resources
+            .write()
+            .await
+            .insert(api_version.clone(), group_version);
+
+        let (group, _) = split_api_version(&api_version)?;
+        if is_custom_group(group) {
+            self.ensure_crd_watch();
+        }
+
+        resource.ok_or_else(|| {
+            self.misses
+                .lock()
+                .expect("discovery lock poisoned")
+                .insert(miss, Instant::now());
+            anyhow!("Cannot find resource {api_version}/{kind}")
+        })
+    }
+
+    /// Whether the resource, or the API group, has been looked up without success
+    /// recently
+    fn is_recent_miss<K: Eq + Hash>(&self, misses: &Misses<K>, miss: &K) -> bool {
+        let mut misses = misses.lock().expect("discovery lock poisoned");
+        match misses.get(miss) {
+            Some(missed_at) if missed_at.elapsed() < self.config.miss_ttl => true,
+            Some(_) => {
+                misses.remove(miss);
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Discover again the resources of all the group versions known so far,
+    /// when the refresh interval has elapsed since the last refresh
+    pub async fn refresh_if_needed(&self) {
+        let Some(refresh_interval) = self.config.refresh_interval else {
+            return;
+        };
+        {
+            let mut last_refresh = self.last_refresh.lock().expect("discovery lock poisoned");
+            if last_refresh.elapsed() < refresh_interval {
+                return;
+            }
+            *last_refresh = Instant::now();
+        }
+
+        self.refresh().await;
+    }
+
+    async fn refresh(&self) {
+        info!("refreshing Kubernetes discovery data");
+        self.preferred_versions.write().await.clear();
+        self.misses.lock().expect("discovery lock poisoned").clear();
+        self.group_misses
+            .lock()
+            .expect("discovery lock poisoned")
+            .clear();
+
+        let api_versions: Vec<String> = self.resources.read().await.keys().cloned().collect();
+        for api_version in api_versions {
+            match self.discover_group_version(&api_version).await {
+                Ok(group_version) => {
+                    self.resources
+                        .write()
+                        .await
+                        .insert(api_version, group_version);
+                }
+                Err(e) => {
+                    // the group version could have been removed, it will be
+                    // discovered again when needed
+                    warn!(
+                        api_version = api_version.as_str(),
+                        error = e.to_string(),
+                        "cannot refresh discovery data"
+                    );
+                    self.resources.write().await.remove(&api_version);
+                }
+            }
+        }
+    }
+
+    async fn resolve_api_version(&self, api_version: &str) -> Result<String> {
+        if api_version.contains('/') || is_version(api_version) {
+            return Ok(api_version.to_owned());
+        }
+
+        let group = api_version;
+        if let Some(preferred) = self.preferred_versions.read().await.get(group) {
+            return Ok(preferred.to_owned());
+        }
+        if self.is_recent_miss(&self.group_misses, &group.to_owned()) {
+            return Err(anyhow!("Cannot find API group {group}"));
+        }
+
+        let groups = self.kube_client.list_api_groups().await?;
+        let mut preferred_versions = self.preferred_versions.write().await;
+        for api_group in groups.groups {
+            let preferred = api_group
+                .preferred_version
+                .or_else(|| api_group.versions.first().cloned());
+            if let Some(preferred) = preferred {
+                preferred_versions.insert(api_group.name, preferred.group_version);
+            }
+        }
+
+        preferred_versions.get(group).cloned().ok_or_else(|| {
+            self.group_misses
+                .lock()
+                .expect("discovery lock poisoned")
+                .insert(group.to_owned(), Instant::now());
+            anyhow!("Cannot find API group {group}")
+        })
+    }
+
+    async fn discover_group_version(&self, api_version: &str) -> Result<GroupVersionResources> {
+        let (group, version) = split_api_version(api_version)?;
+        let resources_list = match group {
+            "" => self.kube_client.list_core_api_resources(version).await?,
+            _ => self
+                .kube_client
+                .list_api_group_resources(api_version)
+                .await
+                .map_err(|e| anyhow!("error discovering resources of {api_version}: {e}"))?,
+        };
+
+        Ok(resources_list
+            .resources
+            .into_iter()
+            // skip subresources, like `pods/status`
+            .filter(|resource| !resource.name.contains('/'))
+            .map(|resource| {
+                let kube_resource = KubeResource {
+                    resource: kube::api::ApiResource {
+                        group: group.to_owned(),
+                        version: version.to_owned(),
+                        api_version: api_version.to_owned(),
+                        kind: resource.kind.clone(),
+                        plural: resource.name,
+                    },
+                    namespaced: resource.namespaced,
+                };
+                (resource.kind, kube_resource)
+            })
+            .collect())
+    }
+
+    /// Start watching the CustomResourceDefinitions, unless this is already
+    /// happening
+    fn ensure_crd_watch(&self) {
+        if !self.config.watch_crds {
+            return;
+        }
+        let mut crd_watch = self.crd_watch.lock().expect("discovery lock poisoned");
+        if crd_watch.is_some() {
+            return;
+        }
+
+        info!("watching CustomResourceDefinitions");
+        let api: Api<CustomResourceDefinition> = Api::all(self.kube_client.clone());
+        let resources = self.resources.clone();
+        let preferred_versions = self.preferred_versions.clone();
+        let misses = self.misses.clone();
+        let group_misses = self.group_misses.clone();
+        *crd_watch = Some(tokio::spawn(async move {
+            let mut events = watcher(api, watcher::Config::default())
+                .default_backoff()
+                .boxed();
+            while let Some(event) = events.next().await {
+                let crd = match event {
+                    Ok(watcher::Event::Apply(crd))
+                    | Ok(watcher::Event::InitApply(crd))
+                    | Ok(watcher::Event::Delete(crd)) => crd,
+                    Ok(_) => continue,
+                    Err(e) => {
+                        warn!(
+                            error = e.to_string(),
+                            "error watching CustomResourceDefinitions"
+                        );
+                        continue;
+                    }
+                };
+
+                let group = crd.spec.group;
+                let prefix = format!("{group}/");
+                resources
+                    .write()
+                    .await
+                    .retain(|api_version, _| !api_version.starts_with(&prefix));
+                preferred_versions.write().await.remove(&group);
+                misses
+                    .lock()
+                    .expect("discovery lock poisoned")
+                    .retain(|(api_version, _), _| !api_version.starts_with(&prefix));
+                group_misses
+                    .lock()
+                    .expect("discovery lock poisoned")
+                    .remove(&group);
+            }
+        }));
+    }
+}
+
+/// Split an apiVersion into its group and version. The core group is
+/// represented by an empty string
+fn split_api_version(api_version: &str) -> Result<(&str, &str)> {
+    match api_version.split_once('/') {
+        Some((group, version)) if !group.is_empty() && !version.is_empty() => Ok((group, version)),
+        None if is_version(api_version) => Ok(("", api_version)),
+        _ => Err(anyhow!(
+            "cannot determine group and version for {api_version}"
+        )),
+    }
+}
+
+/// Checks if the given string is a version, like `v1` or `v1beta1`
+fn is_version(value: &str) -> bool {
+    value
+        .strip_prefix('v')
+        .and_then(|rest| rest.chars().next())
+        .is_some_and(|c| c.is_ascii_digit())
+}
+
+/// Custom resources cannot be defined inside of the core group, or inside of
+/// the groups reserved to Kubernetes, like `apps` or `networking.k8s.io`
+fn is_custom_group(group: &str) -> bool {
+    group.contains('.') && !group.ends_with(".k8s.io") && !group.ends_with(".kubernetes.io")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::{Request, Response, http};
+    use kube::client::Body;
+    use rstest::rstest;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tower_test::mock::Handle;
+
+    #[rstest]
+    #[case::core("v1", Some(("", "v1")))]
+    #[case::group("apps/v1", Some(("apps", "v1")))]
+    #[case::beta("networking.k8s.io/v1beta1", Some(("networking.k8s.io", "v1beta1")))]
+    #[case::group_only("apps", None)]
+    #[case::missing_version("apps/", None)]
+    fn split_api_versions(#[case] api_version: &str, #[case] expected: Option<(&str, &str)>) {
+        assert_eq!(split_api_version(api_version).ok(), expected);
+    }
+
+    #[rstest]
+    #[case::core("", false)]
+    #[case::built_in("apps", false)]
+    #[case::reserved("networking.k8s.io", false)]
+    #[case::custom("policies.kubewarden.io", true)]
+    fn custom_groups(#[case] group: &str, #[case] expected: bool) {
+        assert_eq!(is_custom_group(group), expected);
+    }
+
+    fn deployments_resource_list(with_deployments: bool) -> serde_json::Value {
+        let mut resources = vec![json!({
+            "name": "replicasets",
+            "singularName": "replicaset",
+            "namespaced": true,
+            "kind": "ReplicaSet",
+            "verbs": ["get", "list", "watch"]
+        })];
+        if with_deployments {
+            resources.push(json!({
+                "name": "deployments",
+                "singularName": "deployment",
+                "namespaced": true,
+                "kind": "Deployment",
+                "verbs": ["get", "list", "watch"]
+            }));
+            resources.push(json!({
+                "name": "deployments/status",
+                "singularName": "",
+                "namespaced": true,
+                "kind": "Deployment",
+                "verbs": ["get"]
+            }));
+        }
+        json!({
+            "kind": "APIResourceList",
+            "apiVersion": "v1",
+            "groupVersion": "apps/v1",
+            "resources": resources
+        })
+    }
+
+    /// Serve the discovery requests, `apps_v1_requests` counts the discoveries
+    /// of the `apps/v1` group version, `api_groups_requests` the listings of the
+    /// API groups
+    async fn serve(
+        mut handle: Handle<Request<Body>, Response<Body>>,
+        apps_v1_requests: Arc<AtomicUsize>,
+        api_groups_requests: Arc<AtomicUsize>,
+    ) {
+        loop {
+            let Some((request, send)) = handle.next_request().await else {
+                return;
+            };
+            let response = match (request.method(), request.uri().path()) {
+                (&http::Method::GET, "/apis") => {
+                    api_groups_requests.fetch_add(1, Ordering::SeqCst);
+                    json!({
+                    "kind": "APIGroupList",
+                    "apiVersion": "v1",
+                    "groups": [{
+                        "name": "apps",
+                        "versions": [{"groupVersion": "apps/v1", "version": "v1"}],
+                        "preferredVersion": {"groupVersion": "apps/v1", "version": "v1"}
+                    }]
+                    })
+                }
+                (&http::Method::GET, "/apis/apps/v1") => {
+                    // the first discovery happens before the Deployment kind is served
+                    let requests = apps_v1_requests.fetch_add(1, Ordering::SeqCst) + 1;
+                    deployments_resource_list(requests > 1)
+                }
+                _ => panic!("unexpected request: {request:?}"),
+            };
+            send.send_response(
+                Response::builder()
+                    .body(Body::from(serde_json::to_vec(&response).unwrap()))
+                    .unwrap(),
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn resolve_preferred_version_and_refresh_on_miss() {
+        let (mock_service, handle) = tower_test::mock::pair::<Request<Body>, Response<Body>>();
+        tokio::spawn(serve(
+            handle,
+            Arc::new(AtomicUsize::new(0)),
+            Arc::new(AtomicUsize::new(0)),
+        ));
+        let discovery = Discovery::new(
+            kube::Client::new(mock_service, "default"),
+            DiscoveryConfig::default(),
+        );
+
+        let replica_sets = discovery.resolve("apps", "ReplicaSet").await.unwrap();
+        assert_eq!(replica_sets.resource.api_version, "apps/v1");
+        assert_eq!(replica_sets.resource.plural, "replicasets");
+
+        // the kind is not known yet, hence the group version is discovered again
+        let deployments = discovery.resolve("apps/v1", "Deployment").await.unwrap();
+        assert_eq!(deployments.resource.plural, "deployments");
+        assert!(deployments.namespaced);
+
+        assert!(discovery.resolve("apps/v1", "StatefulSet").await.is_err());
+    }
+
+    #[rstest]
+    #[case::remembered(Duration::from_secs(60), 1)]
+    #[case::expired(Duration::ZERO, 2)]
+    #[tokio::test]
+    async fn remember_missing_kinds(#[case] miss_ttl: Duration, #[case] expected_requests: usize) {
+        let (mock_service, handle) = tower_test::mock::pair::<Request<Body>, Response<Body>>();
+        let apps_v1_requests = Arc::new(AtomicUsize::new(0));
+        tokio::spawn(serve(
+            handle,
+            apps_v1_requests.clone(),
+            Arc::new(AtomicUsize::new(0)),
+        ));
+        let discovery = Discovery::new(
+            kube::Client::new(mock_service, "default"),
+            DiscoveryConfig {
+                miss_ttl,
+                ..Default::default()
+            },
+        );
+
+        assert!(discovery.resolve("apps/v1", "StatefulSet").await.is_err());
+        assert!(discovery.resolve("apps/v1", "StatefulSet").await.is_err());
+        assert_eq!(apps_v1_requests.load(Ordering::SeqCst), expected_requests);
+    }
+
+    #[rstest]
+    #[case::remembered(Duration::from_secs(60), 1)]
+    #[case::expired(Duration::ZERO, 2)]
+    #[tokio::test]
+    async fn remember_missing_groups(#[case] miss_ttl: Duration, #[case] expected_requests: usize) {
+        let (mock_service, handle) = tower_test::mock::pair::<Request<Body>, Response<Body>>();
+        let api_groups_requests = Arc::new(AtomicUsize::new(0));
+        tokio::spawn(serve(
+            handle,
+            Arc::new(AtomicUsize::new(0)),
+            api_groups_requests.clone(),
+        ));
+        let discovery = Discovery::new(
+            kube::Client::new(mock_service, "default"),
+            DiscoveryConfig {
+                miss_ttl,
+                ..Default::default()
+            },
+        );
+
+        assert!(discovery.resolve("unknown", "Widget").await.is_err());
+        assert!(discovery.resolve("unknown", "Widget").await.is_err());
+        assert_eq!(
+            api_groups_requests.load(Ordering::SeqCst),
+            expected_requests
+        );
+    }
+}
@@ -0,0 +1,391 @@
+use anyhow::{Result, anyhow};
+use kube::core::DynamicObject;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use tracing::warn;
+
+use crate::policy_metadata::ContextAwareResource;
+
+/// The field holding the labels of the objects
+const LABELS_FIELD: &str = "metadata.labels";
+
+/// The fields that are always retained, they are required to identify the objects
+const IDENTITY_FIELDS: &[&str] = &[
+    "apiVersion",
+    "kind",
+    "metadata.name",
+    "metadata.namespace",
+    "metadata.uid",
+    "metadata.resourceVersion",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Key(String),
+    AllItems,
+}
+
+/// The path of a field of a Kubernetes object, like `metadata.labels` or
+/// `spec.containers[*].image`.
+///
+/// Keys are separated by dots, `[*]` selects all the items of a list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct FieldPath {
+    segments: Vec<Segment>,
+}
+
+impl FieldPath {
+    pub fn parse(path: &str) -> Result<Self> {
+        let mut segments = Vec::new();
+        for part in path.split('.') {
+            let (key, mut selectors) = match part.find('[') {
+                Some(pos) => part.split_at(pos),
+                None => (part, ""),
+            };
+            if key.is_empty() {
+                return Err(anyhow!("invalid field path '{path}': empty key"));
+            }
+            segments.push(Segment::Key(key.to_string()));
+
+            while !selectors.is_empty() {
+                selectors = selectors.strip_prefix("[*]").ok_or_else(|| {
+                    anyhow!(
+                        "invalid field path '{path}': only `[*]` can be used to select list items"
+                    )
+                })?;
+                segments.push(Segment::AllItems);
+            }
+        }
+
+        Ok(FieldPath { segments })
+    }
+
+    /// Whether the field selected by `other` is part of the one selected by this path
+    fn contains(&self, other: &FieldPath) -> bool {
+        other.segments.starts_with(&self.segments)
+    }
+
+    /// Returns a copy of `value` that contains only the field selected by the path.
+    /// Returns `None` when the field does not exist
+    fn extract(&self, value: &Value) -> Option<Value> {
+        extract(value, &self.segments)
+    }
+}
+
+fn extract(value: &Value, segments: &[Segment]) -> Option<Value> {
+    let Some((segment, rest)) = segments.split_first() else {
+        return Some(value.clone());
+    };
+
+    match segment {
+        Segment::Key(key) => {
+            let child = extract(value.as_object()?.get(key)?, rest)?;
+            Some(Value::Object(serde_json::Map::from_iter([(
+                key.clone(),
+                child,
+            )])))
+        }
+        // The items that do not have the field are kept as empty objects, this
+        // keeps the indexes aligned when merging the results of multiple paths
+        Segment::AllItems => Some(Value::Array(
+            value
+                .as_array()?
+                .iter()
+                .map(|item| {
+                    extract(item, rest).unwrap_or_else(|| Value::Object(Default::default()))
+                })
+                .collect(),
+        )),
+    }
+}
+
+/// Merge the fields of `other` into `value`
+fn merge(value: &mut Value, other: Value) {
+    match (value, other) {
+        (Value::Object(value), Value::Object(other)) => {
+            for (key, other_child) in other {
+                match value.get_mut(&key) {
+                    Some(child) => merge(child, other_child),
+                    None => {
+                        value.insert(key, other_child);
+                    }
+                }
+            }
+        }
+        (Value::Array(value), Value::Array(other)) if value.len() == other.len() => {
+            for (child, other_child) in value.iter_mut().zip(other) {
+                merge(child, other_child);
+            }
+        }
+        (value, other) => *value = other,
+    }
+}
+
+/// The fields of a kind of Kubernetes object that have to be retained
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Projection {
+    paths: Vec<FieldPath>,
+}
+
+impl Projection {
+    pub fn new(paths: &[String]) -> Result<Self> {
+        let paths = IDENTITY_FIELDS
+            .iter()
+            .copied()
+            .chain(paths.iter().map(String::as_str))
+            .map(FieldPath::parse)
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Projection { paths })
+    }
+
+    /// Retain also the labels of the objects
+    pub fn with_labels(mut self) -> Self {
+        if !self.retains(LABELS_FIELD) {
+            self.paths
+                .push(FieldPath::parse(LABELS_FIELD).expect("valid field path"));
+        }
+        self
+    }
+
+    /// Whether all the fields retained by `other` are retained by this projection too
+    pub fn covers(&self, other: &Projection) -> bool {
+        other
+            .paths
+            .iter()
+            .all(|path| self.paths.iter().any(|retained| retained.contains(path)))
+    }
+
+    /// Retain also the fields retained by `other`
+    pub fn union(mut self, other: &Projection) -> Self {
+        for path in &other.paths {
+            if !self.paths.iter().any(|retained| retained.contains(path)) {
+                self.paths.push(path.clone());
+            }
+        }
+        self
+    }
+
+    /// Whether the field at `path`, like `spec.nodeName`, is retained
+    pub fn retains(&self, path: &str) -> bool {
+        FieldPath::parse(path)
+            .is_ok_and(|path| self.paths.iter().any(|retained| retained.contains(&path)))
+    }
+
+    pub fn apply(&self, value: &Value) -> Value {
+        let mut projected = Value::Object(Default::default());
+        for extracted in self.paths.iter().filter_map(|path| path.extract(value)) {
+            merge(&mut projected, extracted);
+        }
+        projected
+    }
+
+    /// Remove all the fields of the object that are not part of the projection
+    pub fn apply_to_object(&self, object: &mut DynamicObject) {
+        let projected = serde_json::to_value(&*object)
+            .map(|value| self.apply(&value))
+            .and_then(serde_json::from_value);
+
+        match projected {
+            Ok(projected) => *object = projected,
+            Err(e) => warn!(error = e.to_string(), "cannot project object fields"),
+        }
+    }
+}
+
+/// The projections to be applied to the kinds of Kubernetes objects accessed
+/// by the policies, as declared to the `CallbackHandlerBuilder`.
+///
+/// The fields retained for a kind are the union of the fields declared by all
+/// the policies accessing it. No projection is applied when at least one of
+/// the policies did not declare the fields it needs.
+///
+/// These are the projections the reflectors are created with. A reflector is
+/// created again, retaining more fields, when a policy needs fields that are
+/// not retained.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct FieldProjections {
+    projections: BTreeMap<(String, String), Option<Projection>>,
+}
+
+impl FieldProjections {
+    pub fn new<'a>(resources: impl IntoIterator<Item = &'a ContextAwareResource>) -> Result<Self> {
+        let mut fields: BTreeMap<(String, String), Option<Vec<String>>> = BTreeMap::new();
+        for resource in resources {
+            let key = (resource.api_version.clone(), resource.kind.clone());
+            let entry = fields.entry(key).or_insert_with(|| Some(Vec::new()));
+            if resource.fields.is_empty() {
+                *entry = None;
+            } else if let Some(paths) = entry {
+                paths.extend(resource.fields.iter().cloned());
+            }
+        }
+
+        let projections = fields
+            .into_iter()
+            .map(|(key, paths)| {
+                let projection = paths.map(|paths| Projection::new(&paths)).transpose()?;
+                Ok((key, projection))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(FieldProjections { projections })
+    }
+
+    /// The projection to be applied to the given kind, if any
+    pub fn get(&self, api_version: &str, kind: &str) -> Option<&Projection> {
+        self.projections
+            .get(&(api_version.to_string(), kind.to_string()))
+            .and_then(Option::as_ref)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use serde_json::json;
+
+    fn pod() -> Value {
+        json!({
+            "apiVersion": "v1",
+            "kind": "Pod",
+            "metadata": {
+                "name": "nginx",
+                "namespace": "default",
+                "labels": {"app": "nginx"},
+                "annotations": {"foo": "bar"}
+            },
+            "spec": {
+                "nodeName": "node-1",
+                "containers": [
+                    {"name": "nginx", "image": "nginx:latest", "ports": [{"containerPort": 80}]},
+                    {"name": "sidecar", "image": "busybox"}
+                ]
+            }
+        })
+    }
+
+    #[rstest]
+    #[case::key("metadata.labels", true)]
+    #[case::all_items("spec.containers[*].image", true)]
+    #[case::nested_lists("spec.containers[*].ports[*]", true)]
+    #[case::index("spec.containers[0].image", false)]
+    #[case::empty_key("spec..image", false)]
+    #[case::unterminated("spec.containers[*", false)]
+    fn parse_field_path(#[case] path: &str, #[case] valid: bool) {
+        assert_eq!(FieldPath::parse(path).is_ok(), valid);
+    }
+
+    #[test]
+    fn project_fields() {
+        let projection = Projection::new(&[
+            "metadata.labels".to_string(),
+            "spec.containers[*].image".to_string(),
+            "spec.containers[*].name".to_string(),
+            "status.phase".to_string(),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            projection.apply(&pod()),
+            json!({
+                "apiVersion": "v1",
+                "kind": "Pod",
+                "metadata": {
+                    "name": "nginx",
+                    "namespace": "default",
+                    "labels": {"app": "nginx"}
+                },
+                "spec": {
+                    "containers": [
+                        {"name": "nginx", "image": "nginx:latest"},
+                        {"name": "sidecar", "image": "busybox"}
+                    ]
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn project_object() {
+        let projection = Projection::new(&["spec.nodeName".to_string()]).unwrap();
+        let mut object: DynamicObject = serde_json::from_value(pod()).unwrap();
+        projection.apply_to_object(&mut object);
+
+        assert_eq!(object.metadata.name.as_deref(), Some("nginx"));
+        assert!(object.metadata.labels.is_none());
+        assert_eq!(object.data, json!({"spec": {"nodeName": "node-1"}}));
+    }
+
+    #[rstest]
+    #[case::identity_field("metadata.name", true)]
+    #[case::declared_field("spec.nodeName", true)]
+    #[case::child_of_declared_field("status.phase", true)]
+    #[case::parent_of_declared_field("spec", false)]
+    #[case::undeclared_field("metadata.labels", false)]
+    #[case::list_items("spec.containers", false)]
+    fn retained_fields(#[case] path: &str, #[case] retained: bool) {
+        let projection = Projection::new(&[
+            "spec.nodeName".to_string(),
+            "spec.containers[*].image".to_string(),
+            "status".to_string(),
+        ])
+        .unwrap();
+        assert_eq!(projection.retains(path), retained);
+    }
+
+    #[test]
+    fn retain_labels() {
+        let projection = Projection::new(&["spec.nodeName".to_string()])
+            .unwrap()
+            .with_labels();
+        assert!(projection.retains("metadata.labels"));
+        assert_eq!(
+            projection.apply(&pod())["metadata"]["labels"],
+            json!({"app": "nginx"})
+        );
+    }
+
+    #[rstest]
+    #[case::same(&["spec.nodeName"], &["spec.nodeName"], true)]
+    #[case::child(&["spec"], &["spec.nodeName"], true)]
+    #[case::identity_fields(&["spec.nodeName"], &["metadata.name"], true)]
+    #[case::parent(&["spec.nodeName"], &["spec"], false)]
+    #[case::other_field(&["spec.nodeName"], &["metadata.labels"], false)]
+    fn covered_projections(#[case] fields: &[&str], #[case] other: &[&str], #[case] covered: bool) {
+        let projection = |fields: &[&str]| {
+            Projection::new(&fields.iter().map(|f| f.to_string()).collect::<Vec<_>>()).unwrap()
+        };
+        let (projection, other) = (projection(fields), projection(other));
+
+        assert_eq!(projection.covers(&other), covered);
+        let union = projection.clone().union(&other);
+        assert!(union.covers(&projection) && union.covers(&other));
+    }
+
+    #[test]
+    fn union_of_declared_fields() {
+        let resource = |kind: &str, fields: &[&str]| ContextAwareResource {
+            api_version: "v1".to_string(),
+            kind: kind.to_string(),
+            fields: fields.iter().map(|f| f.to_string()).collect(),
+            namespace_scope: Default::default(),
+        };
+        let resources = [
+            resource("Pod", &["metadata.labels"]),
+            resource("Pod", &["spec.nodeName"]),
+            resource("Secret", &["type"]),
+            resource("Secret", &[]),
+        ];
+        let projections = FieldProjections::new(&resources).unwrap();
+
+        let projected = projections.get("v1", "Pod").unwrap().apply(&pod());
+        assert_eq!(projected["metadata"]["labels"], json!({"app": "nginx"}));
+        assert_eq!(projected["spec"], json!({"nodeName": "node-1"}));
+
+        // one of the policies needs the whole Secret objects
+        assert!(projections.get("v1", "Secret").is_none());
+        assert!(projections.get("v1", "ConfigMap").is_none());
+    }
+}
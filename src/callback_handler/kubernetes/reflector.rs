@@ -1,15 +1,283 @@
-use anyhow::Result;
-use futures::{Stream, StreamExt, TryStreamExt, future::ready};
-use kube::{Resource, runtime::reflector::store};
+use anyhow::{Result, anyhow};
+use futures::{
+    Stream, StreamExt, TryStreamExt,
+    future::{Either, ready, select},
+};
+use kube::{Resource, core::DynamicObject, runtime::reflector::store};
 use kube::{
     ResourceExt,
     runtime::{WatchStreamExt, reflector::store::Writer, watcher},
 };
-use std::hash::Hash;
-use tokio::{sync::watch, time::Instant};
+use std::{hash::Hash, sync::Mutex, time::Duration};
+use tokio::{sync::watch, task::JoinHandle, time::Instant};
 use tracing::{debug, info, warn};
 
-use crate::callback_handler::kubernetes::KubeResource;
+use crate::callback_handler::kubernetes::{
+    KubeResource,
+    projection::Projection,
+    selectors::{FieldSelector, LabelSelector},
+};
+
+/// How the list queries made by the policies are mapped to reflectors
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReflectorMode {
+    /// A reflector is created for each distinct combination of namespace,
+    /// label selector and field selector. Only the objects matching the
+    /// selectors are cached
+    #[default]
+    PerQuery,
+    /// A single reflector caches all the objects of a kind, either inside of
+    /// a namespace or across the whole cluster. Label and field selectors are
+    /// evaluated in memory against the cached objects.
+    ///
+    /// When the fields of the objects are projected, the labels are always
+    /// retained. Queries with field selectors using fields that are not retained
+    /// are served by a dedicated reflector, like with `PerQuery`.
+    ///
+    /// This reduces the number of watches opened against the API server, at
+    /// the cost of caching more objects
+    PerKind,
+}
+
+/// Configuration of the reflectors used to answer the list queries made by
+/// the policies. By default no limit is enforced: reflectors are kept running
+/// for the whole life of the `CallbackHandler`
+#[derive(Clone, Debug, Default)]
+pub struct ReflectorConfig {
+    pub mode: ReflectorMode,
+    /// Stop the reflectors that have not been used for longer than this
+    pub idle_timeout: Option<Duration>,
+    /// Maximum number of reflectors, hence of watches, running at the same
+    /// time. The least recently used reflectors are stopped to make room for
+    /// new ones
+    pub max_reflectors: Option<usize>,
+    /// Maximum number of objects cached by all the reflectors. The least
+    /// recently used reflectors are stopped to make room for new objects.
+    /// The objects are listed in pages, a reflector listing more objects than
+    /// this limit is stopped before its cache is filled. The limit is also
+    /// checked periodically, since the objects watched by the running
+    /// reflectors can grow
+    pub max_cached_objects: Option<usize>,
+}
+
+/// Details about a running reflector
+#[derive(Clone, Debug)]
+pub struct ReflectorInfo {
+    pub api_version: String,
+    pub kind: String,
+    pub namespace: Option<String>,
+    pub label_selector: Option<String>,
+    pub field_selector: Option<String>,
+    /// Number of objects currently cached
+    pub cached_objects: usize,
+    pub last_change_seen_at: Instant,
+    pub last_used_at: Instant,
+}
+
+/// The set of objects of a given kind watched by a reflector
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct ReflectorScope {
+    pub namespace: Option<String>,
+    pub label_selector: Option<String>,
+    pub field_selector: Option<String>,
+}
+
+impl ReflectorScope {
+    /// Returns the filter to be applied to the objects watched by this scope
+    /// to obtain the ones of the `query` scope. Returns `None` when the objects
+    /// of `query` are not a subset of the ones of this scope, or when the filter
+    /// needs fields that are not retained by the `projection` of the cached objects.
+    pub fn filter_for(
+        &self,
+        query: &ReflectorScope,
+        projection: Option<&Projection>,
+    ) -> Option<ObjectFilter> {
+        if self.namespace.is_some() && self.namespace != query.namespace {
+            return None;
+        }
+        if self.label_selector.is_some() && self.label_selector != query.label_selector {
+            return None;
+        }
+        if self.field_selector.is_some() && self.field_selector != query.field_selector {
+            return None;
+        }
+
+        // selectors that cannot be evaluated locally are left to the API server
+        let label_selector = match (&self.label_selector, &query.label_selector) {
+            (None, Some(selector)) => Some(LabelSelector::parse(selector).ok()?),
+            _ => None,
+        };
+        let field_selector = match (&self.field_selector, &query.field_selector) {
+            (None, Some(selector)) => Some(FieldSelector::parse(selector).ok()?),
+            _ => None,
+        };
+
+        let filter = ObjectFilter {
+            namespace: self
+                .namespace
+                .is_none()
+                .then(|| query.namespace.clone())
+                .flatten(),
+            label_selector,
+            field_selector,
+        };
+        filter.can_be_evaluated_on(projection).then_some(filter)
+    }
+
+    /// The scope of the reflector to be created to answer the queries of
+    /// this scope, according to the given mode. `projection` is the one applied
+    /// to the objects cached by the reflector
+    pub fn for_mode(&self, mode: ReflectorMode, projection: Option<&Projection>) -> ReflectorScope {
+        let shared = ReflectorScope {
+            namespace: self.namespace.clone(),
+            ..Default::default()
+        };
+        match mode {
+            // a dedicated reflector is used when the selectors cannot be evaluated locally
+            ReflectorMode::PerKind if shared.filter_for(self, projection).is_some() => shared,
+            _ => self.clone(),
+        }
+    }
+}
+
+/// Filter evaluated in memory against the objects cached by a reflector
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ObjectFilter {
+    namespace: Option<String>,
+    label_selector: Option<LabelSelector>,
+    field_selector: Option<FieldSelector>,
+}
+
+impl ObjectFilter {
+    /// Whether the filter can be evaluated against objects that have been
+    /// reduced by the given projection
+    fn can_be_evaluated_on(&self, projection: Option<&Projection>) -> bool {
+        let Some(projection) = projection else {
+            return true;
+        };
+
+        let labels_retained =
+            self.label_selector.is_none() || projection.retains("metadata.labels");
+        let fields_retained = self.field_selector.as_ref().is_none_or(|field_selector| {
+            field_selector.paths().all(|path| projection.retains(path))
+        });
+        labels_retained && fields_retained
+    }
+
+    pub fn matches(&self, object: &DynamicObject) -> bool {
+        if let Some(namespace) = &self.namespace
+            && object.metadata.namespace.as_ref() != Some(namespace)
+        {
+            return false;
+        }
+        if let Some(label_selector) = &self.label_selector
+            && !label_selector.matches(object.labels())
+        {
+            return false;
+        }
+        if let Some(field_selector) = &self.field_selector {
+            return serde_json::to_value(object)
+                .is_ok_and(|object| field_selector.matches(&object));
+        }
+
+        true
+    }
+}
+
+/// Compute the reflectors to be stopped to make room for a new one caching
+/// `new_objects` objects. `reflectors` is made of the id, the last usage time and
+/// the number of cached objects of each running reflector.
+pub(crate) fn reflectors_to_evict(
+    mut reflectors: Vec<(String, Instant, usize)>,
+    config: &ReflectorConfig,
+    new_objects: usize,
+) -> Result<Vec<String>> {
+    if let Some(max) = config.max_cached_objects
+        && new_objects > max
+    {
+        return Err(anyhow!(
+            "the query returns {new_objects} objects, more than the {max} objects that can be cached"
+        ));
+    }
+
+    // least recently used first
+    reflectors.sort_by_key(|(_, last_used_at, _)| *last_used_at);
+    let mut cached_objects: usize = reflectors.iter().map(|(_, _, objects)| objects).sum();
+    let mut running = reflectors.len();
+
+    let mut evicted = Vec::new();
+    for (id, _, objects) in reflectors {
+        let too_many_reflectors = config.max_reflectors.is_some_and(|max| running + 1 > max);
+        let too_many_objects = config
+            .max_cached_objects
+            .is_some_and(|max| cached_objects + new_objects > max);
+        if !too_many_reflectors && !too_many_objects {
+            break;
+        }
+        cached_objects -= objects;
+        running -= 1;
+        evicted.push(id);
+    }
+
+    Ok(evicted)
+}
+
+/// Compute the reflectors to be stopped because the objects they cache exceed
+/// the configured maximum. This happens when the watched objects grow after the
+/// reflectors have been created. `reflectors` is made of the id, the last usage
+/// time and the number of cached objects of each running reflector.
+pub(crate) fn reflectors_over_objects_limit(
+    mut reflectors: Vec<(String, Instant, usize)>,
+    config: &ReflectorConfig,
+) -> Vec<String> {
+    let Some(max) = config.max_cached_objects else {
+        return Vec::new();
+    };
+
+    // least recently used first
+    reflectors.sort_by_key(|(_, last_used_at, _)| *last_used_at);
+    let mut cached_objects: usize = reflectors.iter().map(|(_, _, objects)| objects).sum();
+
+    let mut evicted = Vec::new();
+    for (id, _, objects) in reflectors {
+        if cached_objects <= max {
+            break;
+        }
+        cached_objects -= objects;
+        evicted.push(id);
+    }
+
+    evicted
+}
+
+/// Number of objects requested by each page of the initial list
+const LIST_PAGE_SIZE: usize = 500;
+
+/// Stop the stream when an initial list returns more than `max_objects`
+/// objects, before they are all buffered by the reflector store. When this
+/// happens `true` is sent to the `over_objects_limit` watch channel
+fn limit_listed_objects<K, W>(
+    stream: W,
+    max_objects: Option<usize>,
+    over_objects_limit: watch::Sender<bool>,
+) -> impl Stream<Item = W::Item>
+where
+    W: Stream<Item = watcher::Result<watcher::Event<K>>>,
+{
+    let mut listed_objects: usize = 0;
+    stream.take_while(move |event| {
+        match event {
+            Ok(watcher::Event::Init) => listed_objects = 0,
+            Ok(watcher::Event::InitApply(_)) => listed_objects += 1,
+            _ => {}
+        }
+        let within_limit = max_objects.is_none_or(|max| listed_objects <= max);
+        if !within_limit {
+            over_objects_limit.send_replace(true);
+        }
+        ready(within_limit)
+    })
+}
 
 /// Like `kube::runtime::reflector::reflector`, but also sends the time of the last change to a
 /// watch channel
@@ -34,7 +302,8 @@ where
 /// A reflector fetches kubernetes objects based on filtering criteria.
 /// When created, the list is populated slowly, to prevent hammering the Kubernetes API server.
 /// The items are stored in-memory. The `managedFields` attribute is stripped from all the objects
-/// to reduce memory consumption. All the other fields are retained.
+/// to reduce memory consumption. When a projection is provided, only the fields it selects are
+/// retained, otherwise all the other fields are retained.
 /// A Kubernetes Watch is then created to keep the contents of the list updated.
 ///
 /// This is code relies heavily on the `kube::runtime::reflector` module.
@@ -48,39 +317,72 @@ where
 ///
 /// Finally, when started, the Reflector takes some time to make the loaded data available to
 /// consumers.
+///
+/// ## Lifecycle
+///
+/// The watch is stopped when the Reflector is dropped, or when listing the objects
+/// returns more objects than the maximum allowed.
 pub(crate) struct Reflector {
     /// Read-only access to the data cached by the Reflector
     pub reader: kube::runtime::reflector::Store<kube::core::DynamicObject>,
+    pub api_version: String,
+    pub kind: String,
+    pub scope: ReflectorScope,
+    /// The projection applied to the cached objects
+    pub projection: Option<Projection>,
     last_change_seen_at: watch::Receiver<Instant>,
+    last_used_at: Mutex<Instant>,
+    over_objects_limit: watch::Receiver<bool>,
+    watch_task: JoinHandle<()>,
+}
+
+impl Drop for Reflector {
+    fn drop(&mut self) {
+        info!(
+            api_version = self.api_version.as_str(),
+            kind = self.kind.as_str(),
+            scope = ?self.scope,
+            "stopping reflector"
+        );
+        self.watch_task.abort();
+    }
 }
 
 impl Reflector {
     /// Compute a unique identifier for the Reflector. This is used to prevent the creation of two
     /// Reflectors watching the same set of resources.
-    pub fn compute_id(
-        resource: &KubeResource,
-        namespace: Option<&str>,
-        label_selector: Option<&str>,
-        field_selector: Option<&str>,
-    ) -> String {
+    pub fn compute_id(resource: &KubeResource, scope: &ReflectorScope) -> String {
         format!(
-            "{}|{}|{namespace:?}|{label_selector:?}|{field_selector:?}",
-            resource.resource.api_version, resource.resource.kind
+            "{}|{}|{:?}|{:?}|{:?}",
+            resource.resource.api_version,
+            resource.resource.kind,
+            scope.namespace.as_deref(),
+            scope.label_selector.as_deref(),
+            scope.field_selector.as_deref()
         )
     }
 
     /// Create the reflector and start a tokio task in the background that keeps
-    /// the contents of the Reflector updated
+    /// the contents of the Reflector updated. An error is returned when the
+    /// objects listed are more than `max_objects`
     pub async fn create_and_run(
         kube_client: kube::Client,
         resource: KubeResource,
         namespace: Option<String>,
         label_selector: Option<String>,
         field_selector: Option<String>,
+        projection: Option<Projection>,
+        max_objects: Option<usize>,
     ) -> Result<Self> {
+        let api_version = resource.resource.api_version.clone();
         let group = resource.resource.group.clone();
         let version = resource.resource.version.clone();
         let kind = resource.resource.kind.clone();
+        let scope = ReflectorScope {
+            namespace: namespace.clone(),
+            label_selector: label_selector.clone(),
+            field_selector: field_selector.clone(),
+        };
 
         info!(
             group,
@@ -106,28 +408,43 @@ impl Reflector {
 
         let writer = Writer::new(resource.resource);
         let reader = writer.as_reader();
+        let object_projection = projection.clone();
 
+        // a page is never bigger than needed to detect the objects limit has been exceeded
+        let page_size = max_objects.map_or(LIST_PAGE_SIZE, |max| {
+            LIST_PAGE_SIZE.min(max.saturating_add(1))
+        });
         let filter = watcher::Config {
             label_selector: label_selector.clone(),
             field_selector: field_selector.clone(),
+            page_size: Some(page_size.try_into().unwrap_or(u32::MAX)),
             ..Default::default()
         };
-        let stream = watcher(api, filter).map_ok(|ev| {
+        let stream = watcher(api, filter).map_ok(move |ev| {
             ev.modify(|obj| {
                 // clear managed fields to reduce memory usage
                 obj.managed_fields_mut().clear();
                 // clear last-applied-configuration to reduce memory usage
                 obj.annotations_mut()
                     .remove("kubectl.kubernetes.io/last-applied-configuration");
+                // keep only the fields needed by the policies
+                if let Some(projection) = &object_projection {
+                    projection.apply_to_object(obj);
+                }
             })
         });
 
         // this is a watch channel that tracks the last time the reflector saw a change
         let (updated_at_watch_tx, updated_at_watch_rx) = watch::channel(Instant::now());
 
+        let (over_objects_limit_tx, mut over_objects_limit_rx) = watch::channel(false);
+        let stream = limit_listed_objects(stream, max_objects, over_objects_limit_tx);
+
         let rf = reflector_tracking_changes_instant(writer, stream, updated_at_watch_tx);
 
-        tokio::spawn(async move {
+        let watch_kind = kind.clone();
+        let watch_task = tokio::spawn(async move {
+            let kind = watch_kind;
             let infinite_watch = rf.default_backoff().touched_objects().for_each(|obj| {
                 match obj {
                     Ok(o) => debug!(
@@ -156,16 +473,458 @@ impl Reflector {
             infinite_watch.await
         });
 
-        reader.wait_until_ready().await?;
+        let result = {
+            let ready = std::pin::pin!(reader.wait_until_ready());
+            let over_objects_limit =
+                std::pin::pin!(over_objects_limit_rx.wait_for(|over_limit| *over_limit));
+            match select(ready, over_objects_limit).await {
+                Either::Left((result, _)) => result.map_err(anyhow::Error::from),
+                // the watch stops only when the limit is exceeded
+                Either::Right(_) => Err(anyhow!(
+                    "the query returns more than the {} objects that can be cached",
+                    max_objects.unwrap_or_default()
+                )),
+            }
+        };
+        if let Err(e) = result {
+            watch_task.abort();
+            return Err(e);
+        }
 
         Ok(Reflector {
             reader,
+            api_version,
+            kind,
+            scope,
+            projection,
             last_change_seen_at: updated_at_watch_rx,
+            last_used_at: Mutex::new(Instant::now()),
+            over_objects_limit: over_objects_limit_rx,
+            watch_task,
         })
     }
 
+    /// Whether listing the objects again, for example after the watch has been
+    /// interrupted, returned more objects than allowed. The reflector stopped
+    /// watching the objects when this happened
+    pub fn over_objects_limit(&self) -> bool {
+        *self.over_objects_limit.borrow()
+    }
+
     /// Get the last time a change was seen by the reflector
     pub async fn last_change_seen_at(&self) -> Instant {
         *self.last_change_seen_at.borrow()
     }
+
+    /// Record the usage of the reflector, this prevents it from being evicted
+    pub fn touch(&self) {
+        *self.last_used_at.lock().expect("reflector lock poisoned") = Instant::now();
+    }
+
+    pub fn last_used_at(&self) -> Instant {
+        *self.last_used_at.lock().expect("reflector lock poisoned")
+    }
+
+    pub async fn info(&self) -> ReflectorInfo {
+        ReflectorInfo {
+            api_version: self.api_version.clone(),
+            kind: self.kind.clone(),
+            namespace: self.scope.namespace.clone(),
+            label_selector: self.scope.label_selector.clone(),
+            field_selector: self.scope.field_selector.clone(),
+            cached_objects: self.reader.state().len(),
+            last_change_seen_at: self.last_change_seen_at().await,
+            last_used_at: self.last_used_at(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use serde_json::json;
+    use std::{
+        collections::HashMap,
+        sync::{
+            Arc,
+            atomic::{AtomicUsize, Ordering},
+        },
+    };
+
+    fn scope(
+        namespace: Option<&str>,
+        label_selector: Option<&str>,
+        field_selector: Option<&str>,
+    ) -> ReflectorScope {
+        ReflectorScope {
+            namespace: namespace.map(str::to_string),
+            label_selector: label_selector.map(str::to_string),
+            field_selector: field_selector.map(str::to_string),
+        }
+    }
+
+    fn pod(namespace: &str, app: &str, node: &str) -> DynamicObject {
+        serde_json::from_value(json!({
+            "apiVersion": "v1",
+            "kind": "Pod",
+            "metadata": {"name": "pod", "namespace": namespace, "labels": {"app": app}},
+            "spec": {"nodeName": node}
+        }))
+        .unwrap()
+    }
+
+    #[rstest]
+    #[case::same_scope(
+        scope(Some("default"), Some("app=a"), None),
+        scope(Some("default"), Some("app=a"), None),
+        true
+    )]
+    #[case::cluster_wide(
+        scope(None, None, None),
+        scope(Some("default"), Some("app=a"), Some("spec.nodeName=n1")),
+        true
+    )]
+    #[case::other_namespace(
+        scope(Some("kube-system"), None, None),
+        scope(Some("default"), None, None),
+        false
+    )]
+    #[case::namespace_for_cluster_wide_query(
+        scope(Some("default"), None, None),
+        scope(None, None, None),
+        false
+    )]
+    #[case::other_label_selector(
+        scope(None, Some("app=b"), None),
+        scope(None, Some("app=a"), None),
+        false
+    )]
+    #[case::narrower_label_selector(
+        scope(None, Some("app=a"), None),
+        scope(None, None, None),
+        false
+    )]
+    #[case::invalid_label_selector(
+        scope(None, None, None),
+        scope(None, Some("app a"), None),
+        false
+    )]
+    fn scope_can_serve_query(
+        #[case] reflector: ReflectorScope,
+        #[case] query: ReflectorScope,
+        #[case] expected: bool,
+    ) {
+        assert_eq!(reflector.filter_for(&query, None).is_some(), expected);
+    }
+
+    #[rstest]
+    #[case::per_query(
+        ReflectorMode::PerQuery,
+        scope(Some("default"), Some("app=a"), None),
+        scope(Some("default"), Some("app=a"), None)
+    )]
+    #[case::per_kind(
+        ReflectorMode::PerKind,
+        scope(Some("default"), Some("app=a"), Some("spec.nodeName=n1")),
+        scope(Some("default"), None, None)
+    )]
+    #[case::per_kind_cluster_wide(
+        ReflectorMode::PerKind,
+        scope(None, Some("tier notin (db)"), None),
+        scope(None, None, None)
+    )]
+    #[case::per_kind_invalid_selector(
+        ReflectorMode::PerKind,
+        scope(None, Some("app a"), None),
+        scope(None, Some("app a"), None)
+    )]
+    fn reflector_scope_for_mode(
+        #[case] mode: ReflectorMode,
+        #[case] query: ReflectorScope,
+        #[case] expected: ReflectorScope,
+    ) {
+        assert_eq!(query.for_mode(mode, None), expected);
+    }
+
+    #[test]
+    fn queries_with_different_selectors_share_reflector() {
+        let resource = KubeResource {
+            resource: kube::api::ApiResource::erase::<k8s_openapi::api::core::v1::Pod>(&()),
+            namespaced: true,
+        };
+        let app_a = scope(None, Some("app=a"), None).for_mode(ReflectorMode::PerKind, None);
+        let app_b = scope(None, Some("app=b"), None).for_mode(ReflectorMode::PerKind, None);
+
+        assert_eq!(
+            Reflector::compute_id(&resource, &app_a),
+            Reflector::compute_id(&resource, &app_b)
+        );
+    }
+
+    #[test]
+    fn filter_objects_of_broader_scope() {
+        let filter = scope(None, None, None)
+            .filter_for(
+                &scope(
+                    Some("default"),
+                    Some("app in (a, b)"),
+                    Some("spec.nodeName=n1"),
+                ),
+                None,
+            )
+            .unwrap();
+
+        assert!(filter.matches(&pod("default", "a", "n1")));
+        assert!(!filter.matches(&pod("kube-system", "a", "n1")));
+        assert!(!filter.matches(&pod("default", "c", "n1")));
+        assert!(!filter.matches(&pod("default", "b", "n2")));
+    }
+
+    #[rstest]
+    #[case::equals("app=a", vec!["a"])]
+    #[case::not_equals("app!=a", vec!["b"])]
+    #[case::exists("app", vec!["a", "b"])]
+    fn per_kind_label_query_on_projected_objects(
+        #[case] label_selector: &str,
+        #[case] expected: Vec<&str>,
+    ) {
+        // the labels are retained by the projections of the PerKind reflectors
+        let projection = Projection::new(&["spec.nodeName".to_string()])
+            .unwrap()
+            .with_labels();
+        let query = scope(Some("default"), Some(label_selector), None);
+        let reflector_scope = query.for_mode(ReflectorMode::PerKind, Some(&projection));
+        assert_eq!(reflector_scope, scope(Some("default"), None, None));
+
+        let filter = reflector_scope
+            .filter_for(&query, Some(&projection))
+            .unwrap();
+        let cached: Vec<DynamicObject> = ["a", "b"]
+            .into_iter()
+            .map(|app| {
+                let mut object = pod("default", app, "n1");
+                projection.apply_to_object(&mut object);
+                object
+            })
+            .collect();
+
+        let found: Vec<String> = cached
+            .iter()
+            .filter(|object| filter.matches(object))
+            .map(|object| object.labels()["app"].clone())
+            .collect();
+        assert_eq!(found, expected);
+    }
+
+    #[rstest]
+    #[case::label_selector_on_dropped_labels(&[], Some("app=a"), None, false)]
+    #[case::field_selector_on_retained_field(&["spec.nodeName"], None, Some("spec.nodeName=n1"), true)]
+    #[case::field_selector_on_identity_field(&[], None, Some("metadata.name=pod"), true)]
+    #[case::field_selector_on_dropped_field(&["metadata.labels"], None, Some("spec.nodeName=n1"), false)]
+    fn selectors_on_projected_objects(
+        #[case] fields: &[&str],
+        #[case] label_selector: Option<&str>,
+        #[case] field_selector: Option<&str>,
+        #[case] shared: bool,
+    ) {
+        let projection =
+            Projection::new(&fields.iter().map(|f| f.to_string()).collect::<Vec<_>>()).unwrap();
+        let query = scope(None, label_selector, field_selector);
+
+        assert_eq!(
+            scope(None, None, None)
+                .filter_for(&query, Some(&projection))
+                .is_some(),
+            shared
+        );
+        // a dedicated reflector, filtering the objects on the API server side,
+        // is used when the selectors cannot be evaluated on the cached objects
+        let expected = if shared {
+            scope(None, None, None)
+        } else {
+            query.clone()
+        };
+        assert_eq!(
+            query.for_mode(ReflectorMode::PerKind, Some(&projection)),
+            expected
+        );
+    }
+
+    #[rstest]
+    #[case::no_limits(ReflectorConfig::default(), 100, vec![])]
+    #[case::too_many_reflectors(
+        ReflectorConfig { max_reflectors: Some(2), ..Default::default() },
+        1,
+        vec!["oldest", "old"]
+    )]
+    #[case::too_many_objects(
+        ReflectorConfig { max_cached_objects: Some(30), ..Default::default() },
+        10,
+        vec!["oldest"]
+    )]
+    #[case::room_available(
+        ReflectorConfig { max_reflectors: Some(4), max_cached_objects: Some(100), ..Default::default() },
+        10,
+        vec![]
+    )]
+    fn evict_least_recently_used_reflectors(
+        #[case] config: ReflectorConfig,
+        #[case] new_objects: usize,
+        #[case] expected: Vec<&str>,
+    ) {
+        let now = Instant::now();
+        let reflectors = vec![
+            ("recent".to_string(), now, 10),
+            ("oldest".to_string(), now - Duration::from_secs(20), 15),
+            ("old".to_string(), now - Duration::from_secs(10), 5),
+        ];
+
+        let evicted = reflectors_to_evict(reflectors, &config, new_objects).unwrap();
+        assert_eq!(evicted, expected);
+    }
+
+    #[rstest]
+    #[case::no_limit(None, vec![])]
+    #[case::below_limit(Some(30), vec![])]
+    #[case::grown_past_limit(Some(25), vec!["oldest"])]
+    #[case::grown_far_past_limit(Some(8), vec!["oldest", "old", "recent"])]
+    fn evict_reflectors_grown_past_objects_limit(
+        #[case] max_cached_objects: Option<usize>,
+        #[case] expected: Vec<&str>,
+    ) {
+        let now = Instant::now();
+        let reflectors = vec![
+            ("recent".to_string(), now, 10),
+            ("oldest".to_string(), now - Duration::from_secs(20), 15),
+            ("old".to_string(), now - Duration::from_secs(10), 5),
+        ];
+        let config = ReflectorConfig {
+            max_cached_objects,
+            ..Default::default()
+        };
+
+        assert_eq!(reflectors_over_objects_limit(reflectors, &config), expected);
+    }
+
+    /// Serve lists of `total` pods, paginated according to the `limit` and
+    /// `continue` parameters of the requests. Watch requests are never answered.
+    /// `list_requests` counts the pages served
+    async fn serve_pods(
+        mut handle: tower_test::mock::Handle<
+            hyper::Request<kube::client::Body>,
+            hyper::Response<kube::client::Body>,
+        >,
+        total: usize,
+        list_requests: Arc<AtomicUsize>,
+    ) {
+        let mut watches = Vec::new();
+        while let Some((request, send)) = handle.next_request().await {
+            assert_eq!(request.uri().path(), "/api/v1/pods");
+            let params: HashMap<&str, &str> = request
+                .uri()
+                .query()
+                .unwrap_or_default()
+                .split('&')
+                .filter_map(|param| param.split_once('='))
+                .collect();
+            if params.get("watch") == Some(&"true") {
+                watches.push(send);
+                continue;
+            }
+
+            list_requests.fetch_add(1, Ordering::SeqCst);
+            let offset: usize = params
+                .get("continue")
+                .map_or(0, |token| token.parse().unwrap());
+            let limit: usize = params
+                .get("limit")
+                .map_or(total, |limit| limit.parse().unwrap());
+            let end = total.min(offset + limit);
+            let items: Vec<_> = (offset..end)
+                .map(|i| json!({"metadata": {"name": format!("pod-{i}"), "namespace": "default"}}))
+                .collect();
+            let mut metadata = json!({"resourceVersion": "1"});
+            if end < total {
+                metadata["continue"] = json!(end.to_string());
+            }
+            let response = json!({
+                "apiVersion": "v1",
+                "kind": "PodList",
+                "metadata": metadata,
+                "items": items
+            });
+            send.send_response(
+                hyper::Response::builder()
+                    .body(kube::client::Body::from(
+                        serde_json::to_vec(&response).unwrap(),
+                    ))
+                    .unwrap(),
+            );
+        }
+    }
+
+    #[rstest]
+    #[case::no_limit(4, None, Some(4), 1)]
+    #[case::within_limit(4, Some(5), Some(4), 1)]
+    #[case::paginated(600, None, Some(600), 2)]
+    #[case::over_limit(1000, Some(5), None, 1)]
+    #[tokio::test]
+    async fn objects_limit_enforced_while_listing(
+        #[case] total: usize,
+        #[case] max_objects: Option<usize>,
+        #[case] expected_objects: Option<usize>,
+        #[case] expected_list_requests: usize,
+    ) {
+        let (mock_service, handle) = tower_test::mock::pair::<
+            hyper::Request<kube::client::Body>,
+            hyper::Response<kube::client::Body>,
+        >();
+        let list_requests = Arc::new(AtomicUsize::new(0));
+        tokio::spawn(serve_pods(handle, total, list_requests.clone()));
+        let resource = KubeResource {
+            resource: kube::api::ApiResource {
+                group: String::new(),
+                version: "v1".to_string(),
+                api_version: "v1".to_string(),
+                kind: "Pod".to_string(),
+                plural: "pods".to_string(),
+            },
+            namespaced: true,
+        };
+
+        let reflector = Reflector::create_and_run(
+            kube::Client::new(mock_service, "default"),
+            resource,
+            None,
+            None,
+            None,
+            None,
+            max_objects,
+        )
+        .await;
+
+        match expected_objects {
+            Some(objects) => assert_eq!(reflector.unwrap().reader.state().len(), objects),
+            None => {
+                let error = reflector
+                    .err()
+                    .expect("the reflector should not be created");
+                assert!(
+                    error.to_string().contains("more than the 5 objects"),
+                    "unexpected error: {error}"
+                );
+            }
+        }
+        assert_eq!(list_requests.load(Ordering::SeqCst), expected_list_requests);
+    }
+
+    #[test]
+    fn query_too_big_to_be_cached() {
+        let config = ReflectorConfig {
+            max_cached_objects: Some(10),
+            ..Default::default()
+        };
+        assert!(reflectors_to_evict(vec![], &config, 11).is_err());
+    }
 }
@@ -0,0 +1,235 @@
+use anyhow::{Result, anyhow};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// A requirement of a label selector, see
+/// https://kubernetes.io/docs/concepts/overview/working-with-objects/labels/#label-selectors
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum LabelRequirement {
+    Exists(String),
+    DoesNotExist(String),
+    Equals(String, String),
+    NotEquals(String, String),
+    In(String, BTreeSet<String>),
+    NotIn(String, BTreeSet<String>),
+}
+
+impl LabelRequirement {
+    fn matches(&self, labels: &BTreeMap<String, String>) -> bool {
+        match self {
+            LabelRequirement::Exists(key) => labels.contains_key(key),
+            LabelRequirement::DoesNotExist(key) => !labels.contains_key(key),
+            LabelRequirement::Equals(key, value) => labels.get(key) == Some(value),
+            LabelRequirement::NotEquals(key, value) => labels.get(key) != Some(value),
+            LabelRequirement::In(key, values) => {
+                labels.get(key).is_some_and(|v| values.contains(v))
+            }
+            LabelRequirement::NotIn(key, values) => {
+                !labels.get(key).is_some_and(|v| values.contains(v))
+            }
+        }
+    }
+}
+
+/// A label selector evaluated in memory, using the same string syntax
+/// accepted by the Kubernetes API server
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct LabelSelector {
+    requirements: Vec<LabelRequirement>,
+}
+
+impl LabelSelector {
+    pub fn parse(selector: &str) -> Result<Self> {
+        let requirements = split_requirements(selector)
+            .into_iter()
+            .map(parse_label_requirement)
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(LabelSelector { requirements })
+    }
+
+    pub fn matches(&self, labels: &BTreeMap<String, String>) -> bool {
+        self.requirements.iter().all(|req| req.matches(labels))
+    }
+}
+
+/// Split the selector on the commas that are not inside of a set of values,
+/// like `env in (prod, staging)`
+fn split_requirements(selector: &str) -> Vec<&str> {
+    let mut requirements = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+
+    for (pos, c) in selector.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                requirements.push(&selector[start..pos]);
+                start = pos + 1;
+            }
+            _ => {}
+        }
+    }
+    requirements.push(&selector[start..]);
+
+    requirements
+        .into_iter()
+        .map(str::trim)
+        .filter(|req| !req.is_empty())
+        .collect()
+}
+
+fn parse_label_requirement(requirement: &str) -> Result<LabelRequirement> {
+    if let Some((key, value)) = requirement.split_once("!=") {
+        return Ok(LabelRequirement::NotEquals(
+            key.trim().to_string(),
+            value.trim().to_string(),
+        ));
+    }
+    if let Some((key, value)) = requirement
+        .split_once("==")
+        .or_else(|| requirement.split_once('='))
+    {
+        return Ok(LabelRequirement::Equals(
+            key.trim().to_string(),
+            value.trim().to_string(),
+        ));
+    }
+    if let Some((key, values)) = requirement.split_once(" notin ") {
+        return Ok(LabelRequirement::NotIn(
+            key.trim().to_string(),
+            parse_set(values)?,
+        ));
+    }
+    if let Some((key, values)) = requirement.split_once(" in ") {
+        return Ok(LabelRequirement::In(
+            key.trim().to_string(),
+            parse_set(values)?,
+        ));
+    }
+    if let Some(key) = requirement.strip_prefix('!') {
+        return Ok(LabelRequirement::DoesNotExist(key.trim().to_string()));
+    }
+    if requirement.contains(char::is_whitespace) {
+        return Err(anyhow!("invalid label selector requirement: {requirement}"));
+    }
+
+    Ok(LabelRequirement::Exists(requirement.to_string()))
+}
+
+fn parse_set(values: &str) -> Result<BTreeSet<String>> {
+    let values = values
+        .trim()
+        .strip_prefix('(')
+        .and_then(|v| v.strip_suffix(')'))
+        .ok_or_else(|| anyhow!("invalid set of values inside of label selector: {values}"))?;
+
+    Ok(values
+        .split(',')
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+        .collect())
+}
+
+/// A field selector evaluated in memory, using the same string syntax
+/// accepted by the Kubernetes API server.
+///
+/// Field paths are resolved against the JSON representation of the object,
+/// missing fields are considered to be empty strings.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct FieldSelector {
+    requirements: Vec<(String, bool, String)>,
+}
+
+impl FieldSelector {
+    pub fn parse(selector: &str) -> Result<Self> {
+        let requirements = split_requirements(selector)
+            .into_iter()
+            .map(|requirement| {
+                if let Some((path, value)) = requirement.split_once("!=") {
+                    Ok((path.trim().to_string(), false, value.trim().to_string()))
+                } else if let Some((path, value)) = requirement
+                    .split_once("==")
+                    .or_else(|| requirement.split_once('='))
+                {
+                    Ok((path.trim().to_string(), true, value.trim().to_string()))
+                } else {
+                    Err(anyhow!("invalid field selector requirement: {requirement}"))
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(FieldSelector { requirements })
+    }
+
+    /// The paths of the fields used by the selector, like `spec.nodeName`
+    pub fn paths(&self) -> impl Iterator<Item = &str> {
+        self.requirements.iter().map(|(path, _, _)| path.as_str())
+    }
+
+    pub fn matches(&self, object: &serde_json::Value) -> bool {
+        self.requirements.iter().all(|(path, equal, expected)| {
+            let value = path
+                .split('.')
+                .try_fold(object, |value, key| value.get(key))
+                .map(|value| match value {
+                    serde_json::Value::String(s) => s.to_owned(),
+                    serde_json::Value::Null => String::new(),
+                    other => other.to_string(),
+                })
+                .unwrap_or_default();
+            (&value == expected) == *equal
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use serde_json::json;
+
+    #[rstest]
+    #[case::empty("", true)]
+    #[case::equals("app=nginx", true)]
+    #[case::double_equals("app==nginx", true)]
+    #[case::not_equals("app!=nginx", false)]
+    #[case::exists("tier", true)]
+    #[case::does_not_exist("!tier", false)]
+    #[case::in_set("tier in (frontend, backend)", true)]
+    #[case::not_in_set("tier notin (frontend, backend)", false)]
+    #[case::multiple("app=nginx,tier in (backend),!canary", false)]
+    #[case::multiple_matching("app=nginx, tier in (frontend,backend), !canary", true)]
+    fn label_selector(#[case] selector: &str, #[case] expected: bool) {
+        let labels = BTreeMap::from([
+            ("app".to_string(), "nginx".to_string()),
+            ("tier".to_string(), "frontend".to_string()),
+        ]);
+
+        let selector = LabelSelector::parse(selector).expect("cannot parse selector");
+        assert_eq!(selector.matches(&labels), expected);
+    }
+
+    #[rstest]
+    #[case::invalid_set("tier in frontend")]
+    #[case::invalid_requirement("app nginx")]
+    fn invalid_label_selector(#[case] selector: &str) {
+        assert!(LabelSelector::parse(selector).is_err());
+    }
+
+    #[rstest]
+    #[case::equals("metadata.name=nginx", true)]
+    #[case::not_equals("metadata.namespace!=default", false)]
+    #[case::missing_field("spec.nodeName=", true)]
+    #[case::non_string_field("spec.replicas=3", true)]
+    #[case::multiple("metadata.name=nginx,metadata.namespace=kube-system", false)]
+    fn field_selector(#[case] selector: &str, #[case] expected: bool) {
+        let object = json!({
+            "metadata": {"name": "nginx", "namespace": "default"},
+            "spec": {"replicas": 3}
+        });
+
+        let selector = FieldSelector::parse(selector).expect("cannot parse selector");
+        assert_eq!(selector.matches(&object), expected);
+    }
+}
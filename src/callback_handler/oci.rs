@@ -1,17 +1,24 @@
-use std::time::Duration;
+use std::collections::BTreeMap;
 
-use anyhow::Result;
-use cached::proc_macro::cached;
+use anyhow::{Result, anyhow};
 use kubewarden_policy_sdk::host_capabilities::oci::ManifestDigestResponse;
 use policy_fetcher::{
     oci_client::{
-        Reference,
-        manifest::{OciImageManifest, OciManifest},
+        self, Reference, RegistryOperation,
+        errors::{OciDistributionError, OciErrorCode},
+        manifest::{OciImageIndex, OciImageManifest, OciManifest},
     },
     registry::Registry,
+    sigstore,
     sources::Sources,
 };
 use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use super::cache::Cache;
+
+/// Media type of the layers holding the cosign attestations
+const DSSE_ENVELOPE_MEDIA_TYPE: &str = "application/vnd.dsse.envelope.v1+json";
 
 /// Helper struct to interact with an OCI registry
 pub(crate) struct Client {
@@ -26,6 +33,43 @@ pub struct ManifestAndConfigResponse {
     pub config: serde_json::Value,
 }
 
+/// Request of the `oci` `v1/oci_referrers` operation
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct OciReferrersRequest {
+    /// String pointing to the object (e.g.: `registry.testing.lan/busybox:1.0.0`)
+    pub image: String,
+    /// Return only the referrers of the given artifact type, like
+    /// `application/spdx+json`. Optional
+    #[serde(default)]
+    pub artifact_type: Option<String>,
+}
+
+/// An artifact referring to an OCI object, like a SBOM or an attestation
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct OciReferrer {
+    pub media_type: String,
+    #[serde(default)]
+    pub artifact_type: Option<String>,
+    pub digest: String,
+    pub size: i64,
+    #[serde(default)]
+    pub annotations: Option<BTreeMap<String, String>>,
+}
+
+/// Response of the `oci` `v1/oci_referrers` operation
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct OciReferrersResponse {
+    /// Digest of the manifest the referrers point to
+    pub digest: String,
+    /// The artifacts found via the OCI 1.1 referrers API, or via the referrers
+    /// tag schema when the registry does not implement the API
+    pub referrers: Vec<OciReferrer>,
+    /// Reference of the cosign attestations of the object, stored using the
+    /// `sha256-<digest>.att` tag convention. `None` when there are none
+    pub cosign_attestations: Option<String>,
+}
+
 impl Client {
     pub fn new(sources: Option<Sources>) -> Self {
         let registry = Registry {};
@@ -75,74 +119,345 @@ impl Client {
             config,
         })
     }
+
+    /// Find the artifacts referring to the OCI resource referenced via `image`
+    pub async fn referrers(
+        &self,
+        image: &str,
+        artifact_type: Option<&str>,
+    ) -> Result<OciReferrersResponse> {
+        let image_ref: Reference = image.parse()?;
+        let digest = self.digest(image).await?;
+        let subject = Reference::with_digest(
+            image_ref.registry().to_owned(),
+            image_ref.repository().to_owned(),
+            digest.clone(),
+        );
+
+        // registries not implementing the referrers API answer with a 404
+        let referrers = match found(self.referrers_from_api(&subject, artifact_type).await)? {
+            Some(referrers) => referrers,
+            None => {
+                debug!(
+                    image,
+                    "the referrers API is not supported, falling back to the referrers tag schema"
+                );
+                self.referrers_from_tag_schema(&image_ref, &digest, artifact_type)
+                    .await?
+            }
+        };
+
+        let cosign_attestations = found(
+            self.registry
+                .manifest_digest(
+                    &format!(
+                        "registry://{}",
+                        cosign_attestations_tag(&image_ref, &digest)
+                    ),
+                    self.sources.as_ref(),
+                )
+                .await,
+        )?
+        .map(|attestations_digest| {
+            format!(
+                "{}/{}@{attestations_digest}",
+                image_ref.registry(),
+                image_ref.repository()
+            )
+        });
+
+        Ok(OciReferrersResponse {
+            digest,
+            referrers,
+            cosign_attestations,
+        })
+    }
+
+    /// Fetch the DSSE envelopes of the cosign attestations of the OCI resource
+    /// referenced via `image`. Returns the digest of the resource too
+    pub async fn cosign_attestation_envelopes(
+        &self,
+        image: &str,
+    ) -> Result<(String, Vec<Vec<u8>>)> {
+        let image_ref: Reference = image.parse()?;
+        let digest = self.digest(image).await?;
+        let attestations_ref: Reference = cosign_attestations_tag(&image_ref, &digest).parse()?;
+
+        let manifest = self
+            .registry
+            .manifest(
+                &format!("registry://{}", attestations_ref.whole()),
+                self.sources.as_ref(),
+            )
+            .await
+            .map_err(|e| anyhow!("cannot find the attestations of {image}: {e}"))?;
+        let OciManifest::Image(manifest) = manifest else {
+            return Err(anyhow!(
+                "the attestations of {image} are not an image manifest"
+            ));
+        };
+
+        let client = self.authenticated_client(&attestations_ref).await?;
+        let mut envelopes = vec![];
+        for layer in manifest
+            .layers
+            .iter()
+            .filter(|layer| layer.media_type == DSSE_ENVELOPE_MEDIA_TYPE)
+        {
+            let mut envelope = Vec::new();
+            client
+                .pull_blob(&attestations_ref, layer, &mut envelope)
+                .await?;
+            envelopes.push(envelope);
+        }
+
+        Ok((digest, envelopes))
+    }
+
+    async fn referrers_from_api(
+        &self,
+        subject: &Reference,
+        artifact_type: Option<&str>,
+    ) -> Result<Vec<OciReferrer>> {
+        let client = self.authenticated_client(subject).await?;
+        let index = client.pull_referrers(subject, artifact_type).await?;
+        referrers_from_index(index, artifact_type)
+    }
+
+    async fn referrers_from_tag_schema(
+        &self,
+        image_ref: &Reference,
+        digest: &str,
+        artifact_type: Option<&str>,
+    ) -> Result<Vec<OciReferrer>> {
+        let tag = format!(
+            "registry://{}/{}:{}",
+            image_ref.registry(),
+            image_ref.repository(),
+            digest.replace(':', "-")
+        );
+        match found(self.registry.manifest(&tag, self.sources.as_ref()).await)? {
+            Some(OciManifest::ImageIndex(index)) => referrers_from_index(index, artifact_type),
+            Some(_) => Err(anyhow!("the referrers tag {tag} is not an image index")),
+            // the tag exists only when some artifact refers to the object
+            None => Ok(vec![]),
+        }
+    }
+
+    async fn authenticated_client(&self, reference: &Reference) -> Result<oci_client::Client> {
+        let client_config: sigstore::registry::ClientConfig =
+            self.sources.clone().unwrap_or_default().into();
+        let client = oci_client::Client::new(client_config.into());
+        client
+            .auth(
+                reference,
+                &Registry::auth(reference.registry()),
+                RegistryOperation::Pull,
+            )
+            .await?;
+        Ok(client)
+    }
+}
+
+/// The tag used by cosign to store the attestations of the object with the
+/// given digest
+fn cosign_attestations_tag(image_ref: &Reference, digest: &str) -> String {
+    format!(
+        "{}/{}:{}.att",
+        image_ref.registry(),
+        image_ref.repository(),
+        digest.replace(':', "-")
+    )
+}
+
+/// Turn the errors reporting that the requested object does not exist into
+/// `None`, all the other errors are returned
+fn found<T, E>(result: std::result::Result<T, E>) -> Result<Option<T>>
+where
+    E: Into<anyhow::Error>,
+{
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(e) => {
+            let error: anyhow::Error = e.into();
+            if is_not_found(&error) {
+                Ok(None)
+            } else {
+                Err(error)
+            }
+        }
+    }
+}
+
+fn is_not_found(error: &anyhow::Error) -> bool {
+    error
+        .chain()
+        .filter_map(|cause| cause.downcast_ref::<OciDistributionError>())
+        .any(|cause| match cause {
+            OciDistributionError::ImageManifestNotFoundError(_) => true,
+            OciDistributionError::ServerError { code, .. } => *code == 404,
+            OciDistributionError::RegistryError { envelope, .. } => envelope
+                .errors
+                .iter()
+                .any(|error| matches!(error.code, OciErrorCode::ManifestUnknown)),
+            _ => false,
+        })
+}
+
+fn referrers_from_index(
+    index: OciImageIndex,
+    artifact_type: Option<&str>,
+) -> Result<Vec<OciReferrer>> {
+    let referrers: Vec<OciReferrer> =
+        serde_json::from_value(serde_json::to_value(index.manifests)?)?;
+    Ok(referrers
+        .into_iter()
+        .filter(|referrer| {
+            artifact_type.is_none() || referrer.artifact_type.as_deref() == artifact_type
+        })
+        .collect())
 }
 
 // Interacting with a remote OCI registry is time expensive, this can cause a massive slow down
 // of policy evaluations, especially inside of PolicyServer.
-// Because of that we will keep a cache of the digests results.
-//
-// Details about this cache:
-//   * only the image "url" is used as key. oci::Client is not hashable, plus
-//     the client is always the same
-//   * the cache is time bound: cached values are purged after 60 seconds
-//   * only successful results are cached
-#[cached(
-    time = 60,
-    result = true,
-    sync_writes = "default",
-    key = "String",
-    convert = r#"{ format!("{}", img) }"#,
-    with_cached_flag = true
-)]
+// Because of that we keep a cache of the results, only the image "url" is used as key.
+// The lifetime of the cached results is set via `CallbackHandlerBuilder::cache_config`.
 pub(crate) async fn get_oci_digest_cached(
     oci_client: &Client,
+    cache: &Cache<ManifestDigestResponse>,
     img: &str,
 ) -> Result<cached::Return<ManifestDigestResponse>> {
-    oci_client
-        .digest(img)
+    cache
+        .get_or_insert_with(img.to_string(), async {
+            oci_client
+                .digest(img)
+                .await
+                .map(|digest| ManifestDigestResponse { digest })
+        })
         .await
-        .map(|digest| ManifestDigestResponse { digest })
-        .map(cached::Return::new)
 }
 
-// Interacting with a remote OCI registry is time expensive, this can cause a massive slow down
-// of policy evaluations, especially inside of PolicyServer.
-// Because of that we will keep a cache of the manifest results.
-//
-// Details about this cache:
-//   * only the image "url" is used as key. oci::Client is not hashable, plus
-//     the client is always the same
-//   * the cache is time bound: cached values are purged after 60 seconds
-//   * only successful results are cached
-#[cached(
-    time = 60,
-    result = true,
-    sync_writes = "default",
-    key = "String",
-    convert = r#"{ format!("{}", img) }"#,
-    with_cached_flag = true
-)]
 pub(crate) async fn get_oci_manifest_cached(
     oci_client: &Client,
+    cache: &Cache<OciManifest>,
     img: &str,
 ) -> Result<cached::Return<OciManifest>> {
-    oci_client.manifest(img).await.map(cached::Return::new)
+    cache
+        .get_or_insert_with(img.to_string(), oci_client.manifest(img))
+        .await
+}
+
+pub(crate) async fn get_oci_referrers_cached(
+    oci_client: &Client,
+    cache: &Cache<OciReferrersResponse>,
+    request: &OciReferrersRequest,
+) -> Result<cached::Return<OciReferrersResponse>> {
+    let key = format!("{}{:?}", request.image, request.artifact_type);
+    cache
+        .get_or_insert_with(
+            key,
+            oci_client.referrers(&request.image, request.artifact_type.as_deref()),
+        )
+        .await
 }
 
-#[cached(
-    time = 60,
-    result = true,
-    sync_writes = "default",
-    key = "String",
-    convert = r#"{ format!("{}", img) }"#,
-    with_cached_flag = true
-)]
 pub(crate) async fn get_oci_manifest_and_config_cached(
     oci_client: &Client,
+    cache: &Cache<ManifestAndConfigResponse>,
     img: &str,
 ) -> Result<cached::Return<ManifestAndConfigResponse>> {
-    oci_client
-        .manifest_and_config(img)
+    cache
+        .get_or_insert_with(img.to_string(), oci_client.manifest_and_config(img))
         .await
-        .map(cached::Return::new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case::tag(
+        "ghcr.io/kubewarden/policy-server:latest",
+        "ghcr.io/kubewarden/policy-server:sha256-1234.att"
+    )]
+    #[case::docker_hub("busybox", "docker.io/library/busybox:sha256-1234.att")]
+    fn cosign_attestations_tags(#[case] image: &str, #[case] expected: &str) {
+        let image_ref: Reference = image.parse().unwrap();
+        assert_eq!(cosign_attestations_tag(&image_ref, "sha256:1234"), expected);
+    }
+
+    #[rstest]
+    #[case::all(None, vec!["sha256:sbom", "sha256:provenance"])]
+    #[case::filtered(Some("application/spdx+json"), vec!["sha256:sbom"])]
+    fn referrers_of_index(#[case] artifact_type: Option<&str>, #[case] expected: Vec<&str>) {
+        let index: OciImageIndex = serde_json::from_value(serde_json::json!({
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.index.v1+json",
+            "manifests": [
+                {
+                    "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                    "artifactType": "application/spdx+json",
+                    "digest": "sha256:sbom",
+                    "size": 100
+                },
+                {
+                    "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                    "artifactType": "application/vnd.in-toto+json",
+                    "digest": "sha256:provenance",
+                    "size": 200
+                }
+            ]
+        }))
+        .unwrap();
+
+        let digests: Vec<String> = referrers_from_index(index, artifact_type)
+            .unwrap()
+            .into_iter()
+            .map(|referrer| referrer.digest)
+            .collect();
+        assert_eq!(digests, expected);
+    }
+
+    fn registry_error(code: &str) -> OciDistributionError {
+        OciDistributionError::RegistryError {
+            envelope: serde_json::from_value(serde_json::json!({
+                "errors": [{ "code": code, "message": "", "detail": null }]
+            }))
+            .unwrap(),
+            url: "https://ghcr.io/v2/kubewarden/policy-server/manifests/latest".to_string(),
+        }
+    }
+
+    fn server_error(code: u16) -> OciDistributionError {
+        OciDistributionError::ServerError {
+            code,
+            url: "https://ghcr.io/v2/kubewarden/policy-server/referrers/sha256:1234".to_string(),
+            message: String::new(),
+        }
+    }
+
+    #[rstest]
+    #[case::manifest_not_found(
+        OciDistributionError::ImageManifestNotFoundError("latest".to_string()),
+        true
+    )]
+    #[case::manifest_unknown(registry_error("MANIFEST_UNKNOWN"), true)]
+    #[case::http_not_found(server_error(404), true)]
+    #[case::unauthorized(registry_error("UNAUTHORIZED"), false)]
+    #[case::server_error(server_error(500), false)]
+    fn not_found_errors(#[case] error: OciDistributionError, #[case] not_found: bool) {
+        let result = found::<(), _>(Err(error));
+        if not_found {
+            assert!(result.unwrap().is_none());
+        } else {
+            assert!(result.is_err());
+        }
+    }
+
+    #[test]
+    fn wrapped_not_found_errors() {
+        let error = anyhow::Error::from(server_error(404)).context("cannot fetch the manifest");
+        assert!(found::<(), _>(Err(error)).unwrap().is_none());
+    }
 }
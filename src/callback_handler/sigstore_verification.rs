@@ -1,7 +1,7 @@
-use std::{collections::BTreeMap, sync::Arc, time::Duration};
+use std::{collections::BTreeMap, sync::Arc};
 
 use anyhow::{Result, anyhow};
-use cached::proc_macro::cached;
+use base64::{Engine as _, engine::general_purpose};
 use itertools::Itertools;
 use kubewarden_policy_sdk::host_capabilities::verification::{
     KeylessInfo, KeylessPrefixInfo, VerificationResponse,
@@ -15,6 +15,7 @@ use policy_fetcher::{
         fetch_sigstore_remote_data,
     },
 };
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use sigstore::{
     cosign::verification_constraint::{
@@ -25,6 +26,66 @@ use sigstore::{
 use tokio::sync::Mutex;
 use tracing::warn;
 
+use super::cache::Cache;
+use super::{crypto, oci};
+
+/// Request of the `oci` `v1/verify_attestation` operation
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AttestationVerificationRequest {
+    /// String pointing to the object (e.g.: `registry.testing.lan/busybox:1.0.0`)
+    pub image: String,
+    /// The in-toto predicate type of the attestation, like
+    /// `https://slsa.dev/provenance/v1`
+    pub predicate_type: String,
+    /// PEM encoded public keys. The attestation must be signed by at least one of them
+    pub pub_keys: Vec<String>,
+}
+
+/// Response of the `oci` `v1/verify_attestation` operation
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AttestationVerificationResponse {
+    pub is_trusted: bool,
+    /// Digest of the verified image
+    pub digest: String,
+    pub predicate_type: String,
+    /// The in-toto predicate of the attestation
+    pub predicate: serde_json::Value,
+}
+
+/// The payload type of the DSSE envelopes holding an in-toto statement
+const IN_TOTO_PAYLOAD_TYPE: &str = "application/vnd.in-toto+json";
+
+/// A DSSE envelope, as stored by cosign inside of the attestation layers
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Envelope {
+    payload_type: String,
+    /// base64 encoded payload
+    payload: String,
+    signatures: Vec<EnvelopeSignature>,
+}
+
+#[derive(Deserialize)]
+struct EnvelopeSignature {
+    /// base64 encoded signature
+    sig: String,
+}
+
+/// An in-toto statement
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Statement {
+    subject: Vec<StatementSubject>,
+    predicate_type: String,
+    #[serde(default)]
+    predicate: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct StatementSubject {
+    digest: BTreeMap<String, String>,
+}
+
 #[derive(Clone)]
 pub(crate) struct Client {
     cosign_client: Arc<Mutex<sigstore::cosign::Client>>,
@@ -258,113 +319,178 @@ impl Client {
     }
 }
 
+/// Verify the cosign attestations of the image, stored using the
+/// `sha256-<digest>.att` tag convention. Returns the predicate of the first
+/// attestation of the requested type that is signed by one of the keys and
+/// refers to the image
+pub(crate) async fn verify_attestation(
+    oci_client: &oci::Client,
+    request: &AttestationVerificationRequest,
+) -> Result<AttestationVerificationResponse> {
+    let pub_keys = parse_pub_keys(&request.pub_keys)?;
+
+    let (digest, envelopes) = oci_client
+        .cosign_attestation_envelopes(&request.image)
+        .await?;
+    for envelope in envelopes {
+        match verify_envelope(&envelope, &digest, &request.predicate_type, &pub_keys) {
+            Ok(Some(predicate)) => {
+                return Ok(AttestationVerificationResponse {
+                    is_trusted: true,
+                    digest,
+                    predicate_type: request.predicate_type.clone(),
+                    predicate,
+                });
+            }
+            Ok(None) => {}
+            Err(e) => warn!(
+                image = request.image.as_str(),
+                error = e.to_string(),
+                "skipping invalid attestation"
+            ),
+        }
+    }
+
+    Err(anyhow!(
+        "no attestation of type {} signed by the provided keys found for {}",
+        request.predicate_type,
+        request.image
+    ))
+}
+
+/// Parse the PEM encoded public keys, all of them must be valid
+fn parse_pub_keys(pub_keys: &[String]) -> Result<Vec<crypto::PublicKey>> {
+    if pub_keys.is_empty() {
+        return Err(anyhow!("Must provide at least one pub key"));
+    }
+
+    pub_keys
+        .iter()
+        .map(|pub_key| crypto::PublicKey::from_pem(pub_key))
+        .collect()
+}
+
+/// Returns the predicate of the statement held by the envelope, when the
+/// envelope holds an in-toto statement signed by one of the given keys,
+/// and the statement has the requested predicate type and refers to the image
+/// with the given digest
+fn verify_envelope(
+    envelope: &[u8],
+    image_digest: &str,
+    predicate_type: &str,
+    pub_keys: &[crypto::PublicKey],
+) -> Result<Option<serde_json::Value>> {
+    let envelope: Envelope = serde_json::from_slice(envelope)?;
+    if envelope.payload_type != IN_TOTO_PAYLOAD_TYPE {
+        return Ok(None);
+    }
+    let payload = general_purpose::STANDARD.decode(&envelope.payload)?;
+    let message = pre_authentication_encoding(&envelope.payload_type, &payload);
+
+    let signed = envelope.signatures.iter().any(|signature| {
+        // a malformed signature is not produced by any of the keys
+        general_purpose::STANDARD
+            .decode(&signature.sig)
+            .is_ok_and(|signature| {
+                pub_keys
+                    .iter()
+                    .any(|pub_key| pub_key.verify(&message, &signature))
+            })
+    });
+    if !signed {
+        return Ok(None);
+    }
+
+    let statement: Statement = serde_json::from_slice(&payload)?;
+    let (algorithm, hash) = image_digest
+        .split_once(':')
+        .ok_or_else(|| anyhow!("invalid digest {image_digest}"))?;
+    let refers_to_image = statement
+        .subject
+        .iter()
+        .any(|subject| subject.digest.get(algorithm).map(String::as_str) == Some(hash));
+    if statement.predicate_type != predicate_type || !refers_to_image {
+        return Ok(None);
+    }
+
+    Ok(Some(statement.predicate))
+}
+
+/// The DSSE pre-authentication encoding, which is what gets signed
+fn pre_authentication_encoding(payload_type: &str, payload: &[u8]) -> Vec<u8> {
+    let mut message = format!(
+        "DSSEv1 {} {} {} ",
+        payload_type.len(),
+        payload_type,
+        payload.len()
+    )
+    .into_bytes();
+    message.extend_from_slice(payload);
+    message
+}
+
 // Sigstore verifications are time expensive, this can cause a massive slow down
 // of policy evaluations, especially inside of PolicyServer.
-// Because of that we will keep a cache of the digests results.
-//
-// Details about this cache:
-//   * the cache is time bound: cached values are purged after 60 seconds
-//   * only successful results are cached
-#[cached(
-    time = 60,
-    result = true,
-    sync_writes = "default",
-    key = "String",
-    convert = r#"{ format!("{}{:?}{:?}", image, pub_keys, annotations)}"#,
-    with_cached_flag = true
-)]
+// Because of that we keep a cache of the results, shared by all the kinds of
+// verification. The keys are prefixed by the kind of verification to avoid clashes.
+// The lifetime of the cached results is set via `CallbackHandlerBuilder::cache_config`.
 pub(crate) async fn get_sigstore_pub_key_verification_cached(
     client: &mut Client,
+    cache: &Cache<VerificationResponse>,
     image: String,
     pub_keys: Vec<String>,
     annotations: Option<BTreeMap<String, String>>,
 ) -> Result<cached::Return<VerificationResponse>> {
-    client
-        .verify_public_key(image, pub_keys, annotations)
+    let key = format!("pub_key:{image}{pub_keys:?}{annotations:?}");
+    cache
+        .get_or_insert_with(key, client.verify_public_key(image, pub_keys, annotations))
         .await
-        .map(cached::Return::new)
 }
 
-// Sigstore verifications are time expensive, this can cause a massive slow down
-// of policy evaluations, especially inside of PolicyServer.
-// Because of that we will keep a cache of the digests results.
-//
-// Details about this cache:
-//   * the cache is time bound: cached values are purged after 60 seconds
-//   * only successful results are cached
-#[cached(
-    time = 60,
-    result = true,
-    sync_writes = "default",
-    key = "String",
-    convert = r#"{ format!("{}{:?}{:?}", image, keyless, annotations)}"#,
-    with_cached_flag = true
-)]
 pub(crate) async fn get_sigstore_keyless_verification_cached(
     client: &mut Client,
+    cache: &Cache<VerificationResponse>,
     image: String,
     keyless: Vec<KeylessInfo>,
     annotations: Option<BTreeMap<String, String>>,
 ) -> Result<cached::Return<VerificationResponse>> {
-    client
-        .verify_keyless(image, keyless, annotations)
+    let key = format!("keyless:{image}{keyless:?}{annotations:?}");
+    cache
+        .get_or_insert_with(key, client.verify_keyless(image, keyless, annotations))
         .await
-        .map(cached::Return::new)
 }
 
-// Sigstore verifications are time expensive, this can cause a massive slow down
-// of policy evaluations, especially inside of PolicyServer.
-// Because of that we will keep a cache of the digests results.
-//
-// Details about this cache:
-//   * the cache is time bound: cached values are purged after 60 seconds
-//   * only successful results are cached
-#[cached(
-    time = 60,
-    result = true,
-    sync_writes = "default",
-    key = "String",
-    convert = r#"{ format!("{}{:?}{:?}", image, keyless_prefix, annotations)}"#,
-    with_cached_flag = true
-)]
 pub(crate) async fn get_sigstore_keyless_prefix_verification_cached(
     client: &mut Client,
+    cache: &Cache<VerificationResponse>,
     image: String,
     keyless_prefix: Vec<KeylessPrefixInfo>,
     annotations: Option<BTreeMap<String, String>>,
 ) -> Result<cached::Return<VerificationResponse>> {
-    client
-        .verify_keyless_prefix(image, keyless_prefix, annotations)
+    let key = format!("keyless_prefix:{image}{keyless_prefix:?}{annotations:?}");
+    cache
+        .get_or_insert_with(
+            key,
+            client.verify_keyless_prefix(image, keyless_prefix, annotations),
+        )
         .await
-        .map(cached::Return::new)
 }
 
-// Sigstore verifications are time expensive, this can cause a massive slow down
-// of policy evaluations, especially inside of PolicyServer.
-// Because of that we will keep a cache of the digests results.
-//
-// Details about this cache:
-//   * the cache is time bound: cached values are purged after 60 seconds
-//   * only successful results are cached
-#[cached(
-    time = 60,
-    result = true,
-    sync_writes = "default",
-    key = "String",
-    convert = r#"{ format!("{}{:?}{:?}{:?}", image, owner, repo, annotations)}"#,
-    with_cached_flag = true
-)]
 pub(crate) async fn get_sigstore_github_actions_verification_cached(
     client: &mut Client,
+    cache: &Cache<VerificationResponse>,
     image: String,
     owner: String,
     repo: Option<String>,
     annotations: Option<BTreeMap<String, String>>,
 ) -> Result<cached::Return<VerificationResponse>> {
-    client
-        .verify_github_actions(image, owner, repo, annotations)
+    let key = format!("github_actions:{image}{owner:?}{repo:?}{annotations:?}");
+    cache
+        .get_or_insert_with(
+            key,
+            client.verify_github_actions(image, owner, repo, annotations),
+        )
         .await
-        .map(cached::Return::new)
 }
 
 fn get_sigstore_certificate_verification_cache_key(
@@ -402,30 +528,158 @@ fn get_sigstore_certificate_verification_cache_key(
     format!("{:x}", hasher.finalize())
 }
 
-#[cached(
-    time = 60,
-    result = true,
-    sync_writes = "default",
-    key = "String",
-    convert = r#"{ format!("{}", get_sigstore_certificate_verification_cache_key(image, certificate, certificate_chain, require_rekor_bundle, annotations.as_ref()))}"#,
-    with_cached_flag = true
-)]
 pub(crate) async fn get_sigstore_certificate_verification_cached(
     client: &mut Client,
+    cache: &Cache<VerificationResponse>,
     image: &str,
     certificate: &[u8],
     certificate_chain: Option<&[Vec<u8>]>,
     require_rekor_bundle: bool,
     annotations: Option<BTreeMap<String, String>>,
 ) -> Result<cached::Return<VerificationResponse>> {
-    client
-        .verify_certificate(
+    let key = format!(
+        "certificate:{}",
+        get_sigstore_certificate_verification_cache_key(
             image,
             certificate,
             certificate_chain,
             require_rekor_bundle,
-            annotations,
+            annotations.as_ref(),
         )
+    );
+    cache
+        .get_or_insert_with(
+            key,
+            client.verify_certificate(
+                image,
+                certificate,
+                certificate_chain,
+                require_rekor_bundle,
+                annotations,
+            ),
+        )
+        .await
+}
+
+pub(crate) async fn get_sigstore_attestation_verification_cached(
+    oci_client: &oci::Client,
+    cache: &Cache<AttestationVerificationResponse>,
+    request: AttestationVerificationRequest,
+) -> Result<cached::Return<AttestationVerificationResponse>> {
+    let key = format!(
+        "attestation:{}{}{:?}",
+        request.image, request.predicate_type, request.pub_keys
+    );
+    cache
+        .get_or_insert_with(key, verify_attestation(oci_client, &request))
         .await
-        .map(cached::Return::new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rcgen::KeyPair;
+    use rstest::rstest;
+    use serde_json::json;
+
+    const IMAGE_DIGEST: &str = "sha256:1234";
+    const PREDICATE_TYPE: &str = "https://slsa.dev/provenance/v1";
+
+    fn signed_envelope(signing_key: &KeyPair, payload_type: &str, subject_digest: &str) -> Vec<u8> {
+        let statement = json!({
+            "_type": "https://in-toto.io/Statement/v1",
+            "subject": [{"name": "ghcr.io/kubewarden/policy", "digest": {"sha256": subject_digest}}],
+            "predicateType": PREDICATE_TYPE,
+            "predicate": {"builder": {"id": "https://github.com/actions"}}
+        });
+        let payload = serde_json::to_vec(&statement).unwrap();
+
+        let rng = ring::rand::SystemRandom::new();
+        let key_pair = ring::signature::EcdsaKeyPair::from_pkcs8(
+            &ring::signature::ECDSA_P256_SHA256_ASN1_SIGNING,
+            &signing_key.serialize_der(),
+            &rng,
+        )
+        .unwrap();
+        let signature = key_pair
+            .sign(&rng, &pre_authentication_encoding(payload_type, &payload))
+            .unwrap();
+
+        serde_json::to_vec(&json!({
+            "payloadType": payload_type,
+            "payload": general_purpose::STANDARD.encode(&payload),
+            "signatures": [{"keyid": "", "sig": general_purpose::STANDARD.encode(signature.as_ref())}]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn pre_authentication_encoding_of_payload() {
+        assert_eq!(
+            pre_authentication_encoding("http://example.com/HelloWorld", b"hello world"),
+            b"DSSEv1 29 http://example.com/HelloWorld 11 hello world".to_vec()
+        );
+    }
+
+    #[rstest]
+    #[case::verified(true, IN_TOTO_PAYLOAD_TYPE, "1234", PREDICATE_TYPE, true)]
+    #[case::other_key(false, IN_TOTO_PAYLOAD_TYPE, "1234", PREDICATE_TYPE, false)]
+    #[case::other_payload_type(true, "application/json", "1234", PREDICATE_TYPE, false)]
+    #[case::other_image(true, IN_TOTO_PAYLOAD_TYPE, "5678", PREDICATE_TYPE, false)]
+    #[case::other_predicate_type(
+        true,
+        IN_TOTO_PAYLOAD_TYPE,
+        "1234",
+        "https://spdx.dev/Document",
+        false
+    )]
+    fn envelope_verification(
+        #[case] same_key: bool,
+        #[case] payload_type: &str,
+        #[case] subject_digest: &str,
+        #[case] predicate_type: &str,
+        #[case] verified: bool,
+    ) {
+        let signing_key = KeyPair::generate().unwrap();
+        let envelope = signed_envelope(&signing_key, payload_type, subject_digest);
+        let pub_key = if same_key {
+            signing_key.public_key_pem()
+        } else {
+            KeyPair::generate().unwrap().public_key_pem()
+        };
+
+        let pub_keys = parse_pub_keys(&[pub_key]).unwrap();
+
+        let predicate =
+            verify_envelope(&envelope, IMAGE_DIGEST, predicate_type, &pub_keys).unwrap();
+        assert_eq!(
+            predicate,
+            verified.then(|| json!({"builder": {"id": "https://github.com/actions"}}))
+        );
+    }
+
+    #[test]
+    fn signed_by_one_of_the_keys() {
+        let signing_key = KeyPair::generate().unwrap();
+        let envelope = signed_envelope(&signing_key, IN_TOTO_PAYLOAD_TYPE, "1234");
+        let pub_keys = parse_pub_keys(&[
+            KeyPair::generate().unwrap().public_key_pem(),
+            signing_key.public_key_pem(),
+        ])
+        .unwrap();
+
+        let predicate =
+            verify_envelope(&envelope, IMAGE_DIGEST, PREDICATE_TYPE, &pub_keys).unwrap();
+        assert!(predicate.is_some());
+    }
+
+    #[rstest]
+    #[case::no_keys(vec![])]
+    #[case::malformed_key(vec![
+        KeyPair::generate().unwrap().public_key_pem(),
+        "not a key".to_string(),
+    ])]
+    fn invalid_pub_keys(#[case] pub_keys: Vec<String>) {
+        assert!(parse_pub_keys(&pub_keys).is_err());
+    }
 }
@@ -0,0 +1,281 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+use tracing::warn;
+
+use crate::callback_requests::{CallbackRequestType, CallbackResponse};
+
+/// The outcome of a request made by a policy to the host
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", tag = "outcome")]
+pub enum RecordedResponse {
+    /// The JSON payload given back to the policy
+    Success { payload: serde_json::Value },
+    /// The error reported to the policy
+    Failure { error: String },
+}
+
+/// A request made by a policy to the host, together with the response it got
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct CallbackExchange {
+    pub policy_id: String,
+    pub binding: String,
+    pub operation: String,
+    /// The serialized `CallbackRequestType`. Fields that change on every
+    /// evaluation, like the instant used by `HasKubernetesListResourceAllResultChangedSinceInstant`,
+    /// are removed to make the request reproducible
+    pub request: serde_json::Value,
+    pub response: RecordedResponse,
+}
+
+/// All the exchanges between the policies and the host, in the order they happened
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct CallbackSession {
+    pub exchanges: Vec<CallbackExchange>,
+}
+
+/// Compute the reproducible representation of a request
+pub(crate) fn request_key(request: &CallbackRequestType) -> serde_json::Result<serde_json::Value> {
+    let mut key = serde_json::to_value(request)?;
+    if let Some(fields) = key
+        .get_mut("HasKubernetesListResourceAllResultChangedSinceInstant")
+        .and_then(|fields| fields.as_object_mut())
+    {
+        fields.remove("since");
+    }
+    Ok(key)
+}
+
+/// Records all the requests made by the policies to the host capabilities,
+/// together with the responses they got.
+///
+/// The recorder is shared by all the clones of the `EvaluationContext`
+/// it has been assigned to.
+#[derive(Clone, Default)]
+pub struct CallbackRecorder {
+    session: Arc<Mutex<CallbackSession>>,
+}
+
+impl CallbackRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn record(
+        &self,
+        policy_id: &str,
+        binding: &str,
+        operation: &str,
+        request: serde_json::Value,
+        response: &Result<CallbackResponse>,
+    ) {
+        let response = match response {
+            Ok(response) => match serde_json::from_slice(&response.payload) {
+                Ok(payload) => RecordedResponse::Success { payload },
+                Err(e) => {
+                    warn!(
+                        policy_id,
+                        binding,
+                        operation,
+                        error = e.to_string(),
+                        "cannot record callback response: payload is not JSON"
+                    );
+                    return;
+                }
+            },
+            Err(e) => RecordedResponse::Failure {
+                error: e.to_string(),
+            },
+        };
+
+        let exchange = CallbackExchange {
+            policy_id: policy_id.to_string(),
+            binding: binding.to_string(),
+            operation: operation.to_string(),
+            request,
+            response,
+        };
+        self.session
+            .lock()
+            .expect("callback recorder lock poisoned")
+            .exchanges
+            .push(exchange);
+    }
+
+    /// Returns a copy of the session recorded so far
+    pub fn session(&self) -> CallbackSession {
+        self.session
+            .lock()
+            .expect("callback recorder lock poisoned")
+            .clone()
+    }
+
+    /// Returns the session recorded so far, and starts a new one
+    pub fn take_session(&self) -> CallbackSession {
+        std::mem::take(
+            &mut *self
+                .session
+                .lock()
+                .expect("callback recorder lock poisoned"),
+        )
+    }
+}
+
+/// Serves the responses of a recorded session.
+///
+/// Identical requests are answered with the recorded responses in the order
+/// they were recorded. Once all of them have been served, the last one is
+/// used for all the subsequent requests.
+///
+/// Requests that were not recorded are answered with an error and are
+/// collected, so that they can be inspected via `unrecorded_requests`.
+#[derive(Clone, Default)]
+pub struct CallbackReplayer {
+    inner: Arc<Mutex<ReplayState>>,
+}
+
+#[derive(Default)]
+struct ReplayState {
+    responses: BTreeMap<String, VecDeque<RecordedResponse>>,
+    unrecorded_requests: Vec<serde_json::Value>,
+}
+
+impl CallbackReplayer {
+    pub fn new(session: CallbackSession) -> Self {
+        let mut responses: BTreeMap<String, VecDeque<RecordedResponse>> = BTreeMap::new();
+        for exchange in session.exchanges {
+            responses
+                .entry(exchange.request.to_string())
+                .or_default()
+                .push_back(exchange.response);
+        }
+
+        CallbackReplayer {
+            inner: Arc::new(Mutex::new(ReplayState {
+                responses,
+                unrecorded_requests: Vec::new(),
+            })),
+        }
+    }
+
+    /// Compute the payload of the response to the given request
+    pub(crate) fn respond(&self, request: &CallbackRequestType) -> Result<Vec<u8>> {
+        let key = request_key(request).map_err(|e| anyhow!("cannot serialize request: {e}"))?;
+        let mut state = self.inner.lock().expect("callback replayer lock poisoned");
+
+        let response = match state.responses.get_mut(&key.to_string()) {
+            Some(responses) if responses.len() > 1 => responses.pop_front(),
+            Some(responses) => responses.front().cloned(),
+            None => None,
+        };
+
+        match response {
+            Some(RecordedResponse::Success { payload }) => serde_json::to_vec(&payload)
+                .map_err(|e| anyhow!("error serializing payload: {e:?}")),
+            Some(RecordedResponse::Failure { error }) => Err(anyhow!(error)),
+            None => {
+                warn!(request = key.to_string(), "request was not recorded");
+                state.unrecorded_requests.push(key);
+                Err(anyhow!("request was not recorded"))
+            }
+        }
+    }
+
+    /// Requests that have been received, but were not part of the recorded session
+    pub fn unrecorded_requests(&self) -> Vec<serde_json::Value> {
+        self.inner
+            .lock()
+            .expect("callback replayer lock poisoned")
+            .unrecorded_requests
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dns_lookup(host: &str) -> CallbackRequestType {
+        CallbackRequestType::DNSLookupHost {
+            host: host.to_string(),
+        }
+    }
+
+    fn record(recorder: &CallbackRecorder, request: &CallbackRequestType, response: Result<&str>) {
+        recorder.record(
+            "policy",
+            "kubewarden",
+            "v1/dns_lookup_host",
+            request_key(request).unwrap(),
+            &response.map(|payload| CallbackResponse {
+                payload: payload.as_bytes().to_vec(),
+            }),
+        );
+    }
+
+    #[test]
+    fn record_and_replay() {
+        let recorder = CallbackRecorder::new();
+        record(
+            &recorder,
+            &dns_lookup("kubewarden.io"),
+            Ok(r#"{"ips":["127.0.0.1"]}"#),
+        );
+        record(
+            &recorder,
+            &dns_lookup("kubewarden.io"),
+            Ok(r#"{"ips":["127.0.0.2"]}"#),
+        );
+        record(&recorder, &dns_lookup("example.com"), Err(anyhow!("boom")));
+
+        // the session survives a round trip through its serialized form
+        let session: CallbackSession =
+            serde_json::from_str(&serde_json::to_string(&recorder.take_session()).unwrap())
+                .unwrap();
+        assert_eq!(session.exchanges.len(), 3);
+        assert!(recorder.session().exchanges.is_empty());
+
+        let replayer = CallbackReplayer::new(session);
+        let replay = |host: &str| {
+            replayer
+                .respond(&dns_lookup(host))
+                .map(|payload| String::from_utf8(payload).unwrap())
+        };
+
+        assert_eq!(replay("kubewarden.io").unwrap(), r#"{"ips":["127.0.0.1"]}"#);
+        assert_eq!(replay("kubewarden.io").unwrap(), r#"{"ips":["127.0.0.2"]}"#);
+        // the last response is served once the recorded ones are over
+        assert_eq!(replay("kubewarden.io").unwrap(), r#"{"ips":["127.0.0.2"]}"#);
+        assert_eq!(replay("example.com").unwrap_err().to_string(), "boom");
+        assert!(replayer.unrecorded_requests().is_empty());
+
+        assert!(replay("unknown.lan").is_err());
+        assert_eq!(
+            replayer.unrecorded_requests(),
+            vec![request_key(&dns_lookup("unknown.lan")).unwrap()]
+        );
+    }
+
+    #[test]
+    fn request_key_ignores_instants() {
+        let request =
+            |since| CallbackRequestType::HasKubernetesListResourceAllResultChangedSinceInstant {
+                api_version: "v1".to_string(),
+                kind: "Pod".to_string(),
+                label_selector: None,
+                field_selector: None,
+                since,
+            };
+
+        let now = tokio::time::Instant::now();
+        assert_eq!(
+            request_key(&request(now)).unwrap(),
+            request_key(&request(now - std::time::Duration::from_secs(10))).unwrap()
+        );
+    }
+}
@@ -10,6 +10,11 @@ use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 use tokio::{sync::oneshot, time::Instant};
 
+use crate::callback_handler::{
+    AttestationVerificationRequest, CertificateVerificationRequestV2, DigestRequest,
+    OciReferrersRequest, SignatureVerificationRequest,
+};
+
 /// Holds the response to a waPC evaluation request
 #[derive(Debug, Clone)]
 pub struct CallbackResponse {
@@ -122,9 +127,34 @@ pub enum CallbackRequestType {
         annotations: Option<BTreeMap<String, String>>,
     },
 
+    /// Find the artifacts referring to an OCI object, like SBOMs and
+    /// attestations
+    OciReferrers { request: OciReferrersRequest },
+
+    /// Require the verification of the cosign attestations of an OCI object,
+    /// returning the in-toto predicate of the verified attestation
+    SigstoreAttestationVerify {
+        request: AttestationVerificationRequest,
+    },
+
     /// Lookup the addresses for a given hostname via DNS
     DNSLookupHost { host: String },
 
+    /// Lookup the hostnames associated with an IP address via DNS (PTR records)
+    DNSReverseLookup {
+        /// The IPv4 or IPv6 address
+        address: String,
+    },
+
+    /// Lookup the SRV records of a name via DNS
+    DNSLookupSrv {
+        /// The name of the service, like `_ldap._tcp.example.com`
+        name: String,
+    },
+
+    /// Lookup the TXT records of a name via DNS
+    DNSLookupTxt { name: String },
+
     /// Get all the Kubernetes resources defined inside of the given
     /// namespace
     /// Note: cannot be used with cluster-wide resources
@@ -141,6 +171,10 @@ pub enum CallbackRequestType {
         /// A selector to restrict the list of returned objects by their fields.
         /// Defaults to everything if `None`
         field_selector: Option<String>,
+        /// The fields of the objects needed by the policy, like `spec.nodeName`.
+        /// The other fields are omitted. All the fields are returned if `None`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        fields: Option<Vec<String>>,
     },
 
     /// Get all the Kubernetes resources defined inside of the given
@@ -157,6 +191,10 @@ pub enum CallbackRequestType {
         /// A selector to restrict the list of returned objects by their fields.
         /// Defaults to everything if `None`
         field_selector: Option<String>,
+        /// The fields of the objects needed by the policy, like `spec.nodeName`.
+        /// The other fields are omitted. All the fields are returned if `None`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        fields: Option<Vec<String>>,
     },
 
     /// Get a Kubernetes resource with the specified `name`.
@@ -178,6 +216,10 @@ pub enum CallbackRequestType {
         /// However, making too many requests against the Kubernetes API Server
         /// might cause issues to the cluster
         disable_cache: bool,
+        /// The fields of the objects needed by the policy, like `spec.nodeName`.
+        /// The other fields are omitted. All the fields are returned if `None`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        fields: Option<Vec<String>>,
     },
 
     /// Get the plural name of a Kubernetes resource. E.g. `v1/Service` -> `services`
@@ -230,6 +272,21 @@ pub enum CallbackRequestType {
         /// a chain to validate it with.
         request: CertificateVerificationRequest,
     },
+
+    /// Check if the given certificate is trusted by the certificate chain, is
+    /// not expired, has not been revoked and satisfies the usage and identity
+    /// constraints of the request
+    CryptoIsCertificateTrustedV2 {
+        request: CertificateVerificationRequestV2,
+    },
+
+    /// Verify the detached signature of a message
+    CryptoVerifySignature {
+        request: SignatureVerificationRequest,
+    },
+
+    /// Compute the digest of some data
+    CryptoDigest { request: DigestRequest },
 }
 mod tokio_instant_serializer {
     use serde::de::Error;
@@ -353,6 +410,7 @@ impl From<kubewarden_policy_sdk::host_capabilities::kubernetes::ListResourcesByN
             namespace: req.namespace,
             label_selector: req.label_selector,
             field_selector: req.field_selector,
+            fields: None,
         }
     }
 }
@@ -368,6 +426,7 @@ impl From<kubewarden_policy_sdk::host_capabilities::kubernetes::ListAllResources
             kind: req.kind,
             label_selector: req.label_selector,
             field_selector: req.field_selector,
+            fields: None,
         }
     }
 }
@@ -382,6 +441,7 @@ impl From<kubewarden_policy_sdk::host_capabilities::kubernetes::GetResourceReque
             name: req.name,
             namespace: req.namespace,
             disable_cache: req.disable_cache,
+            fields: None,
         }
     }
 }
@@ -400,3 +460,33 @@ impl From<CertificateVerificationRequest> for CallbackRequestType {
         CallbackRequestType::CryptoIsCertificateTrusted { request }
     }
 }
+
+impl From<CertificateVerificationRequestV2> for CallbackRequestType {
+    fn from(request: CertificateVerificationRequestV2) -> Self {
+        CallbackRequestType::CryptoIsCertificateTrustedV2 { request }
+    }
+}
+
+impl From<OciReferrersRequest> for CallbackRequestType {
+    fn from(request: OciReferrersRequest) -> Self {
+        CallbackRequestType::OciReferrers { request }
+    }
+}
+
+impl From<AttestationVerificationRequest> for CallbackRequestType {
+    fn from(request: AttestationVerificationRequest) -> Self {
+        CallbackRequestType::SigstoreAttestationVerify { request }
+    }
+}
+
+impl From<SignatureVerificationRequest> for CallbackRequestType {
+    fn from(request: SignatureVerificationRequest) -> Self {
+        CallbackRequestType::CryptoVerifySignature { request }
+    }
+}
+
+impl From<DigestRequest> for CallbackRequestType {
+    fn from(request: DigestRequest) -> Self {
+        CallbackRequestType::CryptoDigest { request }
+    }
+}
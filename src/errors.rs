@@ -34,6 +34,9 @@ pub enum ArtifactHubError {
         "annotation \"{0}\" in policy metadata is malformed, must be a string \"true\" or \"false\""
     )]
     MalformedBoolString(String),
+
+    #[error("cannot generate questions-ui from the settings schema: {0}")]
+    InvalidSettingsSchema(String),
 }
 
 #[derive(Error, Debug)]
@@ -43,6 +46,14 @@ pub enum PolicyEvaluatorError {
 
     #[error("protocol_version is only applicable to a Kubewarden policy")]
     InvokeWapcProtocolVersion(#[source] crate::runtimes::wapc::errors::WapcRuntimeError),
+
+    #[error("cannot check mutation idempotency: {0}")]
+    IdempotencyCheck(String),
+
+    #[error(
+        "the mutation is not idempotent, evaluating the mutated object produced another patch: {0}"
+    )]
+    NonIdempotentMutation(String),
 }
 
 #[derive(Error, Debug)]
@@ -101,4 +112,104 @@ pub enum MetadataError {
 pub enum ResponseError {
     #[error("cannot deserialize JSONPatch: {0}")]
     Deserialize(#[source] serde_json::Error),
+
+    #[error("cannot deserialize the mutation returned by the policy: {0}")]
+    InvalidMutation(#[source] serde_json::Error),
+
+    #[error("the JSONPatch returned by the policy cannot be applied: {0}")]
+    InvalidJsonPatch(#[source] json_patch::PatchError),
+
+    #[error("cannot decode base64 encoded JSONPatch: {0}")]
+    InvalidPatchEncoding(#[source] base64::DecodeError),
+}
+
+#[derive(Error, Debug)]
+pub enum AdmissionReviewError {
+    #[error("cannot deserialize AdmissionReview: {0}")]
+    Deserialize(#[source] serde_json::Error),
+
+    #[error("unsupported AdmissionReview apiVersion: {0}")]
+    UnsupportedApiVersion(String),
+
+    #[error("invalid AdmissionReview kind: {0}")]
+    InvalidKind(String),
+
+    #[error("AdmissionReview does not contain a request")]
+    MissingRequest,
+
+    #[error(
+        "the uid of the response ({response}) does not match the uid of the request ({request})"
+    )]
+    UidMismatch { request: String, response: String },
+}
+
+#[derive(Error, Debug)]
+pub enum TestSuiteError {
+    #[error("cannot read {path}: {error}")]
+    Read {
+        path: std::path::PathBuf,
+        #[source]
+        error: std::io::Error,
+    },
+
+    #[error("cannot parse test suite: {0}")]
+    Parse(#[source] serde_yaml::Error),
+
+    #[error("invalid request: {0}")]
+    InvalidRequest(#[source] serde_json::Error),
+
+    #[error(transparent)]
+    InvalidAdmissionReview(#[from] AdmissionReviewError),
+
+    #[error("invalid regular expression '{pattern}': {error}")]
+    InvalidRegex {
+        pattern: String,
+        #[source]
+        error: regex::Error,
+    },
+
+    #[error("cannot load host capabilities fixtures: {0}")]
+    Fixtures(#[source] anyhow::Error),
+
+    #[error("cannot read policy metadata: {0}")]
+    Metadata(#[from] MetadataError),
+
+    #[error(transparent)]
+    SettingsSchema(#[from] SettingsSchemaError),
+
+    #[error(transparent)]
+    PolicyEvaluatorBuilder(#[from] PolicyEvaluatorBuilderError),
+
+    #[error("cannot create policy evaluator: {0}")]
+    PolicyEvaluator(String),
+
+    #[error("cannot run test cases: {0}")]
+    Join(String),
+}
+
+#[derive(Error, Debug)]
+pub enum WebhookConfigurationError {
+    #[error("raw policies cannot be used to validate Kubernetes admission requests")]
+    RawPolicy,
+
+    #[error("the policy does not define any rule")]
+    NoRules,
+
+    #[error("webhook name must be fully qualified, like `policy.kubewarden.admission`: {0}")]
+    InvalidName(String),
+
+    #[error("webhook timeout must be between 1 and 30 seconds: {0}")]
+    InvalidTimeout(i32),
+
+    #[error("admission review versions must be some of `v1` and `v1beta1`: {0:?}")]
+    InvalidAdmissionReviewVersions(Vec<String>),
+}
+
+#[derive(Error, Debug)]
+pub enum SettingsSchemaError {
+    #[error("invalid settings schema: {0}")]
+    InvalidSchema(String),
+
+    #[error("settings do not match the schema of the policy: {}", .0.join("; "))]
+    InvalidSettings(Vec<String>),
 }
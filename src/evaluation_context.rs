@@ -2,6 +2,7 @@ use std::collections::BTreeSet;
 use std::fmt;
 use tokio::sync::mpsc;
 
+use crate::callback_recording::CallbackRecorder;
 use crate::callback_requests::CallbackRequest;
 use crate::policy_metadata::ContextAwareResource;
 
@@ -20,7 +21,8 @@ pub struct EvaluationContext {
     /// asynchronous block
     pub callback_channel: Option<mpsc::Sender<CallbackRequest>>,
 
-    /// List of ContextAwareResource the policy is granted access to.
+    /// List of ContextAwareResource the policy is granted access to, together
+    /// with the fields of the objects it needs.
     pub ctx_aware_resources_allow_list: BTreeSet<ContextAwareResource>,
 
     /// Optional epoch deadline to set on the wasmtime store. This is used to
@@ -29,19 +31,54 @@ pub struct EvaluationContext {
     /// This could either be the global epoch deadline, or the one
     /// specific to the policy
     pub epoch_deadline: Option<u64>,
+
+    /// Optional recorder of all the requests made by the policy to the host
+    /// capabilities, together with their responses. Recorded sessions can then
+    /// be replayed via `CallbackHandlerBuilder::replay_session`
+    pub callback_recorder: Option<CallbackRecorder>,
 }
 
 impl EvaluationContext {
-    /// Checks if a policy has access to a Kubernetes resource, based on the privileges
-    /// that have been granted by the user
-    pub(crate) fn can_access_kubernetes_resource(&self, api_version: &str, kind: &str) -> bool {
-        let wanted_resource = ContextAwareResource {
-            api_version: api_version.to_string(),
-            kind: kind.to_string(),
-        };
+    /// Checks if a policy has access to the Kubernetes resources defined inside of
+    /// `namespace`, based on the privileges that have been granted by the user.
+    /// `None` stands for the resources of all the namespaces, or for the cluster-wide
+    /// ones. `request_namespace` is the namespace of the admission request being evaluated
+    pub(crate) fn can_access_kubernetes_resource(
+        &self,
+        api_version: &str,
+        kind: &str,
+        namespace: Option<&str>,
+        request_namespace: Option<&str>,
+    ) -> bool {
+        // the fields needed by the policy do not affect the access privileges
+        self.ctx_aware_resources_allow_list.iter().any(|resource| {
+            resource.api_version == api_version
+                && resource.kind == kind
+                && resource
+                    .namespace_scope
+                    .allows(namespace, request_namespace)
+        })
+    }
 
-        self.ctx_aware_resources_allow_list
-            .contains(&wanted_resource)
+    /// The fields of the Kubernetes objects of the given kind needed by the policy.
+    /// `None` when the policy needs the whole objects, because at least one of the
+    /// resources it has been granted access to does not declare its fields
+    pub(crate) fn kubernetes_fields(&self, api_version: &str, kind: &str) -> Option<Vec<String>> {
+        let mut fields = BTreeSet::new();
+        let mut declared = false;
+        for resource in self
+            .ctx_aware_resources_allow_list
+            .iter()
+            .filter(|resource| resource.api_version == api_version && resource.kind == kind)
+        {
+            if resource.fields.is_empty() {
+                return None;
+            }
+            fields.extend(resource.fields.iter().cloned());
+            declared = true;
+        }
+
+        declared.then(|| fields.into_iter().collect())
     }
 }
 
@@ -63,58 +100,112 @@ impl fmt::Debug for EvaluationContext {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::policy_metadata::NamespaceScope;
     use rstest::rstest;
 
+    fn config_maps(namespace_scope: NamespaceScope) -> BTreeSet<ContextAwareResource> {
+        BTreeSet::from([ContextAwareResource {
+            api_version: "v1".to_string(),
+            kind: "ConfigMap".to_string(),
+            fields: Vec::new(),
+            namespace_scope,
+        }])
+    }
+
     #[rstest]
-    #[case("nothing allowed", BTreeSet::new(), "v1", "Secret", false)]
-    #[case(
-        "try to access denied resource",
-        BTreeSet::from([
-            ContextAwareResource{
-                api_version: "v1".to_string(),
-                kind: "ConfigMap".to_string(),
-            }]),
-        "v1",
-        "Secret",
-        false,
+    #[case::nothing_allowed(BTreeSet::new(), "Secret", Some("default"), false)]
+    #[case::denied_resource(config_maps(NamespaceScope::Cluster), "Secret", None, false)]
+    #[case::allowed_resource(config_maps(NamespaceScope::Cluster), "ConfigMap", None, true)]
+    #[case::allowed_resource_in_namespace(
+        config_maps(NamespaceScope::Cluster),
+        "ConfigMap",
+        Some("kube-system"),
+        true
     )]
-    #[case(
-        "access allowed resource",
-        BTreeSet::from([
-            ContextAwareResource{
-                api_version: "v1".to_string(),
-                kind: "ConfigMap".to_string(),
-            }]),
-        "v1",
+    #[case::request_namespace(
+        config_maps(NamespaceScope::RequestNamespace),
         "ConfigMap",
-        true,
+        Some("default"),
+        true
+    )]
+    #[case::not_request_namespace(
+        config_maps(NamespaceScope::RequestNamespace),
+        "ConfigMap",
+        Some("kube-system"),
+        false
+    )]
+    #[case::request_namespace_all_namespaces(
+        config_maps(NamespaceScope::RequestNamespace),
+        "ConfigMap",
+        None,
+        false
+    )]
+    #[case::allow_listed_namespace(
+        config_maps(NamespaceScope::Namespaces(BTreeSet::from(["kube-system".to_string()]))),
+        "ConfigMap",
+        Some("kube-system"),
+        true
+    )]
+    #[case::not_allow_listed_namespace(
+        config_maps(NamespaceScope::Namespaces(BTreeSet::from(["kube-system".to_string()]))),
+        "ConfigMap",
+        Some("default"),
+        false
     )]
-
     fn can_access_kubernetes_resource(
-        #[case] name: &str,
         #[case] allowed_resources: BTreeSet<ContextAwareResource>,
-        #[case] api_version: &str,
         #[case] kind: &str,
+        #[case] namespace: Option<&str>,
         #[case] allowed: bool,
     ) {
         let ctx = EvaluationContext {
-            policy_id: name.to_string(),
+            policy_id: "test".to_string(),
             callback_channel: None,
             ctx_aware_resources_allow_list: allowed_resources,
             epoch_deadline: None,
+            callback_recorder: None,
         };
 
-        let requested_resource = ContextAwareResource {
-            api_version: api_version.to_string(),
-            kind: kind.to_string(),
+        assert_eq!(
+            allowed,
+            ctx.can_access_kubernetes_resource("v1", kind, namespace, Some("default"))
+        );
+    }
+
+    fn config_maps_with_fields(fields: &[&[&str]]) -> BTreeSet<ContextAwareResource> {
+        fields
+            .iter()
+            .enumerate()
+            .map(|(i, fields)| ContextAwareResource {
+                api_version: "v1".to_string(),
+                kind: "ConfigMap".to_string(),
+                fields: fields.iter().map(|field| field.to_string()).collect(),
+                namespace_scope: NamespaceScope::Namespaces(BTreeSet::from([format!("ns-{i}")])),
+            })
+            .collect()
+    }
+
+    #[rstest]
+    #[case::not_allowed(BTreeSet::new(), None)]
+    #[case::whole_objects(config_maps_with_fields(&[&[]]), None)]
+    #[case::declared(config_maps_with_fields(&[&["data"]]), Some(vec!["data"]))]
+    #[case::union(
+        config_maps_with_fields(&[&["data"], &["metadata.labels", "data"]]),
+        Some(vec!["data", "metadata.labels"])
+    )]
+    #[case::one_needs_whole_objects(config_maps_with_fields(&[&["data"], &[]]), None)]
+    fn kubernetes_fields(
+        #[case] allowed_resources: BTreeSet<ContextAwareResource>,
+        #[case] expected: Option<Vec<&str>>,
+    ) {
+        let ctx = EvaluationContext {
+            ctx_aware_resources_allow_list: allowed_resources,
+            ..Default::default()
         };
 
         assert_eq!(
-            allowed,
-            ctx.can_access_kubernetes_resource(
-                &requested_resource.api_version,
-                &requested_resource.kind
-            )
+            ctx.kubernetes_fields("v1", "ConfigMap"),
+            expected.map(|fields| fields.into_iter().map(str::to_string).collect())
         );
     }
 }
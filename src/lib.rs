@@ -3,7 +3,9 @@ pub extern crate burrego;
 pub mod admission_request;
 pub mod admission_response;
 pub mod admission_response_handler;
+pub mod admission_review;
 pub mod callback_handler;
+pub mod callback_recording;
 pub mod callback_requests;
 pub mod constants;
 pub mod errors;
@@ -14,6 +16,9 @@ pub mod policy_group_evaluator;
 pub mod policy_metadata;
 mod policy_tracing;
 pub mod runtimes;
+#[cfg(test)]
+mod test_fixtures;
+pub mod testing;
 
 // API's that expose other crate types (such as Kubewarden Policy SDK
 // or `policy_fetcher`) can either implement their own exposed types,
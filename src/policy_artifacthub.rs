@@ -183,6 +183,13 @@ impl ArtifactHubPkg {
         {
             return Err(ArtifactHubError::EmptyQuestionsUI);
         }
+        // the questions provided by the user take precedence over the ones
+        // generated from the settings schema
+        let generated_questions = match (questions, &metadata.settings_schema) {
+            (None, Some(schema)) => Some(questions_ui_from_schema(schema)?),
+            _ => None,
+        };
+        let questions = questions.or(generated_questions.as_deref());
 
         // build struct
         let name = parse_name(metadata_annots)?;
@@ -220,6 +227,134 @@ impl ArtifactHubPkg {
     }
 }
 
+/// The Rancher questions-ui, as defined in
+/// <https://ranchermanager.docs.rancher.com/how-to-guides/new-user-guides/helm-charts-in-rancher/create-apps#question-variable-reference>
+#[derive(Serialize, Debug, Clone, PartialEq)]
+struct QuestionsUi {
+    questions: Vec<Question>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+struct Question {
+    variable: String,
+    label: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    #[serde(rename = "type")]
+    question_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    default: Option<serde_json::Value>,
+    required: bool,
+    group: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    options: Option<Vec<serde_json::Value>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    value_multiline: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sequence_questions: Option<Vec<Question>>,
+}
+
+const QUESTIONS_UI_GROUP: &str = "Settings";
+
+/// Generate the contents of the questions-ui annotation from the JSON Schema
+/// of the policy settings.
+///
+/// Each property of the settings becomes a question. The properties of nested
+/// objects are flattened using dotted variable names, like `limits.cpu`, while
+/// arrays of objects become `sequence[` questions.
+pub fn questions_ui_from_schema(schema: &serde_json::Value) -> Result<String> {
+    if schema.get("properties").is_none_or(|p| !p.is_object()) {
+        return Err(ArtifactHubError::InvalidSettingsSchema(String::from(
+            "the schema must describe an object with properties",
+        )));
+    }
+
+    let questions_ui = QuestionsUi {
+        questions: schema_questions(schema, ""),
+    };
+    serde_yaml::to_string(&questions_ui)
+        .map_err(|e| ArtifactHubError::InvalidSettingsSchema(e.to_string()))
+}
+
+fn schema_questions(schema: &serde_json::Value, prefix: &str) -> Vec<Question> {
+    let Some(properties) = schema.get("properties").and_then(|p| p.as_object()) else {
+        return vec![];
+    };
+    let required: Vec<&str> = schema
+        .get("required")
+        .and_then(|r| r.as_array())
+        .map(|r| r.iter().filter_map(|name| name.as_str()).collect())
+        .unwrap_or_default();
+
+    let mut questions = Vec::new();
+    for (name, property) in properties {
+        let variable = format!("{prefix}{name}");
+        let mut question = Question {
+            variable: variable.clone(),
+            label: property
+                .get("title")
+                .and_then(|t| t.as_str())
+                .unwrap_or(name)
+                .to_string(),
+            description: property
+                .get("description")
+                .and_then(|d| d.as_str())
+                .map(str::to_string),
+            question_type: String::from("string"),
+            default: property.get("default").cloned(),
+            required: required.contains(&name.as_str()),
+            group: String::from(QUESTIONS_UI_GROUP),
+            options: None,
+            value_multiline: None,
+            sequence_questions: None,
+        };
+
+        if let Some(options) = property.get("enum").and_then(|e| e.as_array()) {
+            question.question_type = String::from("enum");
+            question.options = Some(options.clone());
+            questions.push(question);
+            continue;
+        }
+
+        match schema_type(property) {
+            Some("integer") | Some("number") => question.question_type = String::from("int"),
+            Some("boolean") => question.question_type = String::from("boolean"),
+            Some("object") if property.get("properties").is_some() => {
+                questions.extend(schema_questions(property, &format!("{variable}.")));
+                continue;
+            }
+            Some("object") => question.question_type = String::from("map["),
+            Some("array") => match property.get("items") {
+                Some(items) if items.get("properties").is_some() => {
+                    question.question_type = String::from("sequence[");
+                    question.sequence_questions = Some(schema_questions(items, ""));
+                }
+                _ => {
+                    question.question_type = String::from("array[");
+                    question.value_multiline = Some(false);
+                }
+            },
+            _ => {}
+        }
+        questions.push(question);
+    }
+
+    questions
+}
+
+/// The type of a schema. When multiple types are allowed, like `["string", "null"]`,
+/// the first one that is not `null` is used
+fn schema_type(schema: &serde_json::Value) -> Option<&str> {
+    match schema.get("type")? {
+        serde_json::Value::String(t) => Some(t.as_str()),
+        serde_json::Value::Array(types) => types
+            .iter()
+            .filter_map(|t| t.as_str())
+            .find(|t| *t != "null"),
+        _ => None,
+    }
+}
+
 fn parse_name(metadata_annots: &BTreeMap<String, String>) -> Result<String> {
     metadata_annots
         .get(KUBEWARDEN_ANNOTATION_POLICY_TITLE)
@@ -533,6 +668,7 @@ mod tests {
             execution_mode: Default::default(),
             policy_type: PolicyType::Kubernetes,
             minimum_kubewarden_version: None,
+            settings_schema: None,
         }
     }
 
@@ -541,6 +677,8 @@ mod tests {
         context_aware_resources.insert(ContextAwareResource {
             api_version: "v1".to_string(),
             kind: "Pod".to_string(),
+            fields: Vec::new(),
+            namespace_scope: Default::default(),
         });
 
         Metadata {
@@ -601,6 +739,7 @@ mod tests {
             context_aware_resources,
             execution_mode: Default::default(),
             minimum_kubewarden_version: None,
+            settings_schema: None,
             policy_type: Default::default(),
         }
     }
@@ -972,4 +1111,137 @@ kwctl scaffold manifest -t ClusterAdmissionPolicy registry://ghcr.io/ocirepo/nam
         assert_json_eq!(expected, actual);
         Ok(())
     }
+
+    #[test]
+    fn questions_ui_generated_from_settings_schema() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "mode": {
+                    "title": "Mode",
+                    "description": "How violations are handled",
+                    "enum": ["deny", "warn"],
+                    "default": "deny"
+                },
+                "maxReplicas": {"type": ["integer", "null"]},
+                "allowedRegistries": {"type": "array", "items": {"type": "string"}},
+                "limits": {
+                    "type": "object",
+                    "properties": {"cpu": {"type": "string"}},
+                    "required": ["cpu"]
+                },
+                "exemptions": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {"namespace": {"type": "string"}}
+                    }
+                }
+            },
+            "required": ["mode"]
+        });
+
+        let mut questions: serde_json::Value =
+            serde_yaml::from_str(&questions_ui_from_schema(&schema).unwrap()).unwrap();
+        // the order of the questions depends on the serde_json features enabled
+        questions["questions"]
+            .as_array_mut()
+            .unwrap()
+            .sort_by_key(|q| q["variable"].as_str().unwrap_or_default().to_string());
+        assert_json_eq!(
+            questions,
+            json!({
+                "questions": [
+                    {
+                        "variable": "allowedRegistries",
+                        "label": "allowedRegistries",
+                        "type": "array[",
+                        "value_multiline": false,
+                        "required": false,
+                        "group": "Settings"
+                    },
+                    {
+                        "variable": "exemptions",
+                        "label": "exemptions",
+                        "type": "sequence[",
+                        "required": false,
+                        "group": "Settings",
+                        "sequence_questions": [
+                            {
+                                "variable": "namespace",
+                                "label": "namespace",
+                                "type": "string",
+                                "required": false,
+                                "group": "Settings"
+                            }
+                        ]
+                    },
+                    {
+                        "variable": "limits.cpu",
+                        "label": "cpu",
+                        "type": "string",
+                        "required": true,
+                        "group": "Settings"
+                    },
+                    {
+                        "variable": "maxReplicas",
+                        "label": "maxReplicas",
+                        "type": "int",
+                        "required": false,
+                        "group": "Settings"
+                    },
+                    {
+                        "variable": "mode",
+                        "label": "Mode",
+                        "description": "How violations are handled",
+                        "type": "enum",
+                        "options": ["deny", "warn"],
+                        "default": "deny",
+                        "required": true,
+                        "group": "Settings"
+                    }
+                ]
+            })
+        );
+    }
+
+    #[test]
+    fn questions_ui_annotation_from_settings_schema() {
+        let metadata = Metadata {
+            settings_schema: Some(json!({
+                "type": "object",
+                "properties": {"enabled": {"type": "boolean"}}
+            })),
+            ..mock_metadata_with_minimum_required()
+        };
+
+        let arthub =
+            ArtifactHubPkg::from_metadata(&metadata, OffsetDateTime::UNIX_EPOCH, None).unwrap();
+        let questions: serde_json::Value = serde_yaml::from_str(
+            &arthub.annotations[ARTIFACTHUB_ANNOTATION_KUBEWARDEN_QUESTIONSUI],
+        )
+        .unwrap();
+        assert_eq!(questions["questions"][0]["type"], "boolean");
+
+        // the questions provided by the user take precedence
+        let arthub = ArtifactHubPkg::from_metadata(
+            &metadata,
+            OffsetDateTime::UNIX_EPOCH,
+            Some("questions contents"),
+        )
+        .unwrap();
+        assert_eq!(
+            arthub.annotations[ARTIFACTHUB_ANNOTATION_KUBEWARDEN_QUESTIONSUI],
+            "questions contents"
+        );
+
+        let metadata = Metadata {
+            settings_schema: Some(json!({"type": "string"})),
+            ..mock_metadata_with_minimum_required()
+        };
+        assert!(matches!(
+            ArtifactHubPkg::from_metadata(&metadata, OffsetDateTime::UNIX_EPOCH, None),
+            Err(ArtifactHubError::InvalidSettingsSchema(_))
+        ));
+    }
 }
@@ -4,6 +4,7 @@ pub mod policy_evaluator_builder;
 mod policy_evaluator_pre;
 mod stack_pre;
 
+pub use crate::testing;
 pub use evaluator::PolicyEvaluator;
 pub use policy_evaluator_pre::PolicyEvaluatorPre;
 
@@ -56,6 +57,40 @@ impl ValidateRequest {
             ValidateRequest::AdmissionRequest(adm_req) => &adm_req.uid,
         }
     }
+
+    /// The namespace of the admission request. Raw requests do not have a namespace
+    pub fn namespace(&self) -> Option<&str> {
+        match self {
+            ValidateRequest::Raw(_) => None,
+            ValidateRequest::AdmissionRequest(adm_req) => adm_req.namespace.as_deref(),
+        }
+    }
+
+    /// Returns a copy of the request, where the object being validated has been
+    /// changed by the given JSONPatch.
+    ///
+    /// The whole payload of a raw request is considered to be the object.
+    pub fn with_patched_object(&self, patch: &json_patch::Patch) -> Result<ValidateRequest> {
+        match self {
+            ValidateRequest::Raw(raw_req) => {
+                let mut patched = raw_req.clone();
+                json_patch::patch(&mut patched, patch)?;
+                Ok(ValidateRequest::Raw(patched))
+            }
+            ValidateRequest::AdmissionRequest(adm_req) => {
+                let mut patched = adm_req
+                    .object
+                    .as_ref()
+                    .map(|object| object.0.clone())
+                    .ok_or_else(|| anyhow!("the request does not have an object to patch"))?;
+                json_patch::patch(&mut patched, patch)?;
+
+                let mut adm_req = adm_req.clone();
+                adm_req.object = Some(RawExtension(patched));
+                Ok(ValidateRequest::AdmissionRequest(adm_req))
+            }
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -162,6 +197,59 @@ mod tests {
         assert!(actual.is_err());
     }
 
+    #[test]
+    fn patch_raw_request() {
+        let request = ValidateRequest::Raw(json!({"user": "tonio", "resource": "banana"}));
+        let patch: json_patch::Patch = serde_json::from_value(json!([
+            {"op": "replace", "path": "/resource", "value": "hay"}
+        ]))
+        .unwrap();
+
+        let patched = request.with_patched_object(&patch).unwrap();
+        assert_eq!(
+            serde_json::to_value(&patched).unwrap(),
+            json!({"user": "tonio", "resource": "hay"})
+        );
+    }
+
+    #[test]
+    fn patch_admission_request() {
+        let adm_req: AdmissionRequest =
+            serde_json::from_str(include_str!("../tests/data/pod_creation_flux_cat.json")).unwrap();
+        let request = ValidateRequest::AdmissionRequest(Box::new(adm_req.clone()));
+        let patch: json_patch::Patch = serde_json::from_value(json!([
+            {"op": "add", "path": "/metadata/labels", "value": {"app": "nginx"}}
+        ]))
+        .unwrap();
+
+        let ValidateRequest::AdmissionRequest(patched) =
+            request.with_patched_object(&patch).unwrap()
+        else {
+            panic!("the patched request should be an admission request");
+        };
+        assert_eq!(
+            patched.object.unwrap().0["metadata"]["labels"],
+            json!({"app": "nginx"})
+        );
+        // only the object is patched
+        assert_eq!(patched.old_object, adm_req.old_object);
+        assert_eq!(patched.uid, adm_req.uid);
+    }
+
+    #[test]
+    fn patch_admission_request_without_object() {
+        let mut adm_req: AdmissionRequest =
+            serde_json::from_str(include_str!("../tests/data/pod_creation_flux_cat.json")).unwrap();
+        adm_req.object = None;
+        let request = ValidateRequest::AdmissionRequest(Box::new(adm_req));
+
+        assert!(
+            request
+                .with_patched_object(&json_patch::Patch(vec![]))
+                .is_err()
+        );
+    }
+
     #[rstest]
     #[case::dictionrary(json!({"key1": "value1", "key2": "value2"}), true)]
     #[case::empty_dictionrary(json!({}), true)]
@@ -1,10 +1,12 @@
 use kubewarden_policy_sdk::{metadata::ProtocolVersion, settings::SettingsValidationResponse};
 use std::fmt;
+use tracing::warn;
 
-use crate::admission_response::AdmissionResponse;
+use crate::admission_response::{AdmissionResponse, mutation::PatchGeneration};
 use crate::errors::PolicyEvaluatorError;
 use crate::evaluation_context::EvaluationContext;
 use crate::policy_evaluator::{PolicySettings, ValidateRequest};
+use crate::policy_metadata::SettingsSchema;
 use crate::runtimes::Runtime;
 use crate::runtimes::rego::Runtime as BurregoRuntime;
 use crate::runtimes::wapc::Runtime as WapcRuntime;
@@ -13,31 +15,140 @@ use crate::runtimes::wasi_cli::Runtime as WasiRuntime;
 pub struct PolicyEvaluator {
     runtime: Runtime,
     eval_ctx: EvaluationContext,
+    idempotency_check: bool,
+    settings_schema: Option<SettingsSchema>,
+    patch_generation: PatchGeneration,
 }
 
 impl PolicyEvaluator {
-    pub(crate) fn new(runtime: Runtime, eval_ctx: &EvaluationContext) -> Self {
+    pub(crate) fn new(
+        runtime: Runtime,
+        eval_ctx: &EvaluationContext,
+        settings_schema: Option<SettingsSchema>,
+        patch_generation: PatchGeneration,
+    ) -> Self {
         Self {
             runtime,
             eval_ctx: eval_ctx.to_owned(),
+            idempotency_check: false,
+            settings_schema,
+            patch_generation,
         }
     }
 
+    /// Ensure the mutations performed by the policy are idempotent.
+    ///
+    /// Kubernetes can invoke a mutating webhook multiple times (see `reinvocationPolicy`).
+    /// When the check is enabled, each mutation is applied to the object being validated and
+    /// the policy is evaluated again against the mutated object. If this second evaluation
+    /// changes the object once more, a warning is added to the admission response.
+    ///
+    /// Note: this doubles the evaluation time of mutating requests.
+    ///
+    /// The check can also be enabled for all the evaluators created by a `PolicyEvaluatorPre`
+    /// via [`PolicyEvaluatorBuilder::enable_idempotency_check`](crate::policy_evaluator_builder::PolicyEvaluatorBuilder::enable_idempotency_check).
+    pub fn enable_idempotency_check(&mut self) {
+        self.idempotency_check = true;
+    }
+
     #[tracing::instrument(skip(request))]
     pub fn validate(
         &mut self,
         request: ValidateRequest,
         settings: &PolicySettings,
+    ) -> AdmissionResponse {
+        if !self.idempotency_check {
+            return self.evaluate(request, settings);
+        }
+
+        let admission_response = self.evaluate(request.clone(), settings);
+        match self.check_mutation_idempotency(&request, settings, &admission_response) {
+            Ok(()) => admission_response,
+            Err(e) => {
+                warn!(error = e.to_string(), "mutation idempotency check failed");
+                let mut warnings = admission_response.warnings.unwrap_or_default();
+                warnings.push(e.to_string());
+                AdmissionResponse {
+                    warnings: Some(warnings),
+                    ..admission_response
+                }
+            }
+        }
+    }
+
+    /// Validate the request, then ensure the mutation performed by the policy, if any,
+    /// is idempotent. A non idempotent mutation is reported as an error.
+    ///
+    /// This is meant to be used inside of the test suites of the policies.
+    pub fn validate_and_check_idempotency(
+        &mut self,
+        request: ValidateRequest,
+        settings: &PolicySettings,
+    ) -> Result<AdmissionResponse, PolicyEvaluatorError> {
+        let admission_response = self.evaluate(request.clone(), settings);
+        self.check_mutation_idempotency(&request, settings, &admission_response)?;
+        Ok(admission_response)
+    }
+
+    /// Ensure the mutation contained inside of `admission_response`, which has been produced
+    /// by the evaluation of `request`, is idempotent.
+    ///
+    /// The mutation is applied to the object of the request, which is then evaluated again.
+    /// The mutation is idempotent when the second evaluation does not change the mutated
+    /// object any further.
+    pub fn check_mutation_idempotency(
+        &mut self,
+        request: &ValidateRequest,
+        settings: &PolicySettings,
+        admission_response: &AdmissionResponse,
+    ) -> Result<(), PolicyEvaluatorError> {
+        let patch = match admission_response.decode_patch() {
+            Ok(Some(patch)) => patch,
+            Ok(None) => return Ok(()),
+            Err(e) => return Err(PolicyEvaluatorError::IdempotencyCheck(e.to_string())),
+        };
+
+        let mutated_request = request
+            .with_patched_object(&patch)
+            .map_err(|e| PolicyEvaluatorError::IdempotencyCheck(e.to_string()))?;
+        let second_response = self.evaluate(mutated_request.clone(), settings);
+
+        let second_patch = match second_response.decode_patch() {
+            Ok(Some(second_patch)) => second_patch,
+            Ok(None) => return Ok(()),
+            Err(e) => return Err(PolicyEvaluatorError::IdempotencyCheck(e.to_string())),
+        };
+
+        // The policy could return a patch that doesn't change the object, like
+        // setting a field to the value it already has
+        let twice_mutated_request = mutated_request
+            .with_patched_object(&second_patch)
+            .map_err(|e| PolicyEvaluatorError::IdempotencyCheck(e.to_string()))?;
+        let serialize = |request: &ValidateRequest| {
+            serde_json::to_value(request)
+                .map_err(|e| PolicyEvaluatorError::IdempotencyCheck(e.to_string()))
+        };
+        if serialize(&twice_mutated_request)? == serialize(&mutated_request)? {
+            return Ok(());
+        }
+
+        Err(PolicyEvaluatorError::NonIdempotentMutation(
+            serde_json::to_string(&second_patch).unwrap_or_default(),
+        ))
+    }
+
+    fn evaluate(
+        &mut self,
+        request: ValidateRequest,
+        settings: &PolicySettings,
     ) -> AdmissionResponse {
         match self.runtime {
             Runtime::Wapc(ref mut wapc_stack) => {
-                WapcRuntime(wapc_stack).validate(settings, &request)
+                WapcRuntime(wapc_stack).validate(settings, &request, self.patch_generation)
             }
             Runtime::Rego(ref mut burrego_evaluator) => {
-                let kube_ctx = burrego_evaluator.build_kubernetes_context(
-                    self.eval_ctx.callback_channel.as_ref(),
-                    &self.eval_ctx.ctx_aware_resources_allow_list,
-                );
+                let kube_ctx =
+                    burrego_evaluator.build_kubernetes_context(&self.eval_ctx, request.namespace());
                 match kube_ctx {
                     Ok(ctx) => BurregoRuntime(burrego_evaluator).validate(settings, &request, &ctx),
                     Err(e) => {
@@ -45,12 +156,28 @@ impl PolicyEvaluator {
                     }
                 }
             }
-            Runtime::Cli(ref mut cli_stack) => WasiRuntime(cli_stack).validate(settings, &request),
+            Runtime::Cli(ref mut cli_stack) => {
+                WasiRuntime(cli_stack).validate(settings, &request, self.patch_generation)
+            }
         }
     }
 
+    /// Validate the settings of the policy.
+    ///
+    /// When a settings schema has been provided, the settings are checked against it
+    /// first. The `validate_settings` function of the policy is invoked only when the
+    /// settings match the schema.
     #[tracing::instrument]
     pub fn validate_settings(&mut self, settings: &PolicySettings) -> SettingsValidationResponse {
+        if let Some(schema) = &self.settings_schema
+            && let Err(err) = schema.validate(settings)
+        {
+            return SettingsValidationResponse {
+                valid: false,
+                message: Some(err.to_string()),
+            };
+        }
+
         let settings_str = match serde_json::to_string(settings) {
             Ok(settings) => settings,
             Err(err) => {
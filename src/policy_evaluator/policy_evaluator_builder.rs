@@ -3,9 +3,11 @@ use std::result::Result;
 
 use wasmtime_provider::wasmtime;
 
+use crate::admission_response::mutation::PatchGeneration;
 use crate::errors::PolicyEvaluatorBuilderError;
 use crate::policy_evaluator::errors::InvalidUserInputError;
 use crate::policy_evaluator::{PolicyEvaluatorPre, PolicyExecutionMode, stack_pre::StackPre};
+use crate::policy_metadata::SettingsSchema;
 use crate::runtimes::{rego, wapc, wasi_cli};
 
 /// Configure behavior of wasmtime [epoch-based interruptions](https://docs.rs/wasmtime/latest/wasmtime/struct.Config.html#method.epoch_interruption)
@@ -34,6 +36,9 @@ pub struct PolicyEvaluatorBuilder {
     execution_mode: Option<PolicyExecutionMode>,
     wasmtime_cache: bool,
     epoch_deadlines: Option<EpochDeadlines>,
+    settings_schema: Option<SettingsSchema>,
+    idempotency_check: bool,
+    patch_generation: PatchGeneration,
 }
 
 impl PolicyEvaluatorBuilder {
@@ -131,6 +136,37 @@ impl PolicyEvaluatorBuilder {
         self
     }
 
+    /// Validate the settings against the given schema before invoking the
+    /// `validate_settings` function of the policy. Settings that do not match
+    /// the schema are rejected without instantiating the policy.
+    ///
+    /// The schema is usually obtained from the policy metadata, see
+    /// [`Metadata::compile_settings_schema`](crate::policy_metadata::Metadata::compile_settings_schema)
+    #[must_use]
+    pub fn settings_schema(mut self, schema: SettingsSchema) -> Self {
+        self.settings_schema = Some(schema);
+        self
+    }
+
+    /// Ensure the mutations performed by the policy are idempotent, see
+    /// [`PolicyEvaluator::enable_idempotency_check`](crate::policy_evaluator::PolicyEvaluator::enable_idempotency_check)
+    ///
+    /// The check is enabled on all the `PolicyEvaluator` instances created via
+    /// the `PolicyEvaluatorPre`
+    #[must_use]
+    pub fn enable_idempotency_check(mut self) -> Self {
+        self.idempotency_check = true;
+        self
+    }
+
+    /// How the JSON Patch of a mutating policy is generated out of the mutated
+    /// object returned by the policy. Defaults to [`PatchGeneration::Diff`]
+    #[must_use]
+    pub fn patch_generation(mut self, patch_generation: PatchGeneration) -> Self {
+        self.patch_generation = patch_generation;
+        self
+    }
+
     /// Ensure the configuration provided to the build is correct
     fn validate_user_input(&self) -> Result<(), InvalidUserInputError> {
         if self.policy_file.is_some() && self.policy_contents.is_some() {
@@ -191,7 +227,12 @@ impl PolicyEvaluatorBuilder {
             }
         };
 
-        Ok(PolicyEvaluatorPre::new(stack_pre))
+        Ok(PolicyEvaluatorPre::new(
+            stack_pre,
+            self.settings_schema.clone(),
+            self.idempotency_check,
+            self.patch_generation,
+        ))
     }
 
     fn build_engine(&self) -> Result<wasmtime::Engine, PolicyEvaluatorBuilderError> {
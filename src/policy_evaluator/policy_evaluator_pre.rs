@@ -1,8 +1,10 @@
 use std::result::Result;
 
+use crate::admission_response::mutation::PatchGeneration;
 use crate::errors::PolicyEvaluatorPreError;
 use crate::evaluation_context::EvaluationContext;
 use crate::policy_evaluator::{PolicyEvaluator, stack_pre::StackPre};
+use crate::policy_metadata::SettingsSchema;
 use crate::runtimes::{Runtime, rego, wapc, wasi_cli};
 
 /// This struct provides a way to quickly allocate a `PolicyEvaluator`
@@ -12,11 +14,24 @@ use crate::runtimes::{Runtime, rego, wapc, wasi_cli};
 #[derive(Clone)]
 pub struct PolicyEvaluatorPre {
     stack_pre: StackPre,
+    settings_schema: Option<SettingsSchema>,
+    idempotency_check: bool,
+    patch_generation: PatchGeneration,
 }
 
 impl PolicyEvaluatorPre {
-    pub(crate) fn new(stack_pre: StackPre) -> Self {
-        PolicyEvaluatorPre { stack_pre }
+    pub(crate) fn new(
+        stack_pre: StackPre,
+        settings_schema: Option<SettingsSchema>,
+        idempotency_check: bool,
+        patch_generation: PatchGeneration,
+    ) -> Self {
+        PolicyEvaluatorPre {
+            stack_pre,
+            settings_schema,
+            idempotency_check,
+            patch_generation,
+        }
     }
 
     /// Create a `PolicyEvaluator` instance. The creation of the instance is achieved by
@@ -47,6 +62,16 @@ impl PolicyEvaluatorPre {
             }
         };
 
-        Ok(PolicyEvaluator::new(runtime, eval_ctx))
+        let mut policy_evaluator = PolicyEvaluator::new(
+            runtime,
+            eval_ctx,
+            self.settings_schema.clone(),
+            self.patch_generation,
+        );
+        if self.idempotency_check {
+            policy_evaluator.enable_idempotency_check();
+        }
+
+        Ok(policy_evaluator)
     }
 }
@@ -1,9 +1,14 @@
-use std::{collections::BTreeSet, fmt};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt,
+    time::Duration,
+};
 
 use kubewarden_policy_sdk::crd::policies::{
     admission_policy_group::PolicyGroupMember,
     cluster_admission_policy_group::PolicyGroupMemberWithContext,
 };
+use serde::{Deserialize, Serialize};
 
 pub mod errors;
 pub mod evaluator;
@@ -24,30 +29,92 @@ pub struct PolicyGroupMemberSettings {
     pub epoch_deadline: Option<u64>,
 }
 
+/// The outcome of the evaluation of a policy group member
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PolicyGroupMemberVerdict {
+    /// The policy accepted the request
+    Allowed,
+    /// The policy rejected the request
+    Denied,
+    /// The policy could not be evaluated, for example because it could not be
+    /// rehydrated or because the evaluation raised an error
+    Error,
+    /// The policy has not been evaluated because the group expression
+    /// short-circuited before reaching it
+    NotEvaluated,
+}
+
+impl fmt::Display for PolicyGroupMemberVerdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PolicyGroupMemberVerdict::Allowed => write!(f, "ALLOWED"),
+            PolicyGroupMemberVerdict::Denied => write!(f, "DENIED"),
+            PolicyGroupMemberVerdict::Error => write!(f, "ERROR"),
+            PolicyGroupMemberVerdict::NotEvaluated => write!(f, "NOT EVALUATED"),
+        }
+    }
+}
+
 /// This holds the a summary of the evaluation results of a policy group member
-struct PolicyGroupMemberEvaluationResult {
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PolicyGroupMemberEvaluationResult {
+    /// The outcome of the evaluation
+    pub verdict: PolicyGroupMemberVerdict,
+    /// the optional message included inside of the evaluation result of the policy,
+    /// or the error that prevented the policy from being evaluated
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// The time spent evaluating the policy. This is `None` when the policy has
+    /// not been evaluated
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency: Option<Duration>,
+    /// The warnings returned by the policy
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warnings: Option<Vec<String>>,
+    /// The audit annotations returned by the policy
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub audit_annotations: Option<HashMap<String, String>>,
+}
+
+impl PolicyGroupMemberEvaluationResult {
+    /// Build the result of a policy that has not been evaluated
+    pub fn not_evaluated() -> Self {
+        Self {
+            verdict: PolicyGroupMemberVerdict::NotEvaluated,
+            message: None,
+            latency: None,
+            warnings: None,
+            audit_annotations: None,
+        }
+    }
+
     /// whether the request is allowed or not
-    allowed: bool,
-    /// the optional message included inside of the evaluation result of the policy
-    message: Option<String>,
+    pub fn allowed(&self) -> bool {
+        self.verdict == PolicyGroupMemberVerdict::Allowed
+    }
 }
 
 impl From<AdmissionResponse> for PolicyGroupMemberEvaluationResult {
     fn from(response: AdmissionResponse) -> Self {
         Self {
-            allowed: response.allowed,
+            verdict: if response.allowed {
+                PolicyGroupMemberVerdict::Allowed
+            } else {
+                PolicyGroupMemberVerdict::Denied
+            },
             message: response.status.and_then(|status| status.message),
+            latency: None,
+            warnings: response.warnings,
+            audit_annotations: response.audit_annotations,
         }
     }
 }
 
 impl fmt::Display for PolicyGroupMemberEvaluationResult {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if self.allowed {
-            write!(f, "[ALLOWED]")?;
-        } else {
-            write!(f, "[DENIED]")?;
-        }
+        write!(f, "[{}]", self.verdict)?;
         if let Some(message) = &self.message {
             write!(f, " - {}", message)?;
         }
@@ -56,6 +123,119 @@ impl fmt::Display for PolicyGroupMemberEvaluationResult {
     }
 }
 
+/// The detailed report of the evaluation of a policy group.
+///
+/// It contains the verdict of each member of the group, including the ones
+/// that have been allowed or that have not been evaluated at all. This is
+/// useful to report partial compliance, since the `AdmissionResponse` only
+/// lists the members that rejected the request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PolicyGroupEvaluationReport {
+    /// The unique identifier of the policy group
+    pub policy_id: String,
+    /// The final verdict of the policy group
+    pub allowed: bool,
+    /// The evaluation result of each member of the group, indexed by member name
+    pub members: BTreeMap<String, PolicyGroupMemberEvaluationResult>,
+}
+
+/// Defines which policy group members contribute to an aggregated field
+/// of the policy group response
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MemberAggregation {
+    /// The field is not aggregated, the policy group response does not include it
+    Disabled,
+    /// All the members that have been evaluated contribute to the field
+    #[default]
+    Evaluated,
+    /// Only the members that rejected the request contribute to the field
+    Denied,
+}
+
+impl MemberAggregation {
+    fn includes(&self, verdict: PolicyGroupMemberVerdict) -> bool {
+        match self {
+            MemberAggregation::Disabled => false,
+            MemberAggregation::Evaluated => matches!(
+                verdict,
+                PolicyGroupMemberVerdict::Allowed | PolicyGroupMemberVerdict::Denied
+            ),
+            MemberAggregation::Denied => verdict == PolicyGroupMemberVerdict::Denied,
+        }
+    }
+}
+
+/// The rules used to aggregate the warnings and the audit annotations produced by the
+/// members of a policy group into the policy group response.
+///
+/// Warnings are prefixed with the name of the member that produced them, e.g.
+/// `member_name: warning message`.
+/// Audit annotations keys are prefixed with the name of the member that produced them,
+/// e.g. `member_name.key`. A dot is used as separator because the Kubernetes API server
+/// prefixes the keys with the name of the webhook, followed by a slash.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PolicyGroupResponseAggregation {
+    /// Which members contribute to the warnings of the policy group response
+    pub warnings: MemberAggregation,
+    /// Which members contribute to the audit annotations of the policy group response
+    pub audit_annotations: MemberAggregation,
+}
+
+impl PolicyGroupResponseAggregation {
+    /// Aggregate the warnings of the policy group members. Returns `None` when
+    /// no warning has been produced
+    pub fn aggregate_warnings(
+        &self,
+        members: &BTreeMap<String, PolicyGroupMemberEvaluationResult>,
+    ) -> Option<Vec<String>> {
+        let warnings: Vec<String> = members
+            .iter()
+            .filter(|(_, result)| self.warnings.includes(result.verdict))
+            .flat_map(|(name, result)| {
+                result
+                    .warnings
+                    .iter()
+                    .flatten()
+                    .map(move |warning| format!("{name}: {warning}"))
+            })
+            .collect();
+
+        if warnings.is_empty() {
+            None
+        } else {
+            Some(warnings)
+        }
+    }
+
+    /// Aggregate the audit annotations of the policy group members. Returns `None` when
+    /// no audit annotation has been produced
+    pub fn aggregate_audit_annotations(
+        &self,
+        members: &BTreeMap<String, PolicyGroupMemberEvaluationResult>,
+    ) -> Option<HashMap<String, String>> {
+        let audit_annotations: HashMap<String, String> = members
+            .iter()
+            .filter(|(_, result)| self.audit_annotations.includes(result.verdict))
+            .flat_map(|(name, result)| {
+                result
+                    .audit_annotations
+                    .iter()
+                    .flatten()
+                    .map(move |(key, value)| (format!("{name}.{key}"), value.clone()))
+            })
+            .collect();
+
+        if audit_annotations.is_empty() {
+            None
+        } else {
+            Some(audit_annotations)
+        }
+    }
+}
+
 impl TryFrom<&PolicyGroupMemberWithContext> for PolicyGroupMemberSettings {
     type Error = &'static str;
 
@@ -102,8 +282,81 @@ mod tests {
     use assert_json_diff::assert_json_eq;
     use k8s_openapi::apimachinery::pkg::runtime::RawExtension;
     use kubewarden_policy_sdk::crd::policies::common::ContextAwareResource as ContextAwareResourceSdk;
+    use rstest::rstest;
     use serde_json::json;
 
+    fn member_result(
+        verdict: PolicyGroupMemberVerdict,
+        warnings: &[&str],
+        audit_annotations: &[(&str, &str)],
+    ) -> PolicyGroupMemberEvaluationResult {
+        PolicyGroupMemberEvaluationResult {
+            verdict,
+            message: None,
+            latency: None,
+            warnings: Some(warnings.iter().map(|w| w.to_string()).collect()),
+            audit_annotations: Some(
+                audit_annotations
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect(),
+            ),
+        }
+    }
+
+    #[rstest]
+    #[case::evaluated(
+        MemberAggregation::Evaluated,
+        Some(vec!["allowed: w1".to_string(), "denied: w2".to_string()]),
+        Some(HashMap::from([
+            ("allowed.key".to_string(), "a".to_string()),
+            ("denied.key".to_string(), "d".to_string()),
+        ])),
+    )]
+    #[case::denied(
+        MemberAggregation::Denied,
+        Some(vec!["denied: w2".to_string()]),
+        Some(HashMap::from([("denied.key".to_string(), "d".to_string())])),
+    )]
+    #[case::disabled(MemberAggregation::Disabled, None, None)]
+    fn aggregate_members_warnings_and_audit_annotations(
+        #[case] aggregation: MemberAggregation,
+        #[case] expected_warnings: Option<Vec<String>>,
+        #[case] expected_audit_annotations: Option<HashMap<String, String>>,
+    ) {
+        let members = BTreeMap::from([
+            (
+                "allowed".to_string(),
+                member_result(PolicyGroupMemberVerdict::Allowed, &["w1"], &[("key", "a")]),
+            ),
+            (
+                "denied".to_string(),
+                member_result(PolicyGroupMemberVerdict::Denied, &["w2"], &[("key", "d")]),
+            ),
+            (
+                "error".to_string(),
+                member_result(PolicyGroupMemberVerdict::Error, &["w3"], &[("key", "e")]),
+            ),
+            (
+                "not_evaluated".to_string(),
+                PolicyGroupMemberEvaluationResult::not_evaluated(),
+            ),
+        ]);
+        let response_aggregation = PolicyGroupResponseAggregation {
+            warnings: aggregation,
+            audit_annotations: aggregation,
+        };
+
+        assert_eq!(
+            response_aggregation.aggregate_warnings(&members),
+            expected_warnings
+        );
+        assert_eq!(
+            response_aggregation.aggregate_audit_annotations(&members),
+            expected_audit_annotations
+        );
+    }
+
     #[test]
     fn test_convert_policy_group_member_with_context_into_policy_group_member() {
         let settings = json!({
@@ -126,10 +379,14 @@ mod tests {
             ContextAwareResource {
                 api_version: "v1".to_string(),
                 kind: "Pod".to_string(),
+                fields: Vec::new(),
+                namespace_scope: Default::default(),
             },
             ContextAwareResource {
                 api_version: "v1".to_string(),
                 kind: "Service".to_string(),
+                fields: Vec::new(),
+                namespace_scope: Default::default(),
             },
         ]);
 
@@ -2,6 +2,7 @@ use std::{
     collections::HashMap,
     fmt,
     sync::{Arc, Mutex},
+    time::Instant,
 };
 
 use kubewarden_policy_sdk::settings::SettingsValidationResponse;
@@ -14,7 +15,8 @@ use crate::callback_requests::CallbackRequest;
 use crate::evaluation_context::EvaluationContext;
 use crate::policy_evaluator::{PolicyEvaluatorPre, ValidateRequest};
 use crate::policy_group_evaluator::{
-    PolicyGroupMemberEvaluationResult, PolicyGroupMemberSettings,
+    PolicyGroupEvaluationReport, PolicyGroupMemberEvaluationResult, PolicyGroupMemberSettings,
+    PolicyGroupMemberVerdict, PolicyGroupResponseAggregation,
     errors::{EvaluationError, Result},
 };
 
@@ -60,6 +62,9 @@ pub struct PolicyGroupEvaluator {
     /// to request the computation of code that can only be run inside of an
     /// asynchronous block
     callback_channel: Option<mpsc::Sender<CallbackRequest>>,
+
+    /// The rules used to aggregate the warnings and the audit annotations of the members
+    response_aggregation: PolicyGroupResponseAggregation,
 }
 
 impl fmt::Debug for PolicyGroupEvaluator {
@@ -89,9 +94,19 @@ impl PolicyGroupEvaluator {
             policy_members: HashMap::new(),
             policy_members_settings: HashMap::new(),
             callback_channel,
+            response_aggregation: PolicyGroupResponseAggregation::default(),
         }
     }
 
+    /// Set the rules used to aggregate the warnings and the audit annotations
+    /// produced by the policy members into the policy group response
+    pub fn set_response_aggregation(
+        &mut self,
+        response_aggregation: PolicyGroupResponseAggregation,
+    ) {
+        self.response_aggregation = response_aggregation;
+    }
+
     /// Add a policy to the group
     pub fn add_policy_member(
         &mut self,
@@ -109,8 +124,25 @@ impl PolicyGroupEvaluator {
     ///
     /// Note, `self` is wrapped inside of `Arc` because this method is called from within a Rhai engine closure that
     /// requires `+send` and `+sync`.
-    #[tracing::instrument(skip(request))]
     pub fn validate(self: Arc<Self>, request: &ValidateRequest) -> AdmissionResponse {
+        let (admission_response, _) = self.validate_with_report(request);
+        admission_response
+    }
+
+    /// Validate the request against the group of policies, returning also a detailed
+    /// report about the evaluation of each member of the group.
+    ///
+    /// The report includes the members that allowed the request, the ones that could not be
+    /// evaluated because of an error and the ones that were not evaluated at all because the
+    /// expression short-circuited.
+    ///
+    /// Note, `self` is wrapped inside of `Arc` because this method is called from within a Rhai engine closure that
+    /// requires `+send` and `+sync`.
+    #[tracing::instrument(skip(request))]
+    pub fn validate_with_report(
+        self: Arc<Self>,
+        request: &ValidateRequest,
+    ) -> (AdmissionResponse, PolicyGroupEvaluationReport) {
         // We create a RAW engine, which has a really limited set of built-ins available
         let mut rhai_engine = rhai::Engine::new_raw();
 
@@ -129,20 +161,38 @@ impl PolicyGroupEvaluator {
             rhai_engine.register_fn(
                 sub_policy_name.clone().as_str(),
                 move || -> std::result::Result<bool, Box<EvalAltResult>> {
+                    let start = Instant::now();
                     let response = Self::validate_policy(
                         rhai_eval_env.clone(),
                         &sub_policy_name,
                         &validate_request,
-                    )
-                    .map_err(|e| {
-                        EvalAltResult::ErrorSystem(
-                            format!(
-                                "error invoking {}/{}",
-                                rhai_eval_env.policy_id, sub_policy_name
-                            ),
-                            Box::new(e),
-                        )
-                    })?;
+                    );
+                    let latency = start.elapsed();
+
+                    let response = match response {
+                        Ok(response) => response,
+                        Err(e) => {
+                            let mut results = evaluation_results.lock().unwrap();
+                            results.insert(
+                                sub_policy_name.clone(),
+                                PolicyGroupMemberEvaluationResult {
+                                    verdict: PolicyGroupMemberVerdict::Error,
+                                    message: Some(e.to_string()),
+                                    latency: Some(latency),
+                                    warnings: None,
+                                    audit_annotations: None,
+                                },
+                            );
+
+                            return Err(Box::new(EvalAltResult::ErrorSystem(
+                                format!(
+                                    "error invoking {}/{}",
+                                    rhai_eval_env.policy_id, sub_policy_name
+                                ),
+                                Box::new(e),
+                            )));
+                        }
+                    };
 
                     if response.patch.is_some() {
                         // mutation is not allowed inside of group policies
@@ -150,10 +200,12 @@ impl PolicyGroupEvaluator {
                         results.insert(
                             sub_policy_name.clone(),
                             PolicyGroupMemberEvaluationResult {
-                                allowed: false,
+                                verdict: PolicyGroupMemberVerdict::Denied,
                                 message: Some(
                                     "mutation is not allowed inside of policy group".to_string(),
                                 ),
+                                latency: Some(latency),
+                                ..PolicyGroupMemberEvaluationResult::from(response)
                             },
                         );
                         return Ok(false);
@@ -162,7 +214,13 @@ impl PolicyGroupEvaluator {
                     let allowed = response.allowed;
 
                     let mut results = evaluation_results.lock().unwrap();
-                    results.insert(sub_policy_name.clone(), response.into());
+                    results.insert(
+                        sub_policy_name.clone(),
+                        PolicyGroupMemberEvaluationResult {
+                            latency: Some(latency),
+                            ..PolicyGroupMemberEvaluationResult::from(response)
+                        },
+                    );
 
                     Ok(allowed)
                 },
@@ -174,12 +232,34 @@ impl PolicyGroupEvaluator {
 
         // Note: we use `eval_expression` to limit even further what the user is allowed
         // to define inside of the expression
-        let allowed = match rhai_engine.eval_expression::<bool>(self.expression.as_str()) {
+        let evaluation = rhai_engine.eval_expression::<bool>(self.expression.as_str());
+
+        let evaluation_results = policies_evaluation_results.lock().unwrap();
+        let report = PolicyGroupEvaluationReport {
+            policy_id: self.policy_id.clone(),
+            allowed: matches!(evaluation, Ok(true)),
+            members: self
+                .policy_members
+                .keys()
+                .map(|policy_id| {
+                    let result = evaluation_results
+                        .get(policy_id)
+                        .cloned()
+                        .unwrap_or_else(PolicyGroupMemberEvaluationResult::not_evaluated);
+                    (policy_id.clone(), result)
+                })
+                .collect(),
+        };
+
+        let allowed = match evaluation {
             Ok(allowed) => allowed,
             Err(e) => {
                 let message = format!("error evaluating policy group expression: {}", e);
                 debug!(?e, "error evaluating policy group expression");
-                return AdmissionResponse::reject(request.uid().to_string(), message, 500);
+                return (
+                    AdmissionResponse::reject(request.uid().to_string(), message, 500),
+                    report,
+                );
             }
         };
 
@@ -187,11 +267,9 @@ impl PolicyGroupEvaluator {
         // AdmissionResponse.status.details.causes
         let mut status_causes = vec![];
 
-        let evaluation_results = policies_evaluation_results.lock().unwrap();
-
         for policy_id in self.policy_members.keys() {
             if let Some(result) = evaluation_results.get(policy_id)
-                && !result.allowed
+                && result.verdict == PolicyGroupMemberVerdict::Denied
             {
                 let cause = admission_response::StatusCause {
                     field: Some(format!("spec.policies.{}", policy_id)),
@@ -224,15 +302,21 @@ impl PolicyGroupEvaluator {
             })
         };
 
-        AdmissionResponse {
+        let admission_response = AdmissionResponse {
             uid: request.uid().to_string(),
             allowed,
             patch_type: None,
             patch: None,
             status,
-            audit_annotations: None,
-            warnings: None,
-        }
+            audit_annotations: self
+                .response_aggregation
+                .aggregate_audit_annotations(&report.members),
+            warnings: self
+                .response_aggregation
+                .aggregate_warnings(&report.members),
+        };
+
+        (admission_response, report)
     }
 
     /// Validate the request against a single policy
@@ -260,6 +344,7 @@ impl PolicyGroupEvaluator {
             callback_channel: self.callback_channel.clone(),
             ctx_aware_resources_allow_list: settings.ctx_aware_resources_allow_list.clone(),
             epoch_deadline: settings.epoch_deadline,
+            callback_recorder: None,
         };
         let mut evaluator = evaluator_pre.rehydrate(&eval_ctx).map_err(|e| {
             EvaluationError::CannotRehydratePolicyGroupMember(policy_id.to_owned(), e)
@@ -334,6 +419,7 @@ impl PolicyGroupEvaluator {
             callback_channel: self.callback_channel.clone(),
             ctx_aware_resources_allow_list: settings.ctx_aware_resources_allow_list.clone(),
             epoch_deadline: settings.epoch_deadline,
+            callback_recorder: None,
         };
         let mut evaluator = evaluator_pre.rehydrate(&eval_ctx).map_err(|e| {
             EvaluationError::CannotRehydratePolicyGroupMember(policy_id.to_owned(), e)
@@ -503,6 +589,58 @@ mod tests {
         }
     }
 
+    #[test]
+    fn group_policy_evaluation_report() {
+        let mut policy_group_evaluator = PolicyGroupEvaluator::new(
+            "group_policy",
+            "something went wrong",
+            "unhappy_policy_1() || happy_policy_1() || unhappy_policy_2()",
+            None,
+        );
+        for (policy_id, policy_pre) in [
+            ("unhappy_policy_1", POLICY_ALWAYS_UNHAPPY.clone()),
+            ("happy_policy_1", POLICY_ALWAYS_HAPPY.clone()),
+            ("unhappy_policy_2", POLICY_ALWAYS_UNHAPPY.clone()),
+        ] {
+            policy_group_evaluator.add_policy_member(
+                policy_id,
+                Arc::new(policy_pre),
+                PolicyGroupMemberSettings {
+                    settings: Default::default(),
+                    ctx_aware_resources_allow_list: Default::default(),
+                    epoch_deadline: None,
+                },
+            );
+        }
+        let validate_request = build_validate_request();
+
+        let policy_group_evaluator = Arc::new(policy_group_evaluator);
+        let (response, report) = policy_group_evaluator.validate_with_report(&validate_request);
+
+        assert!(response.allowed);
+        assert!(report.allowed);
+        assert_eq!(report.policy_id, "group_policy");
+        assert_eq!(report.members.len(), 3);
+
+        let unhappy_policy_1 = &report.members["unhappy_policy_1"];
+        assert_eq!(unhappy_policy_1.verdict, PolicyGroupMemberVerdict::Denied);
+        assert_eq!(
+            unhappy_policy_1.message,
+            Some("failing as expected".to_string())
+        );
+        assert!(unhappy_policy_1.latency.is_some());
+
+        let happy_policy_1 = &report.members["happy_policy_1"];
+        assert_eq!(happy_policy_1.verdict, PolicyGroupMemberVerdict::Allowed);
+        assert!(happy_policy_1.latency.is_some());
+
+        // the expression short-circuits after `happy_policy_1`
+        assert_eq!(
+            report.members["unhappy_policy_2"],
+            PolicyGroupMemberEvaluationResult::not_evaluated()
+        );
+    }
+
     #[rstest]
     #[case::valid_expression_with_single_policy(
         "true || happy_policy_1()",
@@ -11,7 +11,19 @@ use serde::{Deserialize, Serialize};
 use validator::{Validate, ValidationError};
 use wasmparser::{Parser, Payload};
 
-use crate::{errors::MetadataError, policy_evaluator::PolicyExecutionMode};
+mod settings_schema;
+mod webhook_configuration;
+
+pub use settings_schema::SettingsSchema;
+pub use webhook_configuration::{
+    FailurePolicy, ReinvocationPolicy, WebhookConfiguration, WebhookConfigurationParams,
+};
+
+use crate::{
+    admission_request::{AdmissionRequest, GroupVersionResource},
+    errors::{MetadataError, SettingsSchemaError},
+    policy_evaluator::PolicyExecutionMode,
+};
 
 #[derive(Deserialize, Serialize, Debug, Clone, Hash, Eq, PartialEq)]
 pub enum Operation {
@@ -27,6 +39,19 @@ pub enum Operation {
     All,
 }
 
+impl Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            Operation::Create => "CREATE",
+            Operation::Update => "UPDATE",
+            Operation::Delete => "DELETE",
+            Operation::Connect => "CONNECT",
+            Operation::All => "*",
+        };
+        write!(f, "{op}")
+    }
+}
+
 impl TryFrom<&str> for Operation {
     type Error = &'static str;
 
@@ -138,6 +163,66 @@ fn validate_resources(data: &[String]) -> Result<(), ValidationError> {
     Ok(())
 }
 
+impl Rule {
+    /// Checks if the given request matches the rule, following the semantics
+    /// of the rules of Kubernetes admission webhooks.
+    ///
+    /// The request matches when its operation, API group, API version and resource
+    /// are all matched by the rule. Resources can use wildcards: `*` matches all the
+    /// resources, but not their subresources; `pods/*` matches pods and all their
+    /// subresources; `*/scale` matches the `scale` subresource of all the resources;
+    /// `*/*` matches everything.
+    ///
+    /// Both the originally requested resource and the one sent to the webhook are
+    /// considered, like Kubernetes does when the `Equivalent` match policy is used.
+    pub fn matches(&self, request: &AdmissionRequest) -> bool {
+        if !self.matches_operation(&request.operation) {
+            return false;
+        }
+
+        let original_resource = request
+            .request_resource
+            .as_ref()
+            .map(|resource| (resource, request.request_sub_resource.as_deref()));
+        let resources = [
+            Some((&request.resource, request.sub_resource.as_deref())),
+            original_resource,
+        ];
+
+        resources
+            .into_iter()
+            .flatten()
+            .any(|(resource, sub_resource)| {
+                self.matches_resource(resource, sub_resource.unwrap_or_default())
+            })
+    }
+
+    fn matches_operation(&self, operation: &str) -> bool {
+        match Operation::try_from(operation) {
+            Ok(operation) => self
+                .operations
+                .iter()
+                .any(|op| *op == Operation::All || *op == operation),
+            Err(_) => false,
+        }
+    }
+
+    fn matches_resource(&self, resource: &GroupVersionResource, sub_resource: &str) -> bool {
+        let matches =
+            |values: &[String], value: &str| values.iter().any(|v| v == "*" || v == value);
+
+        matches(&self.api_groups, &resource.group)
+            && matches(&self.api_versions, &resource.version)
+            && self.resources.iter().any(|rule_resource| {
+                let (rule_res, rule_sub) = rule_resource
+                    .split_once('/')
+                    .unwrap_or((rule_resource.as_str(), ""));
+                (rule_res == "*" || rule_res == resource.resource)
+                    && (rule_sub == "*" || rule_sub == sub_resource)
+            })
+    }
+}
+
 impl TryFrom<&NamedRuleWithOperations> for Rule {
     type Error = &'static str;
 
@@ -166,6 +251,78 @@ pub struct ContextAwareResource {
     pub api_version: String,
     #[validate(length(min = 1))]
     pub kind: String,
+    /// The fields of the objects needed by the policy, like `metadata.labels`
+    /// or `spec.containers[*].image`. When provided, all the other fields are
+    /// removed from the objects provided to the policy and, unless other policies
+    /// need them, from the ones cached by the host. When empty, the whole
+    /// objects are provided
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[validate(custom(function = "validate_field_paths"))]
+    pub fields: Vec<String>,
+    /// The namespaces whose resources can be accessed by the policy. By default
+    /// the resources of the whole cluster can be accessed
+    #[serde(default, skip_serializing_if = "NamespaceScope::is_cluster")]
+    #[validate(custom(function = "validate_namespace_scope"))]
+    pub namespace_scope: NamespaceScope,
+}
+
+/// The namespaces a policy can access the resources of
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, Hash, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "camelCase")]
+pub enum NamespaceScope {
+    /// All the namespaced resources, together with the cluster-wide ones
+    #[default]
+    Cluster,
+    /// Only the resources defined inside of the namespace of the admission
+    /// request being evaluated
+    RequestNamespace,
+    /// Only the resources defined inside of the given namespaces
+    Namespaces(BTreeSet<String>),
+}
+
+impl NamespaceScope {
+    pub fn is_cluster(&self) -> bool {
+        *self == NamespaceScope::Cluster
+    }
+
+    /// Checks if the resources of `namespace` can be accessed. `None` stands for
+    /// the resources of all the namespaces, or for the cluster-wide ones.
+    /// `request_namespace` is the namespace of the admission request being evaluated
+    pub fn allows(&self, namespace: Option<&str>, request_namespace: Option<&str>) -> bool {
+        match (self, namespace) {
+            (NamespaceScope::Cluster, _) => true,
+            (_, None) => false,
+            (NamespaceScope::RequestNamespace, Some(namespace)) => {
+                request_namespace == Some(namespace)
+            }
+            (NamespaceScope::Namespaces(namespaces), Some(namespace)) => {
+                namespaces.contains(namespace)
+            }
+        }
+    }
+}
+
+fn validate_namespace_scope(scope: &NamespaceScope) -> Result<(), ValidationError> {
+    if let NamespaceScope::Namespaces(namespaces) = scope
+        && (namespaces.is_empty() || namespaces.iter().any(String::is_empty))
+    {
+        return Err(ValidationError::new(
+            "namespaceScope must list at least one namespace, and namespaces cannot be empty",
+        ));
+    }
+    Ok(())
+}
+
+fn validate_field_paths(paths: &[String]) -> Result<(), ValidationError> {
+    if paths
+        .iter()
+        .any(|path| crate::callback_handler::FieldPath::parse(path).is_err())
+    {
+        return Err(ValidationError::new(
+            "fields must be made of keys separated by dots, `[*]` can be used to select all the items of a list",
+        ));
+    }
+    Ok(())
 }
 
 impl From<&kubewarden_policy_sdk::crd::policies::common::ContextAwareResource>
@@ -175,6 +332,8 @@ impl From<&kubewarden_policy_sdk::crd::policies::common::ContextAwareResource>
         Self {
             api_version: resource.api_version.clone(),
             kind: resource.kind.clone(),
+            fields: Vec::new(),
+            namespace_scope: NamespaceScope::Cluster,
         }
     }
 }
@@ -217,6 +376,9 @@ pub struct Metadata {
     pub context_aware_resources: BTreeSet<ContextAwareResource>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub minimum_kubewarden_version: Option<Version>,
+    /// JSON Schema describing the settings accepted by the policy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub settings_schema: Option<serde_json::Value>,
 }
 
 const fn _default_true() -> bool {
@@ -235,11 +397,29 @@ impl Default for Metadata {
             policy_type: PolicyType::Kubernetes,
             context_aware_resources: BTreeSet::new(),
             minimum_kubewarden_version: None,
+            settings_schema: None,
         }
     }
 }
 
 impl Metadata {
+    /// Checks if the given request matches at least one of the rules of the policy.
+    /// Raw policies never match admission requests.
+    pub fn matches(&self, request: &AdmissionRequest) -> bool {
+        self.policy_type == PolicyType::Kubernetes
+            && self.rules.iter().any(|rule| rule.matches(request))
+    }
+
+    /// Compile the settings schema of the policy, if one is provided
+    pub fn compile_settings_schema(
+        &self,
+    ) -> std::result::Result<Option<SettingsSchema>, SettingsSchemaError> {
+        self.settings_schema
+            .as_ref()
+            .map(SettingsSchema::new)
+            .transpose()
+    }
+
     pub fn from_path(path: &Path) -> std::result::Result<Option<Metadata>, MetadataError> {
         Metadata::from_contents(&std::fs::read(path).map_err(MetadataError::Path)?)
     }
@@ -269,13 +449,20 @@ fn validate_metadata(metadata: &Metadata) -> Result<(), ValidationError> {
             "Must specify a valid protocol version",
         ));
     }
+    if metadata.compile_settings_schema().is_err() {
+        return Err(ValidationError::new(
+            "settingsSchema must be a valid JSON Schema",
+        ));
+    }
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{admission_request::GroupVersionKind, test_fixtures};
     use assert_json_diff::assert_json_eq;
+    use rstest::rstest;
     use serde_json::json;
 
     #[test]
@@ -296,6 +483,28 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn metadata_with_settings_schema() {
+        let metadata: Metadata = serde_json::from_value(json!({
+            "protocolVersion": "v1",
+            "rules": [],
+            "mutating": false,
+            "settingsSchema": {
+                "type": "object",
+                "properties": {"replicas": {"type": "integer"}}
+            }
+        }))
+        .expect("cannot deserialize metadata");
+        assert!(metadata.validate().is_ok());
+        assert!(metadata.compile_settings_schema().unwrap().is_some());
+
+        let metadata = Metadata {
+            settings_schema: Some(json!({"type": 42})),
+            ..metadata
+        };
+        assert!(metadata.validate().is_err());
+    }
+
     #[test]
     fn metadata_validation_failure() -> Result<(), ()> {
         // fail because api_groups has both '*' and another value
@@ -678,6 +887,8 @@ mod tests {
         context_aware_resources.insert(ContextAwareResource {
             api_version: "".to_string(),
             kind: "Pod".to_string(),
+            fields: Vec::new(),
+            namespace_scope: Default::default(),
         });
 
         let metadata = Metadata {
@@ -702,6 +913,8 @@ mod tests {
         context_aware_resources.insert(ContextAwareResource {
             api_version: "v1".to_string(),
             kind: "".to_string(),
+            fields: Vec::new(),
+            namespace_scope: Default::default(),
         });
 
         let metadata = Metadata {
@@ -713,4 +926,162 @@ mod tests {
 
         assert!(metadata.validate().is_err());
     }
+
+    #[rstest]
+    #[case::valid(&["metadata.labels", "spec.containers[*].image"], true)]
+    #[case::invalid(&["spec.containers[0].image"], false)]
+    fn validate_context_aware_resource_fields(#[case] fields: &[&str], #[case] valid: bool) {
+        let metadata = Metadata {
+            protocol_version: Some(ProtocolVersion::V1),
+            context_aware_resources: BTreeSet::from([ContextAwareResource {
+                api_version: "v1".to_string(),
+                kind: "Pod".to_string(),
+                fields: fields.iter().map(|f| f.to_string()).collect(),
+                namespace_scope: Default::default(),
+            }]),
+            ..Default::default()
+        };
+
+        assert_eq!(metadata.validate().is_ok(), valid);
+    }
+
+    #[rstest]
+    #[case::default(json!({"apiVersion": "v1", "kind": "ConfigMap"}), Some(NamespaceScope::Cluster))]
+    #[case::request_namespace(
+        json!({"apiVersion": "v1", "kind": "ConfigMap", "namespaceScope": "requestNamespace"}),
+        Some(NamespaceScope::RequestNamespace)
+    )]
+    #[case::allow_list(
+        json!({"apiVersion": "v1", "kind": "ConfigMap", "namespaceScope": {"namespaces": ["default"]}}),
+        Some(NamespaceScope::Namespaces(BTreeSet::from(["default".to_string()])))
+    )]
+    #[case::empty_allow_list(
+        json!({"apiVersion": "v1", "kind": "ConfigMap", "namespaceScope": {"namespaces": []}}),
+        None
+    )]
+    fn context_aware_resource_namespace_scope(
+        #[case] resource: serde_json::Value,
+        #[case] expected: Option<NamespaceScope>,
+    ) {
+        let resource: ContextAwareResource =
+            serde_json::from_value(resource).expect("cannot deserialize resource");
+        let metadata = Metadata {
+            protocol_version: Some(ProtocolVersion::V1),
+            context_aware_resources: BTreeSet::from([resource.clone()]),
+            ..Default::default()
+        };
+
+        match expected {
+            Some(scope) => {
+                assert!(metadata.validate().is_ok());
+                assert_eq!(resource.namespace_scope, scope);
+            }
+            None => assert!(metadata.validate().is_err()),
+        }
+    }
+
+    fn rule(
+        api_groups: &[&str],
+        api_versions: &[&str],
+        resources: &[&str],
+        operations: &[Operation],
+    ) -> Rule {
+        Rule {
+            api_groups: api_groups.iter().map(|s| s.to_string()).collect(),
+            api_versions: api_versions.iter().map(|s| s.to_string()).collect(),
+            resources: resources.iter().map(|s| s.to_string()).collect(),
+            operations: operations.to_vec(),
+        }
+    }
+
+    fn admission_request(
+        group: &str,
+        version: &str,
+        resource: &str,
+        sub_resource: Option<&str>,
+        operation: &str,
+    ) -> AdmissionRequest {
+        AdmissionRequest {
+            kind: GroupVersionKind {
+                group: group.to_string(),
+                version: version.to_string(),
+                kind: "Whatever".to_string(),
+            },
+            resource: GroupVersionResource {
+                group: group.to_string(),
+                version: version.to_string(),
+                resource: resource.to_string(),
+            },
+            sub_resource: sub_resource.map(str::to_string),
+            operation: operation.to_string(),
+            ..test_fixtures::admission_request()
+        }
+    }
+
+    #[rstest]
+    #[case::exact_match(rule(&[""], &["v1"], &["pods"], &[Operation::Create]), ("", "v1", "pods", None, "CREATE"), true)]
+    #[case::different_operation(rule(&[""], &["v1"], &["pods"], &[Operation::Create]), ("", "v1", "pods", None, "UPDATE"), false)]
+    #[case::all_operations(rule(&[""], &["v1"], &["pods"], &[Operation::All]), ("", "v1", "pods", None, "DELETE"), true)]
+    #[case::unknown_operation(rule(&[""], &["v1"], &["pods"], &[Operation::All]), ("", "v1", "pods", None, "PATCH"), false)]
+    #[case::different_group(rule(&["apps"], &["v1"], &["deployments"], &[Operation::All]), ("extensions", "v1", "deployments", None, "CREATE"), false)]
+    #[case::any_group(rule(&["*"], &["v1"], &["deployments"], &[Operation::All]), ("extensions", "v1", "deployments", None, "CREATE"), true)]
+    #[case::different_version(rule(&["apps"], &["v1"], &["deployments"], &[Operation::All]), ("apps", "v1beta1", "deployments", None, "CREATE"), false)]
+    #[case::any_version(rule(&["apps"], &["*"], &["deployments"], &[Operation::All]), ("apps", "v1beta1", "deployments", None, "CREATE"), true)]
+    #[case::different_resource(rule(&[""], &["v1"], &["pods"], &[Operation::All]), ("", "v1", "services", None, "CREATE"), false)]
+    #[case::wildcard_resource(rule(&[""], &["v1"], &["*"], &[Operation::All]), ("", "v1", "services", None, "CREATE"), true)]
+    #[case::wildcard_resource_does_not_match_subresource(rule(&[""], &["v1"], &["*"], &[Operation::All]), ("", "v1", "pods", Some("exec"), "CONNECT"), false)]
+    #[case::resource_does_not_match_subresource(rule(&[""], &["v1"], &["pods"], &[Operation::All]), ("", "v1", "pods", Some("status"), "UPDATE"), false)]
+    #[case::subresource(rule(&[""], &["v1"], &["pods/exec"], &[Operation::Connect]), ("", "v1", "pods", Some("exec"), "CONNECT"), true)]
+    #[case::different_subresource(rule(&[""], &["v1"], &["pods/exec"], &[Operation::All]), ("", "v1", "pods", Some("attach"), "CONNECT"), false)]
+    #[case::all_subresources(rule(&[""], &["v1"], &["pods/*"], &[Operation::All]), ("", "v1", "pods", Some("attach"), "CONNECT"), true)]
+    #[case::all_subresources_include_resource(rule(&[""], &["v1"], &["pods/*"], &[Operation::All]), ("", "v1", "pods", None, "CREATE"), true)]
+    #[case::subresource_of_all_resources(rule(&["*"], &["*"], &["*/scale"], &[Operation::Update]), ("apps", "v1", "deployments", Some("scale"), "UPDATE"), true)]
+    #[case::subresource_of_all_resources_does_not_match_resource(rule(&["*"], &["*"], &["*/scale"], &[Operation::Update]), ("apps", "v1", "deployments", None, "UPDATE"), false)]
+    #[case::everything(rule(&["*"], &["*"], &["*/*"], &[Operation::All]), ("apps", "v1", "deployments", Some("status"), "UPDATE"), true)]
+    #[case::one_of_many_resources(rule(&[""], &["v1"], &["pods", "services"], &[Operation::All]), ("", "v1", "services", None, "CREATE"), true)]
+    fn rule_matches(
+        #[case] rule: Rule,
+        #[case] request: (&str, &str, &str, Option<&str>, &str),
+        #[case] expected: bool,
+    ) {
+        let (group, version, resource, sub_resource, operation) = request;
+        let request = admission_request(group, version, resource, sub_resource, operation);
+
+        assert_eq!(rule.matches(&request), expected);
+    }
+
+    #[test]
+    fn rule_matches_originally_requested_resource() {
+        let rule = rule(&["apps"], &["v1beta1"], &["deployments"], &[Operation::All]);
+        let mut request = admission_request("apps", "v1", "deployments", None, "CREATE");
+        assert!(!rule.matches(&request));
+
+        request.request_resource = Some(GroupVersionResource {
+            group: "apps".to_string(),
+            version: "v1beta1".to_string(),
+            resource: "deployments".to_string(),
+        });
+        assert!(rule.matches(&request));
+    }
+
+    #[rstest]
+    #[case::matching_rule(PolicyType::Kubernetes, vec![rule(&[""], &["v1"], &["pods"], &[Operation::All])], true)]
+    #[case::no_matching_rule(PolicyType::Kubernetes, vec![rule(&[""], &["v1"], &["services"], &[Operation::All])], false)]
+    #[case::no_rules(PolicyType::Kubernetes, vec![], false)]
+    #[case::raw_policy(PolicyType::Raw, vec![rule(&["*"], &["*"], &["*/*"], &[Operation::All])], false)]
+    fn metadata_matches(
+        #[case] policy_type: PolicyType,
+        #[case] rules: Vec<Rule>,
+        #[case] expected: bool,
+    ) {
+        let metadata = Metadata {
+            protocol_version: Some(ProtocolVersion::V1),
+            rules,
+            policy_type,
+            ..Default::default()
+        };
+        let request = admission_request("", "v1", "pods", None, "CREATE");
+
+        assert_eq!(metadata.matches(&request), expected);
+    }
 }
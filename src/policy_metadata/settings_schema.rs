@@ -0,0 +1,102 @@
+use std::{fmt, sync::Arc};
+
+use crate::{errors::SettingsSchemaError, policy_evaluator::PolicySettings};
+
+type Result<T> = std::result::Result<T, SettingsSchemaError>;
+
+/// A compiled JSON Schema describing the settings accepted by a policy.
+///
+/// The schema is provided by the policy author via the `settingsSchema` field
+/// of the policy metadata. It allows the host to reject invalid settings
+/// without having to instantiate the policy.
+#[derive(Clone)]
+pub struct SettingsSchema {
+    validator: Arc<jsonschema::Validator>,
+}
+
+impl SettingsSchema {
+    pub fn new(schema: &serde_json::Value) -> Result<Self> {
+        let validator = jsonschema::validator_for(schema)
+            .map_err(|e| SettingsSchemaError::InvalidSchema(e.to_string()))?;
+
+        Ok(SettingsSchema {
+            validator: Arc::new(validator),
+        })
+    }
+
+    /// Validate the settings against the schema. All the violations are
+    /// reported, not only the first one
+    pub fn validate(&self, settings: &PolicySettings) -> Result<()> {
+        let settings = serde_json::Value::Object(settings.0.clone());
+        let violations: Vec<String> = self
+            .validator
+            .iter_errors(&settings)
+            .map(|error| {
+                let path = error.instance_path.to_string();
+                if path.is_empty() {
+                    error.to_string()
+                } else {
+                    format!("{path}: {error}")
+                }
+            })
+            .collect();
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(SettingsSchemaError::InvalidSettings(violations))
+        }
+    }
+}
+
+impl fmt::Debug for SettingsSchema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SettingsSchema").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use serde_json::json;
+
+    fn schema() -> SettingsSchema {
+        SettingsSchema::new(&json!({
+            "type": "object",
+            "properties": {
+                "allowedRegistries": {
+                    "type": "array",
+                    "items": {"type": "string"}
+                },
+                "maxReplicas": {"type": "integer", "minimum": 1}
+            },
+            "required": ["allowedRegistries"],
+            "additionalProperties": false
+        }))
+        .expect("cannot compile schema")
+    }
+
+    #[rstest]
+    #[case::valid(json!({"allowedRegistries": ["ghcr.io"], "maxReplicas": 3}), 0)]
+    #[case::missing_required(json!({"maxReplicas": 3}), 1)]
+    #[case::wrong_types(json!({"allowedRegistries": "ghcr.io", "maxReplicas": 0}), 2)]
+    #[case::unknown_property(json!({"allowedRegistries": [], "foo": true}), 1)]
+    fn validate_settings(#[case] settings: serde_json::Value, #[case] violations: usize) {
+        let settings = PolicySettings::try_from(&settings).expect("cannot build settings");
+
+        match schema().validate(&settings) {
+            Ok(()) => assert_eq!(violations, 0),
+            Err(SettingsSchemaError::InvalidSettings(errors)) => {
+                assert_eq!(errors.len(), violations, "{errors:?}")
+            }
+            Err(e) => panic!("unexpected error: {e}"),
+        }
+    }
+
+    #[test]
+    fn invalid_schema() {
+        let schema = SettingsSchema::new(&json!({"type": "not-a-type"}));
+        assert!(matches!(schema, Err(SettingsSchemaError::InvalidSchema(_))));
+    }
+}
@@ -0,0 +1,356 @@
+use k8s_openapi::{
+    ByteString,
+    api::admissionregistration::v1::{
+        MutatingWebhook, MutatingWebhookConfiguration, RuleWithOperations, ServiceReference,
+        ValidatingWebhook, ValidatingWebhookConfiguration, WebhookClientConfig,
+    },
+    apimachinery::pkg::apis::meta::v1::{LabelSelector, ObjectMeta},
+};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use super::{Metadata, PolicyType, Rule};
+use crate::errors::WebhookConfigurationError;
+
+type Result<T> = std::result::Result<T, WebhookConfigurationError>;
+
+/// The versions of `AdmissionReview` the server evaluating the policies can handle
+const SUPPORTED_ADMISSION_REVIEW_VERSIONS: [&str; 2] = ["v1", "v1beta1"];
+
+/// How the Kubernetes API server handles the errors raised while calling the webhook
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub enum FailurePolicy {
+    Ignore,
+    #[default]
+    Fail,
+}
+
+impl fmt::Display for FailurePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FailurePolicy::Ignore => write!(f, "Ignore"),
+            FailurePolicy::Fail => write!(f, "Fail"),
+        }
+    }
+}
+
+/// Whether the Kubernetes API server calls a mutating webhook again when the
+/// object is changed by the webhooks invoked after it
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub enum ReinvocationPolicy {
+    #[default]
+    Never,
+    /// The policy should be idempotent, see
+    /// `PolicyEvaluatorBuilder::enable_idempotency_check`
+    IfNeeded,
+}
+
+impl fmt::Display for ReinvocationPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReinvocationPolicy::Never => write!(f, "Never"),
+            ReinvocationPolicy::IfNeeded => write!(f, "IfNeeded"),
+        }
+    }
+}
+
+/// Deployment details of the webhook that evaluates a policy
+#[derive(Clone, Debug)]
+pub struct WebhookConfigurationParams {
+    /// Name of the webhook configuration and of its webhook. Must be a fully
+    /// qualified name, like `privileged-pods.kubewarden.admission`
+    pub name: String,
+    /// The Service exposing the server that evaluates the policy
+    pub service: ServiceReference,
+    /// PEM encoded CA bundle used to validate the certificate of the server
+    pub ca_bundle: Option<Vec<u8>>,
+    pub namespace_selector: Option<LabelSelector>,
+    pub object_selector: Option<LabelSelector>,
+    pub failure_policy: FailurePolicy,
+    /// Must be between 1 and 30 seconds. The Kubernetes default, 10 seconds,
+    /// is used when not provided
+    pub timeout_seconds: Option<i32>,
+    /// Used only by mutating policies. Defaults to `Never`
+    pub reinvocation_policy: ReinvocationPolicy,
+    /// The versions of `AdmissionReview` sent to the server, in order of
+    /// preference. Defaults to `v1`
+    pub admission_review_versions: Vec<String>,
+}
+
+impl Default for WebhookConfigurationParams {
+    fn default() -> Self {
+        WebhookConfigurationParams {
+            name: String::default(),
+            service: ServiceReference::default(),
+            ca_bundle: None,
+            namespace_selector: None,
+            object_selector: None,
+            failure_policy: FailurePolicy::default(),
+            timeout_seconds: None,
+            reinvocation_policy: ReinvocationPolicy::default(),
+            admission_review_versions: vec!["v1".to_string()],
+        }
+    }
+}
+
+/// The webhook configuration required to have Kubernetes send the admission
+/// requests to a policy
+#[derive(Clone, Debug, PartialEq)]
+pub enum WebhookConfiguration {
+    Validating(ValidatingWebhookConfiguration),
+    Mutating(MutatingWebhookConfiguration),
+}
+
+impl From<&Rule> for RuleWithOperations {
+    fn from(rule: &Rule) -> Self {
+        RuleWithOperations {
+            api_groups: Some(rule.api_groups.clone()),
+            api_versions: Some(rule.api_versions.clone()),
+            resources: Some(rule.resources.clone()),
+            operations: Some(rule.operations.iter().map(|op| op.to_string()).collect()),
+            scope: None,
+        }
+    }
+}
+
+impl Metadata {
+    /// Build the webhook configuration that sends the admission requests
+    /// matching the rules of the policy to the given service.
+    ///
+    /// Mutating policies produce a MutatingWebhookConfiguration, all the
+    /// other ones a ValidatingWebhookConfiguration.
+    pub fn webhook_configuration(
+        &self,
+        params: &WebhookConfigurationParams,
+    ) -> Result<WebhookConfiguration> {
+        if self.policy_type == PolicyType::Raw {
+            return Err(WebhookConfigurationError::RawPolicy);
+        }
+        if self.rules.is_empty() {
+            return Err(WebhookConfigurationError::NoRules);
+        }
+        // Kubernetes requires webhook names to be made of at least three segments
+        if params.name.split('.').filter(|s| !s.is_empty()).count() < 3 {
+            return Err(WebhookConfigurationError::InvalidName(params.name.clone()));
+        }
+        if let Some(timeout) = params.timeout_seconds
+            && !(1..=30).contains(&timeout)
+        {
+            return Err(WebhookConfigurationError::InvalidTimeout(timeout));
+        }
+        if params.admission_review_versions.is_empty()
+            || params
+                .admission_review_versions
+                .iter()
+                .any(|version| !SUPPORTED_ADMISSION_REVIEW_VERSIONS.contains(&version.as_str()))
+        {
+            return Err(WebhookConfigurationError::InvalidAdmissionReviewVersions(
+                params.admission_review_versions.clone(),
+            ));
+        }
+
+        let metadata = ObjectMeta {
+            name: Some(params.name.clone()),
+            ..Default::default()
+        };
+        let client_config = WebhookClientConfig {
+            ca_bundle: params.ca_bundle.clone().map(ByteString),
+            service: Some(params.service.clone()),
+            url: None,
+        };
+        let rules = Some(self.rules.iter().map(RuleWithOperations::from).collect());
+        let admission_review_versions = params.admission_review_versions.clone();
+        let side_effects = "None".to_string();
+        let match_policy = Some("Equivalent".to_string());
+
+        if self.mutating {
+            return Ok(WebhookConfiguration::Mutating(
+                MutatingWebhookConfiguration {
+                    metadata,
+                    webhooks: Some(vec![MutatingWebhook {
+                        admission_review_versions,
+                        client_config,
+                        failure_policy: Some(params.failure_policy.to_string()),
+                        match_policy,
+                        name: params.name.clone(),
+                        namespace_selector: params.namespace_selector.clone(),
+                        object_selector: params.object_selector.clone(),
+                        reinvocation_policy: Some(params.reinvocation_policy.to_string()),
+                        rules,
+                        side_effects,
+                        timeout_seconds: params.timeout_seconds,
+                        ..Default::default()
+                    }]),
+                },
+            ));
+        }
+
+        Ok(WebhookConfiguration::Validating(
+            ValidatingWebhookConfiguration {
+                metadata,
+                webhooks: Some(vec![ValidatingWebhook {
+                    admission_review_versions,
+                    client_config,
+                    failure_policy: Some(params.failure_policy.to_string()),
+                    match_policy,
+                    name: params.name.clone(),
+                    namespace_selector: params.namespace_selector.clone(),
+                    object_selector: params.object_selector.clone(),
+                    rules,
+                    side_effects,
+                    timeout_seconds: params.timeout_seconds,
+                    ..Default::default()
+                }]),
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy_metadata::Operation;
+    use k8s_openapi::api::admissionregistration::v1::NamedRuleWithOperations;
+    use kubewarden_policy_sdk::metadata::ProtocolVersion;
+    use rstest::rstest;
+
+    fn metadata(mutating: bool) -> Metadata {
+        Metadata {
+            protocol_version: Some(ProtocolVersion::V1),
+            rules: vec![Rule {
+                api_groups: vec!["".to_string()],
+                api_versions: vec!["v1".to_string()],
+                resources: vec!["pods".to_string(), "pods/exec".to_string()],
+                operations: vec![Operation::Create, Operation::Connect],
+            }],
+            mutating,
+            ..Default::default()
+        }
+    }
+
+    fn params() -> WebhookConfigurationParams {
+        WebhookConfigurationParams {
+            name: "privileged-pods.kubewarden.admission".to_string(),
+            service: ServiceReference {
+                name: "policy-server".to_string(),
+                namespace: "kubewarden".to_string(),
+                path: Some("/validate/privileged-pods".to_string()),
+                port: Some(8443),
+            },
+            failure_policy: FailurePolicy::Ignore,
+            timeout_seconds: Some(5),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn validating_webhook_configuration() {
+        let configuration = metadata(false)
+            .webhook_configuration(&params())
+            .expect("cannot build webhook configuration");
+
+        let WebhookConfiguration::Validating(configuration) = configuration else {
+            panic!("expected a validating webhook configuration");
+        };
+        assert_eq!(
+            configuration.metadata.name.as_deref(),
+            Some("privileged-pods.kubewarden.admission")
+        );
+        let webhook = &configuration.webhooks.unwrap()[0];
+        assert_eq!(webhook.failure_policy.as_deref(), Some("Ignore"));
+        assert_eq!(webhook.timeout_seconds, Some(5));
+        assert_eq!(webhook.side_effects, "None");
+        assert_eq!(webhook.client_config.service, Some(params().service));
+
+        let rules = webhook.rules.as_ref().unwrap();
+        assert_eq!(
+            rules[0].operations,
+            Some(vec!["CREATE".to_string(), "CONNECT".to_string()])
+        );
+        assert_eq!(
+            rules[0].resources,
+            Some(vec!["pods".to_string(), "pods/exec".to_string()])
+        );
+    }
+
+    #[test]
+    fn mutating_webhook_configuration() {
+        let configuration = metadata(true)
+            .webhook_configuration(&params())
+            .expect("cannot build webhook configuration");
+
+        let WebhookConfiguration::Mutating(configuration) = configuration else {
+            panic!("expected a mutating webhook configuration");
+        };
+        let webhook = &configuration.webhooks.unwrap()[0];
+        assert_eq!(webhook.reinvocation_policy.as_deref(), Some("Never"));
+        assert_eq!(webhook.admission_review_versions, vec!["v1".to_string()]);
+        assert_eq!(webhook.rules.as_ref().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn mutating_webhook_configuration_with_reinvocation() {
+        let params = WebhookConfigurationParams {
+            reinvocation_policy: ReinvocationPolicy::IfNeeded,
+            admission_review_versions: vec!["v1".to_string(), "v1beta1".to_string()],
+            ..params()
+        };
+        let configuration = metadata(true)
+            .webhook_configuration(&params)
+            .expect("cannot build webhook configuration");
+
+        let WebhookConfiguration::Mutating(configuration) = configuration else {
+            panic!("expected a mutating webhook configuration");
+        };
+        let webhook = &configuration.webhooks.unwrap()[0];
+        assert_eq!(webhook.reinvocation_policy.as_deref(), Some("IfNeeded"));
+        assert_eq!(
+            webhook.admission_review_versions,
+            vec!["v1".to_string(), "v1beta1".to_string()]
+        );
+    }
+
+    #[test]
+    fn rule_round_trip() {
+        let rule = metadata(false).rules[0].clone();
+        let webhook_rule = RuleWithOperations::from(&rule);
+        let named_rule = NamedRuleWithOperations {
+            api_groups: webhook_rule.api_groups,
+            api_versions: webhook_rule.api_versions,
+            operations: webhook_rule.operations,
+            resources: webhook_rule.resources,
+            ..Default::default()
+        };
+
+        assert_eq!(Rule::try_from(&named_rule), Ok(rule));
+    }
+
+    #[rstest]
+    #[case::raw_policy(
+        Metadata { policy_type: PolicyType::Raw, ..metadata(false) },
+        params()
+    )]
+    #[case::no_rules(Metadata { rules: vec![], ..metadata(false) }, params())]
+    #[case::name_not_fully_qualified(
+        metadata(false),
+        WebhookConfigurationParams { name: "privileged-pods".to_string(), ..params() }
+    )]
+    #[case::timeout_too_long(
+        metadata(false),
+        WebhookConfigurationParams { timeout_seconds: Some(31), ..params() }
+    )]
+    #[case::no_admission_review_versions(
+        metadata(false),
+        WebhookConfigurationParams { admission_review_versions: vec![], ..params() }
+    )]
+    #[case::unsupported_admission_review_version(
+        metadata(false),
+        WebhookConfigurationParams { admission_review_versions: vec!["v2".to_string()], ..params() }
+    )]
+    fn invalid_webhook_configuration(
+        #[case] metadata: Metadata,
+        #[case] params: WebhookConfigurationParams,
+    ) {
+        assert!(metadata.webhook_configuration(&params).is_err());
+    }
+}
@@ -11,6 +11,11 @@ use kubewarden_policy_sdk::host_capabilities::{
 use tokio::sync::{mpsc, oneshot, oneshot::Receiver};
 use tracing::{debug, error, warn};
 
+use crate::callback_handler::{
+    AttestationVerificationRequest, CertificateVerificationRequestV2, DigestRequest,
+    OciReferrersRequest, SignatureVerificationRequest,
+};
+use crate::callback_recording::request_key;
 use crate::callback_requests::{CallbackRequest, CallbackRequestType, CallbackResponse};
 use crate::evaluation_context::EvaluationContext;
 
@@ -27,13 +32,44 @@ fn unknown_namespace(namespace: &str) -> Result<Vec<u8>, Box<dyn std::error::Err
     Err(format!("unknown namespace: {}", namespace).into())
 }
 
-/// The callback function used by waPC and Wasi policies to use host capabilities
+/// Ensure the policy has been granted access to the Kubernetes resources it
+/// requested. Violations are reported
+fn check_kubernetes_access(
+    eval_ctx: &EvaluationContext,
+    api_version: &str,
+    kind: &str,
+    namespace: Option<&str>,
+    request_namespace: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if eval_ctx.can_access_kubernetes_resource(api_version, kind, namespace, request_namespace) {
+        return Ok(());
+    }
+
+    error!(
+        policy = eval_ctx.policy_id,
+        resource_requested = format!("{api_version}/{kind}"),
+        namespace_requested = namespace,
+        request_namespace,
+        resources_allowed = ?eval_ctx.ctx_aware_resources_allow_list,
+        "Policy tried to access a Kubernetes resource it doesn't have access to");
+    let location = namespace
+        .map(|namespace| format!(" inside of the {namespace} namespace"))
+        .unwrap_or_default();
+    Err(format!(
+        "Policy has not been granted access to Kubernetes {api_version}/{kind} resources{location}. The violation has been reported."
+    )
+    .into())
+}
+
+/// The callback function used by waPC and Wasi policies to use host capabilities.
+/// `request_namespace` is the namespace of the admission request being evaluated
 pub(crate) fn host_callback(
     binding: &str,
     namespace: &str,
     operation: &str,
     payload: &[u8],
     eval_ctx: &Arc<EvaluationContext>,
+    request_namespace: Option<&str>,
 ) -> Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>> {
     match binding {
         "kubewarden" => match namespace {
@@ -147,6 +183,52 @@ pub(crate) fn host_callback(
                         eval_ctx,
                     )
                 }
+                "v1/oci_referrers" => {
+                    let req: OciReferrersRequest = serde_json::from_slice(payload)?;
+                    debug!(
+                        eval_ctx.policy_id,
+                        binding,
+                        operation,
+                        ?req,
+                        "Sending request via callback channel"
+                    );
+                    let (tx, rx) = oneshot::channel::<Result<CallbackResponse>>();
+                    let req = CallbackRequest {
+                        request: CallbackRequestType::from(req),
+                        response_channel: tx,
+                    };
+                    send_request_and_wait_for_response(
+                        &eval_ctx.policy_id,
+                        binding,
+                        operation,
+                        req,
+                        rx,
+                        eval_ctx,
+                    )
+                }
+                "v1/verify_attestation" => {
+                    let req: AttestationVerificationRequest = serde_json::from_slice(payload)?;
+                    debug!(
+                        eval_ctx.policy_id,
+                        binding,
+                        operation,
+                        ?req,
+                        "Sending request via callback channel"
+                    );
+                    let (tx, rx) = oneshot::channel::<Result<CallbackResponse>>();
+                    let req = CallbackRequest {
+                        request: CallbackRequestType::from(req),
+                        response_channel: tx,
+                    };
+                    send_request_and_wait_for_response(
+                        &eval_ctx.policy_id,
+                        binding,
+                        operation,
+                        req,
+                        rx,
+                        eval_ctx,
+                    )
+                }
                 _ => unknown_operation(namespace, operation),
             },
             "net" => match operation {
@@ -170,6 +252,66 @@ pub(crate) fn host_callback(
                         eval_ctx,
                     )
                 }
+                "v1/dns_reverse_lookup" => {
+                    let address: String = serde_json::from_slice(payload)?;
+                    debug!(
+                        eval_ctx.policy_id,
+                        binding, operation, address, "Sending request via callback channel"
+                    );
+                    let (tx, rx) = oneshot::channel::<Result<CallbackResponse>>();
+                    let req = CallbackRequest {
+                        request: CallbackRequestType::DNSReverseLookup { address },
+                        response_channel: tx,
+                    };
+                    send_request_and_wait_for_response(
+                        &eval_ctx.policy_id,
+                        binding,
+                        operation,
+                        req,
+                        rx,
+                        eval_ctx,
+                    )
+                }
+                "v1/dns_lookup_srv" => {
+                    let name: String = serde_json::from_slice(payload)?;
+                    debug!(
+                        eval_ctx.policy_id,
+                        binding, operation, name, "Sending request via callback channel"
+                    );
+                    let (tx, rx) = oneshot::channel::<Result<CallbackResponse>>();
+                    let req = CallbackRequest {
+                        request: CallbackRequestType::DNSLookupSrv { name },
+                        response_channel: tx,
+                    };
+                    send_request_and_wait_for_response(
+                        &eval_ctx.policy_id,
+                        binding,
+                        operation,
+                        req,
+                        rx,
+                        eval_ctx,
+                    )
+                }
+                "v1/dns_lookup_txt" => {
+                    let name: String = serde_json::from_slice(payload)?;
+                    debug!(
+                        eval_ctx.policy_id,
+                        binding, operation, name, "Sending request via callback channel"
+                    );
+                    let (tx, rx) = oneshot::channel::<Result<CallbackResponse>>();
+                    let req = CallbackRequest {
+                        request: CallbackRequestType::DNSLookupTxt { name },
+                        response_channel: tx,
+                    };
+                    send_request_and_wait_for_response(
+                        &eval_ctx.policy_id,
+                        binding,
+                        operation,
+                        req,
+                        rx,
+                        eval_ctx,
+                    )
+                }
                 _ => unknown_operation(namespace, operation),
             },
             "crypto" => match operation {
@@ -197,23 +339,91 @@ pub(crate) fn host_callback(
                         eval_ctx,
                     )
                 }
+                "v2/is_certificate_trusted" => {
+                    let req: CertificateVerificationRequestV2 = serde_json::from_slice(payload)?;
+
+                    debug!(
+                        eval_ctx.policy_id,
+                        binding,
+                        operation,
+                        ?req,
+                        "Sending request via callback channel"
+                    );
+                    let (tx, rx) = oneshot::channel::<Result<CallbackResponse>>();
+                    let req = CallbackRequest {
+                        request: CallbackRequestType::from(req),
+                        response_channel: tx,
+                    };
+                    send_request_and_wait_for_response(
+                        &eval_ctx.policy_id,
+                        binding,
+                        operation,
+                        req,
+                        rx,
+                        eval_ctx,
+                    )
+                }
+                "v1/verify_signature" => {
+                    let req: SignatureVerificationRequest = serde_json::from_slice(payload)?;
+
+                    debug!(
+                        eval_ctx.policy_id,
+                        binding,
+                        operation,
+                        ?req,
+                        "Sending request via callback channel"
+                    );
+                    let (tx, rx) = oneshot::channel::<Result<CallbackResponse>>();
+                    let req = CallbackRequest {
+                        request: CallbackRequestType::from(req),
+                        response_channel: tx,
+                    };
+                    send_request_and_wait_for_response(
+                        &eval_ctx.policy_id,
+                        binding,
+                        operation,
+                        req,
+                        rx,
+                        eval_ctx,
+                    )
+                }
+                "v1/digest" => {
+                    let req: DigestRequest = serde_json::from_slice(payload)?;
+
+                    debug!(
+                        eval_ctx.policy_id,
+                        binding,
+                        operation,
+                        ?req,
+                        "Sending request via callback channel"
+                    );
+                    let (tx, rx) = oneshot::channel::<Result<CallbackResponse>>();
+                    let req = CallbackRequest {
+                        request: CallbackRequestType::from(req),
+                        response_channel: tx,
+                    };
+                    send_request_and_wait_for_response(
+                        &eval_ctx.policy_id,
+                        binding,
+                        operation,
+                        req,
+                        rx,
+                        eval_ctx,
+                    )
+                }
                 _ => unknown_operation(namespace, operation),
             },
             "kubernetes" => match operation {
                 "list_resources_by_namespace" => {
                     let req: ListResourcesByNamespaceRequest = serde_json::from_slice(payload)?;
 
-                    if !eval_ctx.can_access_kubernetes_resource(&req.api_version, &req.kind) {
-                        error!(
-                            policy = eval_ctx.policy_id,
-                            resource_requested = format!("{}/{}", req.api_version, req.kind),
-                            resources_allowed = ?eval_ctx.ctx_aware_resources_allow_list,
-                            "Policy tried to access a Kubernetes resource it doesn't have access to");
-                        return Err(format!(
-                                "Policy has not been granted access to Kubernetes {}/{} resources. The violation has been reported.",
-                                req.api_version,
-                                req.kind).into());
-                    }
+                    check_kubernetes_access(
+                        eval_ctx,
+                        &req.api_version,
+                        &req.kind,
+                        Some(req.namespace.as_str()),
+                        request_namespace,
+                    )?;
 
                     debug!(
                         eval_ctx.policy_id,
@@ -224,7 +434,14 @@ pub(crate) fn host_callback(
                     );
                     let (tx, rx) = oneshot::channel::<Result<CallbackResponse>>();
                     let req = CallbackRequest {
-                        request: CallbackRequestType::from(req),
+                        request: CallbackRequestType::KubernetesListResourceNamespace {
+                            fields: eval_ctx.kubernetes_fields(&req.api_version, &req.kind),
+                            api_version: req.api_version,
+                            kind: req.kind,
+                            namespace: req.namespace,
+                            label_selector: req.label_selector,
+                            field_selector: req.field_selector,
+                        },
                         response_channel: tx,
                     };
                     send_request_and_wait_for_response(
@@ -238,17 +455,13 @@ pub(crate) fn host_callback(
                 }
                 "list_resources_all" => {
                     let req: ListAllResourcesRequest = serde_json::from_slice(payload)?;
-                    if !eval_ctx.can_access_kubernetes_resource(&req.api_version, &req.kind) {
-                        error!(
-                            policy = eval_ctx.policy_id,
-                            resource_requested = format!("{}/{}", req.api_version, req.kind),
-                            resources_allowed = ?eval_ctx.ctx_aware_resources_allow_list,
-                            "Policy tried to access a Kubernetes resource it doesn't have access to");
-                        return Err(format!(
-                                "Policy has not been granted access to Kubernetes {}/{} resources. The violation has been reported.",
-                                req.api_version,
-                                req.kind).into());
-                    }
+                    check_kubernetes_access(
+                        eval_ctx,
+                        &req.api_version,
+                        &req.kind,
+                        None,
+                        request_namespace,
+                    )?;
 
                     debug!(
                         eval_ctx.policy_id,
@@ -259,7 +472,13 @@ pub(crate) fn host_callback(
                     );
                     let (tx, rx) = oneshot::channel::<Result<CallbackResponse>>();
                     let req = CallbackRequest {
-                        request: CallbackRequestType::from(req),
+                        request: CallbackRequestType::KubernetesListResourceAll {
+                            fields: eval_ctx.kubernetes_fields(&req.api_version, &req.kind),
+                            api_version: req.api_version,
+                            kind: req.kind,
+                            label_selector: req.label_selector,
+                            field_selector: req.field_selector,
+                        },
                         response_channel: tx,
                     };
                     send_request_and_wait_for_response(
@@ -273,17 +492,13 @@ pub(crate) fn host_callback(
                 }
                 "get_resource" => {
                     let req: GetResourceRequest = serde_json::from_slice(payload)?;
-                    if !eval_ctx.can_access_kubernetes_resource(&req.api_version, &req.kind) {
-                        error!(
-                            policy = eval_ctx.policy_id,
-                            resource_requested = format!("{}/{}", req.api_version, req.kind),
-                            resources_allowed = ?eval_ctx.ctx_aware_resources_allow_list,
-                            "Policy tried to access a Kubernetes resource it doesn't have access to");
-                        return Err(format!(
-                                "Policy has not been granted access to Kubernetes {}/{} resources. The violation has been reported.",
-                                req.api_version,
-                                req.kind).into());
-                    }
+                    check_kubernetes_access(
+                        eval_ctx,
+                        &req.api_version,
+                        &req.kind,
+                        req.namespace.as_deref(),
+                        request_namespace,
+                    )?;
 
                     debug!(
                         eval_ctx.policy_id,
@@ -294,7 +509,14 @@ pub(crate) fn host_callback(
                     );
                     let (tx, rx) = oneshot::channel::<Result<CallbackResponse>>();
                     let req = CallbackRequest {
-                        request: CallbackRequestType::from(req),
+                        request: CallbackRequestType::KubernetesGetResource {
+                            fields: eval_ctx.kubernetes_fields(&req.api_version, &req.kind),
+                            api_version: req.api_version,
+                            kind: req.kind,
+                            name: req.name,
+                            namespace: req.namespace,
+                            disable_cache: req.disable_cache,
+                        },
                         response_channel: tx,
                     };
                     send_request_and_wait_for_response(
@@ -342,6 +564,7 @@ pub(crate) fn host_callback(
                     kind: "Ingress".to_string(),
                     label_selector: None,
                     field_selector: None,
+                    fields: None,
                 };
 
                 warn!(
@@ -369,6 +592,7 @@ pub(crate) fn host_callback(
                     kind: "Namespace".to_string(),
                     label_selector: None,
                     field_selector: None,
+                    fields: None,
                 };
 
                 warn!(
@@ -396,6 +620,7 @@ pub(crate) fn host_callback(
                     kind: "Service".to_string(),
                     label_selector: None,
                     field_selector: None,
+                    fields: None,
                 };
 
                 warn!(
@@ -448,6 +673,22 @@ fn send_request_and_wait_for_response(
         ))
     }?;
 
+    // the request is consumed by the channel, compute what has to be
+    // recorded before sending it
+    let recorded_request = eval_ctx.callback_recorder.as_ref().and_then(|_| {
+        request_key(&req.request)
+            .map_err(|e| {
+                warn!(
+                    policy_id,
+                    binding,
+                    operation,
+                    error = e.to_string(),
+                    "cannot record callback request"
+                )
+            })
+            .ok()
+    });
+
     let send_result = cb_channel.try_send(req);
     if let Err(e) = send_result {
         return Err(format!("Error sending request over callback channel: {e:?}").into());
@@ -455,19 +696,26 @@ fn send_request_and_wait_for_response(
 
     // wait for the response
     match rx.blocking_recv() {
-        Ok(msg) => match msg {
-            Ok(resp) => Ok(resp.payload),
-            Err(e) => {
-                error!(
-                    policy_id,
-                    binding,
-                    operation,
-                    error = ?e,
-                    "callback evaluation failed"
-                );
-                Err(format!("Callback evaluation failure: {e:?}").into())
+        Ok(msg) => {
+            if let (Some(recorder), Some(request)) = (&eval_ctx.callback_recorder, recorded_request)
+            {
+                recorder.record(policy_id, binding, operation, request, &msg);
             }
-        },
+
+            match msg {
+                Ok(resp) => Ok(resp.payload),
+                Err(e) => {
+                    error!(
+                        policy_id,
+                        binding,
+                        operation,
+                        error = ?e,
+                        "callback evaluation failed"
+                    );
+                    Err(format!("Callback evaluation failure: {e:?}").into())
+                }
+            }
+        }
         Err(e) => {
             error!(
                 policy_id,
@@ -1,8 +1,10 @@
 use kube::api::ObjectList;
 use std::collections::{BTreeMap, BTreeSet};
 use tokio::sync::{mpsc, oneshot};
+use tracing::warn;
 
 use crate::{
+    callback_recording::{CallbackRecorder, request_key},
     callback_requests::{CallbackRequest, CallbackRequestType, CallbackResponse},
     policy_metadata::ContextAwareResource,
     runtimes::rego::{
@@ -11,6 +13,10 @@ use crate::{
     },
 };
 
+/// The binding used when recording the requests made to build the Kubernetes context,
+/// the same one used by the waPC policies
+const RECORDING_BINDING: &str = "kubewarden";
+
 #[derive(serde::Serialize)]
 #[serde(untagged)]
 pub(crate) enum KubernetesContext {
@@ -19,20 +25,51 @@ pub(crate) enum KubernetesContext {
     Gatekeeper(Vec<u8>),
 }
 
+/// The channel used to send requests to the callback handler, together with the
+/// recorder of the requests made by the policy, if any
+#[derive(Clone, Copy)]
+pub(crate) struct CallbackChannel<'a> {
+    policy_id: &'a str,
+    sender: &'a mpsc::Sender<CallbackRequest>,
+    recorder: Option<&'a CallbackRecorder>,
+}
+
+impl<'a> CallbackChannel<'a> {
+    pub fn new(
+        policy_id: &'a str,
+        sender: &'a mpsc::Sender<CallbackRequest>,
+        recorder: Option<&'a CallbackRecorder>,
+    ) -> Self {
+        Self {
+            policy_id,
+            sender,
+            recorder,
+        }
+    }
+}
+
 /// Uses the callback channel to get all the Kubernetes resources defined inside of
 /// the cluster whose type is mentioned inside of `allowed_resources`.
 ///
 /// The resources are returned based on the actual RBAC privileges of the client
-/// used by the runtime.
+/// used by the runtime. Only the resources defined inside of the namespaces the
+/// policy has access to are returned, `request_namespace` is the namespace of
+/// the admission request being evaluated.
 pub(crate) fn get_allowed_resources(
-    callback_channel: &mpsc::Sender<CallbackRequest>,
+    callback_channel: CallbackChannel<'_>,
     allowed_resources: &BTreeSet<ContextAwareResource>,
+    request_namespace: Option<&str>,
 ) -> Result<BTreeMap<ContextAwareResource, ObjectList<kube::core::DynamicObject>>> {
     let mut kube_resources: BTreeMap<ContextAwareResource, ObjectList<kube::core::DynamicObject>> =
         BTreeMap::new();
 
     for resource in allowed_resources {
-        let resource_list = get_all_resources_by_type(callback_channel, resource)?;
+        let mut resource_list = get_all_resources_by_type(callback_channel, resource)?;
+        resource_list.items.retain(|obj| {
+            resource
+                .namespace_scope
+                .allows(obj.metadata.namespace.as_deref(), request_namespace)
+        });
         kube_resources.insert(resource.to_owned(), resource_list);
     }
 
@@ -40,7 +77,7 @@ pub(crate) fn get_allowed_resources(
 }
 
 fn get_all_resources_by_type(
-    callback_channel: &mpsc::Sender<CallbackRequest>,
+    callback_channel: CallbackChannel<'_>,
     resource_type: &ContextAwareResource,
 ) -> Result<ObjectList<kube::core::DynamicObject>> {
     let req_type = CallbackRequestType::KubernetesListResourceAll {
@@ -48,16 +85,18 @@ fn get_all_resources_by_type(
         kind: resource_type.kind.to_owned(),
         label_selector: None,
         field_selector: None,
+        fields: (!resource_type.fields.is_empty()).then(|| resource_type.fields.clone()),
     };
 
-    let response = make_request_via_callback_channel(req_type, callback_channel)?;
+    let response =
+        make_request_via_callback_channel(req_type, "list_resources_all", callback_channel)?;
     serde_json::from_slice::<ObjectList<kube::core::DynamicObject>>(&response.payload)
         .map_err(RegoRuntimeError::CallbackConvertList)
 }
 
 /// For each allowed resource, check if the "list all resources" result changed since the given instant
 pub(crate) fn have_allowed_resources_changed_since_instant(
-    callback_channel: &mpsc::Sender<CallbackRequest>,
+    callback_channel: CallbackChannel<'_>,
     allowed_resources: &BTreeSet<ContextAwareResource>,
     since: tokio::time::Instant,
 ) -> Result<bool> {
@@ -74,7 +113,7 @@ pub(crate) fn have_allowed_resources_changed_since_instant(
 /// Note: this function doesn't take label_selector and field_selector into account because
 /// it's used only by gatekeeper policies, which don't use these selectors.
 fn has_resource_changed_since(
-    callback_channel: &mpsc::Sender<CallbackRequest>,
+    callback_channel: CallbackChannel<'_>,
     resource_type: &ContextAwareResource,
     since: tokio::time::Instant,
 ) -> Result<bool> {
@@ -86,7 +125,11 @@ fn has_resource_changed_since(
         since,
     };
 
-    let response = make_request_via_callback_channel(req_type, callback_channel)?;
+    let response = make_request_via_callback_channel(
+        req_type,
+        "has_list_resources_all_result_changed_since_instant",
+        callback_channel,
+    )?;
     serde_json::from_slice::<bool>(&response.payload).map_err(RegoRuntimeError::CallbackConvertBool)
 }
 
@@ -94,7 +137,7 @@ fn has_resource_changed_since(
 /// For example, the key for {`apps/v1`, `Deployment`} will have `deployments` as value.
 /// The map is built by making request via the given callback channel.
 pub(crate) fn get_plural_names(
-    callback_channel: &mpsc::Sender<CallbackRequest>,
+    callback_channel: CallbackChannel<'_>,
     allowed_resources: &BTreeSet<ContextAwareResource>,
 ) -> Result<BTreeMap<ContextAwareResource, String>> {
     let mut plural_names_by_resource: BTreeMap<ContextAwareResource, String> = BTreeMap::new();
@@ -105,7 +148,11 @@ pub(crate) fn get_plural_names(
             kind: resource.kind.to_owned(),
         };
 
-        let response = make_request_via_callback_channel(req_type, callback_channel)?;
+        let response = make_request_via_callback_channel(
+            req_type,
+            "get_resource_plural_name",
+            callback_channel,
+        )?;
         let plural_name = serde_json::from_slice::<String>(&response.payload)
             .map_err(RegoRuntimeError::CallbackGetPluralName)?;
 
@@ -117,21 +164,53 @@ pub(crate) fn get_plural_names(
 
 /// Internal helper function that sends a request over the callback channel and returns the
 /// response
+///
+/// The exchange is recorded when the channel has a recorder. `operation` names the request
+/// inside of the recording, the name of the equivalent waPC operation is used when there's one.
 fn make_request_via_callback_channel(
     request_type: CallbackRequestType,
-    callback_channel: &mpsc::Sender<CallbackRequest>,
+    operation: &str,
+    callback_channel: CallbackChannel<'_>,
 ) -> Result<CallbackResponse> {
+    // the request is consumed by the channel, compute what has to be
+    // recorded before sending it
+    let recorded_request = callback_channel.recorder.and_then(|_| {
+        request_key(&request_type)
+            .map_err(|e| {
+                warn!(
+                    policy_id = callback_channel.policy_id,
+                    binding = RECORDING_BINDING,
+                    operation,
+                    error = e.to_string(),
+                    "cannot record callback request"
+                )
+            })
+            .ok()
+    });
+
     let (tx, rx) = oneshot::channel::<std::result::Result<CallbackResponse, wasmtime::Error>>();
     let req = CallbackRequest {
         request: request_type,
         response_channel: tx,
     };
     callback_channel
+        .sender
         .try_send(req)
         .map_err(|e| RegoRuntimeError::CallbackSend(e.to_string()))?;
 
     match rx.blocking_recv() {
-        Ok(msg) => msg.map_err(RegoRuntimeError::CallbackRequest),
+        Ok(msg) => {
+            if let (Some(recorder), Some(request)) = (callback_channel.recorder, recorded_request) {
+                recorder.record(
+                    callback_channel.policy_id,
+                    RECORDING_BINDING,
+                    operation,
+                    request,
+                    &msg,
+                );
+            }
+            msg.map_err(RegoRuntimeError::CallbackRequest)
+        }
         Err(e) => Err(RegoRuntimeError::CallbackResponse(e.to_string())),
     }
 }
@@ -139,6 +218,8 @@ fn make_request_via_callback_channel(
 #[cfg(test)]
 pub(crate) mod tests {
     use super::*;
+    use crate::callback_recording::RecordedResponse;
+    use crate::policy_metadata::NamespaceScope;
     use anyhow::{Result, anyhow};
     use assert_json_diff::assert_json_eq;
     use rstest::rstest;
@@ -177,6 +258,8 @@ pub(crate) mod tests {
         let resource = ContextAwareResource {
             api_version: "v1".to_string(),
             kind: "Service".to_string(),
+            fields: Vec::new(),
+            namespace_scope: Default::default(),
         };
         let expected_resource = resource.clone();
         let services = [
@@ -196,11 +279,13 @@ pub(crate) mod tests {
                     kind,
                     label_selector,
                     field_selector,
+                    fields,
                 } => {
                     assert_eq!(api_version, expected_resource.api_version);
                     assert_eq!(kind, expected_resource.kind);
                     assert!(label_selector.is_none());
                     assert!(field_selector.is_none());
+                    assert!(fields.is_none());
                 }
                 _ => {
                     panic!("not the expected request type");
@@ -216,7 +301,11 @@ pub(crate) mod tests {
         });
 
         tokio::task::spawn_blocking(move || {
-            let actual = get_all_resources_by_type(&callback_tx, &resource).unwrap();
+            let actual = get_all_resources_by_type(
+                CallbackChannel::new("test", &callback_tx, None),
+                &resource,
+            )
+            .unwrap();
             let actual_json = serde_json::to_value(actual).unwrap();
             let expected_json = serde_json::to_value(services_list).unwrap();
             assert_json_eq!(actual_json, expected_json);
@@ -225,12 +314,74 @@ pub(crate) mod tests {
         .unwrap();
     }
 
+    #[rstest]
+    #[case::cluster(NamespaceScope::Cluster, None, 2)]
+    #[case::request_namespace(NamespaceScope::RequestNamespace, Some("kube-system"), 2)]
+    #[case::other_request_namespace(NamespaceScope::RequestNamespace, Some("default"), 0)]
+    #[case::no_request_namespace(NamespaceScope::RequestNamespace, None, 0)]
+    #[case::allow_listed_namespace(
+        NamespaceScope::Namespaces(BTreeSet::from(["kube-system".to_string()])),
+        None,
+        2
+    )]
+    #[case::not_allow_listed_namespace(
+        NamespaceScope::Namespaces(BTreeSet::from(["default".to_string()])),
+        None,
+        0
+    )]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn get_allowed_resources_scoped_to_namespaces(
+        #[case] namespace_scope: NamespaceScope,
+        #[case] request_namespace: Option<&'static str>,
+        #[case] expected_items: usize,
+    ) {
+        let (callback_tx, mut callback_rx) = mpsc::channel::<CallbackRequest>(10);
+        let resource = ContextAwareResource {
+            api_version: "v1".to_string(),
+            kind: "Service".to_string(),
+            fields: Vec::new(),
+            namespace_scope,
+        };
+        let services = [
+            dynamic_object_from_fixture("services", Some("kube-system"), "kube-dns").unwrap(),
+            dynamic_object_from_fixture("services", Some("kube-system"), "metrics-server").unwrap(),
+        ];
+
+        tokio::spawn(async move {
+            let req = match callback_rx.recv().await {
+                Some(r) => r,
+                None => return,
+            };
+            let services_list = object_list_from_dynamic_objects(&services).unwrap();
+            let callback_response = CallbackResponse {
+                payload: serde_json::to_vec(&services_list).unwrap(),
+            };
+
+            req.response_channel.send(Ok(callback_response)).unwrap();
+        });
+
+        tokio::task::spawn_blocking(move || {
+            let resources = BTreeSet::from([resource.clone()]);
+            let actual = get_allowed_resources(
+                CallbackChannel::new("test", &callback_tx, None),
+                &resources,
+                request_namespace,
+            )
+            .unwrap();
+            assert_eq!(actual[&resource].items.len(), expected_items);
+        })
+        .await
+        .unwrap();
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn get_resource_plural_name_success() {
         let (callback_tx, mut callback_rx) = mpsc::channel::<CallbackRequest>(10);
         let resource = ContextAwareResource {
             api_version: "v1".to_string(),
             kind: "Service".to_string(),
+            fields: Vec::new(),
+            namespace_scope: Default::default(),
         };
         let plural_name = "services";
 
@@ -265,32 +416,106 @@ pub(crate) mod tests {
         });
 
         tokio::task::spawn_blocking(move || {
-            let actual = get_plural_names(&callback_tx, &resources).unwrap();
+            let actual =
+                get_plural_names(CallbackChannel::new("test", &callback_tx, None), &resources)
+                    .unwrap();
             assert_eq!(actual, expected_names);
         })
         .await
         .unwrap();
     }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn requests_are_recorded() {
+        let (callback_tx, mut callback_rx) = mpsc::channel::<CallbackRequest>(10);
+        let resource = ContextAwareResource {
+            api_version: "v1".to_string(),
+            kind: "Service".to_string(),
+            fields: Vec::new(),
+            namespace_scope: Default::default(),
+        };
+        let resources = BTreeSet::from([resource.clone()]);
+        let services =
+            [dynamic_object_from_fixture("services", Some("kube-system"), "kube-dns").unwrap()];
+
+        tokio::spawn(async move {
+            while let Some(req) = callback_rx.recv().await {
+                let payload = match req.request {
+                    CallbackRequestType::KubernetesListResourceAll { .. } => {
+                        serde_json::to_vec(&object_list_from_dynamic_objects(&services).unwrap())
+                            .unwrap()
+                    }
+                    CallbackRequestType::KubernetesGetResourcePluralName { .. } => {
+                        serde_json::to_vec(&"services").unwrap()
+                    }
+                    _ => panic!("not the expected request type"),
+                };
+                req.response_channel
+                    .send(Ok(CallbackResponse { payload }))
+                    .unwrap();
+            }
+        });
+
+        let recorder = CallbackRecorder::new();
+        let session_recorder = recorder.clone();
+        tokio::task::spawn_blocking(move || {
+            let chan = CallbackChannel::new("test", &callback_tx, Some(&recorder));
+            get_allowed_resources(chan, &resources, None).unwrap();
+            get_plural_names(chan, &resources).unwrap();
+        })
+        .await
+        .unwrap();
+
+        let exchanges = session_recorder.session().exchanges;
+        assert_eq!(
+            exchanges
+                .iter()
+                .map(|exchange| (
+                    exchange.policy_id.as_str(),
+                    exchange.binding.as_str(),
+                    exchange.operation.as_str()
+                ))
+                .collect::<Vec<_>>(),
+            vec![
+                ("test", "kubewarden", "list_resources_all"),
+                ("test", "kubewarden", "get_resource_plural_name"),
+            ]
+        );
+        assert_eq!(
+            exchanges[1].request,
+            request_key(&CallbackRequestType::KubernetesGetResourcePluralName {
+                api_version: resource.api_version,
+                kind: resource.kind,
+            })
+            .unwrap()
+        );
+        assert_eq!(
+            exchanges[1].response,
+            RecordedResponse::Success {
+                payload: serde_json::json!("services")
+            }
+        );
+    }
     #[rstest]
     #[case(
-        HashMap::<ContextAwareResource, bool>::from([(ContextAwareResource{api_version: "v1".to_string(), kind: "Service".to_string()}, true)]),
+        HashMap::<ContextAwareResource, bool>::from([(ContextAwareResource{api_version: "v1".to_string(), kind: "Service".to_string(), fields: Vec::new(), namespace_scope: Default::default()}, true)]),
         true,
     )]
     #[case(
-        HashMap::<ContextAwareResource, bool>::from([(ContextAwareResource{api_version: "v1".to_string(), kind: "Service".to_string()}, false)]),
+        HashMap::<ContextAwareResource, bool>::from([(ContextAwareResource{api_version: "v1".to_string(), kind: "Service".to_string(), fields: Vec::new(), namespace_scope: Default::default()}, false)]),
         false,
     )]
     #[case(
         HashMap::<ContextAwareResource, bool>::from([
-            (ContextAwareResource{api_version: "v1".to_string(), kind: "Service".to_string()}, true),
-            (ContextAwareResource{api_version: "v1".to_string(), kind: "Pod".to_string()}, false),
+            (ContextAwareResource{api_version: "v1".to_string(), kind: "Service".to_string(), fields: Vec::new(), namespace_scope: Default::default()}, true),
+            (ContextAwareResource{api_version: "v1".to_string(), kind: "Pod".to_string(), fields: Vec::new(), namespace_scope: Default::default()}, false),
         ]),
         true,
     )]
     #[case(
         HashMap::<ContextAwareResource, bool>::from([
-            (ContextAwareResource{api_version: "v1".to_string(), kind: "Service".to_string()}, false),
-            (ContextAwareResource{api_version: "v1".to_string(), kind: "Pod".to_string()}, false),
+            (ContextAwareResource{api_version: "v1".to_string(), kind: "Service".to_string(), fields: Vec::new(), namespace_scope: Default::default()}, false),
+            (ContextAwareResource{api_version: "v1".to_string(), kind: "Pod".to_string(), fields: Vec::new(), namespace_scope: Default::default()}, false),
         ]),
         false,
     )]
@@ -320,6 +545,8 @@ pub(crate) mod tests {
                     let resource = ContextAwareResource {
                         api_version: api_version.clone(),
                         kind: kind.clone(),
+                        fields: Vec::new(),
+                        namespace_scope: Default::default(),
                     };
                     assert!(label_selector.is_none());
                     assert!(field_selector.is_none());
@@ -343,9 +570,12 @@ pub(crate) mod tests {
 
         tokio::task::spawn_blocking(move || {
             let resources = resources_with_change_status.keys().cloned().collect();
-            let actual =
-                have_allowed_resources_changed_since_instant(&callback_tx, &resources, since)
-                    .unwrap();
+            let actual = have_allowed_resources_changed_since_instant(
+                CallbackChannel::new("test", &callback_tx, None),
+                &resources,
+                since,
+            )
+            .unwrap();
             assert_json_eq!(expected, actual);
         })
         .await
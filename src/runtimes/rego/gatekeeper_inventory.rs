@@ -206,6 +206,8 @@ mod tests {
             ContextAwareResource {
                 api_version: "v1".to_string(),
                 kind: "Service".to_string(),
+                fields: Vec::new(),
+                namespace_scope: Default::default(),
             },
             services_list,
         );
@@ -225,6 +227,8 @@ mod tests {
             ContextAwareResource {
                 api_version: "apps/v1".to_string(),
                 kind: "Deployment".to_string(),
+                fields: Vec::new(),
+                namespace_scope: Default::default(),
             },
             deployments_list,
         );
@@ -238,6 +242,8 @@ mod tests {
             ContextAwareResource {
                 api_version: "v1".to_string(),
                 kind: "Namespace".to_string(),
+                fields: Vec::new(),
+                namespace_scope: Default::default(),
             },
             namespaces_list,
         );
@@ -4,14 +4,13 @@ use std::{
     collections::{BTreeSet, HashMap},
     sync::{Arc, RwLock},
 };
-use tokio::{sync::mpsc, time::Instant};
+use tokio::time::Instant;
 
 use crate::runtimes::rego::context_aware::{
-    get_allowed_resources, have_allowed_resources_changed_since_instant,
+    CallbackChannel, get_allowed_resources, have_allowed_resources_changed_since_instant,
 };
 use crate::{
-    callback_requests::CallbackRequest,
-    policy_metadata::ContextAwareResource,
+    policy_metadata::{ContextAwareResource, NamespaceScope},
     runtimes::rego::{
         errors::{RegoRuntimeError, Result},
         gatekeeper_inventory::GatekeeperInventory,
@@ -42,6 +41,22 @@ struct GatekeeperInput {
     inventory: GatekeeperInventory,
 }
 
+/// Identifies an inventory: the resources it gives access to, plus the namespace
+/// of the admission request when at least one of the resources is scoped to it
+type InventoryKey = (BTreeSet<ContextAwareResource>, Option<String>);
+
+fn inventory_key(
+    ctx_aware_resources: &BTreeSet<ContextAwareResource>,
+    request_namespace: Option<&str>,
+) -> InventoryKey {
+    let request_namespace = ctx_aware_resources
+        .iter()
+        .any(|resource| resource.namespace_scope == NamespaceScope::RequestNamespace)
+        .then(|| request_namespace.map(str::to_string))
+        .flatten();
+    (ctx_aware_resources.to_owned(), request_namespace)
+}
+
 /// Hold all the inventories for the Gatekeeper runtime
 ///
 /// The inventories are stored inside of a dictionary that has the list of resources
@@ -56,7 +71,7 @@ struct GatekeeperInput {
 pub(crate) struct GateKeeperInventoryCache {
     // Note: the Arc is used to make some `clone` invocation faster. The `clone` operations
     // are required because the whole `inventories` variable is located inside of a RwLock
-    inventories: RwLock<HashMap<BTreeSet<ContextAwareResource>, Arc<CachedInventory>>>,
+    inventories: RwLock<HashMap<InventoryKey, Arc<CachedInventory>>>,
 }
 
 impl GateKeeperInventoryCache {
@@ -69,25 +84,30 @@ impl GateKeeperInventoryCache {
     /// This function returns the serialized inventory for the given set of resources.
     /// The inventory is computed and serialized only if it's not already present in the cache.
     /// The inventory is also recreated if the set of resources has changed since the time
-    /// the inventory was computed
+    /// the inventory was computed.
+    ///
+    /// `request_namespace` is the namespace of the admission request being evaluated,
+    /// it's used to filter the resources that are scoped to it.
     pub fn get_inventory(
         &self,
-        callback_channel: &mpsc::Sender<CallbackRequest>,
+        callback_channel: CallbackChannel<'_>,
         ctx_aware_resources: &BTreeSet<ContextAwareResource>,
+        request_namespace: Option<&str>,
     ) -> Result<Vec<u8>> {
+        let key = inventory_key(ctx_aware_resources, request_namespace);
         let inventory = {
             let inventories = self.inventories.read().unwrap();
-            inventories.get(ctx_aware_resources).cloned()
+            inventories.get(&key).cloned()
         };
         let inventory = match inventory {
-            None => self.create_and_register_inventory(ctx_aware_resources, callback_channel),
+            None => self.create_and_register_inventory(key, callback_channel),
             Some(cached_inventory) => {
                 if have_allowed_resources_changed_since_instant(
                     callback_channel,
                     ctx_aware_resources,
                     cached_inventory.cache_time,
                 )? {
-                    self.create_and_register_inventory(ctx_aware_resources, callback_channel)
+                    self.create_and_register_inventory(key, callback_channel)
                 } else {
                     Ok(cached_inventory)
                 }
@@ -100,11 +120,16 @@ impl GateKeeperInventoryCache {
     /// automatically removed from the cache.
     fn create_and_register_inventory(
         &self,
-        ctx_aware_resources: &BTreeSet<ContextAwareResource>,
-        callback_channel: &mpsc::Sender<CallbackRequest>,
+        key: InventoryKey,
+        callback_channel: CallbackChannel<'_>,
     ) -> Result<Arc<CachedInventory>> {
         let now = Instant::now();
-        let cluster_resources = get_allowed_resources(callback_channel, ctx_aware_resources)?;
+        let (ctx_aware_resources, request_namespace) = &key;
+        let cluster_resources = get_allowed_resources(
+            callback_channel,
+            ctx_aware_resources,
+            request_namespace.as_deref(),
+        )?;
         let inventory = GatekeeperInput {
             inventory: GatekeeperInventory::new(&cluster_resources)?,
         };
@@ -117,7 +142,7 @@ impl GateKeeperInventoryCache {
         self.inventories
             .write()
             .unwrap()
-            .insert(ctx_aware_resources.to_owned(), cached_inventory.clone());
+            .insert(key, cached_inventory.clone());
         Ok(cached_inventory)
     }
 }
@@ -125,9 +150,10 @@ impl GateKeeperInventoryCache {
 #[cfg(test)]
 pub(crate) mod tests {
     use super::*;
-    use crate::callback_requests::{CallbackRequestType, CallbackResponse};
+    use crate::callback_requests::{CallbackRequest, CallbackRequestType, CallbackResponse};
     use serial_test::serial;
     use std::collections::BTreeMap;
+    use tokio::sync::mpsc;
 
     use crate::runtimes::rego::context_aware::tests::{
         dynamic_object_from_fixture, object_list_from_dynamic_objects,
@@ -140,6 +166,8 @@ pub(crate) mod tests {
         let resource = ContextAwareResource {
             api_version: "v1".to_string(),
             kind: "Service".to_string(),
+            fields: Vec::new(),
+            namespace_scope: Default::default(),
         };
         let expected_resource = resource.clone();
         let services = [
@@ -162,6 +190,7 @@ pub(crate) mod tests {
                         kind,
                         label_selector,
                         field_selector,
+                        ..
                     } => {
                         assert_eq!(api_version, expected_resource.api_version);
                         assert_eq!(kind, expected_resource.kind);
@@ -190,13 +219,17 @@ pub(crate) mod tests {
             let resources: BTreeSet<ContextAwareResource> = BTreeSet::from([resource]);
 
             let cached_inventory = GATEKEEPER_INVENTORY_CACHE
-                .get_inventory(&callback_tx, &resources)
+                .get_inventory(
+                    CallbackChannel::new("test", &callback_tx, None),
+                    &resources,
+                    None,
+                )
                 .unwrap();
             assert!(!cached_inventory.is_empty());
 
             {
                 let inventories = GATEKEEPER_INVENTORY_CACHE.inventories.read().unwrap();
-                let cached_input_json = inventories.get(&resources).unwrap();
+                let cached_input_json = inventories.get(&(resources.clone(), None)).unwrap();
                 let actual_inventory =
                     serde_json::from_slice::<GatekeeperInput>(&cached_input_json.data)
                         .unwrap()
@@ -215,6 +248,8 @@ pub(crate) mod tests {
         let resource = ContextAwareResource {
             api_version: "v1".to_string(),
             kind: "Service".to_string(),
+            fields: Vec::new(),
+            namespace_scope: Default::default(),
         };
         let expected_resource = resource.clone();
 
@@ -229,7 +264,7 @@ pub(crate) mod tests {
         {
             let mut inventories = GATEKEEPER_INVENTORY_CACHE.inventories.write().unwrap();
             inventories.insert(
-                resources.clone(),
+                (resources.clone(), None),
                 Arc::new(expected_cached_inventory.clone()),
             );
         }
@@ -268,7 +303,11 @@ pub(crate) mod tests {
 
         tokio::task::spawn_blocking(move || {
             let actual = GATEKEEPER_INVENTORY_CACHE
-                .get_inventory(&callback_tx, &resources)
+                .get_inventory(
+                    CallbackChannel::new("test", &callback_tx, None),
+                    &resources,
+                    None,
+                )
                 .unwrap();
             assert_eq!(expected_cached_inventory.data, actual);
         })
@@ -283,6 +322,8 @@ pub(crate) mod tests {
         let resource = ContextAwareResource {
             api_version: "v1".to_string(),
             kind: "Service".to_string(),
+            fields: Vec::new(),
+            namespace_scope: Default::default(),
         };
         let expected_resource = resource.clone();
 
@@ -306,7 +347,10 @@ pub(crate) mod tests {
 
         {
             let mut inventories = GATEKEEPER_INVENTORY_CACHE.inventories.write().unwrap();
-            inventories.insert(resources.clone(), Arc::new(stale_cached_inventory.clone()));
+            inventories.insert(
+                (resources.clone(), None),
+                Arc::new(stale_cached_inventory.clone()),
+            );
         }
 
         tokio::spawn(async move {
@@ -321,6 +365,7 @@ pub(crate) mod tests {
                         kind,
                         label_selector,
                         field_selector,
+                        ..
                     } => {
                         assert_eq!(api_version, expected_resource.api_version);
                         assert_eq!(kind, expected_resource.kind);
@@ -357,7 +402,11 @@ pub(crate) mod tests {
 
         tokio::task::spawn_blocking(move || {
             let actual = GATEKEEPER_INVENTORY_CACHE
-                .get_inventory(&callback_tx, &resources)
+                .get_inventory(
+                    CallbackChannel::new("test", &callback_tx, None),
+                    &resources,
+                    None,
+                )
                 .unwrap();
             assert!(actual != stale_cached_inventory.data);
             let actual_inventory = serde_json::from_slice::<GatekeeperInput>(&actual).unwrap();
@@ -365,13 +414,13 @@ pub(crate) mod tests {
 
             {
                 let inventories = GATEKEEPER_INVENTORY_CACHE.inventories.read().unwrap();
-                let actual_inventory = inventories.get(&resources).unwrap();
+                let actual_inventory = inventories.get(&(resources.clone(), None)).unwrap();
                 assert!(actual_inventory.cache_time > stale_cached_inventory.cache_time);
             }
 
             {
                 let inventories = GATEKEEPER_INVENTORY_CACHE.inventories.read().unwrap();
-                let actual_inventory = inventories.get(&resources).unwrap();
+                let actual_inventory = inventories.get(&(resources.clone(), None)).unwrap();
                 assert!(actual_inventory.cache_time > stale_cached_inventory.cache_time);
             }
         })
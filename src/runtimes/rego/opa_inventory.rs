@@ -215,6 +215,8 @@ mod tests {
         let ctx_aware_resource = ContextAwareResource {
             api_version: "v1".to_string(),
             kind: "Service".to_string(),
+            fields: Vec::new(),
+            namespace_scope: Default::default(),
         };
         plural_names.insert(ctx_aware_resource.clone(), "services".to_string());
         kube_resources.insert(ctx_aware_resource, services_list);
@@ -233,6 +235,8 @@ mod tests {
         let ctx_aware_resource = ContextAwareResource {
             api_version: "apps/v1".to_string(),
             kind: "Deployment".to_string(),
+            fields: Vec::new(),
+            namespace_scope: Default::default(),
         };
         plural_names.insert(ctx_aware_resource.clone(), "deployments".to_string());
         kube_resources.insert(ctx_aware_resource, deployments_list);
@@ -245,6 +249,8 @@ mod tests {
         let ctx_aware_resource = ContextAwareResource {
             api_version: "v1".to_string(),
             kind: "Namespace".to_string(),
+            fields: Vec::new(),
+            namespace_scope: Default::default(),
         };
         plural_names.insert(ctx_aware_resource.clone(), "namespaces".to_string());
         kube_resources.insert(ctx_aware_resource, namespaces_list);
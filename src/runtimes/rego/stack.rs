@@ -1,9 +1,6 @@
 use std::collections::BTreeSet;
 
-use tokio::sync::mpsc;
-
 use crate::{
-    callback_requests::CallbackRequest,
     evaluation_context::EvaluationContext,
     policy_evaluator::RegoPolicyExecutionMode,
     policy_metadata::ContextAwareResource,
@@ -35,35 +32,63 @@ impl Stack {
         })
     }
 
+    /// Build the Kubernetes context of the policy, the requests made to the
+    /// callback handler are recorded by the recorder of `eval_ctx`, if any
     pub fn build_kubernetes_context(
         &self,
-        callback_channel: Option<&mpsc::Sender<CallbackRequest>>,
-        ctx_aware_resources_allow_list: &BTreeSet<ContextAwareResource>,
+        eval_ctx: &EvaluationContext,
+        request_namespace: Option<&str>,
     ) -> Result<context_aware::KubernetesContext> {
+        let ctx_aware_resources_allow_list = &eval_ctx.ctx_aware_resources_allow_list;
         if ctx_aware_resources_allow_list.is_empty() {
             return Ok(context_aware::KubernetesContext::Empty);
         }
 
-        match callback_channel {
+        match &eval_ctx.callback_channel {
             None => Err(RegoRuntimeError::CallbackChannelNotSet),
-            Some(chan) => match self.policy_execution_mode {
-                RegoPolicyExecutionMode::Opa => {
-                    let cluster_resources =
-                        context_aware::get_allowed_resources(chan, ctx_aware_resources_allow_list)?;
-                    let plural_names_by_resource =
-                        context_aware::get_plural_names(chan, ctx_aware_resources_allow_list)?;
-                    let inventory =
-                        OpaInventory::new(&cluster_resources, &plural_names_by_resource)?;
-                    Ok(context_aware::KubernetesContext::Opa(inventory))
-                }
-                RegoPolicyExecutionMode::Gatekeeper => {
-                    let cached_inventory = GATEKEEPER_INVENTORY_CACHE
-                        .get_inventory(chan, ctx_aware_resources_allow_list)?;
-                    Ok(context_aware::KubernetesContext::Gatekeeper(
-                        cached_inventory,
-                    ))
-                }
-            },
+            Some(sender) => {
+                let chan = context_aware::CallbackChannel::new(
+                    &eval_ctx.policy_id,
+                    sender,
+                    eval_ctx.callback_recorder.as_ref(),
+                );
+                self.build_kubernetes_context_via_channel(
+                    chan,
+                    ctx_aware_resources_allow_list,
+                    request_namespace,
+                )
+            }
+        }
+    }
+
+    fn build_kubernetes_context_via_channel(
+        &self,
+        chan: context_aware::CallbackChannel<'_>,
+        ctx_aware_resources_allow_list: &BTreeSet<ContextAwareResource>,
+        request_namespace: Option<&str>,
+    ) -> Result<context_aware::KubernetesContext> {
+        match self.policy_execution_mode {
+            RegoPolicyExecutionMode::Opa => {
+                let cluster_resources = context_aware::get_allowed_resources(
+                    chan,
+                    ctx_aware_resources_allow_list,
+                    request_namespace,
+                )?;
+                let plural_names_by_resource =
+                    context_aware::get_plural_names(chan, ctx_aware_resources_allow_list)?;
+                let inventory = OpaInventory::new(&cluster_resources, &plural_names_by_resource)?;
+                Ok(context_aware::KubernetesContext::Opa(inventory))
+            }
+            RegoPolicyExecutionMode::Gatekeeper => {
+                let cached_inventory = GATEKEEPER_INVENTORY_CACHE.get_inventory(
+                    chan,
+                    ctx_aware_resources_allow_list,
+                    request_namespace,
+                )?;
+                Ok(context_aware::KubernetesContext::Gatekeeper(
+                    cached_inventory,
+                ))
+            }
         }
     }
 }
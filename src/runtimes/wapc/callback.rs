@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 
 use anyhow::Result;
 use tracing::debug;
@@ -18,14 +18,31 @@ type HostCallback = Box<
         + Sync,
 >;
 
+/// The namespace of the admission request being evaluated by the policy. It's
+/// set by the runtime before each evaluation, and read by the host callback
+pub(crate) type RequestNamespace = Arc<RwLock<Option<String>>>;
+
 /// Returns a host callback function that can be used by the waPC runtime.
-/// The callback function will be able to access the `EvaluationContext` instance.
-pub(crate) fn new_host_callback(eval_ctx: Arc<EvaluationContext>) -> HostCallback {
+/// The callback function will be able to access the `EvaluationContext` instance,
+/// and the namespace of the request being evaluated.
+pub(crate) fn new_host_callback(
+    eval_ctx: Arc<EvaluationContext>,
+    request_namespace: RequestNamespace,
+) -> HostCallback {
     Box::new({
         move |wapc_id, binding, namespace, operation, payload| {
             debug!(wapc_id, "invoking host_callback");
+            let request_namespace = request_namespace
+                .read()
+                .expect("request namespace lock poisoned")
+                .clone();
             crate::runtimes::callback::host_callback(
-                binding, namespace, operation, payload, &eval_ctx,
+                binding,
+                namespace,
+                operation,
+                payload,
+                &eval_ctx,
+                request_namespace.as_deref(),
             )
         }
     })
@@ -8,7 +8,10 @@ use serde_json::json;
 use tracing::{error, info};
 
 use crate::{
-    admission_response::AdmissionResponse,
+    admission_response::{
+        AdmissionResponse,
+        mutation::{PatchGeneration, PolicyValidationResponseExtensions},
+    },
     policy_evaluator::{PolicySettings, ValidateRequest},
     runtimes::wapc::{
         WapcStack,
@@ -30,8 +33,11 @@ impl Runtime<'_> {
         &mut self,
         settings: &PolicySettings,
         request: &ValidateRequest,
+        patch_generation: PatchGeneration,
     ) -> AdmissionResponse {
         let uid = request.uid();
+        self.0
+            .set_request_namespace(request.namespace().map(str::to_string));
 
         let req_json_value =
             serde_json::to_value(request).expect("cannot convert request to json value");
@@ -64,10 +70,16 @@ impl Runtime<'_> {
                     .map_err(WapcRuntimeError::InvalidResponseWithError);
                 pol_val_resp
                     .and_then(|pol_val_resp| {
-                        AdmissionResponse::from_policy_validation_response(
+                        let extensions = PolicyValidationResponseExtensions::from_slice(&res)
+                            .map_err(|e| -> WapcRuntimeError {
+                                WapcRuntimeError::InvalidResponseFormat(e.into())
+                            })?;
+                        AdmissionResponse::from_policy_validation_response_with_mutation(
                             uid.to_string(),
                             req_obj,
                             &pol_val_resp,
+                            extensions.mutation.as_ref(),
+                            patch_generation,
                         )
                         .map_err(|e| -> WapcRuntimeError {
                             WapcRuntimeError::InvalidResponseFormat(e.into())
@@ -131,6 +143,7 @@ impl Runtime<'_> {
     }
 
     pub fn validate_settings(&mut self, settings: String) -> SettingsValidationResponse {
+        self.0.set_request_namespace(None);
         match self.0.call("validate_settings", settings.as_bytes()) {
             Ok(res) => {
                 let vr: Result<SettingsValidationResponse> = serde_json::from_slice(&res)
@@ -207,6 +220,7 @@ mod tests {
             callback_channel: None,
             ctx_aware_resources_allow_list: Default::default(),
             epoch_deadline: Some(epoch_deadline),
+            callback_recorder: None,
         };
 
         let eval_ctx = Arc::new(eval_ctx);
@@ -216,7 +230,7 @@ mod tests {
             .expect("error creating wasmtime engine provider");
         let host = wapc::WapcHost::new(
             Box::new(wapc_engine),
-            Some(Box::new(new_host_callback(eval_ctx))),
+            Some(Box::new(new_host_callback(eval_ctx, Default::default()))),
         )
         .expect("cannot create waPC host");
 
@@ -2,7 +2,7 @@ use std::sync::Arc;
 
 use crate::evaluation_context::EvaluationContext;
 use crate::runtimes::wapc::{
-    callback::new_host_callback,
+    callback::{RequestNamespace, new_host_callback},
     errors::{Result, WapcRuntimeError},
 };
 
@@ -12,17 +12,21 @@ pub(crate) struct WapcStack {
     wapc_host: wapc::WapcHost,
     stack_pre: StackPre,
     eval_ctx: Arc<EvaluationContext>,
+    request_namespace: RequestNamespace,
 }
 
 impl WapcStack {
     pub(crate) fn new_from_pre(stack_pre: &StackPre, eval_ctx: &EvaluationContext) -> Result<Self> {
         let eval_ctx = Arc::new(eval_ctx.to_owned());
-        let wapc_host = Self::wapc_host_from_pre(stack_pre, eval_ctx.clone())?;
+        let request_namespace = RequestNamespace::default();
+        let wapc_host =
+            Self::wapc_host_from_pre(stack_pre, eval_ctx.clone(), request_namespace.clone())?;
 
         Ok(Self {
             wapc_host,
             stack_pre: stack_pre.to_owned(),
             eval_ctx: eval_ctx.to_owned(),
+            request_namespace,
         })
     }
 
@@ -34,13 +38,25 @@ impl WapcStack {
     /// variable.
     pub(crate) fn reset(&mut self) -> Result<()> {
         // Create a new wapc_host
-        let new_wapc_host = Self::wapc_host_from_pre(&self.stack_pre, self.eval_ctx.clone())?;
+        let new_wapc_host = Self::wapc_host_from_pre(
+            &self.stack_pre,
+            self.eval_ctx.clone(),
+            self.request_namespace.clone(),
+        )?;
 
         self.wapc_host = new_wapc_host;
 
         Ok(())
     }
 
+    /// Set the namespace of the admission request that is about to be evaluated
+    pub(crate) fn set_request_namespace(&self, namespace: Option<String>) {
+        *self
+            .request_namespace
+            .write()
+            .expect("request namespace lock poisoned") = namespace;
+    }
+
     /// Invokes the given waPC function using the provided payload
     pub(crate) fn call(
         &self,
@@ -55,11 +71,14 @@ impl WapcStack {
     fn wapc_host_from_pre(
         pre: &StackPre,
         eval_ctx: Arc<EvaluationContext>,
+        request_namespace: RequestNamespace,
     ) -> Result<wapc::WapcHost> {
         let engine_provider = pre.rehydrate(eval_ctx.epoch_deadline)?;
-        let wapc_host =
-            wapc::WapcHost::new(Box::new(engine_provider), Some(new_host_callback(eval_ctx)))
-                .map_err(WapcRuntimeError::WapcHostBuilder)?;
+        let wapc_host = wapc::WapcHost::new(
+            Box::new(engine_provider),
+            Some(new_host_callback(eval_ctx, request_namespace)),
+        )
+        .map_err(WapcRuntimeError::WapcHostBuilder)?;
         Ok(wapc_host)
     }
 }
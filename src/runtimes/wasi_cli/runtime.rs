@@ -3,7 +3,10 @@ use kubewarden_policy_sdk::settings::SettingsValidationResponse;
 use serde_json::json;
 use tracing::{error, warn};
 
-use crate::admission_response::AdmissionResponse;
+use crate::admission_response::{
+    AdmissionResponse,
+    mutation::{PatchGeneration, PolicyValidationResponseExtensions},
+};
 use crate::policy_evaluator::{PolicySettings, ValidateRequest};
 use crate::runtimes::wasi_cli::stack::{RunResult, Stack};
 
@@ -14,6 +17,7 @@ impl Runtime<'_> {
         &self,
         settings: &PolicySettings,
         request: &ValidateRequest,
+        patch_generation: PatchGeneration,
     ) -> AdmissionResponse {
         let validate_params = json!({
             "request": request,
@@ -35,7 +39,7 @@ impl Runtime<'_> {
         };
         let args = ["policy.wasm", "validate"];
 
-        match self.0.run(&input, &args) {
+        match self.0.run(&input, &args, request.namespace()) {
             Ok(RunResult { stdout, stderr }) => {
                 if !stderr.is_empty() {
                     warn!(
@@ -54,10 +58,16 @@ impl Runtime<'_> {
                             ValidateRequest::AdmissionRequest(_) => req_json_value.get("object"),
                         };
 
-                        AdmissionResponse::from_policy_validation_response(
-                            request.uid().to_string(),
-                            req_obj,
-                            &pvr,
+                        PolicyValidationResponseExtensions::from_slice(stdout.as_bytes()).and_then(
+                            |extensions| {
+                                AdmissionResponse::from_policy_validation_response_with_mutation(
+                                    request.uid().to_string(),
+                                    req_obj,
+                                    &pvr,
+                                    extensions.mutation.as_ref(),
+                                    patch_generation,
+                                )
+                            },
                         )
                     }
                     .unwrap_or_else(|e| {
@@ -79,7 +89,7 @@ impl Runtime<'_> {
     pub fn validate_settings(&self, settings: String) -> SettingsValidationResponse {
         let args = ["policy.wasm", "validate-settings"];
 
-        match self.0.run(settings.as_bytes(), &args) {
+        match self.0.run(settings.as_bytes(), &args, None) {
             Ok(RunResult { stdout, stderr }) => {
                 if !stderr.is_empty() {
                     warn!(operation = "validate-settings", "stderr: {:?}", stderr)
@@ -21,6 +21,8 @@ pub(crate) struct Context {
     pub(crate) wasi_ctx: WasiCtx,
     pub(crate) stdin_pipe: Arc<RwLock<WasiPipe>>,
     pub(crate) eval_ctx: Arc<EvaluationContext>,
+    /// The namespace of the admission request being evaluated
+    pub(crate) request_namespace: Option<String>,
 }
 
 pub(crate) struct Stack {
@@ -41,11 +43,13 @@ impl Stack {
         }
     }
 
-    /// Run a WASI program with the given input and args
+    /// Run a WASI program with the given input and args. `request_namespace` is
+    /// the namespace of the admission request being evaluated, if any
     pub(crate) fn run(
         &self,
         input: &[u8],
         args: &[&str],
+        request_namespace: Option<&str>,
     ) -> std::result::Result<RunResult, WasiRuntimeError> {
         let stdout_pipe = WritePipe::new_in_memory();
         let stderr_pipe = WritePipe::new_in_memory();
@@ -64,6 +68,7 @@ impl Stack {
             wasi_ctx,
             stdin_pipe,
             eval_ctx: self.eval_ctx.clone(),
+            request_namespace: request_namespace.map(str::to_string),
         };
 
         let mut store = self
@@ -87,7 +87,14 @@ fn add_host_call_to_linker(linker: &mut wasmtime::Linker<Context>) -> Result<()>
         let op_vec = get_vec_from_memory(caller.as_context(), memory, op_ptr, op_len);
         let op = std::str::from_utf8(&op_vec).map_err(WasiRuntimeError::WasiMemOpToUtF8)?;
 
-        let host_callback_response = host_callback(bd, ns, op, &vec, &caller.data().eval_ctx);
+        let host_callback_response = host_callback(
+            bd,
+            ns,
+            op,
+            &vec,
+            &caller.data().eval_ctx,
+            caller.data().request_namespace.as_deref(),
+        );
 
         // return 1 if the host callback failed, 0 otherwise
         let func_return_value = host_callback_response.is_err() as i32;
@@ -0,0 +1,29 @@
+//! Fixtures shared by the unit tests of the crate
+
+use serde_json::json;
+
+use crate::admission_request::AdmissionRequest;
+
+/// The creation of the `nginx` Pod inside of the `default` namespace, made by
+/// the `admin` user
+pub(crate) fn admission_request() -> AdmissionRequest {
+    serde_json::from_value(json!({
+        "uid": "hello",
+        "kind": {"group": "", "version": "v1", "kind": "Pod"},
+        "resource": {"group": "", "version": "v1", "resource": "pods"},
+        "name": "nginx",
+        "namespace": "default",
+        "operation": "CREATE",
+        "userInfo": {"username": "admin"},
+        "object": {
+            "apiVersion": "v1",
+            "kind": "Pod",
+            "metadata": {
+                "name": "nginx",
+                "namespace": "default",
+                "labels": {"app": "nginx"}
+            }
+        }
+    }))
+    .expect("cannot build admission request")
+}
@@ -0,0 +1,761 @@
+//! Run declarative test suites against a policy.
+//!
+//! A test suite is a YAML document like the following one:
+//!
+//! ```yaml
+//! name: pod-privileged
+//! policy: policy.wasm
+//! executionMode: kubewarden-wapc
+//! settings: {}
+//! # optional, answers the host capabilities requests of context aware policies
+//! fixtures: fixtures.yaml
+//! contextAwareResources:
+//!   - apiVersion: v1
+//!     kind: Namespace
+//! tests:
+//!   - name: reject privileged containers
+//!     request: requests/privileged_pod.json
+//!     expect:
+//!       allowed: false
+//!       message: "^privileged container .* is not allowed$"
+//!   - name: add default security context
+//!     request: requests/pod.json
+//!     settings:
+//!       mutate: true
+//!     expect:
+//!       allowed: true
+//!       patch:
+//!         - op: add
+//!           path: /spec/securityContext
+//!           value: {runAsNonRoot: true}
+//! ```
+//!
+//! Relative paths are resolved against the directory containing the suite.
+//! The request can either be an `AdmissionRequest`, a full `AdmissionReview`
+//! or, when `raw` is set, a raw request.
+
+use std::{
+    collections::BTreeSet,
+    fmt::Write as _,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
+
+use crate::admission_request::AdmissionRequest;
+use crate::admission_response::AdmissionResponse;
+use crate::admission_review::{ADMISSION_REVIEW_KIND, AdmissionReviewRequest};
+use crate::callback_handler::{CallbackFixtures, CallbackHandlerBuilder};
+use crate::errors::TestSuiteError;
+use crate::evaluation_context::EvaluationContext;
+use crate::policy_evaluator::{
+    PolicyEvaluatorPre, PolicyExecutionMode, PolicySettings, ValidateRequest,
+    policy_evaluator_builder::PolicyEvaluatorBuilder,
+};
+use crate::policy_metadata::{ContextAwareResource, Metadata};
+
+type Result<T> = std::result::Result<T, TestSuiteError>;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct TestSuite {
+    pub name: String,
+    /// Path to the Wasm module of the policy
+    pub policy: PathBuf,
+    pub execution_mode: PolicyExecutionMode,
+    /// The settings used by all the test cases that do not provide their own
+    #[serde(default)]
+    pub settings: PolicySettings,
+    /// The Kubernetes resources the policy is granted access to
+    #[serde(default)]
+    pub context_aware_resources: BTreeSet<ContextAwareResource>,
+    /// Path to the `CallbackFixtures` used to answer the host capabilities
+    /// requests. When not provided, all these requests fail
+    #[serde(default)]
+    pub fixtures: Option<PathBuf>,
+    pub tests: Vec<TestCase>,
+    /// Directory used to resolve relative paths
+    #[serde(skip)]
+    base_dir: PathBuf,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct TestCase {
+    pub name: String,
+    pub request: TestRequest,
+    /// Send the request as it is, without wrapping it into an `AdmissionRequest`
+    #[serde(default)]
+    pub raw: bool,
+    #[serde(default)]
+    pub settings: Option<PolicySettings>,
+    pub expect: Expectation,
+}
+
+/// The request of a test case, either the path of a JSON file or the
+/// request itself
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum TestRequest {
+    Path(PathBuf),
+    Inline(serde_json::Value),
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Expectation {
+    pub allowed: bool,
+    /// Regular expression the rejection message must match
+    #[serde(default)]
+    pub message: Option<String>,
+    /// The JSONPatch the policy must produce. Patches are compared by the
+    /// changes they make to the object, not by their operations
+    #[serde(default)]
+    pub patch: Option<json_patch::Patch>,
+    /// Whether the policy must mutate the request or not
+    #[serde(default)]
+    pub mutated: Option<bool>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TestOutcome {
+    Passed,
+    /// The policy did not behave as expected, the reasons are reported
+    Failed(Vec<String>),
+    /// The test case could not be evaluated
+    Error(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct TestCaseReport {
+    pub name: String,
+    pub duration: Duration,
+    pub outcome: TestOutcome,
+}
+
+#[derive(Clone, Debug)]
+pub struct TestSuiteReport {
+    pub name: String,
+    pub cases: Vec<TestCaseReport>,
+}
+
+impl TestSuite {
+    /// Load a test suite from a YAML file
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let data = std::fs::read(path).map_err(|e| TestSuiteError::Read {
+            path: path.to_path_buf(),
+            error: e,
+        })?;
+        let mut suite: TestSuite = serde_yaml::from_slice(&data).map_err(TestSuiteError::Parse)?;
+        suite.base_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        Ok(suite)
+    }
+
+    /// Set the directory used to resolve relative paths
+    pub fn with_base_dir(mut self, base_dir: &Path) -> Self {
+        self.base_dir = base_dir.to_path_buf();
+        self
+    }
+
+    /// Evaluate all the test cases of the suite.
+    ///
+    /// A failing test case does not stop the execution of the suite, the
+    /// outcome of all the test cases is reported.
+    pub async fn run(&self) -> Result<TestSuiteReport> {
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+
+        let callback_channel = match &self.fixtures {
+            Some(fixtures) => {
+                let fixtures = CallbackFixtures::from_path(&self.base_dir.join(fixtures))
+                    .map_err(TestSuiteError::Fixtures)?;
+                let mut callback_handler = CallbackHandlerBuilder::new(shutdown_rx)
+                    .fixtures(fixtures)
+                    .build()
+                    .await
+                    .map_err(TestSuiteError::Fixtures)?;
+                let callback_channel = callback_handler.sender_channel();
+                tokio::spawn(async move { callback_handler.loop_eval().await });
+                Some(callback_channel)
+            }
+            None => None,
+        };
+
+        // the settings are validated against the schema embedded into the
+        // policy metadata, the same way the policy server does
+        let policy_path = self.base_dir.join(&self.policy);
+        let settings_schema = match Metadata::from_path(&policy_path)? {
+            Some(metadata) => metadata.compile_settings_schema()?,
+            None => None,
+        };
+        let mut policy_evaluator_builder = PolicyEvaluatorBuilder::new()
+            .execution_mode(self.execution_mode)
+            .policy_file(&policy_path)?;
+        if let Some(settings_schema) = settings_schema {
+            policy_evaluator_builder = policy_evaluator_builder.settings_schema(settings_schema);
+        }
+        let policy_evaluator_pre = policy_evaluator_builder.build_pre()?;
+        let eval_ctx = EvaluationContext {
+            policy_id: self.name.clone(),
+            callback_channel,
+            ctx_aware_resources_allow_list: self.context_aware_resources.clone(),
+            ..Default::default()
+        };
+
+        // The evaluation of the policies is blocking, the host capabilities
+        // requests are answered by the CallbackHandler running on the async runtime
+        let suite = self.clone();
+        let cases = tokio::task::spawn_blocking(move || {
+            suite
+                .tests
+                .iter()
+                .map(|test_case| suite.run_test_case(&policy_evaluator_pre, &eval_ctx, test_case))
+                .collect::<Vec<_>>()
+        })
+        .await
+        .map_err(|e| TestSuiteError::Join(e.to_string()))?;
+
+        // the CallbackHandler could be already gone when no fixtures are used
+        let _ = shutdown_tx.send(());
+
+        Ok(TestSuiteReport {
+            name: self.name.clone(),
+            cases,
+        })
+    }
+
+    fn run_test_case(
+        &self,
+        policy_evaluator_pre: &PolicyEvaluatorPre,
+        eval_ctx: &EvaluationContext,
+        test_case: &TestCase,
+    ) -> TestCaseReport {
+        let start = Instant::now();
+        let outcome = match self.evaluate_test_case(policy_evaluator_pre, eval_ctx, test_case) {
+            Ok(failures) if failures.is_empty() => TestOutcome::Passed,
+            Ok(failures) => TestOutcome::Failed(failures),
+            Err(e) => TestOutcome::Error(e.to_string()),
+        };
+
+        TestCaseReport {
+            name: test_case.name.clone(),
+            duration: start.elapsed(),
+            outcome,
+        }
+    }
+
+    fn evaluate_test_case(
+        &self,
+        policy_evaluator_pre: &PolicyEvaluatorPre,
+        eval_ctx: &EvaluationContext,
+        test_case: &TestCase,
+    ) -> Result<Vec<String>> {
+        let request = self.load_request(test_case)?;
+        let settings = test_case.settings.as_ref().unwrap_or(&self.settings);
+
+        let mut policy_evaluator = policy_evaluator_pre
+            .rehydrate(eval_ctx)
+            .map_err(|e| TestSuiteError::PolicyEvaluator(e.to_string()))?;
+
+        let settings_validation = policy_evaluator.validate_settings(settings);
+        if !settings_validation.valid {
+            return Ok(vec![format!(
+                "settings rejected by the policy: {}",
+                settings_validation.message.unwrap_or_default()
+            )]);
+        }
+
+        let response = policy_evaluator.validate(request.clone(), settings);
+        check_response(&test_case.expect, &request, &response)
+    }
+
+    fn load_request(&self, test_case: &TestCase) -> Result<ValidateRequest> {
+        let request = match &test_case.request {
+            TestRequest::Inline(request) => request.clone(),
+            TestRequest::Path(path) => {
+                let path = self.base_dir.join(path);
+                let data = std::fs::read(&path).map_err(|e| TestSuiteError::Read {
+                    path: path.clone(),
+                    error: e,
+                })?;
+                serde_json::from_slice(&data).map_err(TestSuiteError::InvalidRequest)?
+            }
+        };
+
+        if test_case.raw {
+            return Ok(ValidateRequest::Raw(request));
+        }
+
+        if request.get("kind").and_then(|kind| kind.as_str()) == Some(ADMISSION_REVIEW_KIND) {
+            let review = AdmissionReviewRequest::from_value(request)?;
+            return Ok(ValidateRequest::try_from(review)?);
+        }
+
+        let request: AdmissionRequest =
+            serde_json::from_value(request).map_err(TestSuiteError::InvalidRequest)?;
+        Ok(ValidateRequest::AdmissionRequest(Box::new(request)))
+    }
+}
+
+/// Compare the response of the policy with the expected one. Returns the
+/// list of differences
+fn check_response(
+    expect: &Expectation,
+    request: &ValidateRequest,
+    response: &AdmissionResponse,
+) -> Result<Vec<String>> {
+    let mut failures = Vec::new();
+
+    if response.allowed != expect.allowed {
+        failures.push(format!(
+            "expected allowed to be {}, got {}",
+            expect.allowed, response.allowed
+        ));
+    }
+
+    if let Some(pattern) = &expect.message {
+        let regex = Regex::new(pattern).map_err(|e| TestSuiteError::InvalidRegex {
+            pattern: pattern.clone(),
+            error: e,
+        })?;
+        let message = response
+            .status
+            .as_ref()
+            .and_then(|status| status.message.as_deref())
+            .unwrap_or_default();
+        if !regex.is_match(message) {
+            failures.push(format!(
+                "expected message to match '{pattern}', got '{message}'"
+            ));
+        }
+    }
+
+    let patch = match response.decode_patch() {
+        Ok(patch) => patch,
+        Err(e) => {
+            failures.push(format!(
+                "cannot decode the patch returned by the policy: {e}"
+            ));
+            return Ok(failures);
+        }
+    };
+
+    if let Some(mutated) = expect.mutated
+        && mutated != patch.is_some()
+    {
+        failures.push(format!(
+            "expected mutated to be {mutated}, got {}",
+            patch.is_some()
+        ));
+    }
+
+    if let Some(expected_patch) = &expect.patch {
+        match &patch {
+            // an empty patch does not mutate the request
+            None if expected_patch.0.is_empty() => {}
+            None => failures.push("expected a patch, the policy did not mutate the request".into()),
+            Some(patch) if !same_changes(request, expected_patch, patch) => {
+                failures.push(format!(
+                    "expected patch {}, got {}",
+                    serde_json::to_string(expected_patch).unwrap_or_default(),
+                    serde_json::to_string(patch).unwrap_or_default()
+                ));
+            }
+            Some(_) => {}
+        }
+    }
+
+    Ok(failures)
+}
+
+/// Two patches are equivalent when they produce the same object. When the
+/// patches cannot be applied to the request, their JSON representations are
+/// compared
+fn same_changes(
+    request: &ValidateRequest,
+    expected: &json_patch::Patch,
+    actual: &json_patch::Patch,
+) -> bool {
+    let patched_objects = request
+        .with_patched_object(expected)
+        .and_then(|expected| Ok((expected, request.with_patched_object(actual)?)));
+
+    match patched_objects {
+        Ok((expected, actual)) => {
+            serde_json::to_value(expected).ok() == serde_json::to_value(actual).ok()
+        }
+        Err(_) => serde_json::to_value(expected).ok() == serde_json::to_value(actual).ok(),
+    }
+}
+
+impl TestSuiteReport {
+    pub fn passed(&self) -> bool {
+        self.cases
+            .iter()
+            .all(|case| case.outcome == TestOutcome::Passed)
+    }
+
+    fn count(&self, filter: impl Fn(&TestOutcome) -> bool) -> usize {
+        self.cases
+            .iter()
+            .filter(|case| filter(&case.outcome))
+            .count()
+    }
+
+    /// Render the report using the JUnit XML format
+    pub fn to_junit(&self) -> String {
+        let failures = self.count(|outcome| matches!(outcome, TestOutcome::Failed(_)));
+        let errors = self.count(|outcome| matches!(outcome, TestOutcome::Error(_)));
+        let duration: Duration = self.cases.iter().map(|case| case.duration).sum();
+
+        let mut junit = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        let _ = writeln!(
+            junit,
+            r#"<testsuite name="{}" tests="{}" failures="{failures}" errors="{errors}" time="{:.3}">"#,
+            xml_escape(&self.name),
+            self.cases.len(),
+            duration.as_secs_f64(),
+        );
+        for case in &self.cases {
+            let _ = write!(
+                junit,
+                r#"  <testcase name="{}" classname="{}" time="{:.3}""#,
+                xml_escape(&case.name),
+                xml_escape(&self.name),
+                case.duration.as_secs_f64(),
+            );
+            match &case.outcome {
+                TestOutcome::Passed => junit.push_str("/>\n"),
+                TestOutcome::Failed(failures) => {
+                    let _ = writeln!(
+                        junit,
+                        ">\n    <failure message=\"{}\"/>\n  </testcase>",
+                        xml_escape(&failures.join("; "))
+                    );
+                }
+                TestOutcome::Error(error) => {
+                    let _ = writeln!(
+                        junit,
+                        ">\n    <error message=\"{}\"/>\n  </testcase>",
+                        xml_escape(error)
+                    );
+                }
+            }
+        }
+        junit.push_str("</testsuite>\n");
+
+        junit
+    }
+
+    /// Render the report using the Test Anything Protocol, version 13
+    pub fn to_tap(&self) -> String {
+        let mut tap = format!("TAP version 13\n1..{}\n", self.cases.len());
+        for (index, case) in self.cases.iter().enumerate() {
+            let reasons = match &case.outcome {
+                TestOutcome::Passed => {
+                    let _ = writeln!(tap, "ok {} - {}", index + 1, case.name);
+                    continue;
+                }
+                TestOutcome::Failed(failures) => failures.clone(),
+                TestOutcome::Error(error) => vec![format!("error: {error}")],
+            };
+            let _ = writeln!(tap, "not ok {} - {}", index + 1, case.name);
+            for reason in reasons {
+                let _ = writeln!(tap, "  # {reason}");
+            }
+        }
+
+        tap
+    }
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::{Engine as _, engine::general_purpose};
+    use rstest::rstest;
+    use serde_json::json;
+
+    use crate::admission_response::{AdmissionResponseStatus, PatchType};
+
+    fn request() -> ValidateRequest {
+        ValidateRequest::Raw(json!({"metadata": {"name": "nginx"}}))
+    }
+
+    fn response(
+        allowed: bool,
+        message: Option<&str>,
+        patch: Option<serde_json::Value>,
+    ) -> AdmissionResponse {
+        AdmissionResponse {
+            uid: "uid".to_string(),
+            allowed,
+            patch_type: patch.as_ref().map(|_| PatchType::JSONPatch),
+            patch: patch.map(|patch| general_purpose::STANDARD.encode(patch.to_string())),
+            status: message.map(|message| AdmissionResponseStatus {
+                message: Some(message.to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[rstest]
+    #[case::allowed(
+        json!({"allowed": true}),
+        response(true, None, None),
+        0
+    )]
+    #[case::wrong_verdict(
+        json!({"allowed": true}),
+        response(false, Some("nope"), None),
+        1
+    )]
+    #[case::message_matches(
+        json!({"allowed": false, "message": "^container .* is privileged$"}),
+        response(false, Some("container nginx is privileged"), None),
+        0
+    )]
+    #[case::message_does_not_match(
+        json!({"allowed": false, "message": "^privileged$"}),
+        response(false, Some("container nginx is privileged"), None),
+        1
+    )]
+    #[case::equivalent_patch(
+        json!({"allowed": true, "patch": [
+            {"op": "add", "path": "/metadata/labels", "value": {"a": "1", "b": "2"}}
+        ]}),
+        response(true, None, Some(json!([
+            {"op": "add", "path": "/metadata/labels", "value": {}},
+            {"op": "add", "path": "/metadata/labels/b", "value": "2"},
+            {"op": "add", "path": "/metadata/labels/a", "value": "1"},
+        ]))),
+        0
+    )]
+    #[case::different_patch(
+        json!({"allowed": true, "patch": [
+            {"op": "add", "path": "/metadata/labels", "value": {"a": "1"}}
+        ]}),
+        response(true, None, Some(json!([
+            {"op": "add", "path": "/metadata/labels", "value": {"a": "2"}},
+        ]))),
+        1
+    )]
+    #[case::missing_patch(
+        json!({"allowed": true, "patch": [
+            {"op": "add", "path": "/metadata/labels", "value": {"a": "1"}}
+        ]}),
+        response(true, None, None),
+        1
+    )]
+    #[case::empty_patch(
+        json!({"allowed": true, "patch": []}),
+        response(true, None, None),
+        0
+    )]
+    #[case::unexpected_mutation(
+        json!({"allowed": true, "mutated": false}),
+        response(true, None, Some(json!([{"op": "remove", "path": "/metadata/name"}]))),
+        1
+    )]
+    fn check_policy_response(
+        #[case] expect: serde_json::Value,
+        #[case] response: AdmissionResponse,
+        #[case] expected_failures: usize,
+    ) {
+        let expect: Expectation = serde_json::from_value(expect).unwrap();
+        let failures = check_response(&expect, &request(), &response).unwrap();
+        assert_eq!(failures.len(), expected_failures, "{failures:?}");
+    }
+
+    #[test]
+    fn unexpected_mutation_message() {
+        let expect: Expectation =
+            serde_json::from_value(json!({"allowed": true, "mutated": true})).unwrap();
+        let failures = check_response(&expect, &request(), &response(true, None, None)).unwrap();
+        assert_eq!(failures, vec!["expected mutated to be true, got false"]);
+    }
+
+    #[test]
+    fn invalid_message_regex() {
+        let expect = Expectation {
+            message: Some("(".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            check_response(&expect, &request(), &response(false, None, None)),
+            Err(TestSuiteError::InvalidRegex { .. })
+        ));
+    }
+
+    fn report() -> TestSuiteReport {
+        TestSuiteReport {
+            name: "suite".to_string(),
+            cases: vec![
+                TestCaseReport {
+                    name: "passing".to_string(),
+                    duration: Duration::from_millis(10),
+                    outcome: TestOutcome::Passed,
+                },
+                TestCaseReport {
+                    name: "failing".to_string(),
+                    duration: Duration::from_millis(20),
+                    outcome: TestOutcome::Failed(vec!["expected <true>".to_string()]),
+                },
+                TestCaseReport {
+                    name: "broken".to_string(),
+                    duration: Duration::from_millis(5),
+                    outcome: TestOutcome::Error("cannot read request".to_string()),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn junit_report() {
+        let report = report();
+        assert!(!report.passed());
+        assert_eq!(
+            report.to_junit(),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuite name="suite" tests="3" failures="1" errors="1" time="0.035">
+  <testcase name="passing" classname="suite" time="0.010"/>
+  <testcase name="failing" classname="suite" time="0.020">
+    <failure message="expected &lt;true&gt;"/>
+  </testcase>
+  <testcase name="broken" classname="suite" time="0.005">
+    <error message="cannot read request"/>
+  </testcase>
+</testsuite>
+"#
+        );
+    }
+
+    #[test]
+    fn tap_report() {
+        assert_eq!(
+            report().to_tap(),
+            "TAP version 13
+1..3
+ok 1 - passing
+not ok 2 - failing
+  # expected <true>
+not ok 3 - broken
+  # error: cannot read request
+"
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn run_test_suite() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let data_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data");
+        let suite_path = tempdir.path().join("suite.yaml");
+        std::fs::write(
+            &suite_path,
+            format!(
+                r#"
+name: always-happy
+policy: {}
+executionMode: gatekeeper
+tests:
+  - name: accept pods
+    request: {}
+    expect:
+      allowed: true
+      mutated: false
+  - name: reject pods
+    request: {}
+    expect:
+      allowed: false
+  - name: missing request
+    request: missing.json
+    expect:
+      allowed: true
+"#,
+                data_dir
+                    .join("gatekeeper_always_happy_policy.wasm")
+                    .display(),
+                data_dir.join("pod_creation_flux_cat.json").display(),
+                data_dir.join("pod_creation_flux_cat.json").display(),
+            ),
+        )
+        .unwrap();
+
+        let report = TestSuite::from_path(&suite_path)
+            .unwrap()
+            .run()
+            .await
+            .unwrap();
+
+        assert!(!report.passed());
+        assert_eq!(report.cases[0].outcome, TestOutcome::Passed);
+        assert_eq!(
+            report.cases[1].outcome,
+            TestOutcome::Failed(vec!["expected allowed to be false, got true".to_string()])
+        );
+        assert!(matches!(report.cases[2].outcome, TestOutcome::Error(_)));
+
+        let tap = report.to_tap();
+        assert!(
+            tap.starts_with(
+                "TAP version 13
+1..3
+ok 1 - accept pods
+not ok 2 - reject pods
+  # expected allowed to be false, got true
+not ok 3 - missing request
+  # error: "
+            ),
+            "unexpected TAP report: {tap}"
+        );
+
+        let junit = report.to_junit();
+        assert!(
+            junit.contains(r#"<testsuite name="always-happy" tests="3" failures="1" errors="1""#),
+            "unexpected JUnit report: {junit}"
+        );
+        assert!(junit.contains(r#"<failure message="expected allowed to be false, got true"/>"#));
+    }
+
+    #[test]
+    fn parse_test_suite() {
+        let suite: TestSuite = serde_yaml::from_str(
+            r#"
+name: suite
+policy: policy.wasm
+executionMode: opa
+fixtures: fixtures.yaml
+tests:
+  - name: from file
+    request: request.json
+    expect:
+      allowed: true
+  - name: inline
+    raw: true
+    request:
+      user: alice
+    settings:
+      allowedUsers: [alice]
+    expect:
+      allowed: true
+"#,
+        )
+        .expect("cannot parse test suite");
+
+        assert_eq!(suite.execution_mode, PolicyExecutionMode::Opa);
+        assert!(matches!(suite.tests[0].request, TestRequest::Path(_)));
+        assert!(matches!(suite.tests[1].request, TestRequest::Inline(_)));
+        assert!(suite.tests[1].raw);
+    }
+}
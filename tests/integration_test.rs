@@ -3,12 +3,15 @@ mod common;
 mod k8s_mock;
 
 use anyhow::Result;
+use base64::{Engine as _, engine::general_purpose};
 use core::panic;
 use hyper::{Request, Response};
 use kube::Client;
 use kube::client::Body;
 use kubewarden_policy_sdk::host_capabilities::oci::ManifestDigestResponse;
-use policy_evaluator::admission_response::PatchType;
+use policy_evaluator::admission_response::{AdmissionResponse, PatchType};
+use policy_evaluator::errors::PolicyEvaluatorError;
+use policy_evaluator::policy_evaluator_builder::PolicyEvaluatorBuilder;
 use policy_fetcher::oci_client::manifest::OciImageManifest;
 use rstest::*;
 use serde_json::json;
@@ -203,6 +206,7 @@ async fn test_policy_evaluator(
         callback_channel: None,
         ctx_aware_resources_allow_list: Default::default(),
         epoch_deadline: None,
+        callback_recorder: None,
     };
 
     let mut policy_evaluator = build_policy_evaluator(execution_mode, &policy, &eval_ctx);
@@ -245,6 +249,100 @@ async fn test_policy_evaluator(
     }
 }
 
+#[rstest]
+#[case::idempotent(
+    json!({
+        "forbiddenResources": ["banana", "carrot"],
+        "defaultResource": "hay",
+    }),
+    None,
+    true
+)]
+#[case::noop_second_patch(
+    json!({
+        "forbiddenResources": ["banana", "hay"],
+        "defaultResource": "hay",
+    }),
+    None,
+    true
+)]
+#[case::non_idempotent(
+    json!({
+        "forbiddenResources": ["banana", "carrot"],
+        "defaultResource": "hay",
+    }),
+    // the first mutation replaces the resource with another forbidden one,
+    // which is then changed again by the second evaluation
+    Some("carrot"),
+    false
+)]
+#[tokio::test]
+async fn test_mutation_idempotency(
+    #[case] settings: serde_json::Value,
+    #[case] first_mutation: Option<&str>,
+    #[case] idempotent: bool,
+) {
+    let settings = PolicySettings::try_from(&settings).expect("cannot convert settings");
+    let tempdir = tempfile::TempDir::new().expect("cannot create tempdir");
+    let policy = fetch_policy(
+        "ghcr.io/kubewarden/tests/raw-mutation-policy:v0.1.0",
+        tempdir.path().to_owned(),
+    )
+    .await;
+
+    let eval_ctx = EvaluationContext {
+        policy_id: "test".to_owned(),
+        callback_channel: None,
+        ctx_aware_resources_allow_list: Default::default(),
+        epoch_deadline: None,
+        callback_recorder: None,
+    };
+
+    let mut policy_evaluator = PolicyEvaluatorBuilder::new()
+        .execution_mode(PolicyExecutionMode::KubewardenWapc)
+        .policy_file(&policy.local_path)
+        .expect("cannot read policy file")
+        .enable_idempotency_check()
+        .build_pre()
+        .expect("cannot build policy evaluator pre")
+        .rehydrate(&eval_ctx)
+        .expect("cannot rehydrate policy evaluator");
+
+    let request_data = load_request_data("raw_mutation.json");
+    let request =
+        ValidateRequest::Raw(serde_json::from_slice(&request_data).expect("cannot deserialize"));
+
+    let admission_response = match first_mutation {
+        Some(resource) => {
+            let patch = json!([{"op": "replace", "path": "/resource", "value": resource}]);
+            AdmissionResponse {
+                allowed: true,
+                patch_type: Some(PatchType::JSONPatch),
+                patch: Some(general_purpose::STANDARD.encode(patch.to_string())),
+                ..Default::default()
+            }
+        }
+        None => {
+            // the idempotency check enabled via the builder must not add any warning
+            let admission_response = policy_evaluator.validate(request.clone(), &settings);
+            assert!(admission_response.patch.is_some());
+            assert!(admission_response.warnings.is_none());
+            admission_response
+        }
+    };
+
+    let result =
+        policy_evaluator.check_mutation_idempotency(&request, &settings, &admission_response);
+    if idempotent {
+        assert!(result.is_ok(), "unexpected error: {result:?}");
+    } else {
+        assert!(matches!(
+            result,
+            Err(PolicyEvaluatorError::NonIdempotentMutation(_))
+        ));
+    }
+}
+
 #[test_log::test(rstest)]
 #[case::wasi(
     PolicyExecutionMode::Wasi,
@@ -305,17 +403,24 @@ async fn test_runtime_context_aware<F, Fut>(
             ContextAwareResource {
                 api_version: "v1".to_owned(),
                 kind: "Namespace".to_owned(),
+                fields: Vec::new(),
+                namespace_scope: Default::default(),
             },
             ContextAwareResource {
                 api_version: "apps/v1".to_owned(),
                 kind: "Deployment".to_owned(),
+                fields: Vec::new(),
+                namespace_scope: Default::default(),
             },
             ContextAwareResource {
                 api_version: "v1".to_owned(),
                 kind: "Service".to_owned(),
+                fields: Vec::new(),
+                namespace_scope: Default::default(),
             },
         ]),
         epoch_deadline: Some(2),
+        callback_recorder: None,
     };
 
     let request_data = load_request_data(request_file_path);
@@ -363,6 +468,7 @@ async fn test_oci_manifest_capability(
         callback_channel: Some(callback_handler_channel),
         ctx_aware_resources_allow_list: Default::default(),
         epoch_deadline: None,
+        callback_recorder: None,
     };
 
     let cb_channel: mpsc::Sender<CallbackRequest> = eval_ctx
@@ -454,6 +560,7 @@ async fn test_oci_manifest_and_config_capability(
         callback_channel: Some(callback_handler_channel),
         ctx_aware_resources_allow_list: Default::default(),
         epoch_deadline: None,
+        callback_recorder: None,
     };
 
     let cb_channel: mpsc::Sender<CallbackRequest> = eval_ctx
@@ -522,6 +629,7 @@ async fn test_oci_digest_capability() {
         callback_channel: Some(callback_handler_channel),
         ctx_aware_resources_allow_list: Default::default(),
         epoch_deadline: None,
+        callback_recorder: None,
     };
 
     let cb_channel: mpsc::Sender<CallbackRequest> = eval_ctx